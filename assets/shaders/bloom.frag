@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec2 pass_uv;
+
+layout(binding = 0) uniform Post {
+    float exposure;
+    float bloom_threshold;
+    float bloom_intensity;
+    uint tonemapping;
+    uint color_grading;
+    uint fxaa;
+} post;
+
+layout(binding = 1) uniform sampler2D hdr;
+
+layout(push_constant, std430) uniform Push {
+    // Size of a texel of hdr in uv coordinates
+    vec2 texel_size;
+} push;
+
+layout(location = 0) out vec4 out_color;
+
+// The part of the color above the threshold
+vec3 bright(vec3 color) {
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    return color * max(luminance - post.bloom_threshold, 0.0) / max(luminance, 0.0001);
+}
+
+// Tent filter over 3x3 texels of the half sized output
+void main() {
+    vec3 sum = vec3(0.0);
+    float total = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float weight = float((2 - abs(x)) * (2 - abs(y)));
+            vec2 offset = vec2(x, y) * 2.0 * push.texel_size;
+            sum += weight * bright(texture(hdr, pass_uv + offset).rgb * post.exposure);
+            total += weight;
+        }
+    }
+    out_color = vec4(sum / total, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout (location = 0) in vec3 in_pos;
+layout (location = 1) in vec4 in_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_projection;
+} push_constants;
+
+layout (location = 0) out vec4 pass_color;
+
+void main() {
+    gl_Position = push_constants.view_projection * vec4(in_pos, 1.0);
+    pass_color = in_color;
+}
@@ -0,0 +1,66 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec2 pass_uv;
+
+layout(binding = 0) uniform Post {
+    float exposure;
+    float bloom_threshold;
+    float bloom_intensity;
+    uint tonemapping;
+    uint color_grading;
+    uint fxaa;
+} post;
+
+// Tone mapped colors
+layout(binding = 1) uniform sampler2D color;
+
+layout(push_constant, std430) uniform Push {
+    // Size of a texel of color in uv coordinates
+    vec2 texel_size;
+} push;
+
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+layout(location = 0) out vec4 out_color;
+
+// Perceived brightness, the colors are linear
+float luma(vec3 rgb) {
+    return sqrt(dot(rgb, vec3(0.299, 0.587, 0.114)));
+}
+
+vec3 fetch(vec2 offset) {
+    return texture(color, pass_uv + offset * push.texel_size).rgb;
+}
+
+// Blurs along the edge through the fragment, if there is one
+void main() {
+    vec3 middle = fetch(vec2(0.0));
+    if (post.fxaa == 0u) {
+        out_color = vec4(middle, 1.0);
+        return;
+    }
+    float nw = luma(fetch(vec2(-1.0, -1.0)));
+    float ne = luma(fetch(vec2(1.0, -1.0)));
+    float sw = luma(fetch(vec2(-1.0, 1.0)));
+    float se = luma(fetch(vec2(1.0, 1.0)));
+    float m = luma(middle);
+    float luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    float luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2((sw + se) - (nw + ne), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, -SPAN_MAX, SPAN_MAX);
+
+    vec3 a = 0.5 * (fetch(dir * (1.0 / 3.0 - 0.5)) + fetch(dir * (2.0 / 3.0 - 0.5)));
+    vec3 b = a * 0.5 + 0.25 * (fetch(dir * -0.5) + fetch(dir * 0.5));
+    float luma_b = luma(b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        out_color = vec4(a, 1.0);
+    } else {
+        out_color = vec4(b, 1.0);
+    }
+}
@@ -0,0 +1,124 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 pass_normal;
+layout (location = 1) in vec3 pass_fragment_position;
+layout (location = 2) in vec4 pass_tint;
+
+struct Material {
+    vec4 ambient;
+    vec4 diffuse;
+    vec4 specular;
+    float shininess;
+};
+
+// Slot of the material in the registry
+layout(push_constant, std430) uniform Push {
+    layout (offset = 64) uint material_index;
+} push;
+
+// SolidMaterial of every slot
+layout(std430, binding = 4) readonly buffer Materials {
+    Material materials[];
+};
+
+layout(binding = 1) uniform Environment {
+    vec4 view_position;
+    // Color multiplied with the intensity of the ambient light
+    vec4 ambient;
+    uint light_count;
+} environment;
+
+const int MAX_LIGHTS = 16;
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+layout(binding = 5) uniform Light {
+    // w is the range of point and spot lights
+    vec4 position;
+    vec4 direction;
+    // Color multiplied with the intensity, w is 1.0 if the shadow map applies
+    vec4 color;
+    uint kind;
+    float inner_cos;
+    float outer_cos;
+} lights[MAX_LIGHTS];
+
+layout(binding = 2) uniform Shadow {
+    mat4 light_space;
+    float bias;
+    float texel_size;
+    int pcf_radius;
+} shadow;
+
+layout(binding = 3) uniform sampler2D shadow_map;
+
+layout(location = 0) out vec4 out_color;
+
+// Color of light i that arrives at the position, light_dir points towards the light
+vec3 incoming(int i, vec3 position, out vec3 light_dir) {
+    if (lights[i].kind == DIRECTIONAL) {
+        light_dir = -lights[i].direction.xyz;
+        return lights[i].color.rgb;
+    }
+    vec3 to_light = lights[i].position.xyz - position;
+    float dist = length(to_light);
+    light_dir = to_light / dist;
+    // Inverse square falloff, that fades out smoothly at the range
+    float window = clamp(1.0 - pow(dist / lights[i].position.w, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (dist * dist + 1.0);
+    if (lights[i].kind == SPOT) {
+        float cos_angle = dot(-light_dir, lights[i].direction.xyz);
+        attenuation *= smoothstep(lights[i].outer_cos, lights[i].inner_cos, cos_angle);
+    }
+    return lights[i].color.rgb * attenuation;
+}
+
+// 1.0 if the fragment is lit by the sun, averaged over the neighbouring texels (PCF)
+float sunlight(vec3 position) {
+    vec4 light_position = shadow.light_space * vec4(position, 1.0);
+    vec3 projected = light_position.xyz / light_position.w;
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    // Outside of the shadow map everything is lit
+    if (projected.z > 1.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        return 1.0;
+    }
+    float depth = projected.z - shadow.bias;
+    float lit = 0.0;
+    for (int x = -shadow.pcf_radius; x <= shadow.pcf_radius; x++) {
+        for (int y = -shadow.pcf_radius; y <= shadow.pcf_radius; y++) {
+            float closest = texture(shadow_map, uv + vec2(x, y) * shadow.texel_size).r;
+            lit += depth > closest ? 0.0 : 1.0;
+        }
+    }
+    float samples = float((2 * shadow.pcf_radius + 1) * (2 * shadow.pcf_radius + 1));
+    return lit / samples;
+}
+
+void main() {
+    Material material = materials[push.material_index];
+    // ambient
+    vec3 ambient = environment.ambient.rgb * vec3(material.ambient);
+    vec3 normal = normalize(pass_normal);
+    vec3 view_dir = normalize(environment.view_position.xyz - pass_fragment_position);
+    // shadow
+    float sun = sunlight(pass_fragment_position);
+
+    vec3 lit = vec3(0.0);
+    for (int i = 0; i < int(environment.light_count); i++) {
+        vec3 light_dir;
+        vec3 radiance = incoming(i, pass_fragment_position, light_dir);
+        radiance *= mix(1.0, sun, lights[i].color.a);
+        // diffuse
+        float diff = max(dot(light_dir, normal), 0.0);
+        vec3 diffuse = diff * vec3(material.diffuse);
+        // specular
+        vec3 reflect_dir = reflect(-light_dir, normal);
+        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), material.shininess);
+        vec3 specular = spec * vec3(material.specular);
+        lit += radiance * (diffuse + specular);
+    }
+
+    out_color = vec4(ambient + lit, material.specular.a) * pass_tint;
+}
@@ -0,0 +1,172 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 pass_normal;
+layout (location = 1) in vec3 pass_fragment_position;
+layout (location = 2) in vec4 pass_tint;
+layout (location = 3) in vec2 pass_uv;
+layout (location = 4) in vec4 pass_tangent;
+
+struct Material {
+    vec4 base_color;
+    vec4 emissive;
+    float metallic;
+    float roughness;
+    float normal_scale;
+    float occlusion_strength;
+};
+
+// Slot of the material in the registry
+layout(push_constant, std430) uniform Push {
+    layout (offset = 64) uint material_index;
+} push;
+
+layout(binding = 1) uniform Environment {
+    vec4 view_position;
+    // Color multiplied with the intensity of the ambient light
+    vec4 ambient;
+    uint light_count;
+} environment;
+
+const int MAX_LIGHTS = 16;
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+layout(binding = 10) uniform Light {
+    // w is the range of point and spot lights
+    vec4 position;
+    vec4 direction;
+    // Color multiplied with the intensity, w is 1.0 if the shadow map applies
+    vec4 color;
+    uint kind;
+    float inner_cos;
+    float outer_cos;
+} lights[MAX_LIGHTS];
+
+layout(binding = 2) uniform Shadow {
+    mat4 light_space;
+    float bias;
+    float texel_size;
+    int pcf_radius;
+} shadow;
+
+layout(binding = 3) uniform sampler2D shadow_map;
+
+layout(binding = 4) uniform sampler2D base_color_map;
+// Roughness in g, metallic in b
+layout(binding = 5) uniform sampler2D metallic_roughness_map;
+layout(binding = 6) uniform sampler2D normal_map;
+layout(binding = 7) uniform sampler2D occlusion_map;
+layout(binding = 8) uniform sampler2D emissive_map;
+
+// PbrFactors of every slot
+layout(std430, binding = 9) readonly buffer Materials {
+    Material materials[];
+};
+
+// 1.0 if the fragment is lit by the sun, averaged over the neighbouring texels (PCF)
+float sunlight(vec3 position) {
+    vec4 light_position = shadow.light_space * vec4(position, 1.0);
+    vec3 projected = light_position.xyz / light_position.w;
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    // Outside of the shadow map everything is lit
+    if (projected.z > 1.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        return 1.0;
+    }
+    float depth = projected.z - shadow.bias;
+    float lit = 0.0;
+    for (int x = -shadow.pcf_radius; x <= shadow.pcf_radius; x++) {
+        for (int y = -shadow.pcf_radius; y <= shadow.pcf_radius; y++) {
+            float closest = texture(shadow_map, uv + vec2(x, y) * shadow.texel_size).r;
+            lit += depth > closest ? 0.0 : 1.0;
+        }
+    }
+    float samples = float((2 * shadow.pcf_radius + 1) * (2 * shadow.pcf_radius + 1));
+    return lit / samples;
+}
+
+layout(location = 0) out vec4 out_color;
+
+// Color of light i that arrives at the position, light_dir points towards the light
+vec3 incoming(int i, vec3 position, out vec3 light_dir) {
+    if (lights[i].kind == DIRECTIONAL) {
+        light_dir = -lights[i].direction.xyz;
+        return lights[i].color.rgb;
+    }
+    vec3 to_light = lights[i].position.xyz - position;
+    float dist = length(to_light);
+    light_dir = to_light / dist;
+    // Inverse square falloff, that fades out smoothly at the range
+    float window = clamp(1.0 - pow(dist / lights[i].position.w, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (dist * dist + 1.0);
+    if (lights[i].kind == SPOT) {
+        float cos_angle = dot(-light_dir, lights[i].direction.xyz);
+        attenuation *= smoothstep(lights[i].outer_cos, lights[i].inner_cos, cos_angle);
+    }
+    return lights[i].color.rgb * attenuation;
+}
+
+const float PI = 3.14159265359;
+
+// Trowbridge-Reitz normal distribution
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+void main() {
+    Material material = materials[push.material_index];
+    // material
+    vec4 base_color = material.base_color * texture(base_color_map, pass_uv) * pass_tint;
+    vec4 metallic_roughness = texture(metallic_roughness_map, pass_uv);
+    float metallic = material.metallic * metallic_roughness.b;
+    float roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+    float occlusion = 1.0 + material.occlusion_strength * (texture(occlusion_map, pass_uv).r - 1.0);
+    vec3 emissive = material.emissive.rgb * texture(emissive_map, pass_uv).rgb;
+    // normal mapping
+    vec3 normal = normalize(pass_normal);
+    vec3 tangent = normalize(pass_tangent.xyz - normal * dot(normal, pass_tangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * pass_tangent.w;
+    vec3 mapped = texture(normal_map, pass_uv).xyz * 2.0 - 1.0;
+    mapped.xy *= material.normal_scale;
+    normal = normalize(mat3(tangent, bitangent, normal) * mapped);
+    // cook-torrance
+    vec3 view_dir = normalize(environment.view_position.xyz - pass_fragment_position);
+    float n_dot_v = max(dot(normal, view_dir), 0.0001);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    // shadow
+    float sun = sunlight(pass_fragment_position);
+
+    vec3 direct = vec3(0.0);
+    for (int i = 0; i < int(environment.light_count); i++) {
+        vec3 light_dir;
+        // A white light of intensity 1 lights a white surface like in solid.frag
+        vec3 radiance = PI * incoming(i, pass_fragment_position, light_dir);
+        radiance *= mix(1.0, sun, lights[i].color.a);
+        vec3 halfway_dir = normalize(view_dir + light_dir);
+        float n_dot_l = max(dot(normal, light_dir), 0.0);
+        vec3 f = fresnel_schlick(max(dot(halfway_dir, view_dir), 0.0), f0);
+        float d = distribution_ggx(max(dot(normal, halfway_dir), 0.0), roughness);
+        float g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+        vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+        vec3 diffuse = (vec3(1.0) - f) * (1.0 - metallic) * base_color.rgb / PI;
+        direct += (diffuse + specular) * radiance * n_dot_l;
+    }
+    // ambient
+    vec3 ambient = environment.ambient.rgb * base_color.rgb * occlusion;
+
+    out_color = vec4(ambient + direct + emissive, base_color.a);
+}
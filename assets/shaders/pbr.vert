@@ -0,0 +1,37 @@
+#version 450
+
+layout (location = 0) in vec3 in_pos;
+layout (location = 1) in vec3 in_normal;
+// Per instance
+layout (location = 2) in mat4 instance_model;
+layout (location = 6) in vec4 instance_tint;
+// Surface
+layout (location = 7) in vec2 in_uv;
+layout (location = 8) in vec4 in_tangent;
+
+layout (binding = 0) uniform CameraBuffer {
+       mat4 view_projection;
+} camera;
+
+layout(push_constant) uniform PushConstants {
+    // Transform of the mesh inside of its model
+    mat4 local;
+} push_constants;
+
+layout (location = 0) out vec3 pass_normal;
+layout (location = 1) out vec3 pass_fragment_position;
+layout (location = 2) out vec4 pass_tint;
+layout (location = 3) out vec2 pass_uv;
+layout (location = 4) out vec4 pass_tangent;
+
+void main() {
+    mat4 model = instance_model * push_constants.local;
+    vec4 world_position = model * vec4(in_pos, 1.0);
+    // Pass to fragment shader
+    pass_fragment_position = world_position.xyz;
+    pass_normal = mat3(transpose(inverse(model))) * in_normal;
+    pass_tint = instance_tint;
+    pass_uv = in_uv;
+    pass_tangent = vec4(mat3(model) * in_tangent.xyz, in_tangent.w);
+    gl_Position = camera.view_projection * world_position;
+}
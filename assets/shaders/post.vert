@@ -0,0 +1,11 @@
+#version 450
+
+// A triangle that covers the screen
+layout (location = 0) in vec2 in_pos;
+
+layout (location = 0) out vec2 pass_uv;
+
+void main() {
+    pass_uv = in_pos * 0.5 + 0.5;
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+}
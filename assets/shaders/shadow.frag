@@ -0,0 +1,5 @@
+#version 450
+
+// Depth only, the shadow pass has no color attachment
+void main() {
+}
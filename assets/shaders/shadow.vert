@@ -0,0 +1,21 @@
+#version 450
+
+layout (location = 0) in vec3 in_pos;
+// Per instance
+layout (location = 2) in mat4 instance_model;
+
+layout (binding = 0) uniform Shadow {
+    mat4 light_space;
+    float bias;
+    float texel_size;
+    int pcf_radius;
+} shadow;
+
+layout(push_constant) uniform PushConstants {
+    // Transform of the mesh inside of its model
+    mat4 local;
+} push_constants;
+
+void main() {
+    gl_Position = shadow.light_space * instance_model * push_constants.local * vec4(in_pos, 1.0);
+}
@@ -16,9 +16,10 @@ layout (location = 0) out vec3 pass_normal;
 layout (location = 1) out vec3 pass_fragment_position;
 
 void main() {
+    vec4 world_position = push_constants.model * vec4(in_pos, 1.0);
     // Pass to fragment shader
-    pass_fragment_position = in_pos;
+    pass_fragment_position = world_position.xyz;
     pass_normal = mat3(transpose(inverse(push_constants.model))) * in_normal;
     // Calculate output position
-    gl_Position = camera.view_projection * push_constants.model * vec4(in_pos, 1.0);
+    gl_Position = camera.view_projection * world_position;
 }
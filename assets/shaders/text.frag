@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec2 pass_uv;
+layout (location = 1) in vec4 pass_color;
+
+layout(binding = 0) uniform sampler2D atlas;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    float coverage = texture(atlas, pass_uv).r;
+    out_color = vec4(pass_color.rgb, pass_color.a * coverage);
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout (location = 0) in vec4 in_pos;
+layout (location = 1) in vec2 in_uv;
+layout (location = 2) in vec4 in_color;
+
+layout (location = 0) out vec2 pass_uv;
+layout (location = 1) out vec4 pass_color;
+
+void main() {
+    gl_Position = in_pos;
+    pass_uv = in_uv;
+    pass_color = in_color;
+}
@@ -0,0 +1,60 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec2 pass_uv;
+
+layout(binding = 0) uniform Post {
+    float exposure;
+    float bloom_threshold;
+    // 0.0 if bloom is disabled
+    float bloom_intensity;
+    uint tonemapping;
+    uint color_grading;
+    uint fxaa;
+} post;
+
+// Input attachment, only the texel of the fragment is read
+layout(binding = 1) uniform sampler2D hdr;
+layout(binding = 2) uniform sampler2D bloom;
+// LUT_SIZE slices next to each other, one per blue value
+layout(binding = 3) uniform sampler2D lut;
+
+const float LUT_SIZE = 16.0;
+
+layout(location = 0) out vec4 out_color;
+
+// Fitted aces filmic curve (Krzysztof Narkowicz)
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 to_srgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(low, high, step(vec3(0.0031308), linear));
+}
+
+// The lut is indexed by srgb colors, sampling it decodes them again
+vec3 grade(vec3 color) {
+    vec3 coord = to_srgb(color) * (LUT_SIZE - 1.0);
+    float slice = floor(coord.b);
+    float next = min(slice + 1.0, LUT_SIZE - 1.0);
+    vec2 uv = (coord.rg + 0.5) / vec2(LUT_SIZE * LUT_SIZE, LUT_SIZE);
+    vec3 a = textureLod(lut, uv + vec2(slice / LUT_SIZE, 0.0), 0.0).rgb;
+    vec3 b = textureLod(lut, uv + vec2(next / LUT_SIZE, 0.0), 0.0).rgb;
+    return mix(a, b, coord.b - slice);
+}
+
+void main() {
+    vec3 color = texelFetch(hdr, ivec2(gl_FragCoord.xy), 0).rgb * post.exposure;
+    color += texture(bloom, pass_uv).rgb * post.bloom_intensity;
+    if (post.tonemapping != 0u) {
+        color = aces(color);
+    } else {
+        color = clamp(color, 0.0, 1.0);
+    }
+    if (post.color_grading != 0u) {
+        color = grade(color);
+    }
+    out_color = vec4(color, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 pass_uv;
+layout (location = 1) in vec4 pass_color;
+
+layout(binding = 0) uniform sampler2D image;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    vec4 texel = texture(image, pass_uv.xy);
+    // z is 0 for solid quads, 1 for glyphs (coverage in red) and 2 for icons
+    if (pass_uv.z < 0.5) {
+        out_color = pass_color;
+    } else if (pass_uv.z < 1.5) {
+        out_color = vec4(pass_color.rgb, pass_color.a * texel.r);
+    } else {
+        out_color = pass_color * texel;
+    }
+}
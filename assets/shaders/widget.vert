@@ -0,0 +1,19 @@
+#version 450
+
+layout (location = 0) in vec2 in_pos;
+layout (location = 1) in vec3 in_uv;
+layout (location = 2) in vec4 in_color;
+
+layout(push_constant) uniform PushConstants {
+    vec2 extent;
+} push_constants;
+
+layout (location = 0) out vec3 pass_uv;
+layout (location = 1) out vec4 pass_color;
+
+void main() {
+    // Pixels from the top left of the screen
+    gl_Position = vec4(in_pos / push_constants.extent * 2.0 - 1.0, 0.0, 1.0);
+    pass_uv = in_uv;
+    pass_color = in_color;
+}
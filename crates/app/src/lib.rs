@@ -40,6 +40,7 @@ fn init_app(app: &mut App) {
         app.insert_resource(pool.clone());
         // Aaaaand then an asset server
         app.insert_resource(AssetServer::new(pool));
+        app.insert_resource(AssetStats::default());
         app.add_system(stages::UPDATE, AssetServer::update_system.into_system());
     }
 }
@@ -128,6 +129,15 @@ impl App {
         Ok(handle)
     }
 
+    pub fn load_folder(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> core::anyhow::Result<FolderHandle> {
+        let server = self.resources.get::<AssetServer>()?;
+        let folder = server.load_folder(path)?;
+        Ok(folder)
+    }
+
     pub fn add_asset_loader(&self, loader: impl AssetLoader + 'static) {
         let server = self
             .resources
@@ -157,6 +167,22 @@ impl App {
         self.add_event::<AssetEvent<A>>();
     }
 
+    /// Sets what happens to assets of type `A` once they are no longer referenced
+    pub fn set_unload_policy<A: Asset>(&mut self, policy: UnloadPolicy) {
+        self.resources
+            .get_mut::<Assets<A>>()
+            .expect("[App] (set_unload_policy) asset type was not registered")
+            .set_unload_policy(policy);
+    }
+
+    /// Sets the function used to estimate the memory of an asset of type `A` (see `AssetStats`)
+    pub fn set_asset_size_bytes<A: Asset>(&mut self, size_bytes: fn(&A) -> usize) {
+        self.resources
+            .get_mut::<Assets<A>>()
+            .expect("[App] (set_asset_size_bytes) asset type was not registered")
+            .set_size_bytes(size_bytes);
+    }
+
     pub fn set_runner<Func>(&mut self, runner: Func)
     where
         Func: 'static + FnOnce(Resources, World, Scheduler),
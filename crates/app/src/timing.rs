@@ -12,6 +12,8 @@ pub struct Timing {
     // NOTE(luca): Will be removed later when we have profiling
     counter: f32,
     frames: u32,
+    /// Frames counted during the last full second
+    fps: u32,
 }
 
 impl Timing {
@@ -22,12 +24,17 @@ impl Timing {
             dt: 0f32,
             counter: 0f32,
             frames: 0,
+            fps: 0,
         }
     }
 
     pub fn total_elapsed(&self) -> f32 {
         self.startup.elapsed().as_secs_f32()
     }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
 }
 
 fn timing_update(mut timing: RefMut<Timing>) {
@@ -41,6 +48,7 @@ fn timing_update(mut timing: RefMut<Timing>) {
     if timing.counter > 1f32 {
         timing.counter -= 1f32;
         log::info!("fps: {}", timing.frames);
+        timing.fps = timing.frames;
         timing.frames = 0;
     }
     timing.frames += 1;
@@ -0,0 +1,190 @@
+//! Batching of entities that share a model into instanced draws
+//!
+//! The grouping is plain CPU work, the renderer uploads the instances of every frame into an
+//! `InstanceBuffers` and issues one instanced draw per mesh part and batch
+
+use std::{collections::HashMap, hash::Hash, ops::Range, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec4};
+use render::{
+    prelude::{BufferUsage, GpuContext, MemoryType},
+    resource::buffer::BufferDescriptor,
+};
+
+use crate::renderer::ActiveContext;
+
+/// Per instance vertex data of `instanced.pipe` (vertex buffer binding 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct InstanceData {
+    pub model: Mat4,
+    pub tint: Vec4,
+}
+
+unsafe impl Zeroable for InstanceData {}
+unsafe impl Pod for InstanceData {}
+
+pub const INSTANCE_SIZE: usize = std::mem::size_of::<InstanceData>();
+
+/// Instances that are drawn together, `instances` indexes into `Batches::instances`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch<K> {
+    pub key: K,
+    pub instances: Range<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Batches<K> {
+    pub batches: Vec<Batch<K>>,
+    pub instances: Vec<InstanceData>,
+}
+
+/// Groups instances by key (eg. the model of an entity)
+#[derive(Debug)]
+pub struct Batcher<K> {
+    index: HashMap<K, usize>,
+    groups: Vec<Vec<InstanceData>>,
+}
+
+impl<K: Hash + Eq> Batcher<K> {
+    pub fn new() -> Self {
+        Self {
+            index: HashMap::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: K, instance: InstanceData) {
+        let groups = &mut self.groups;
+        let index = *self.index.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        self.groups[index].push(instance);
+    }
+
+    /// Batches are in the order their key was first pushed, their instances are contiguous
+    pub fn finish(self) -> Batches<K> {
+        let mut keys: Vec<(usize, K)> = self.index.into_iter().map(|(k, i)| (i, k)).collect();
+        keys.sort_by_key(|(i, _)| *i);
+
+        let mut batches = Vec::with_capacity(keys.len());
+        let mut instances = Vec::with_capacity(self.groups.iter().map(Vec::len).sum());
+        for ((_, key), group) in keys.into_iter().zip(self.groups) {
+            let start = instances.len() as u32;
+            instances.extend(group);
+            batches.push(Batch {
+                key,
+                instances: start..instances.len() as u32,
+            });
+        }
+        Batches { batches, instances }
+    }
+}
+
+impl<K: Hash + Eq> Default for Batcher<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type BufferHandle = <ActiveContext as GpuContext>::BufferHandle;
+
+/// A host visible vertex buffer per frame in flight (eg. of the instances), grown when the data
+/// does not fit
+pub struct InstanceBuffers {
+    ctx: Arc<ActiveContext>,
+    name: &'static str,
+    /// Buffers with their size in bytes
+    frames: Vec<Option<(BufferHandle, usize)>>,
+}
+
+impl InstanceBuffers {
+    pub fn new(ctx: Arc<ActiveContext>, frames_in_flight: usize) -> Self {
+        Self {
+            ctx,
+            name: "instance-buffer",
+            frames: (0..frames_in_flight).map(|_| None).collect(),
+        }
+    }
+
+    /// Buffers are named after the frame, with this prefix
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Writes the elements (eg. `InstanceData`) into the buffer of the frame (which is not in use
+    /// by the gpu anymore)
+    pub fn write<T: Pod>(&mut self, frame_index: usize, elements: &[T]) -> &BufferHandle {
+        let needed = std::mem::size_of_val(elements).max(1);
+        let fits = matches!(&self.frames[frame_index], Some((_, capacity)) if *capacity >= needed);
+        if !fits {
+            if let Some((buffer, _)) = self.frames[frame_index].take() {
+                self.ctx.drop_buffer(buffer);
+            }
+            let capacity = needed.next_power_of_two();
+            let buffer = self.ctx.create_buffer(&BufferDescriptor {
+                name: format!("{}-{}", self.name, frame_index).into(),
+                size: capacity as u64,
+                memory_type: MemoryType::HostVisible,
+                usage: BufferUsage::Vertex,
+            });
+            self.frames[frame_index] = Some((buffer, capacity));
+        }
+
+        let (buffer, _) = self.frames[frame_index].as_ref().unwrap();
+        unsafe {
+            self.ctx
+                .write_to_buffer_raw(buffer, bytemuck::cast_slice(elements));
+        }
+        buffer
+    }
+}
+
+impl Drop for InstanceBuffers {
+    fn drop(&mut self) {
+        for (buffer, _) in self.frames.drain(..).flatten() {
+            self.ctx.drop_buffer(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(x: f32) -> InstanceData {
+        InstanceData {
+            model: Mat4::from_translation(glam::vec3(x, 0.0, 0.0)),
+            tint: Vec4::ONE,
+        }
+    }
+
+    #[test]
+    fn groups_instances_by_key() {
+        let mut batcher = Batcher::new();
+        batcher.push("house", instance(0.0));
+        batcher.push("tree", instance(1.0));
+        batcher.push("house", instance(2.0));
+        batcher.push("house", instance(3.0));
+
+        let Batches { batches, instances } = batcher.finish();
+        assert_eq!(
+            batches,
+            vec![
+                Batch {
+                    key: "house",
+                    instances: 0..3
+                },
+                Batch {
+                    key: "tree",
+                    instances: 3..4
+                }
+            ]
+        );
+        let xs: Vec<f32> = instances.iter().map(|i| i.model.w_axis.x).collect();
+        assert_eq!(xs, vec![0.0, 2.0, 3.0, 1.0]);
+    }
+}
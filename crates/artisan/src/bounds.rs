@@ -0,0 +1,148 @@
+//! Bounding volumes and view frustum culling
+
+use glam::{Mat4, Vec3, Vec4};
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing, the union with any box is that box
+    pub const EMPTY: Self = Self {
+        min: glam::const_vec3!([f32::INFINITY, f32::INFINITY, f32::INFINITY]),
+        max: glam::const_vec3!([f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY]),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| Self {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The box around the transformed box
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = matrix.transform_point3(self.center());
+        let extents = self.half_extents();
+        // Extents of the transformed box along each axis (Arvo)
+        let half = matrix.x_axis.truncate().abs() * extents.x
+            + matrix.y_axis.truncate().abs() * extents.y
+            + matrix.z_axis.truncate().abs() * extents.z;
+        Self {
+            min: center - half,
+            max: center + half,
+        }
+    }
+}
+
+/// The six planes of a view volume, pointing inwards (`xyz` is the normal, `w` the distance)
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection with depth in 0..1 (Gribb and Hartmann)
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let m = view_projection.transpose();
+        let (x, y, z, w) = (m.x_axis, m.y_axis, m.z_axis, m.w_axis);
+        let planes = [w + x, w - x, w + y, w - y, z, w - z];
+        Self {
+            planes: planes.map(|plane| plane / plane.truncate().length()),
+        }
+    }
+
+    /// Conservative test, boxes close to the corners of the frustum might be visible anyways
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        let center = aabb.center();
+        let extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = normal.abs().dot(extents);
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+/// Objects of the last frame that were drawn or culled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CullingStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_boxes() {
+        let aabb = Aabb::from_points(vec![Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 1.0)]);
+        assert_eq!(
+            aabb,
+            Aabb::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 1.0))
+        );
+
+        let moved = aabb.transform(&Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)));
+        assert_eq!(moved.min, Vec3::new(9.0, 0.0, -1.0));
+        assert_eq!(moved.max, Vec3::new(11.0, 2.0, 1.0));
+
+        // A box rotated by 45 degrees is wider
+        let rotated = aabb.transform(&Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+        let width = 2.0f32.sqrt();
+        assert!((rotated.max.x - width).abs() < 1e-5);
+        assert!((rotated.max.y - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn culls_boxes_outside_the_frustum() {
+        let projection = Mat4::perspective_rh(45f32.to_radians(), 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y);
+        let frustum = Frustum::from_view_projection(&(projection * view));
+
+        let unit = Aabb::new(-Vec3::ONE, Vec3::ONE);
+        let at =
+            |x: f32, y: f32, z: f32| unit.transform(&Mat4::from_translation(Vec3::new(x, y, z)));
+        // In front
+        assert!(frustum.intersects(&at(0.0, 0.0, -10.0)));
+        // Behind, beyond the far plane and far to the side
+        assert!(!frustum.intersects(&at(0.0, 0.0, 10.0)));
+        assert!(!frustum.intersects(&at(0.0, 0.0, -200.0)));
+        assert!(!frustum.intersects(&at(50.0, 0.0, -10.0)));
+        // Partially inside
+        assert!(frustum.intersects(&at(0.0, 0.0, -100.5)));
+        assert!(!frustum.intersects(&Aabb::EMPTY));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::UP;
+use crate::{bounds::Frustum, UP};
 use app::{App, IntoFunctionSystem, Timing};
 use bytemuck::{Pod, Zeroable};
 use glam::{Vec3Swizzles, XY};
@@ -22,6 +22,9 @@ pub struct Camera {
     // Angles in degrees?
     yaw: f32,
     pitch: f32,
+    /// Distances of the clipping planes
+    pub near: f32,
+    pub far: f32,
 }
 
 const SENSITIVITY: f32 = 30.0;
@@ -29,8 +32,14 @@ const MOVEMENT_SENSITIVITY: f32 = 3.0;
 
 impl Camera {
     fn calc(&self, aspect_ratio: f32) -> (glam::Mat4, glam::Mat4) {
+        self.calc_until(aspect_ratio, self.far)
+    }
+
+    /// Like calc, with another far plane
+    fn calc_until(&self, aspect_ratio: f32, far: f32) -> (glam::Mat4, glam::Mat4) {
         let projection = {
-            let initial = glam::Mat4::perspective_rh(45f32.to_radians(), aspect_ratio, 0.1, 100.0);
+            let initial =
+                glam::Mat4::perspective_rh(45f32.to_radians(), aspect_ratio, self.near, far);
             //log::info!("initial: {}", initial);
             let mut array = initial.to_cols_array();
             array[5] *= -1.0;
@@ -43,6 +52,11 @@ impl Camera {
         (projection, view)
     }
 
+    /// Normalized direction the camera looks in
+    pub fn direction(&self) -> glam::Vec3 {
+        self.dir
+    }
+
     // Loosely taken from: https://antongerdelan.net/opengl/raycasting.html
     pub fn mouse_ray(&self, mouse: &glam::Vec2, aspect_ratio: f32) -> glam::Vec3 {
         let (projection, view) = self.calc(aspect_ratio);
@@ -57,10 +71,35 @@ impl Camera {
         (view.inverse() * ray_eye).truncate().normalize()
     }
 
-    pub fn to_buffer(&self, aspect_ratio: f32) -> CameraBuffer {
+    /// The view volume, everything outside of it can be culled
+    pub fn frustum(&self, aspect_ratio: f32) -> Frustum {
+        let (projection, view) = self.calc(aspect_ratio);
+        Frustum::from_view_projection(&(projection * view))
+    }
+
+    /// World space corners of the view volume, that ends at `distance` (or the far plane)
+    pub fn frustum_corners(&self, aspect_ratio: f32, distance: f32) -> [glam::Vec3; 8] {
+        let (projection, view) = self.calc_until(aspect_ratio, distance.min(self.far));
+        let inverse = (projection * view).inverse();
+        let mut corners = [glam::Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            *corner = inverse.project_point3(glam::vec3(x, y, z));
+        }
+        corners
+    }
+
+    /// Transforms world space into clip space
+    pub fn view_projection(&self, aspect_ratio: f32) -> glam::Mat4 {
         let (projection, view) = self.calc(aspect_ratio);
+        projection * view
+    }
+
+    pub fn to_buffer(&self, aspect_ratio: f32) -> CameraBuffer {
         CameraBuffer {
-            view_projection: projection * view,
+            view_projection: self.view_projection(aspect_ratio),
         }
     }
 }
@@ -132,6 +171,8 @@ pub(crate) fn init(app: &mut App) {
         dir: glam::vec3(0.0, 0.0, -1.0),
         yaw: 0.0,
         pitch: 0.0,
+        near: 0.1,
+        far: 1000.0,
     });
     app.add_system(app::stages::UPDATE, camera_system.into_system());
 }
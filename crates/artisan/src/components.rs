@@ -14,6 +14,10 @@ impl Deref for ModelComponent {
     }
 }
 
+/// Color that the shading of an instanced model is multiplied with, white if missing
+#[derive(Debug, Clone, Copy)]
+pub struct Tint(pub glam::Vec4);
+
 #[derive(Debug, Clone, Copy)]
 pub struct Rotation {
     axis: glam::Vec3,
@@ -0,0 +1,507 @@
+//! Immediate mode debug shapes
+//!
+//! Any system can add lines, boxes, spheres, arrows, grids and text to the `DebugDraw` resource.
+//! The shapes of a frame are batched into a single vertex buffer and drawn as lines by the
+//! debug pass, on top of the scene before post processing. Shapes are depth tested against the
+//! scene unless disabled, and stay for a number of frames (one by default).
+
+use std::{f32::consts::TAU, sync::Arc};
+
+use app::Resources;
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use render::{
+    graph::{builder::GraphBuilder, node::Node, nodes::callbacks::FrameData},
+    prelude::*,
+    resource::render_pass::{LoadOp, StoreOp},
+};
+
+use crate::{
+    batch::InstanceBuffers,
+    bounds::Aabb,
+    camera::Camera,
+    pipelines::{self, PipelineInstance},
+    renderer::ActiveContext,
+    UP,
+};
+
+type ActiveGraphBuilder = <ActiveContext as GpuContext>::GraphBuilder;
+type AttachmentIndex = <ActiveGraphBuilder as GraphBuilder>::AttachmentIndex;
+
+/// Line segments per circle of a sphere
+const CIRCLE_SEGMENTS: usize = 24;
+/// Glyphs are 4 units wide and `GLYPH_HEIGHT` units high, lines of text are `LINE_HEIGHT` apart
+const GLYPH_HEIGHT: f32 = 6.0;
+const GLYPH_ADVANCE: f32 = 5.0;
+const LINE_HEIGHT: f32 = 8.0;
+
+/// Vertex of `debug.pipe`, every two vertices are a line
+#[derive(Debug, Clone, Copy, PartialEq, Zeroable, Pod)]
+#[repr(C)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+enum Primitive {
+    Lines(Vec<[Vec3; 2]>),
+    /// Text faces the camera, so its lines are only known when drawing
+    Text {
+        position: Vec3,
+        text: String,
+        size: f32,
+    },
+}
+
+struct Shape {
+    primitive: Primitive,
+    color: Vec4,
+    depth_test: bool,
+    /// Frames left to draw the shape
+    frames: u32,
+}
+
+/// A shape that was just added, to change its options
+pub struct DebugShape<'a>(&'a mut Shape);
+
+impl<'a> DebugShape<'a> {
+    /// Shapes hidden by the scene are not drawn, unless this is disabled (it is enabled by default)
+    pub fn depth_test(self, depth_test: bool) -> Self {
+        self.0.depth_test = depth_test;
+        self
+    }
+
+    /// Draws the shape for this many frames instead of only the next one
+    pub fn frames(self, frames: u32) -> Self {
+        self.0.frames = frames.max(1);
+        self
+    }
+}
+
+/// Shapes that are drawn with the next frames
+#[derive(Default)]
+pub struct DebugDraw {
+    shapes: Vec<Shape>,
+}
+
+/// Vertices of the shapes of a frame, the depth tested lines come first
+#[derive(Debug, Default)]
+pub struct DebugBatch {
+    pub vertices: Vec<DebugVertex>,
+    pub depth_tested: u32,
+}
+
+impl DebugDraw {
+    fn push(&mut self, primitive: Primitive, color: Vec4) -> DebugShape<'_> {
+        self.shapes.push(Shape {
+            primitive,
+            color,
+            depth_test: true,
+            frames: 1,
+        });
+        DebugShape(self.shapes.last_mut().unwrap())
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec4) -> DebugShape<'_> {
+        self.push(Primitive::Lines(vec![[start, end]]), color)
+    }
+
+    /// The edges of the box
+    pub fn aabb(&mut self, aabb: &Aabb, color: Vec4) -> DebugShape<'_> {
+        let corner = |i: usize| {
+            glam::vec3(
+                if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            )
+        };
+        // Corners that differ in a single axis share an edge
+        let mut lines = Vec::with_capacity(12);
+        for i in 0..8 {
+            for axis in [1, 2, 4].iter() {
+                if i & axis == 0 {
+                    lines.push([corner(i), corner(i | axis)]);
+                }
+            }
+        }
+        self.push(Primitive::Lines(lines), color)
+    }
+
+    /// A circle around each axis
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) -> DebugShape<'_> {
+        let mut lines = Vec::with_capacity(3 * CIRCLE_SEGMENTS);
+        let axes = [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)];
+        for (u, v) in axes.iter() {
+            let point = |i: usize| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                center + (*u * angle.cos() + *v * angle.sin()) * radius
+            };
+            lines.extend((0..CIRCLE_SEGMENTS).map(|i| [point(i), point(i + 1)]));
+        }
+        self.push(Primitive::Lines(lines), color)
+    }
+
+    /// A line with a head at the end, that is a fifth of its length
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, color: Vec4) -> DebugShape<'_> {
+        let mut lines = vec![[start, end]];
+        let direction = end - start;
+        if direction.length_squared() > 0.0 {
+            let back = -direction * 0.2;
+            // Any two directions orthogonal to the arrow
+            let helper = if direction.x.abs() < direction.y.abs() {
+                Vec3::X
+            } else {
+                Vec3::Y
+            };
+            let side = direction.cross(helper).normalize() * back.length() * 0.5;
+            let other = direction.cross(side).normalize() * side.length();
+            for offset in [side, -side, other, -other].iter() {
+                lines.push([end, end + back + *offset]);
+            }
+        }
+        self.push(Primitive::Lines(lines), color)
+    }
+
+    /// A horizontal grid of `cells x cells` squares that are `cell_size` wide
+    pub fn grid(
+        &mut self,
+        center: Vec3,
+        cells: u32,
+        cell_size: f32,
+        color: Vec4,
+    ) -> DebugShape<'_> {
+        let half = cells as f32 * cell_size * 0.5;
+        let mut lines = Vec::with_capacity(2 * cells as usize + 2);
+        for i in 0..=cells {
+            let offset = i as f32 * cell_size - half;
+            lines.push([
+                center + glam::vec3(offset, 0.0, -half),
+                center + glam::vec3(offset, 0.0, half),
+            ]);
+            lines.push([
+                center + glam::vec3(-half, 0.0, offset),
+                center + glam::vec3(half, 0.0, offset),
+            ]);
+        }
+        self.push(Primitive::Lines(lines), color)
+    }
+
+    /// Text that faces the camera, starting with the bottom left corner of the first line at
+    /// position. Size is the height of a capital letter, characters without a glyph are drawn as
+    /// boxes
+    pub fn text_3d(
+        &mut self,
+        position: Vec3,
+        text: &str,
+        size: f32,
+        color: Vec4,
+    ) -> DebugShape<'_> {
+        let text = Primitive::Text {
+            position,
+            text: text.to_owned(),
+            size,
+        };
+        self.push(text, color)
+    }
+
+    /// The lines of every shape, text is laid out along the right and up direction of the camera
+    pub fn batch(&self, right: Vec3, up: Vec3) -> DebugBatch {
+        let mut vertices = Vec::new();
+        let mut depth_tested = 0;
+        for depth_test in [true, false].iter() {
+            for shape in self.shapes.iter().filter(|s| s.depth_test == *depth_test) {
+                let color = shape.color.into();
+                let mut line = |[start, end]: [Vec3; 2]| {
+                    vertices.push(DebugVertex {
+                        position: start.into(),
+                        color,
+                    });
+                    vertices.push(DebugVertex {
+                        position: end.into(),
+                        color,
+                    });
+                };
+                match &shape.primitive {
+                    Primitive::Lines(lines) => lines.iter().copied().for_each(&mut line),
+                    Primitive::Text {
+                        position,
+                        text,
+                        size,
+                    } => text_lines(text, *position, right, up, *size, &mut line),
+                }
+            }
+            if *depth_test {
+                depth_tested = vertices.len() as u32;
+            }
+        }
+        DebugBatch {
+            vertices,
+            depth_tested,
+        }
+    }
+
+    /// Removes the shapes that were drawn for all of their frames
+    pub fn advance(&mut self) {
+        self.shapes.retain(|shape| shape.frames > 1);
+        self.shapes.iter_mut().for_each(|shape| shape.frames -= 1);
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+}
+
+/// Strokes of a glyph, as polylines in a 4 by `GLYPH_HEIGHT` cell with y pointing up
+fn glyph(c: char) -> Option<&'static [&'static [(i8, i8)]]> {
+    let strokes: &'static [&'static [(i8, i8)]] = match c.to_ascii_uppercase() {
+        ' ' => &[],
+        '0' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0), (4, 6)]],
+        '1' => &[&[(1, 5), (2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        '2' => &[&[(0, 6), (4, 6), (4, 3), (0, 3), (0, 0), (4, 0)]],
+        '3' => &[&[(0, 6), (4, 6), (4, 0), (0, 0)], &[(0, 3), (4, 3)]],
+        '4' => &[&[(0, 6), (0, 3), (4, 3)], &[(4, 6), (4, 0)]],
+        '5' | 'S' => &[&[(4, 6), (0, 6), (0, 3), (4, 3), (4, 0), (0, 0)]],
+        '6' => &[&[(4, 6), (0, 6), (0, 0), (4, 0), (4, 3), (0, 3)]],
+        '7' => &[&[(0, 6), (4, 6), (4, 0)]],
+        '8' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 3), (4, 3)]],
+        '9' => &[&[(4, 3), (0, 3), (0, 6), (4, 6), (4, 0), (0, 0)]],
+        'A' => &[&[(0, 0), (0, 4), (2, 6), (4, 4), (4, 0)], &[(0, 3), (4, 3)]],
+        'B' => &[
+            &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)],
+            &[(3, 3), (4, 2), (4, 1), (3, 0), (0, 0)],
+        ],
+        'C' => &[&[(4, 6), (0, 6), (0, 0), (4, 0)]],
+        'D' => &[&[(0, 0), (0, 6), (2, 6), (4, 4), (4, 2), (2, 0), (0, 0)]],
+        'E' => &[&[(4, 6), (0, 6), (0, 0), (4, 0)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(4, 6), (0, 6), (0, 0)], &[(0, 3), (3, 3)]],
+        'G' => &[&[(4, 6), (0, 6), (0, 0), (4, 0), (4, 3), (2, 3)]],
+        'H' => &[&[(0, 6), (0, 0)], &[(4, 6), (4, 0)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(1, 6), (3, 6)], &[(2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        'J' => &[&[(4, 6), (4, 0), (0, 0), (0, 2)]],
+        'K' => &[&[(0, 6), (0, 0)], &[(4, 6), (0, 3), (4, 0)]],
+        'L' => &[&[(0, 6), (0, 0), (4, 0)]],
+        'M' => &[&[(0, 0), (0, 6), (2, 3), (4, 6), (4, 0)]],
+        'N' => &[&[(0, 0), (0, 6), (4, 0), (4, 6)]],
+        'O' => &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)]],
+        'P' => &[&[(0, 0), (0, 6), (4, 6), (4, 3), (0, 3)]],
+        'Q' => &[
+            &[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)],
+            &[(2, 2), (4, -1)],
+        ],
+        'R' => &[&[(0, 0), (0, 6), (4, 6), (4, 3), (0, 3), (4, 0)]],
+        'T' => &[&[(0, 6), (4, 6)], &[(2, 6), (2, 0)]],
+        'U' => &[&[(0, 6), (0, 0), (4, 0), (4, 6)]],
+        'V' => &[&[(0, 6), (2, 0), (4, 6)]],
+        'W' => &[&[(0, 6), (1, 0), (2, 3), (3, 0), (4, 6)]],
+        'X' => &[&[(0, 6), (4, 0)], &[(4, 6), (0, 0)]],
+        'Y' => &[&[(0, 6), (2, 3), (4, 6)], &[(2, 3), (2, 0)]],
+        'Z' => &[&[(0, 6), (4, 6), (0, 0), (4, 0)]],
+        '.' => &[&[(2, 0), (2, 1)]],
+        ',' => &[&[(2, 1), (1, -1)]],
+        ':' => &[&[(2, 1), (2, 2)], &[(2, 4), (2, 5)]],
+        '-' => &[&[(0, 3), (4, 3)]],
+        '+' => &[&[(0, 3), (4, 3)], &[(2, 1), (2, 5)]],
+        '=' => &[&[(0, 2), (4, 2)], &[(0, 4), (4, 4)]],
+        '/' => &[&[(0, 0), (4, 6)]],
+        '_' => &[&[(0, 0), (4, 0)]],
+        '(' => &[&[(3, 6), (1, 4), (1, 2), (3, 0)]],
+        ')' => &[&[(1, 6), (3, 4), (3, 2), (1, 0)]],
+        '!' => &[&[(2, 6), (2, 2)], &[(2, 1), (2, 0)]],
+        '?' => &[&[(0, 6), (4, 6), (4, 3), (2, 3), (2, 2)], &[(2, 1), (2, 0)]],
+        _ => return None,
+    };
+    Some(strokes)
+}
+
+/// Drawn for characters without a glyph
+const MISSING_GLYPH: &[&[(i8, i8)]] = &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)]];
+
+fn text_lines(
+    text: &str,
+    position: Vec3,
+    right: Vec3,
+    up: Vec3,
+    size: f32,
+    mut line: impl FnMut([Vec3; 2]),
+) {
+    let scale = size / GLYPH_HEIGHT;
+    let (mut x, mut y) = (0.0, 0.0);
+    for c in text.chars() {
+        if c == '\n' {
+            x = 0.0;
+            y -= LINE_HEIGHT;
+            continue;
+        }
+        let point = |(px, py): (i8, i8)| {
+            position + (right * (x + px as f32) + up * (y + py as f32)) * scale
+        };
+        for stroke in glyph(c).unwrap_or(MISSING_GLYPH).iter() {
+            for segment in stroke.windows(2) {
+                line([point(segment[0]), point(segment[1])]);
+            }
+        }
+        x += GLYPH_ADVANCE;
+    }
+}
+
+/// Pipelines of the debug pass
+struct DebugPass {
+    depth_tested: PipelineInstance,
+    overlay: PipelineInstance,
+}
+
+/// Adds the debug pass, that draws the shapes into the color of the scene. The scene color is
+/// resolved into resolve (if any), since the debug pass is the last one to draw into it
+pub(crate) fn init(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    scene: AttachmentIndex,
+    depth: AttachmentIndex,
+    resolve: Option<AttachmentIndex>,
+    samples: u8,
+) {
+    let depth_tested_pipe = pipelines::load(resources, "assets/shaders/debug.pipe");
+    let overlay_pipe = pipelines::load(resources, "assets/shaders/debug_overlay.pipe");
+    let frames_in_flight = graph_builder.get_swapchain_image_count();
+    let ctx = resources
+        .get::<Arc<ActiveContext>>()
+        .expect("[Artisan] failed to get context")
+        .clone();
+    let mut vertex_buffers =
+        InstanceBuffers::new(ctx, frames_in_flight).with_name("debug-vertex-buffer");
+
+    let mut builder = graph_builder.build_pass_node("debug_pass".into());
+    let store = if resolve.is_some() {
+        StoreOp::DontCare
+    } else {
+        StoreOp::Store
+    };
+    builder.add_output(scene, LoadOp::Load, store);
+    if let Some(resolve) = resolve {
+        builder.add_resolve(scene, resolve);
+    }
+    builder.set_depth(depth, LoadOp::Load, StoreOp::DontCare);
+    builder.init(Box::new(move |render_pass| {
+        let instance = |pipe, render_pass| {
+            PipelineInstance::new(pipe, gpu.clone(), render_pass).with_samples(samples)
+        };
+        Box::new(DebugPass {
+            depth_tested: instance(&depth_tested_pipe, render_pass.clone()),
+            overlay: instance(&overlay_pipe, render_pass),
+        })
+    }));
+    builder.callback(Box::new(move |frame, pass, _world, resources| {
+        let FrameData {
+            cmd,
+            frame_index,
+            viewport,
+            ..
+        } = frame;
+        let aspect_ratio = viewport.rect.width as f32 / viewport.rect.height as f32;
+        let (camera, batch) = {
+            let camera = resources.get::<Camera>()?;
+            let debug = resources.get::<DebugDraw>()?;
+            // Looking straight up or down, text faces along x
+            let right = camera.direction().cross(UP);
+            let right = if right.length_squared() > 0.0 {
+                right.normalize()
+            } else {
+                Vec3::X
+            };
+            let up = right.cross(camera.direction()).normalize();
+            (camera.to_buffer(aspect_ratio), debug.batch(right, up))
+        };
+        if batch.vertices.is_empty() {
+            return Ok(None);
+        }
+
+        let vertex_buffer = vertex_buffers.write(frame_index as usize, &batch.vertices);
+        let count = batch.vertices.len() as u32;
+        let draws = [
+            (pass.depth_tested.get(resources), 0..batch.depth_tested),
+            (pass.overlay.get(resources), batch.depth_tested..count),
+        ];
+        let mut keep_alive = Vec::new();
+        for (pipeline, vertices) in draws.iter() {
+            let pipeline = match pipeline {
+                Some(pipeline) if !vertices.is_empty() => pipeline,
+                _ => continue,
+            };
+            cmd.bind_graphics_pipeline(pipeline);
+            cmd.set_viewport(0, viewport.clone());
+            cmd.set_scissor(0, viewport.rect.clone());
+            cmd.push_constants(
+                pipeline,
+                ShaderType::Vertex,
+                0,
+                bytemuck::cast_slice(bytemuck::bytes_of(&camera)),
+            );
+            cmd.bind_vertex_buffer(0, vertex_buffer, BufferRange::WHOLE);
+            cmd.draw(vertices.clone(), 0..1);
+            keep_alive.push(pipeline.clone());
+        }
+        // Keep the pipelines alive, until the frame is finished
+        Ok(Some(Box::new(keep_alive)))
+    }));
+    graph_builder.add_node(Node::PassNode(builder.build()));
+}
+
+/// Removes the shapes of the frame, after it was rendered
+pub(crate) fn finish_frame(resources: &Resources) {
+    if let Ok(mut debug) = resources.get_mut::<DebugDraw>() {
+        debug.advance();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipelines::tests::validated_pipe;
+
+    #[test]
+    fn depth_tested_shapes_come_first_and_expire() {
+        let mut debug = DebugDraw::default();
+        debug
+            .line(Vec3::ZERO, Vec3::X, Vec4::ONE)
+            .depth_test(false)
+            .frames(2);
+        debug.aabb(&Aabb::new(Vec3::ZERO, Vec3::ONE), Vec4::ONE);
+
+        let batch = debug.batch(Vec3::X, Vec3::Y);
+        assert_eq!((batch.depth_tested, batch.vertices.len()), (24, 26));
+        assert_eq!(batch.vertices[25].position, [1.0, 0.0, 0.0]);
+
+        debug.advance();
+        let batch = debug.batch(Vec3::X, Vec3::Y);
+        assert_eq!((batch.depth_tested, batch.vertices.len()), (0, 2));
+        debug.advance();
+        assert!(debug.batch(Vec3::X, Vec3::Y).vertices.is_empty());
+    }
+
+    #[test]
+    fn text_is_laid_out_along_the_camera() {
+        let mut debug = DebugDraw::default();
+        debug.text_3d(Vec3::ZERO, "1\n-", GLYPH_HEIGHT, Vec4::ONE);
+
+        // Three segments of the 1, and the dash on the next line
+        let batch = debug.batch(Vec3::Z, Vec3::Y);
+        assert_eq!(batch.vertices.len(), 8);
+        assert!(batch.vertices.iter().all(|v| v.position[0] == 0.0));
+        assert_eq!(batch.vertices[6].position, [0.0, -5.0, 0.0]);
+        assert_eq!(batch.vertices[7].position, [0.0, -5.0, 4.0]);
+    }
+
+    #[test]
+    fn debug_pipes_match_vertex() {
+        for name in ["debug", "debug_overlay"].iter() {
+            let (pipe, parts) = validated_pipe(name);
+            assert_eq!(
+                pipe.vertex_buffers()[0].stride as usize,
+                std::mem::size_of::<DebugVertex>()
+            );
+            assert_eq!(pipe.push_constants()[0].1, 0..64);
+            assert!(parts.is_empty());
+        }
+    }
+}
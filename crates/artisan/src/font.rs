@@ -0,0 +1,226 @@
+//! Font assets and the atlas their glyphs are rasterized into
+
+use std::{any::TypeId, collections::HashMap, hash::Hash};
+
+use app::core::anyhow::{anyhow, Context, Result};
+use app::{App, AssetLoader};
+use rusttype::{GlyphId, Scale};
+
+/// Initial width and height of a `GlyphAtlas`
+pub const ATLAS_SIZE: u32 = 256;
+/// The atlas does not grow beyond this
+pub const MAX_ATLAS_SIZE: u32 = 4096;
+/// Empty texels around every glyph, so that linear sampling does not bleed into its neighbours
+const PADDING: u32 = 1;
+
+/// A truetype or opentype font
+pub struct Font(pub rusttype::Font<'static>);
+
+pub struct FontLoader;
+
+impl AssetLoader for FontLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        ctx: app::LoadContext<'a>,
+    ) -> app::BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let font = rusttype::Font::try_from_vec(bytes.to_vec())
+                .ok_or_else(|| anyhow!("invalid font data"))
+                .context(format!("[FontLoader] failed to load {:?}", ctx.path))?;
+            ctx.send_asset(Font(font)).await;
+            Ok(())
+        })
+    }
+
+    fn ext(&self) -> &[&str] {
+        &["ttf", "otf"]
+    }
+
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<Font>()
+    }
+}
+
+/// A glyph in the atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasGlyph {
+    /// Top left texel of the glyph
+    pub min: [u32; 2],
+    pub size: [u32; 2],
+    /// Of the top left corner, relative to the pen position on the baseline (in pixels)
+    pub offset: [i32; 2],
+}
+
+/// The atlas has no space left for a glyph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasFull;
+
+/// Glyphs rasterized on demand into a single channel texture, packed into rows (shelves)
+///
+/// Glyphs are identified by a key of their font (eg. its handle), their id and their size. Once
+/// the atlas is full, it can grow, which drops every glyph so that they are rasterized again
+pub struct GlyphAtlas<K> {
+    size: u32,
+    /// Coverage of every texel
+    texels: Vec<u8>,
+    glyphs: HashMap<(K, GlyphId, u32), Option<AtlasGlyph>>,
+    /// Top and height of the current (and last) shelf, and the first free texel in it
+    shelf: (u32, u32),
+    cursor: u32,
+    /// Increased whenever the texels change
+    generation: u64,
+}
+
+impl<K: Hash + Eq> GlyphAtlas<K> {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            texels: vec![0; (size * size) as usize],
+            glyphs: HashMap::new(),
+            shelf: (0, 0),
+            cursor: 0,
+            generation: 0,
+        }
+    }
+
+    /// Width and height of the texture
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn texels(&self) -> &[u8] {
+        &self.texels
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// A glyph that was already rasterized, None for glyphs without an outline (eg. spaces)
+    pub fn get(&self, key: K, glyph: GlyphId, size: f32) -> Option<AtlasGlyph> {
+        self.glyphs
+            .get(&(key, glyph, size.to_bits()))
+            .copied()
+            .flatten()
+    }
+
+    /// Rasterizes the glyph of the font (at a size in pixels), unless it is in the atlas already
+    pub fn insert(
+        &mut self,
+        key: K,
+        font: &rusttype::Font,
+        glyph: GlyphId,
+        size: f32,
+    ) -> Result<Option<AtlasGlyph>, AtlasFull> {
+        let key = (key, glyph, size.to_bits());
+        if let Some(entry) = self.glyphs.get(&key) {
+            return Ok(*entry);
+        }
+
+        let glyph = font
+            .glyph(glyph)
+            .scaled(Scale::uniform(size))
+            .positioned(rusttype::point(0.0, 0.0));
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => {
+                self.glyphs.insert(key, None);
+                return Ok(None);
+            }
+        };
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        let min = self.allocate(width + 2 * PADDING, height + 2 * PADDING)?;
+        let min = [min[0] + PADDING, min[1] + PADDING];
+        let atlas_size = self.size;
+        let texels = &mut self.texels;
+        glyph.draw(|x, y, coverage| {
+            let index = (min[1] + y) * atlas_size + min[0] + x;
+            texels[index as usize] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+        });
+        self.generation += 1;
+
+        let entry = Some(AtlasGlyph {
+            min,
+            size: [width, height],
+            offset: [bounds.min.x, bounds.min.y],
+        });
+        self.glyphs.insert(key, entry);
+        Ok(entry)
+    }
+
+    /// Top left texel of a free area, on the current shelf or a new one below it
+    fn allocate(&mut self, width: u32, height: u32) -> Result<[u32; 2], AtlasFull> {
+        if self.cursor + width > self.size {
+            self.shelf = (self.shelf.0 + self.shelf.1, 0);
+            self.cursor = 0;
+        }
+        // The current shelf is the last one, so it can grow to fit the glyph
+        let (top, shelf_height) = self.shelf;
+        if width > self.size || top + height > self.size {
+            return Err(AtlasFull);
+        }
+        self.shelf.1 = shelf_height.max(height);
+        let min = [self.cursor, top];
+        self.cursor += width;
+        Ok(min)
+    }
+
+    /// Doubles the width and height and drops every glyph, false once the maximum size is reached
+    pub fn grow(&mut self) -> bool {
+        if self.size >= MAX_ATLAS_SIZE {
+            return false;
+        }
+        *self = Self {
+            generation: self.generation + 1,
+            ..Self::new(self.size * 2)
+        };
+        true
+    }
+}
+
+pub(crate) fn init(app: &mut App) {
+    app.register_asset::<Font>();
+    app.add_asset_loader(FontLoader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> rusttype::Font<'static> {
+        let bytes = include_bytes!("../../../assets/fonts/FiraCode-Regular.ttf");
+        rusttype::Font::try_from_vec(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn glyphs_are_packed_until_the_atlas_grows() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new(32);
+        let a = font.glyph('A').id();
+        let glyph = atlas.insert(0, &font, a, 16.0).unwrap().unwrap();
+        assert_eq!(glyph.min, [PADDING, PADDING]);
+        // Glyphs sit on the baseline
+        assert!(glyph.offset[1] < 0);
+        assert!(atlas.texels().iter().any(|coverage| *coverage > 0));
+
+        // Rasterized once per font and size
+        let generation = atlas.generation();
+        assert_eq!(atlas.insert(0, &font, a, 16.0), Ok(Some(glyph)));
+        assert_eq!(atlas.generation(), generation);
+        assert_eq!(atlas.insert(0, &font, font.glyph(' ').id(), 16.0), Ok(None));
+
+        let b = atlas.insert(1, &font, a, 16.0).unwrap().unwrap();
+        assert_eq!(b.min, [glyph.size[0] + 3 * PADDING, PADDING]);
+
+        // Larger glyphs do not fit
+        let mut full = false;
+        for size in 1..8 {
+            full |= atlas.insert(2, &font, a, 16.0 + size as f32).is_err();
+        }
+        assert!(full);
+        assert!(atlas.grow());
+        assert_eq!(atlas.size(), 64);
+        assert_eq!(atlas.get(0, a, 16.0), None);
+    }
+}
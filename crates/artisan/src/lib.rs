@@ -2,13 +2,28 @@
 mod def;
 pub use def::*;
 
+pub mod batch;
+pub mod bounds;
 pub mod camera;
 pub mod components;
+pub mod debug;
 pub mod factory;
+pub mod font;
+pub mod light;
 pub mod material;
 pub mod mesh;
+mod pipe;
 mod pipelines;
+pub mod post;
+pub mod registry;
 pub mod renderer;
+pub mod screenshot;
+pub mod shadow;
+#[cfg(feature = "soft")]
+mod software;
+pub mod text;
+pub mod texture;
+pub mod ui;
 
 use app::*;
 
@@ -0,0 +1,330 @@
+//! Lights of the scene, gathered from the components of entities every frame
+//!
+//! Point and spot lights shine from the position of the `Transform` of their entity. The sun is
+//! not an entity, it is a directional light that follows the `TimeOfDay` and the only light that
+//! casts shadows.
+
+use std::cell::{Ref, RefMut};
+
+use app::{App, IntoFunctionSystem, Timing, World};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+
+use crate::components::Transform;
+
+/// Lights beyond this are dropped, point and spot lights far away from the camera first
+pub const MAX_LIGHTS: usize = 16;
+
+/// Every light is bound at its own offset, which has to be a multiple of the uniform buffer
+/// offset alignment of the gpu (at most 256 bytes)
+pub(crate) const LIGHT_STRIDE: u64 = 256;
+
+/// Kinds of `LightUniform`
+pub(crate) const DIRECTIONAL: u32 = 0;
+pub(crate) const POINT: u32 = 1;
+pub(crate) const SPOT: u32 = 2;
+
+/// Light that shines in the same direction everywhere, eg. the moon
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// Direction the light travels in
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl DirectionalLight {
+    fn uniform(&self, shadowed: bool) -> LightUniform {
+        LightUniform {
+            position: Vec4::ZERO,
+            direction: self.direction.normalize().extend(0.0),
+            color: (self.color * self.intensity).extend(if shadowed { 1.0 } else { 0.0 }),
+            kind: DIRECTIONAL,
+            inner_cos: 0.0,
+            outer_cos: 0.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Light that shines in all directions, its intensity falls off with the square of the distance
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely
+    pub range: f32,
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            color: Vec3::ONE,
+            intensity: 10.0,
+            range: 10.0,
+        }
+    }
+}
+
+impl PointLight {
+    fn uniform(&self, position: Vec3) -> LightUniform {
+        LightUniform {
+            position: position.extend(self.range),
+            direction: Vec4::ZERO,
+            color: (self.color * self.intensity).extend(0.0),
+            kind: POINT,
+            inner_cos: 0.0,
+            outer_cos: 0.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// A point light that only shines into a cone around its direction
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    /// Direction the cone points in
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    /// Angle between the direction and the edge of the fully lit cone, in radians
+    pub inner_angle: f32,
+    /// Angle between the direction and the edge of the cone, where the light has faded out
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 10.0,
+            range: 10.0,
+            inner_angle: 20f32.to_radians(),
+            outer_angle: 30f32.to_radians(),
+        }
+    }
+}
+
+impl SpotLight {
+    fn uniform(&self, position: Vec3) -> LightUniform {
+        LightUniform {
+            position: position.extend(self.range),
+            direction: self.direction.normalize().extend(0.0),
+            color: (self.color * self.intensity).extend(0.0),
+            kind: SPOT,
+            inner_cos: self.inner_angle.cos(),
+            outer_cos: self.outer_angle.cos(),
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Light that reaches every surface, multiplied with the ambient color of materials
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientLight {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self {
+            color: Vec3::ONE,
+            intensity: 0.1,
+        }
+    }
+}
+
+/// Time of the day, that moves the sun across the sky
+#[derive(Debug, Clone)]
+pub struct TimeOfDay {
+    /// Hour of the day in `[0, 24)`, the sun rises at 6 and sets at 18
+    pub hour: f32,
+    /// Hours that pass per second, 0 stops the time
+    pub speed: f32,
+    /// Intensity of the sun when it is well above the horizon
+    pub sun_intensity: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            speed: 0.25,
+            sun_intensity: 1.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Direction the sunlight travels in, the sun rises in the east (+x) and passes the south
+    /// (+z) at noon
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour - 6.0) / 12.0 * std::f32::consts::PI;
+        -glam::vec3(angle.cos(), angle.sin(), 0.4).normalize()
+    }
+
+    /// The sun fades out and turns orange close to the horizon, at night it is off
+    pub fn sun(&self) -> DirectionalLight {
+        let direction = self.sun_direction();
+        let elevation = -direction.y;
+        let sunset = glam::vec3(1.0, 0.5, 0.25);
+        DirectionalLight {
+            direction,
+            color: sunset.lerp(Vec3::ONE, (elevation * 3.0).clamp(0.0, 1.0)),
+            intensity: self.sun_intensity * (elevation * 5.0).clamp(0.0, 1.0),
+        }
+    }
+
+    fn update_system(mut time: RefMut<TimeOfDay>, timing: Ref<Timing>) {
+        time.hour = (time.hour + timing.dt * time.speed).rem_euclid(24.0);
+    }
+}
+
+/// A light as seen by the shaders, an element of `lights`
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct LightUniform {
+    /// Position of point and spot lights, w is their range
+    pub position: Vec4,
+    pub direction: Vec4,
+    /// Color multiplied with the intensity, w is 1 if the shadow map applies
+    pub color: Vec4,
+    pub kind: u32,
+    pub inner_cos: f32,
+    pub outer_cos: f32,
+    _padding: f32,
+}
+
+/// A light padded to `LIGHT_STRIDE`
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct LightSlot {
+    light: LightUniform,
+    _padding: [Vec4; 12],
+}
+
+pub(crate) type LightArray = [LightSlot; MAX_LIGHTS];
+
+/// Everything the shaders need besides the lights
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct Environment {
+    pub view_position: Vec4,
+    /// Color multiplied with the intensity of the ambient light
+    pub ambient: Vec4,
+    pub light_count: u32,
+    _padding: [u32; 3],
+}
+
+impl Environment {
+    pub fn new(view_position: Vec3, ambient: &AmbientLight, light_count: usize) -> Self {
+        Self {
+            view_position: view_position.extend(1.0),
+            ambient: (ambient.color * ambient.intensity).extend(1.0),
+            light_count: light_count.min(MAX_LIGHTS) as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// The lights of the frame, the sun and directional lights first, then the point and spot lights
+/// closest to the camera
+pub(crate) fn gather(
+    world: &World,
+    sun: Option<&DirectionalLight>,
+    eye: Vec3,
+) -> Vec<LightUniform> {
+    let mut lights: Vec<LightUniform> = sun.map(|sun| sun.uniform(true)).into_iter().collect();
+    lights.extend(
+        world
+            .query::<&DirectionalLight>()
+            .iter()
+            .map(|(_e, light)| light.uniform(false)),
+    );
+
+    let mut local: Vec<(f32, LightUniform)> =
+        world
+            .query::<(&PointLight, &Transform)>()
+            .iter()
+            .map(|(_e, (light, transform))| (*transform.pos(), light.uniform(*transform.pos())))
+            .chain(world.query::<(&SpotLight, &Transform)>().iter().map(
+                |(_e, (light, transform))| (*transform.pos(), light.uniform(*transform.pos())),
+            ))
+            .map(|(position, light)| (position.distance_squared(eye), light))
+            .collect();
+    local.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    lights.extend(local.into_iter().map(|(_, light)| light));
+
+    lights.truncate(MAX_LIGHTS);
+    lights
+}
+
+/// The lights in the layout of the light buffer, the rest of the slots is zeroed
+pub(crate) fn light_array(lights: &[LightUniform]) -> LightArray {
+    let mut array = [LightSlot::zeroed(); MAX_LIGHTS];
+    for (slot, light) in array.iter_mut().zip(lights) {
+        slot.light = *light;
+    }
+    array
+}
+
+pub(crate) fn init(app: &mut App) {
+    // Resources inserted before the renderer are kept, eg. to start at night
+    if !app.get_resources().contains::<TimeOfDay>() {
+        app.insert_resource(TimeOfDay::default());
+    }
+    if !app.get_resources().contains::<AmbientLight>() {
+        app.insert_resource(AmbientLight::default());
+    }
+    app.add_system(app::stages::UPDATE, TimeOfDay::update_system.into_system());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_follows_time_of_day() {
+        let mut time = TimeOfDay {
+            hour: 12.0,
+            ..Default::default()
+        };
+        let noon = time.sun();
+        assert!(noon.direction.y < -0.9);
+        assert_eq!(noon.intensity, time.sun_intensity);
+
+        time.hour = 0.0;
+        assert!(time.sun_direction().y > 0.0);
+        assert_eq!(time.sun().intensity, 0.0);
+
+        // The sun rises in the east, so its light travels west
+        time.hour = 7.0;
+        assert!(time.sun_direction().x < 0.0);
+    }
+
+    #[test]
+    fn lights_are_padded_to_their_stride() {
+        assert_eq!(std::mem::size_of::<LightUniform>(), 64);
+        assert_eq!(std::mem::size_of::<LightSlot>() as u64, LIGHT_STRIDE);
+
+        let sun = TimeOfDay::default().sun().uniform(true);
+        let lights = vec![sun; MAX_LIGHTS + 4];
+        let array = light_array(&lights);
+        assert_eq!(array[MAX_LIGHTS - 1].light.kind, DIRECTIONAL);
+        let environment = Environment::new(Vec3::ZERO, &AmbientLight::default(), lights.len());
+        assert_eq!(environment.light_count as usize, MAX_LIGHTS);
+    }
+}
@@ -1,24 +1,111 @@
+use app::AssetHandle;
 use bytemuck::{Pod, Zeroable};
 use glam::Vec4;
 
+use crate::texture::TextureAsset;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 #[repr(align(16))]
 pub struct SolidMaterial {
     // Vec4 because of alignment
-    ambient: Vec4,
-    diffuse: Vec4,
-    specular: Vec4,
-    shininess: f32,
+    pub(crate) ambient: Vec4,
+    pub(crate) diffuse: Vec4,
+    pub(crate) specular: Vec4,
+    pub(crate) shininess: f32,
 }
 
 unsafe impl Zeroable for SolidMaterial {}
 unsafe impl Pod for SolidMaterial {}
 
+/// Factors of the glTF metallic-roughness model, they are multiplied with the textures
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+#[repr(align(16))]
+pub struct PbrFactors {
+    pub base_color: Vec4,
+    /// Only rgb is used
+    pub emissive: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Scales x and y of the normal map
+    pub normal_scale: f32,
+    /// 0 ignores the occlusion map
+    pub occlusion_strength: f32,
+}
+
+unsafe impl Zeroable for PbrFactors {}
+unsafe impl Pod for PbrFactors {}
+
+/// Same defaults as glTF
+impl Default for PbrFactors {
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::ONE,
+            emissive: Vec4::ZERO,
+            metallic: 1.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+/// Textures of a pbr material, missing ones are replaced by neutral 1x1 textures
+///
+/// Metallic is read from the blue and roughness from the green channel, occlusion from the red
+/// channel (like glTF). Textures need the texture coordinates and tangents of a `SurfaceVertex`.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub struct PbrTextures {
+    pub base_color: Option<AssetHandle<TextureAsset>>,
+    pub metallic_roughness: Option<AssetHandle<TextureAsset>>,
+    pub normal: Option<AssetHandle<TextureAsset>>,
+    pub occlusion: Option<AssetHandle<TextureAsset>>,
+    pub emissive: Option<AssetHandle<TextureAsset>>,
+}
+
+impl PbrTextures {
+    /// In the order of the bindings of `pbr.pipe`
+    pub fn iter(&self) -> impl Iterator<Item = Option<&AssetHandle<TextureAsset>>> {
+        vec![
+            self.base_color.as_ref(),
+            self.metallic_roughness.as_ref(),
+            self.normal.as_ref(),
+            self.occlusion.as_ref(),
+            self.emissive.as_ref(),
+        ]
+        .into_iter()
+    }
+
+    /// Whether any of the textures is the given one
+    pub fn contains(&self, texture: &AssetHandle<TextureAsset>) -> bool {
+        self.iter().any(|t| t == Some(texture))
+    }
+
+    pub fn as_weak(&self) -> Self {
+        let weak =
+            |texture: &Option<AssetHandle<TextureAsset>>| texture.as_ref().map(|t| t.as_weak());
+        Self {
+            base_color: weak(&self.base_color),
+            metallic_roughness: weak(&self.metallic_roughness),
+            normal: weak(&self.normal),
+            occlusion: weak(&self.occlusion),
+            emissive: weak(&self.emissive),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PbrMaterial {
+    pub factors: PbrFactors,
+    pub textures: PbrTextures,
+}
+
 #[derive(Debug)]
 pub enum Material {
     Solid(SolidMaterial),
-    // Textured(TextureMaterial),
+    /// Drawn with `pbr.pipe`
+    Pbr(PbrMaterial),
 }
 
 impl Material {
@@ -45,6 +132,10 @@ impl Material {
         })
     }
 
+    pub fn pbr(factors: PbrFactors, textures: PbrTextures) -> Self {
+        Self::Pbr(PbrMaterial { factors, textures })
+    }
+
     pub fn from_color(color: Color) -> Self {
         color.into()
     }
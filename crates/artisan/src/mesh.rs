@@ -10,9 +10,10 @@ use render::{
         },
     },
 };
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range};
 
 use crate::{
+    bounds::Aabb,
     material::{Material, SolidMaterial},
     renderer::ActiveContext,
 };
@@ -26,6 +27,79 @@ pub struct Vertex {
 
 pub const VERTEX_SIZE: usize = std::mem::size_of::<Vertex>();
 
+/// Second vertex stream of parts with pbr materials (binding 2 of `pbr.pipe`)
+#[derive(Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+#[repr(C)]
+pub struct SurfaceVertex {
+    pub uv: [f32; 2],
+    /// xyz is the tangent, w the sign of the bitangent (like glTF)
+    pub tangent: [f32; 4],
+}
+
+impl SurfaceVertex {
+    /// Tangents along the texture coordinates of the triangles, for meshes without tangents
+    ///
+    /// Vertices without a usable tangent (eg. all texture coordinates are the same) get one
+    /// that is orthogonal to their normal.
+    pub fn generate(vertices: &[Vertex], uvs: &[[f32; 2]], indices: Option<&Indices>) -> Vec<Self> {
+        let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+        let mut bitangents = vec![glam::Vec3::ZERO; vertices.len()];
+        let triangles: Vec<usize> = match indices {
+            Some(indices) => indices.to_vec(),
+            None => (0..vertices.len()).collect(),
+        };
+        for triangle in triangles.chunks_exact(3) {
+            let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+            let uv = |i: usize| glam::Vec2::from(uvs[i]);
+            let (e1, e2) = (
+                vertices[b].pos - vertices[a].pos,
+                vertices[c].pos - vertices[a].pos,
+            );
+            let (d1, d2) = (uv(b) - uv(a), uv(c) - uv(a));
+            let r = d1.x * d2.y - d2.x * d1.y;
+            if r.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (e1 * d2.y - e2 * d1.y) / r;
+            let bitangent = (e2 * d1.x - e1 * d2.x) / r;
+            for &i in triangle {
+                tangents[i] += tangent;
+                bitangents[i] += bitangent;
+            }
+        }
+
+        vertices
+            .iter()
+            .zip(uvs)
+            .enumerate()
+            .map(|(i, (vertex, uv))| {
+                let normal = vertex.normal.normalize();
+                // Gram-Schmidt
+                let tangent = tangents[i] - normal * normal.dot(tangents[i]);
+                let (tangent, sign) = if tangent.length_squared() > f32::EPSILON {
+                    let sign = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+                    (tangent.normalize(), sign)
+                } else {
+                    let axis = if normal.x.abs() < 0.9 {
+                        glam::Vec3::X
+                    } else {
+                        glam::Vec3::Y
+                    };
+                    (normal.cross(axis).normalize(), 1.0)
+                };
+                Self {
+                    uv: *uv,
+                    tangent: [tangent.x, tangent.y, tangent.z, sign],
+                }
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Indices {
     U16(Vec<u16>),
@@ -39,6 +113,13 @@ impl Indices {
             Indices::U32(slice) => slice.len(),
         }
     }
+
+    pub fn to_vec(&self) -> Vec<usize> {
+        match self {
+            Indices::U16(slice) => slice.iter().map(|i| *i as usize).collect(),
+            Indices::U32(slice) => slice.iter().map(|i| *i as usize).collect(),
+        }
+    }
 }
 
 impl From<&Indices> for IndexType {
@@ -51,7 +132,8 @@ impl From<&Indices> for IndexType {
 }
 
 impl Vertex {
-    pub(crate) fn get_layout() -> (VertexBufferDescriptor, Vec<AttributeDescriptor>) {
+    /// Vertex buffer and attributes of a pipeline that draws meshes (see `solid.pipe`)
+    pub fn get_layout() -> (VertexBufferDescriptor, Vec<AttributeDescriptor>) {
         let size = std::mem::size_of::<Self>();
         let buffer_descriptor = VertexBufferDescriptor::new(0, size as _, VertexInputRate::Vertex);
         let attributes = vec![
@@ -71,116 +153,170 @@ impl Vertex {
 pub struct MeshPart {
     pub(crate) vertex_buffer: <ActiveContext as GpuContext>::BufferHandle,
     pub(crate) index_buffer: Option<(<ActiveContext as GpuContext>::BufferHandle, IndexType)>,
+    /// `SurfaceVertex` stream, only read by pbr materials
+    pub(crate) surface_buffer: <ActiveContext as GpuContext>::BufferHandle,
     pub(crate) draw_count: u32,
-    pub(crate) material: Material,
+    /// Editing the material asset (even from solid to pbr) does not require a new part
+    pub(crate) material: AssetHandle<Material>,
+    /// Bounds of the vertices in model space
+    pub bounds: Aabb,
 }
 
 impl MeshPart {
     pub fn new(
         vertex_buffer: <ActiveContext as GpuContext>::BufferHandle,
         index_buffer: Option<(<ActiveContext as GpuContext>::BufferHandle, IndexType)>,
+        surface_buffer: <ActiveContext as GpuContext>::BufferHandle,
         draw_count: u32,
-        material: Material,
+        material: AssetHandle<Material>,
+        bounds: Aabb,
     ) -> Self {
         Self {
             vertex_buffer,
             index_buffer,
+            surface_buffer,
             draw_count,
             material,
+            bounds,
         }
     }
 
+    /// The texture coordinates are zero, in case the part is drawn with a pbr material
     pub fn from_data(
         name: impl AsRef<str>,
         vertices: &[Vertex],
-        material: Material,
+        material: AssetHandle<Material>,
         ctx: &ActiveContext,
     ) -> Self {
-        // BIG TODO: Abstract that away (see story CPU <-> GPU Dataflow)
-        let vertex_buffer = ctx.create_buffer(&BufferDescriptor {
-            // TODO: Naming
-            name: format!("{}-vertex-buffer", name.as_ref()).into(),
-            size: (VERTEX_SIZE * vertices.len()) as u64,
-            // NOTE: should be upgraded to device local memory (but i dont give a s*** right now)
-            memory_type: MemoryType::HostVisible,
-            usage: BufferUsage::Vertex,
-        });
-        // Upload vertex data
-        unsafe {
-            let vertex_data: &[u8] = bytemuck::cast_slice(vertices);
-            ctx.write_to_buffer_raw(&vertex_buffer, vertex_data);
-        }
-
-        let draw_count = vertices.len();
-
-        Self {
-            vertex_buffer,
-            index_buffer: None,
-            draw_count: draw_count as _,
-            material,
-        }
+        Self::build(name.as_ref(), vertices, None, None, material, ctx)
     }
 
+    /// The texture coordinates are zero, in case the part is drawn with a pbr material
     pub fn from_data_with_indices(
         name: &str,
         vertices: &[Vertex],
         indices: &Indices,
-        material: Material,
+        material: AssetHandle<Material>,
         ctx: &ActiveContext,
     ) -> Self {
-        // BIG TODO: Abstract that away (see story CPU <-> GPU Dataflow)
-        let vertex_buffer = ctx.create_buffer(&BufferDescriptor {
-            // TODO: Naming
-            name: format!("{}-vertex-buffer", name).into(),
-            size: (VERTEX_SIZE * vertices.len()) as u64,
-            // NOTE: should be upgraded to device local memory (but i dont give a s*** right now)
-            memory_type: MemoryType::HostVisible,
-            usage: BufferUsage::Vertex,
-        });
-        // Upload vertex data
-        unsafe {
-            let vertex_data: &[u8] = bytemuck::cast_slice(vertices);
-            ctx.write_to_buffer_raw(&vertex_buffer, vertex_data);
-        }
+        Self::build(name, vertices, Some(indices), None, material, ctx)
+    }
 
-        let index_type: IndexType = indices.into();
-        let draw_count = indices.len();
-        let index_buffer = ctx.create_buffer(&BufferDescriptor {
-            name: format!("{}-index-buffer", name).into(),
-            size: (index_type.get_size() * indices.len()) as u64,
-            memory_type: MemoryType::HostVisible,
-            usage: BufferUsage::Index,
-        });
-        // Upload index data
-        unsafe {
+    /// With texture coordinates and tangents, for textured pbr materials
+    pub fn from_surface_data(
+        name: &str,
+        vertices: &[Vertex],
+        surface: &[SurfaceVertex],
+        indices: Option<&Indices>,
+        material: AssetHandle<Material>,
+        ctx: &ActiveContext,
+    ) -> Self {
+        assert_eq!(
+            vertices.len(),
+            surface.len(),
+            "[MeshPart] (from_surface_data) {} needs a surface vertex per vertex",
+            name
+        );
+        Self::build(name, vertices, indices, Some(surface), material, ctx)
+    }
+
+    fn build(
+        name: &str,
+        vertices: &[Vertex],
+        indices: Option<&Indices>,
+        surface: Option<&[SurfaceVertex]>,
+        material: AssetHandle<Material>,
+        ctx: &ActiveContext,
+    ) -> Self {
+        let vertex_buffer = device_local_buffer(
+            ctx,
+            format!("{}-vertex-buffer", name),
+            BufferUsage::Vertex,
+            bytemuck::cast_slice(vertices),
+        );
+
+        let index_buffer = indices.map(|indices| {
             let index_data: &[u8] = match indices {
-                Indices::U16(data) => bytemuck::cast_slice(&data),
-                Indices::U32(data) => bytemuck::cast_slice(&data),
+                Indices::U16(data) => bytemuck::cast_slice(data),
+                Indices::U32(data) => bytemuck::cast_slice(data),
             };
-            ctx.write_to_buffer_raw(&index_buffer, index_data);
-        }
+            let buffer = device_local_buffer(
+                ctx,
+                format!("{}-index-buffer", name),
+                BufferUsage::Index,
+                index_data,
+            );
+            (buffer, indices.into())
+        });
+
+        let generated;
+        let surface = match surface {
+            Some(surface) => surface,
+            None => {
+                let uvs = vec![[0.0; 2]; vertices.len()];
+                generated = SurfaceVertex::generate(vertices, &uvs, indices);
+                generated.as_slice()
+            }
+        };
+        let surface_buffer = device_local_buffer(
+            ctx,
+            format!("{}-surface-buffer", name),
+            BufferUsage::Vertex,
+            bytemuck::cast_slice(surface),
+        );
 
         Self {
             vertex_buffer,
-            index_buffer: Some((index_buffer, index_type)),
-            draw_count: draw_count as _,
+            index_buffer,
+            surface_buffer,
+            draw_count: indices.map_or(vertices.len(), Indices::len) as _,
             material,
+            bounds: Aabb::from_points(vertices.iter().map(|v| v.pos)),
         }
     }
 }
 
-impl Renderable<ActiveContext> for MeshPart {
-    fn render<Encoder: CommandEncoder<ActiveContext>>(&self, encoder: &mut Encoder) {
+/// Creates a device local buffer and uploads data through the staging ring of the context
+fn device_local_buffer(
+    ctx: &ActiveContext,
+    name: String,
+    usage: BufferUsage,
+    data: &[u8],
+) -> <ActiveContext as GpuContext>::BufferHandle {
+    let buffer = ctx.create_buffer(&BufferDescriptor {
+        name: name.into(),
+        size: data.len() as u64,
+        memory_type: MemoryType::DeviceLocal,
+        usage,
+    });
+    ctx.upload_buffer(&buffer, 0, data);
+    buffer
+}
+
+impl MeshPart {
+    /// Draws the instances of the part, per instance data has to be bound by the caller
+    pub fn draw<Encoder: CommandEncoder<ActiveContext>>(
+        &self,
+        encoder: &mut Encoder,
+        instances: Range<u32>,
+    ) {
         encoder.bind_vertex_buffer(0, &self.vertex_buffer, BufferRange::WHOLE);
+        encoder.bind_vertex_buffer(2, &self.surface_buffer, BufferRange::WHOLE);
         if let Some((index_buffer, index_type)) = &self.index_buffer {
             encoder.bind_index_buffer(index_buffer, BufferRange::WHOLE, index_type.clone());
-            encoder.draw_indexed(0..self.draw_count, 0, 0..1);
+            encoder.draw_indexed(0..self.draw_count, 0, instances);
         } else {
-            encoder.draw(0..self.draw_count, 0..1);
+            encoder.draw(0..self.draw_count, instances);
         }
     }
 }
 
+impl Renderable<ActiveContext> for MeshPart {
+    fn render<Encoder: CommandEncoder<ActiveContext>>(&self, encoder: &mut Encoder) {
+        self.draw(encoder, 0..1);
+    }
+}
+
 #[derive(Debug)]
 pub struct Mesh {
     name: String,
@@ -194,6 +330,13 @@ impl Mesh {
             parts,
         }
     }
+
+    /// Bounds of all parts in model space
+    pub fn bounds(&self) -> Aabb {
+        self.parts
+            .iter()
+            .fold(Aabb::EMPTY, |bounds, part| bounds.union(&part.bounds))
+    }
 }
 
 // TODO: Drop mesh
@@ -220,3 +363,36 @@ impl Default for Model {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tangents_follow_uvs() {
+        let vertex = |x: f32, y: f32| Vertex {
+            pos: glam::Vec3::new(x, y, 0.0),
+            normal: glam::Vec3::Z,
+        };
+        let vertices = [vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)];
+        let indices = Indices::U16(vec![0, 1, 2]);
+
+        let surface = SurfaceVertex::generate(
+            &vertices,
+            &[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            Some(&indices),
+        );
+        assert!(surface.iter().all(|s| s.tangent == [1.0, 0.0, 0.0, 1.0]));
+
+        // Mirrored uvs flip the bitangent
+        let mirrored =
+            SurfaceVertex::generate(&vertices, &[[1.0, 0.0], [0.0, 0.0], [1.0, 1.0]], None);
+        assert!(mirrored.iter().all(|s| s.tangent == [-1.0, 0.0, 0.0, -1.0]));
+
+        // Without distinct uvs the tangent is still orthogonal to the normal
+        let flat = SurfaceVertex::generate(&vertices, &[[0.0; 2]; 3], None);
+        assert!(flat
+            .iter()
+            .all(|s| s.tangent[2] == 0.0 && s.tangent[3] == 1.0));
+    }
+}
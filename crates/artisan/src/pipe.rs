@@ -0,0 +1,343 @@
+//! Format of the `.pipe` files, that describe graphics pipelines in RON
+//!
+//! The types mirror the descriptors of the render crate (which does not depend on serde) and
+//! are converted into them once the shaders are loaded
+
+use std::{collections::HashMap, ops::Range};
+
+use app::core::anyhow::{Context, Result};
+use render::{
+    prelude::{MixturePart, ShaderType},
+    resource::{
+        glue::PartType,
+        pipeline::{
+            AttributeDescriptor, ComparisonFunction, CullFace, Culling, DepthDescriptor,
+            PolygonMode, Primitive, Rasterizer, VertexAttributeFormat, VertexBufferDescriptor,
+            VertexInputRate, Winding,
+        },
+    },
+};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum PipeStage {
+    Vertex,
+    Fragment,
+    Geometry,
+}
+
+impl From<PipeStage> for ShaderType {
+    fn from(stage: PipeStage) -> Self {
+        match stage {
+            PipeStage::Vertex => ShaderType::Vertex,
+            PipeStage::Fragment => ShaderType::Fragment,
+            PipeStage::Geometry => ShaderType::Geometry,
+        }
+    }
+}
+
+/// Paths of the shaders, relative to the `.pipe` file
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeShaders {
+    pub vertex: String,
+    pub fragment: String,
+    #[serde(default)]
+    pub geometry: Option<String>,
+}
+
+/// A binding of the interface, the same as a line of `mixture!`
+#[derive(Debug, Clone, Deserialize)]
+pub enum PipeBinding {
+    Uniform {
+        binding: u32,
+        name: String,
+        /// Size of the uniform in bytes
+        size: usize,
+        #[serde(default)]
+        dynamic: bool,
+        #[serde(default = "one")]
+        array_size: usize,
+    },
+    Sampler {
+        binding: u32,
+        name: String,
+    },
+    StorageBuffer {
+        binding: u32,
+        name: String,
+    },
+    StorageImage {
+        binding: u32,
+        name: String,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+/// Optional fields that are written without `Some`
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+impl PipeBinding {
+    fn part(&self, stage: PipeStage) -> MixturePart {
+        let (binding, name, type_info, is_dynamic, array_size) = match self {
+            PipeBinding::Uniform {
+                binding,
+                name,
+                size,
+                dynamic,
+                array_size,
+            } => (
+                binding,
+                name,
+                PartType::Uniform(*size),
+                *dynamic,
+                *array_size,
+            ),
+            PipeBinding::Sampler { binding, name } => (binding, name, PartType::Sampler, false, 1),
+            PipeBinding::StorageBuffer { binding, name } => {
+                (binding, name, PartType::StorageBuffer, false, 1)
+            }
+            PipeBinding::StorageImage { binding, name } => {
+                (binding, name, PartType::StorageImage, false, 1)
+            }
+        };
+        MixturePart {
+            binding: *binding,
+            name: name.clone(),
+            shader_type: stage.into(),
+            is_dynamic,
+            array_size,
+            type_info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeVertexBuffer {
+    pub binding: u32,
+    pub stride: u32,
+    #[serde(default)]
+    pub instanced: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PipeFormat {
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeAttribute {
+    pub location: u32,
+    pub binding: u32,
+    pub offset: u32,
+    pub format: PipeFormat,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PipePrimitive {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PipePolygonMode {
+    Point,
+    Line,
+    Fill,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PipeCullFace {
+    None,
+    Front,
+    Back,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeRasterizer {
+    pub polygon_mode: PipePolygonMode,
+    pub cull_face: PipeCullFace,
+    /// Front faces are wound clockwise, otherwise counter clockwise
+    #[serde(default = "yes")]
+    pub clockwise: bool,
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl Default for PipeRasterizer {
+    fn default() -> Self {
+        Self {
+            polygon_mode: PipePolygonMode::Fill,
+            cull_face: PipeCullFace::None,
+            clockwise: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PipeCompare {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeDepth {
+    pub compare: PipeCompare,
+    #[serde(default = "yes")]
+    pub write: bool,
+}
+
+/// Contents of a `.pipe` file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pipeline {
+    pub name: String,
+    pub shaders: PipeShaders,
+    /// Bindings of the (only) descriptor set, by the stage that uses them
+    ///
+    /// Left out, the interface is derived from the reflection of the shaders, which needs a
+    /// backend that compiles to SPIR-V
+    #[serde(default, deserialize_with = "present")]
+    pub interface: Option<HashMap<PipeStage, Vec<PipeBinding>>>,
+    /// Byte ranges of the push constants
+    #[serde(default)]
+    pub push_constants: HashMap<PipeStage, (u32, u32)>,
+    #[serde(default)]
+    pub vertex_buffers: Vec<PipeVertexBuffer>,
+    #[serde(default)]
+    pub attributes: Vec<PipeAttribute>,
+    pub primitive: PipePrimitive,
+    #[serde(default)]
+    pub rasterizer: PipeRasterizer,
+    #[serde(default)]
+    pub depth: Option<PipeDepth>,
+    /// Whether blending is enabled, one entry per color attachment
+    pub blend_targets: Vec<bool>,
+}
+
+impl Pipeline {
+    pub fn parse(content: &str) -> Result<Self> {
+        ron::de::from_str(content).context("[Pipeline] failed to parse .pipe file")
+    }
+
+    /// The interface as parts of a mixture, sorted by binding, None if it is left out
+    pub fn parts(&self) -> Option<Vec<MixturePart>> {
+        let mut parts: Vec<MixturePart> = self
+            .interface
+            .as_ref()?
+            .iter()
+            .flat_map(|(stage, bindings)| bindings.iter().map(move |b| b.part(*stage)))
+            .collect();
+        parts.sort_by_key(|p| p.binding);
+        Some(parts)
+    }
+
+    /// Sorted by offset, so that the order does not depend on the map
+    pub fn push_constants(&self) -> Vec<(ShaderType, Range<u32>)> {
+        let mut ranges: Vec<(PipeStage, Range<u32>)> = self
+            .push_constants
+            .iter()
+            .map(|(stage, (start, end))| (*stage, *start..*end))
+            .collect();
+        ranges.sort_by_key(|(_, range)| range.start);
+        ranges
+            .into_iter()
+            .map(|(stage, range)| (stage.into(), range))
+            .collect()
+    }
+
+    pub fn vertex_buffers(&self) -> Vec<VertexBufferDescriptor> {
+        self.vertex_buffers
+            .iter()
+            .map(|b| {
+                let rate = if b.instanced {
+                    VertexInputRate::Instance
+                } else {
+                    VertexInputRate::Vertex
+                };
+                VertexBufferDescriptor::new(b.binding, b.stride, rate)
+            })
+            .collect()
+    }
+
+    pub fn attributes(&self) -> Vec<AttributeDescriptor> {
+        self.attributes
+            .iter()
+            .map(|a| {
+                let format = match a.format {
+                    PipeFormat::Vec2 => VertexAttributeFormat::Vec2,
+                    PipeFormat::Vec3 => VertexAttributeFormat::Vec3,
+                    PipeFormat::Vec4 => VertexAttributeFormat::Vec4,
+                };
+                AttributeDescriptor::new(a.location, a.binding, a.offset, format)
+            })
+            .collect()
+    }
+
+    pub fn primitive(&self) -> Primitive {
+        match self.primitive {
+            PipePrimitive::PointList => Primitive::PointList,
+            PipePrimitive::LineList => Primitive::LineList,
+            PipePrimitive::LineStrip => Primitive::LineStrip,
+            PipePrimitive::TriangleList => Primitive::TriangleList,
+            PipePrimitive::TriangleStrip => Primitive::TriangleStrip,
+        }
+    }
+
+    pub fn rasterizer(&self) -> Rasterizer {
+        let rasterizer = &self.rasterizer;
+        Rasterizer {
+            polygon_mode: match rasterizer.polygon_mode {
+                PipePolygonMode::Point => PolygonMode::Point,
+                PipePolygonMode::Line => PolygonMode::Line,
+                PipePolygonMode::Fill => PolygonMode::Fill,
+            },
+            culling: Culling {
+                winding: if rasterizer.clockwise {
+                    Winding::Clockwise
+                } else {
+                    Winding::CounterClockwise
+                },
+                cull_face: match rasterizer.cull_face {
+                    PipeCullFace::None => CullFace::None,
+                    PipeCullFace::Front => CullFace::Front,
+                    PipeCullFace::Back => CullFace::Back,
+                },
+            },
+        }
+    }
+
+    pub fn depth(&self) -> Option<DepthDescriptor> {
+        self.depth.as_ref().map(|depth| DepthDescriptor {
+            function: match depth.compare {
+                PipeCompare::Never => ComparisonFunction::Never,
+                PipeCompare::Less => ComparisonFunction::Less,
+                PipeCompare::Equal => ComparisonFunction::Equal,
+                PipeCompare::LessEqual => ComparisonFunction::LessEqual,
+                PipeCompare::Greater => ComparisonFunction::Greater,
+                PipeCompare::NotEqual => ComparisonFunction::NotEqual,
+                PipeCompare::GreaterEqual => ComparisonFunction::GreaterEqual,
+                PipeCompare::Always => ComparisonFunction::Always,
+            },
+            write: depth.write,
+        })
+    }
+}
@@ -1,13 +1,35 @@
-use std::sync::Arc;
+use std::{any::TypeId, ops::Deref, path::Path, sync::Arc};
 
 use app::core::anyhow::{anyhow, Context, Result};
-use app::{App, AssetLoader};
-use render::prelude::{GpuContext, ShaderType};
+use app::{
+    App, AssetDescendant, AssetDescendantBuilder, AssetEvent, AssetHandle, AssetLoader,
+    AssetServer, Assets, Events, ManualEventReader, Resources,
+};
+use render::{
+    prelude::{GpuContext, GpuResources, GraphicsPipelineDescriptor, ShaderType},
+    resource::{
+        glue::Mixture,
+        pipeline::{
+            GraphicsPipeline, PipelineShaders, PipelineStates,
+            RenderContext as PipelineRenderContext,
+        },
+        reflect::{mixture_parts, validate_pipeline, ShaderReflection},
+    },
+};
 
-use crate::renderer::ActiveContext;
+use crate::{
+    pipe::Pipeline,
+    renderer::{ActiveContext, RendererAssets},
+};
+
+type RenderPassHandle = <ActiveContext as GpuContext>::RenderPassHandle;
 
 #[derive(Debug)]
-pub struct ShaderAsset(pub Vec<u32>);
+pub struct ShaderAsset {
+    pub code: <ActiveContext as GpuContext>::ShaderCode,
+    /// Interface of the shader, if the backend compiles to SPIR-V
+    pub reflection: Option<ShaderReflection>,
+}
 
 pub struct ShaderLoader {
     ctx: Arc<ActiveContext>,
@@ -39,7 +61,15 @@ impl AssetLoader for ShaderLoader {
             let shader_type = match extension {
                 "vert" => ShaderType::Vertex,
                 "frag" => ShaderType::Fragment,
-                _ => panic!(),
+                "geom" => ShaderType::Geometry,
+                "comp" => ShaderType::Compute,
+                _ => {
+                    return Err(anyhow!(
+                        "[ShaderLoader] unknown shader extension {:?} of {:?}",
+                        extension,
+                        ctx.path
+                    ))
+                }
             };
             let result = self
                 .ctx
@@ -48,22 +78,364 @@ impl AssetLoader for ShaderLoader {
                     shader_type,
                     name: Some(name),
                 })?;
+            let reflection = match ShaderReflection::from_code(self.ctx.as_ref(), &result) {
+                Some(Ok(reflection)) => Some(reflection),
+                Some(Err(e)) => {
+                    log::warn!("[ShaderLoader] failed to reflect {:?}: {}", ctx.path, e);
+                    None
+                }
+                None => None,
+            };
             // And then send the asset
-            ctx.senders
-                .get_pipe::<ShaderAsset>()
-                .send((ctx.handle, Box::new(ShaderAsset(result))))
-                .await;
+            ctx.send_asset(ShaderAsset {
+                code: result,
+                reflection,
+            })
+            .await;
+
+            Ok(())
+        })
+    }
+
+    fn ext(&self) -> &[&str] {
+        &["vert", "frag", "geom", "comp"]
+    }
+
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<ShaderAsset>()
+    }
+}
+
+/// A `.pipe` file with its (strong) shader handles and the mixture of its interface
+pub struct PipelineAsset {
+    pub desc: Pipeline,
+    pub vertex: AssetHandle<ShaderAsset>,
+    pub fragment: AssetHandle<ShaderAsset>,
+    pub geometry: Option<AssetHandle<ShaderAsset>>,
+    /// None if the interface is derived from the shaders
+    pub mixture: Option<Arc<Mixture<ActiveContext>>>,
+}
+
+pub struct PipelineLoader {
+    resources: Arc<GpuResources<ActiveContext>>,
+    server: AssetServer,
+}
+
+impl AssetLoader for PipelineLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        ctx: app::LoadContext<'a>,
+    ) -> app::BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let content = std::str::from_utf8(bytes).context(format!(
+                "[PipelineLoader] failed to parse input as utf8 str: {:?}",
+                ctx.path
+            ))?;
+            let desc = Pipeline::parse(content)
+                .context(format!("[PipelineLoader] failed to load {:?}", ctx.path))?;
+
+            // Shaders are relative to the .pipe file
+            let dir = ctx.path.parent().unwrap_or_else(|| Path::new(""));
+            let load_shader = |path: &String| {
+                self.server
+                    .load_asset::<ShaderAsset>(dir.join(path).to_string_lossy())
+            };
+            let vertex = load_shader(&desc.shaders.vertex);
+            let fragment = load_shader(&desc.shaders.fragment);
+            let geometry = desc.shaders.geometry.as_ref().map(load_shader);
+            let mixture = desc
+                .parts()
+                .map(|parts| Arc::new(self.resources.stir(parts)));
+
+            ctx.send_asset(PipelineAsset {
+                desc,
+                vertex,
+                fragment,
+                geometry,
+                mixture,
+            })
+            .await;
 
             Ok(())
         })
     }
 
     fn ext(&self) -> &[&str] {
-        &["vert", "frag"]
+        &["pipe"]
     }
+
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<PipelineAsset>()
+    }
+}
+
+/// The pipeline of a `PipelineAsset`, created for a render pass
+pub struct LoadedPipeline {
+    pub pipeline: GraphicsPipeline<ActiveContext>,
+    /// Glue for the pipeline has to be bottled from this mixture
+    pub mixture: Arc<Mixture<ActiveContext>>,
+}
+
+impl Deref for LoadedPipeline {
+    type Target = GraphicsPipeline<ActiveContext>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pipeline
+    }
+}
+
+/// Creates the pipeline of a `.pipe` asset for a render pass and recreates it when the file or
+/// one of its shaders changes
+///
+/// The shaders of a pipeline are only known once the `.pipe` is loaded, so the descendant is
+/// rebuilt whenever the pipeline asset is (re)loaded
+pub struct PipelineInstance {
+    pipe: AssetHandle<PipelineAsset>,
+    resources: Arc<GpuResources<ActiveContext>>,
+    render_pass: Arc<RenderPassHandle>,
+    /// Samples of the attachments of the render pass
+    samples: u8,
+    descendant: Option<AssetDescendant<Arc<LoadedPipeline>>>,
+    last: Option<Arc<LoadedPipeline>>,
+    /// So that every (re)load of the pipe rebuilds the descendant once
+    events: ManualEventReader<AssetEvent<PipelineAsset>>,
 }
 
-pub(crate) fn init(app: &mut App, ctx: Arc<ActiveContext>) {
+impl PipelineInstance {
+    pub fn new(
+        pipe: &AssetHandle<PipelineAsset>,
+        resources: Arc<GpuResources<ActiveContext>>,
+        render_pass: Arc<RenderPassHandle>,
+    ) -> Self {
+        Self {
+            pipe: pipe
+                .clone_strong()
+                .expect("[PipelineInstance] pipeline handle has to be strong"),
+            resources,
+            render_pass,
+            samples: 1,
+            descendant: None,
+            last: None,
+            events: Default::default(),
+        }
+    }
+
+    /// For render passes with multisampled attachments
+    pub fn with_samples(mut self, samples: u8) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// The last successfully created pipeline
+    pub fn get(&mut self, resources: &Resources) -> Option<Arc<LoadedPipeline>> {
+        let (reader, pipe) = (&mut self.events, &self.pipe);
+        let reloaded = resources
+            .get::<Events<AssetEvent<PipelineAsset>>>()
+            .map(|events| {
+                reader
+                    .iter(&events)
+                    .any(|e| !e.is_destroyed() && e.get_handle() == pipe)
+            })
+            .unwrap_or(false);
+        if self.descendant.is_none() || reloaded {
+            if let Some(descendant) = self.descendant(resources) {
+                self.descendant = Some(descendant);
+            }
+        }
+
+        if let Some(loaded) = self.descendant.as_mut().and_then(|d| d.get(resources)) {
+            self.last = Some(loaded.clone());
+        }
+        self.last.clone()
+    }
+
+    fn descendant(&self, resources: &Resources) -> Option<AssetDescendant<Arc<LoadedPipeline>>> {
+        let pipes = resources.get::<Assets<PipelineAsset>>().ok()?;
+        let asset = pipes.try_get(&self.pipe)?;
+
+        let mut builder = AssetDescendantBuilder::new()
+            .depends_on(&self.pipe)
+            .depends_on(&asset.vertex)
+            .depends_on(&asset.fragment);
+        if let Some(geometry) = &asset.geometry {
+            builder = builder.depends_on(geometry);
+        }
+
+        let pipe = self.pipe.clone_strong()?;
+        let gpu = self.resources.clone();
+        let render_pass = self.render_pass.clone();
+        let samples = self.samples;
+        Some(builder.build(move |sources| {
+            let asset = sources.get(&pipe)?;
+            let vertex = sources.get(&asset.vertex)?;
+            let fragment = sources.get(&asset.fragment)?;
+            let geometry = match &asset.geometry {
+                Some(handle) => Some(sources.get(handle)?),
+                None => None,
+            };
+            let desc = &asset.desc;
+
+            // Without reflection (eg. in the software backend) the layout is trusted
+            let reflections: Option<Vec<&ShaderReflection>> = std::iter::once(&*vertex)
+                .chain(std::iter::once(&*fragment))
+                .chain(geometry.as_deref())
+                .map(|shader| shader.reflection.as_ref())
+                .collect();
+            let mixture = match (&asset.mixture, &reflections) {
+                (Some(mixture), _) => mixture.clone(),
+                (None, Some(reflections)) => Arc::new(gpu.stir(mixture_parts(reflections, 0)?)),
+                (None, None) => {
+                    return Err(anyhow!(
+                        "[PipelineInstance] {} has no interface and its shaders are not reflected",
+                        desc.name
+                    ))
+                }
+            };
+
+            let pipeline_desc = GraphicsPipelineDescriptor {
+                name: desc.name.clone().into(),
+                mixtures: vec![&mixture],
+                push_constants: desc.push_constants(),
+                shaders: PipelineShaders {
+                    vertex: &vertex.code,
+                    fragment: &fragment.code,
+                    geometry: geometry.as_ref().map(|g| &g.code),
+                },
+                rasterizer: desc.rasterizer(),
+                vertex_buffers: desc.vertex_buffers(),
+                attributes: desc.attributes(),
+                primitive: desc.primitive(),
+                blend_targets: desc.blend_targets.clone(),
+                depth: desc.depth(),
+                samples,
+                pipeline_states: PipelineStates::DYNAMIC,
+            };
+            if let Some(reflections) = reflections {
+                validate_pipeline(&pipeline_desc, &reflections)?;
+            }
+
+            let pipeline = gpu.create_graphics_pipeline(
+                pipeline_desc,
+                PipelineRenderContext::RenderPass((render_pass.deref(), 0)),
+            );
+            Ok(Arc::new(LoadedPipeline { pipeline, mixture }))
+        }))
+    }
+}
+
+pub(crate) fn init(
+    app: &mut App,
+    ctx: Arc<ActiveContext>,
+    resources: Arc<GpuResources<ActiveContext>>,
+) {
     app.register_asset::<ShaderAsset>();
     app.add_asset_loader(ShaderLoader { ctx });
+
+    let server = app.get_res::<AssetServer>().clone();
+    app.register_asset::<PipelineAsset>();
+    app.add_asset_loader(PipelineLoader { resources, server });
+}
+
+/// Loads a pipeline while the graph is built, which happens again once the `MsaaSettings` change
+///
+/// `path` is relative to the `RendererAssets` root
+pub(crate) fn load(resources: &Resources, path: &str) -> AssetHandle<PipelineAsset> {
+    let path = match resources.get::<RendererAssets>() {
+        Ok(assets) => assets.root.join(path),
+        Err(_) => path.into(),
+    };
+    resources
+        .get::<AssetServer>()
+        .expect("[Artisan] failed to get asset server")
+        .load_asset(path.to_string_lossy())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::mem::size_of;
+
+    use render::resource::{
+        glue::{MixturePart, PartType},
+        reflect::PipelineLayout,
+    };
+
+    use super::*;
+    use crate::{camera::CameraBuffer, mesh::Vertex};
+
+    /// Name, uniform size (0 for everything else) and array size of a part
+    type Part = (String, usize, usize);
+
+    fn part(part: &MixturePart) -> Part {
+        let size = match part.type_info {
+            PartType::Uniform(size) => size,
+            _ => 0,
+        };
+        (part.name.clone(), size, part.array_size)
+    }
+
+    fn uniform(name: &str, size: usize) -> Part {
+        (name.to_string(), size, 1)
+    }
+
+    fn compile(compiler: &mut shaderc::Compiler, path: &Path) -> ShaderReflection {
+        let source = std::fs::read_to_string(path).unwrap();
+        let kind = match path.extension().and_then(|e| e.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("geom") => shaderc::ShaderKind::Geometry,
+            _ => panic!("unknown shader {:?}", path),
+        };
+        let name = path.file_name().unwrap().to_string_lossy();
+        let spirv = compiler
+            .compile_into_spirv(&source, kind, &name, "main", None)
+            .unwrap_or_else(|e| panic!("failed to compile {:?}: {}", path, e));
+        ShaderReflection::from_spirv(spirv.as_binary()).unwrap()
+    }
+
+    /// Parses `assets/shaders/<name>.pipe` and checks it against its compiled shaders, the way
+    /// `validate_pipeline` does once it is loaded. Returns the pipe with its parts, which are
+    /// reflected if the pipe leaves them out
+    pub(crate) fn validated_pipe(name: &str) -> (Pipeline, Vec<MixturePart>) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/shaders");
+        let source = std::fs::read_to_string(dir.join(format!("{}.pipe", name))).unwrap();
+        let pipe = Pipeline::parse(&source).unwrap();
+
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let shaders = &pipe.shaders;
+        let reflections: Vec<ShaderReflection> = std::iter::once(&shaders.vertex)
+            .chain(std::iter::once(&shaders.fragment))
+            .chain(shaders.geometry.as_ref())
+            .map(|shader| compile(&mut compiler, &dir.join(shader)))
+            .collect();
+        let reflections: Vec<&ShaderReflection> = reflections.iter().collect();
+        let parts = match pipe.parts() {
+            Some(parts) => parts,
+            None => mixture_parts(&reflections, 0).unwrap(),
+        };
+
+        let layout = PipelineLayout {
+            name,
+            mixtures: vec![&parts],
+            push_constants: &pipe.push_constants(),
+            attributes: &pipe.attributes(),
+        };
+        if let Err(e) = layout.validate(&reflections) {
+            panic!("{}: {}", name, e);
+        }
+        (pipe, parts)
+    }
+
+    #[test]
+    fn simple_pipe_matches_shaders() {
+        let (pipe, parts) = validated_pipe("simple");
+        assert_eq!(
+            parts.iter().map(part).collect::<Vec<_>>(),
+            vec![uniform("camera_buffer", size_of::<CameraBuffer>())]
+        );
+        assert_eq!(
+            pipe.vertex_buffers()[0].stride as usize,
+            size_of::<Vertex>()
+        );
+    }
 }
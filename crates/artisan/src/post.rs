@@ -0,0 +1,588 @@
+//! Full screen passes between the main pass and the backbuffer
+//!
+//! The main pass renders lit colors into an hdr attachment. Bloom blurs its bright parts into a
+//! half sized attachment, tone mapping maps both into displayable colors (and grades them with a
+//! lut) and fxaa smooths the edges while copying the result into the backbuffer. Every effect is
+//! toggled by the `PostProcessSettings` resource, disabled effects pass the image through.
+//! Apps can add their own passes to the `PostChain`, before the renderer is created.
+
+use std::{collections::HashMap, rc::Rc, sync::Arc};
+
+use app::{AssetEvent, AssetHandle, Assets, Events, Resources};
+use bytemuck::{Pod, Zeroable};
+use render::{
+    graph::{
+        attachment::{AttachmentSize, GraphAttachment},
+        builder::GraphBuilder,
+        node::Node,
+        nodes::callbacks::FrameData,
+    },
+    prelude::*,
+    resource::{
+        frame::Extent2D,
+        glue::Mixture,
+        render_pass::{LoadOp, StoreOp},
+        texture::{Sampler, SamplerDescriptor, WrapMode},
+    },
+    util::format::TextureFormat,
+};
+
+use crate::{
+    pipelines::{self, PipelineAsset, PipelineInstance},
+    renderer::ActiveContext,
+    texture::TextureAsset,
+};
+
+type ActiveGraphBuilder = <ActiveContext as GpuContext>::GraphBuilder;
+type AttachmentIndex = <ActiveGraphBuilder as GraphBuilder>::AttachmentIndex;
+
+/// Format of the attachment the main pass renders into
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Sfloat;
+/// Tone mapped colors, before fxaa
+const LDR_FORMAT: TextureFormat = TextureFormat::Rgba8Srgb;
+/// A color grading lut is a strip of `LUT_SIZE` slices (one per blue value) that are
+/// `LUT_SIZE x LUT_SIZE` texels (red to the right, green down), indexed by srgb colors
+pub const LUT_SIZE: u32 = 16;
+
+/// Options of the post processing, that can be changed at runtime
+#[derive(Debug, Clone)]
+pub struct PostProcessSettings {
+    /// Multiplied with the hdr colors before anything else
+    pub exposure: f32,
+    /// Maps hdr colors with the aces filmic curve, otherwise they are clamped
+    pub tonemapping: bool,
+    /// Bright parts of the image bleed into their surroundings
+    pub bloom: bool,
+    /// Luminance above which colors contribute to the bloom
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Grades the tone mapped colors with the `ColorGradingLut`, the identity without one
+    pub color_grading: bool,
+    pub fxaa: bool,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapping: true,
+            bloom: false,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.3,
+            color_grading: false,
+            fxaa: true,
+        }
+    }
+}
+
+/// Lut texture of the color grading (see `LUT_SIZE` for its layout), it should not have mips
+pub struct ColorGradingLut(pub AssetHandle<TextureAsset>);
+
+/// Post uniform of every post process pipeline
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct PostUniform {
+    pub exposure: f32,
+    pub bloom_threshold: f32,
+    /// 0 if bloom is disabled
+    pub bloom_intensity: f32,
+    pub tonemapping: u32,
+    pub color_grading: u32,
+    pub fxaa: u32,
+    _padding: [u32; 2],
+}
+
+impl PostUniform {
+    pub fn new(settings: &PostProcessSettings) -> Self {
+        Self {
+            exposure: settings.exposure,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: if settings.bloom {
+                settings.bloom_intensity
+            } else {
+                0.0
+            },
+            tonemapping: settings.tonemapping as u32,
+            color_grading: settings.color_grading as u32,
+            fxaa: settings.fxaa as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Texels of the identity lut, as srgb encoded rgba8
+fn identity_lut() -> Vec<u8> {
+    let max = (LUT_SIZE - 1) as f32;
+    let mut texels = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+    for green in 0..LUT_SIZE {
+        for blue in 0..LUT_SIZE {
+            for red in 0..LUT_SIZE {
+                let value = |c: u32| (c as f32 / max * 255.0).round() as u8;
+                texels.extend_from_slice(&[value(red), value(green), value(blue), 255]);
+            }
+        }
+    }
+    texels
+}
+
+/// Everything the post process passes share
+struct PostResources {
+    gpu: Arc<GpuResources<ActiveContext>>,
+    frames_in_flight: usize,
+    uniform: SwapBuffer<ActiveContext, PostUniform>,
+    /// A single triangle that covers the screen, positions only
+    triangle: Buffer<ActiveContext>,
+    sampler: Sampler<ActiveContext>,
+    identity_lut: Texture<ActiveContext>,
+}
+
+/// An attachment read or written by a pass of the chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostAttachment {
+    /// Lit colors of the main pass
+    Hdr,
+    /// Bright parts of the hdr colors, half sized
+    Bloom,
+    /// Displayable colors, which screenshots of surfaces are taken from
+    ToneMapped,
+    Backbuffer,
+    /// An attachment only custom passes use, sized like the first pass that writes it
+    Custom(&'static str, TextureFormat),
+}
+
+/// A full screen pass of the chain
+///
+/// Its pipe has the `post` uniform, the texel size as fragment push constant and the positions
+/// of a full screen triangle as vertex buffer, like `fxaa.pipe`.
+#[derive(Clone)]
+pub struct PostPass {
+    pub name: &'static str,
+    /// Path of the pipe
+    pub pipe: &'static str,
+    pub output: PostAttachment,
+    /// Size of the output relative to the surface, the attachments read are surface sized
+    pub scale: f32,
+    /// Attachment that is only read at the texel of the fragment, with its binding name
+    pub input: Option<(&'static str, PostAttachment)>,
+    /// Attachments read through the linear sampler, with their binding names
+    pub sampled: Vec<(&'static str, PostAttachment)>,
+    /// Binds the lut of the color grading as `lut`
+    pub lut: bool,
+    /// Disabled passes do not draw, their output keeps the clear color
+    pub enabled: fn(&PostProcessSettings) -> bool,
+}
+
+/// Passes of the post processing, in the order they run
+///
+/// Insert it before the renderer to change the chain. The last pass should write the
+/// backbuffer, and `ToneMapped` should be written for screenshots of surfaces.
+#[derive(Clone)]
+pub struct PostChain {
+    pub passes: Vec<PostPass>,
+}
+
+impl Default for PostChain {
+    fn default() -> Self {
+        Self {
+            passes: vec![
+                PostPass {
+                    name: "bloom_pass",
+                    pipe: "assets/shaders/bloom.pipe",
+                    output: PostAttachment::Bloom,
+                    scale: 0.5,
+                    input: None,
+                    sampled: vec![("hdr", PostAttachment::Hdr)],
+                    lut: false,
+                    enabled: |settings| settings.bloom,
+                },
+                PostPass {
+                    name: "tonemap_pass",
+                    pipe: "assets/shaders/tonemap.pipe",
+                    output: PostAttachment::ToneMapped,
+                    scale: 1.0,
+                    input: Some(("hdr", PostAttachment::Hdr)),
+                    sampled: vec![("bloom", PostAttachment::Bloom)],
+                    lut: true,
+                    enabled: |_| true,
+                },
+                // Writes the backbuffer, so it always draws (fxaa is toggled in the shader)
+                PostPass {
+                    name: "fxaa_pass",
+                    pipe: "assets/shaders/fxaa.pipe",
+                    output: PostAttachment::Backbuffer,
+                    scale: 1.0,
+                    input: None,
+                    sampled: vec![("color", PostAttachment::ToneMapped)],
+                    lut: false,
+                    enabled: |_| true,
+                },
+            ],
+        }
+    }
+}
+
+impl PostChain {
+    /// Adds a pass before the last one, which writes the backbuffer
+    pub fn add_pass(&mut self, pass: PostPass) {
+        let at = self.passes.len().saturating_sub(1);
+        self.passes.insert(at, pass);
+    }
+}
+
+/// A pass of the chain, with its pipeline loaded and its attachments added to the graph
+struct ChainPass {
+    pass: PostPass,
+    pipe: AssetHandle<PipelineAsset>,
+    output: AttachmentIndex,
+    input: Option<(&'static str, AttachmentIndex)>,
+    sampled: Vec<(&'static str, AttachmentIndex)>,
+}
+
+/// Glue of every frame in flight, bottled for a mixture, the size of the attachments and a lut
+struct PostGlue {
+    mixture: Arc<Mixture<ActiveContext>>,
+    extent: Extent2D,
+    lut: Option<AssetHandle<TextureAsset>>,
+    glue: Arc<Vec<Glue<ActiveContext>>>,
+}
+
+impl PostGlue {
+    fn matches(
+        &self,
+        mixture: &Arc<Mixture<ActiveContext>>,
+        extent: &Extent2D,
+        lut: &Option<AssetHandle<TextureAsset>>,
+    ) -> bool {
+        Arc::ptr_eq(&self.mixture, mixture)
+            && self.extent.width == extent.width
+            && self.extent.height == extent.height
+            && &self.lut == lut
+    }
+}
+
+fn add_pass(graph_builder: &mut ActiveGraphBuilder, post: Rc<PostResources>, pass: ChainPass) {
+    let mut builder = graph_builder.build_pass_node(pass.pass.name.into());
+    builder.add_output(pass.output, LoadOp::Clear, StoreOp::Store);
+    if let Some((_, input)) = pass.input {
+        builder.add_input(input, LoadOp::Load, StoreOp::DontCare);
+    }
+    for (_, sampled) in pass.sampled.iter() {
+        builder.add_sampled(*sampled);
+    }
+    let gpu = post.gpu.clone();
+    let pass = Rc::new(pass);
+    let init_pass = pass.clone();
+    builder.init(Box::new(move |render_pass| {
+        Box::new(PipelineInstance::new(
+            &init_pass.pipe,
+            gpu.clone(),
+            render_pass,
+        ))
+    }));
+    let mut cache: Option<PostGlue> = None;
+    builder.callback(Box::new(move |frame, instance, _world, resources| {
+        let settings = resources
+            .get::<PostProcessSettings>()
+            .map(|settings| settings.clone())
+            .unwrap_or_default();
+        if !(pass.pass.enabled)(&settings) {
+            return Ok(None);
+        }
+        let pipeline = match instance.get(resources) {
+            Some(pipeline) => pipeline,
+            None => return Ok(None),
+        };
+
+        let FrameData {
+            cmd,
+            frame_index,
+            viewport,
+            sampled,
+            inputs,
+        } = frame;
+        post.uniform.write(PostUniform::new(&settings));
+        post.uniform.frame(frame_index);
+
+        // Relative attachments are recreated with the surface, so is their glue
+        let extent = Extent2D {
+            width: viewport.rect.width as u32,
+            height: viewport.rect.height as u32,
+        };
+        let textures = resources.get::<Assets<TextureAsset>>()?;
+        let lut = match resources.get::<ColorGradingLut>() {
+            Ok(lut) if pass.pass.lut => Some(lut.0.as_weak()),
+            _ => None,
+        };
+        // A reloaded lut has to be bottled again
+        if let (Some(handle), Ok(events)) =
+            (&lut, resources.get::<Events<AssetEvent<TextureAsset>>>())
+        {
+            if events
+                .iter()
+                .any(|e| !e.is_created() && e.get_handle() == handle)
+            {
+                cache = None;
+            }
+        }
+        let lut_asset = lut.as_ref().and_then(|handle| textures.try_get(handle));
+        // Until the lut is loaded, the identity is used
+        let lut = lut.filter(|_| lut_asset.is_some());
+        let glue = match &cache {
+            Some(cached) if cached.matches(&pipeline.mixture, &extent, &lut) => cached.glue.clone(),
+            _ => {
+                let lut_texture = lut_asset
+                    .as_deref()
+                    .map(|asset| asset.0.get_handle())
+                    .unwrap_or_else(|| post.identity_lut.get_handle());
+                let glue = Arc::new(
+                    (0..post.frames_in_flight)
+                        .map(|i| {
+                            let mut glue_bottle = post.gpu.bottle(&pipeline.mixture);
+                            glue_bottle.write_buffer(
+                                PartIndex::Name("post".into()),
+                                post.uniform.get(i as u32),
+                                None,
+                            );
+                            let reads = pass
+                                .input
+                                .iter()
+                                .map(|(name, _)| *name)
+                                .zip(inputs.iter())
+                                .chain(
+                                    pass.sampled
+                                        .iter()
+                                        .map(|(name, _)| *name)
+                                        .zip(sampled.iter()),
+                                );
+                            for (name, texture) in reads {
+                                glue_bottle.write_texture(
+                                    PartIndex::Name(name.into()),
+                                    texture,
+                                    &post.sampler,
+                                );
+                            }
+                            if pass.pass.lut {
+                                glue_bottle.write_texture(
+                                    PartIndex::Name("lut".into()),
+                                    lut_texture,
+                                    &post.sampler,
+                                );
+                            }
+                            glue_bottle.apply()
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                cache = Some(PostGlue {
+                    mixture: pipeline.mixture.clone(),
+                    extent,
+                    lut,
+                    glue: glue.clone(),
+                });
+                glue
+            }
+        };
+
+        // Texel size of the attachments that are read
+        let texel_size = [
+            pass.pass.scale / viewport.rect.width as f32,
+            pass.pass.scale / viewport.rect.height as f32,
+        ];
+        cmd.bind_graphics_pipeline(&pipeline);
+        cmd.set_viewport(0, viewport.clone());
+        cmd.set_scissor(0, viewport.rect);
+        cmd.snort_glue(0, &pipeline, &glue[frame_index as usize]);
+        cmd.push_constants(
+            &pipeline,
+            ShaderType::Fragment,
+            0,
+            bytemuck::cast_slice(&texel_size),
+        );
+        cmd.bind_vertex_buffer(0, &post.triangle, BufferRange::WHOLE);
+        cmd.draw(0..3, 0..1);
+
+        // Keep both alive, until the frame is finished
+        Ok(Some(Box::new((pipeline, glue))))
+    }));
+    graph_builder.add_node(Node::PassNode(builder.build()));
+}
+
+/// Adds the passes of the `PostChain`, that read the hdr attachment and write the backbuffer
+///
+/// Returns the tone mapped color
+pub(crate) fn init(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    hdr: AttachmentIndex,
+    backbuffer: AttachmentIndex,
+) -> AttachmentIndex {
+    let settings = resources
+        .get::<PostProcessSettings>()
+        .map(|settings| settings.clone())
+        .unwrap_or_default();
+    let chain = resources
+        .get::<PostChain>()
+        .map(|chain| chain.clone())
+        .unwrap_or_default();
+
+    let bloom = graph_builder.add_attachment(GraphAttachment::new(
+        "Bloom",
+        AttachmentSize::Relative(0.5, 0.5),
+        HDR_FORMAT,
+    ));
+    let ldr = graph_builder.add_attachment(GraphAttachment::new(
+        "Tone Mapped Color",
+        AttachmentSize::SWAPCHAIN,
+        LDR_FORMAT,
+    ));
+
+    let triangle: [[f32; 2]; 3] = [[-1.0, -1.0], [3.0, -1.0], [-1.0, 3.0]];
+    let lut = identity_lut();
+    let post = Rc::new(PostResources {
+        frames_in_flight: graph_builder.get_swapchain_image_count(),
+        uniform: SwapBuffer::new(
+            resources
+                .get::<Arc<ActiveContext>>()
+                .expect("[Artisan] failed to get context")
+                .clone(),
+            "Post Uniform".into(),
+            graph_builder.get_swapchain_image_count(),
+            PostUniform::new(&settings),
+        ),
+        triangle: gpu.create_device_local_buffer(
+            "Fullscreen Triangle".into(),
+            BufferUsage::Vertex,
+            bytemuck::cast_slice(&triangle),
+        ),
+        sampler: gpu.create_sampler(&SamplerDescriptor {
+            wrap: WrapMode::ClampToEdge,
+            ..SamplerDescriptor::LINEAR
+        }),
+        identity_lut: gpu.create_texture(
+            TextureDescriptor {
+                name: "Identity Lut".into(),
+                extent: Extent2D {
+                    width: LUT_SIZE * LUT_SIZE,
+                    height: LUT_SIZE,
+                },
+                format: TextureFormat::Rgba8Srgb,
+                mip_levels: 1,
+                storage: false,
+            },
+            &[&lut],
+        ),
+        gpu,
+    });
+
+    let mut custom = HashMap::new();
+    let mut attachment =
+        |graph_builder: &mut ActiveGraphBuilder, at: PostAttachment, scale: f32| match at {
+            PostAttachment::Hdr => hdr,
+            PostAttachment::Bloom => bloom,
+            PostAttachment::ToneMapped => ldr,
+            PostAttachment::Backbuffer => backbuffer,
+            PostAttachment::Custom(name, format) => *custom.entry(name).or_insert_with(|| {
+                graph_builder.add_attachment(GraphAttachment::new(
+                    name,
+                    AttachmentSize::Relative(scale, scale),
+                    format,
+                ))
+            }),
+        };
+    for pass in chain.passes {
+        // The output first, so the writer of a custom attachment sizes it
+        let output = attachment(graph_builder, pass.output, pass.scale);
+        let input = pass
+            .input
+            .map(|(name, at)| (name, attachment(graph_builder, at, 1.0)));
+        let sampled = pass
+            .sampled
+            .iter()
+            .map(|(name, at)| (*name, attachment(graph_builder, *at, 1.0)))
+            .collect();
+        let pass = ChainPass {
+            pipe: pipelines::load(resources, pass.pipe),
+            output,
+            input,
+            sampled,
+            pass,
+        };
+        add_pass(graph_builder, post.clone(), pass);
+    }
+    ldr
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use render::prelude::PartType;
+
+    use super::*;
+    use crate::pipelines::tests::validated_pipe;
+
+    #[test]
+    fn post_pipes_match_passes() {
+        let pipes = [
+            ("bloom", vec!["post", "hdr"]),
+            ("tonemap", vec!["post", "hdr", "bloom", "lut"]),
+            ("fxaa", vec!["post", "color"]),
+        ];
+        for (pipe, names) in pipes.iter() {
+            let (pipe, parts) = validated_pipe(pipe);
+            let part_names: Vec<&str> = parts.iter().map(|part| part.name.as_str()).collect();
+            assert_eq!(&part_names, names);
+            assert!(matches!(
+                parts[0].type_info,
+                PartType::Uniform(size) if size == size_of::<PostUniform>()
+            ));
+            assert!(parts[1..]
+                .iter()
+                .all(|part| matches!(part.type_info, PartType::Sampler)));
+            // The texel size
+            assert_eq!(pipe.push_constants()[0].1, 0..8);
+            assert_eq!(pipe.vertex_buffers()[0].stride, 8);
+        }
+    }
+
+    #[test]
+    fn custom_passes_run_before_the_backbuffer() {
+        let mut chain = PostChain::default();
+        chain.add_pass(PostPass {
+            name: "vignette_pass",
+            pipe: "assets/shaders/vignette.pipe",
+            output: PostAttachment::Custom("Vignette", LDR_FORMAT),
+            scale: 1.0,
+            input: None,
+            sampled: vec![("color", PostAttachment::ToneMapped)],
+            lut: false,
+            enabled: |_| true,
+        });
+        let names: Vec<_> = chain.passes.iter().map(|pass| pass.name).collect();
+        assert_eq!(
+            names,
+            ["bloom_pass", "tonemap_pass", "vignette_pass", "fxaa_pass"]
+        );
+        assert_eq!(
+            chain.passes.last().unwrap().output,
+            PostAttachment::Backbuffer
+        );
+    }
+
+    #[test]
+    fn identity_lut_maps_colors_to_themselves() {
+        let lut = identity_lut();
+        assert_eq!(lut.len(), (LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+        let texel = |red: u32, green: u32, blue: u32| {
+            let at = ((green * LUT_SIZE * LUT_SIZE + blue * LUT_SIZE + red) * 4) as usize;
+            [lut[at], lut[at + 1], lut[at + 2]]
+        };
+        assert_eq!(texel(0, 0, 0), [0, 0, 0]);
+        assert_eq!(texel(15, 0, 15), [255, 0, 255]);
+        assert_eq!(texel(0, 15, 5), [0, 255, 85]);
+
+        let disabled = PostUniform::new(&PostProcessSettings::default());
+        assert_eq!(disabled.bloom_intensity, 0.0);
+        assert_eq!(disabled.fxaa, 1);
+    }
+}
@@ -0,0 +1,268 @@
+//! Registry of the loaded materials, that are stored in storage buffers on the gpu
+//!
+//! Every material asset gets a slot, draws only push the index of the slot of their material.
+//! Solid materials are read from `materials` of `solid.pipe` and `instanced.pipe`, the factors of
+//! pbr materials from `materials` of `pbr.pipe`. Both arrays are indexed by the same slot.
+//! Updating a material asset (eg. with `AssetServer::update_asset`) rewrites its slot, the meshes
+//! that use it stay untouched.
+
+use std::{
+    cell::{Ref, RefMut},
+    collections::HashMap,
+    sync::Arc,
+};
+
+use app::{App, AssetEvent, AssetHandle, Assets, Events, IntoFunctionSystem};
+use bytemuck::Zeroable;
+use render::{
+    prelude::{BufferUsage, GpuContext, MemoryType},
+    resource::buffer::BufferDescriptor,
+};
+
+use crate::{
+    material::{Material, PbrFactors, SolidMaterial},
+    renderer::ActiveContext,
+};
+
+/// Where a material is stored, the index is the same for both arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialSlot {
+    Solid(u32),
+    Pbr(u32),
+}
+
+impl MaterialSlot {
+    pub fn index(&self) -> u32 {
+        match self {
+            MaterialSlot::Solid(index) | MaterialSlot::Pbr(index) => *index,
+        }
+    }
+}
+
+/// Slots of the loaded materials, the cpu side of the material buffers
+#[derive(Debug, Default)]
+pub struct MaterialRegistry {
+    slots: HashMap<AssetHandle<Material>, MaterialSlot>,
+    /// Slots of destroyed materials, that are reused first
+    free: Vec<u32>,
+    solid: Vec<SolidMaterial>,
+    pbr: Vec<PbrFactors>,
+    /// Incremented on every change, so that the buffers know when they are outdated
+    version: u64,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// None until the material is loaded
+    pub fn slot(&self, material: &AssetHandle<Material>) -> Option<MaterialSlot> {
+        self.slots.get(material).copied()
+    }
+
+    /// Writes the material into its slot, materials without one get a free slot
+    pub fn insert(&mut self, handle: &AssetHandle<Material>, material: &Material) -> MaterialSlot {
+        let index = match self.slots.get(handle) {
+            Some(slot) => slot.index(),
+            None => self.free.pop().unwrap_or_else(|| {
+                self.solid.push(SolidMaterial::zeroed());
+                self.pbr.push(PbrFactors::zeroed());
+                self.solid.len() as u32 - 1
+            }),
+        };
+        let (solid, pbr, slot) = match material {
+            Material::Solid(solid) => (*solid, PbrFactors::zeroed(), MaterialSlot::Solid(index)),
+            Material::Pbr(pbr) => (
+                SolidMaterial::zeroed(),
+                pbr.factors,
+                MaterialSlot::Pbr(index),
+            ),
+        };
+        self.solid[index as usize] = solid;
+        self.pbr[index as usize] = pbr;
+        self.slots.insert(handle.as_weak(), slot);
+        self.version += 1;
+        slot
+    }
+
+    pub fn remove(&mut self, handle: &AssetHandle<Material>) {
+        if let Some(slot) = self.slots.remove(handle) {
+            self.free.push(slot.index());
+            self.version += 1;
+        }
+    }
+
+    /// Number of slots, including free ones
+    pub fn capacity(&self) -> usize {
+        self.solid.len()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn solid_materials(&self) -> &[SolidMaterial] {
+        &self.solid
+    }
+
+    pub fn pbr_factors(&self) -> &[PbrFactors] {
+        &self.pbr
+    }
+
+    fn update_system(
+        mut registry: RefMut<MaterialRegistry>,
+        materials: Ref<Assets<Material>>,
+        events: Ref<Events<AssetEvent<Material>>>,
+    ) {
+        for event in events.iter() {
+            let handle = event.get_handle();
+            if event.is_destroyed() {
+                registry.remove(handle);
+            } else if let Some(material) = materials.try_get(handle) {
+                registry.insert(handle, &material);
+            }
+        }
+    }
+}
+
+type BufferHandle = <ActiveContext as GpuContext>::BufferHandle;
+
+/// The buffers of a frame in flight, with the version of the registry they contain
+struct FrameMaterials {
+    solid: BufferHandle,
+    pbr: BufferHandle,
+    capacity: usize,
+    version: Option<u64>,
+}
+
+/// Host visible storage buffers per frame in flight, grown when the slots do not fit
+pub(crate) struct MaterialBuffers {
+    ctx: Arc<ActiveContext>,
+    frames: Vec<FrameMaterials>,
+    /// Incremented whenever a buffer is replaced, glue that binds them has to be bottled again
+    generation: u64,
+}
+
+impl MaterialBuffers {
+    pub fn new(ctx: Arc<ActiveContext>, frames_in_flight: usize) -> Self {
+        let frames = (0..frames_in_flight)
+            .map(|frame_index| Self::create(&ctx, frame_index, 64))
+            .collect();
+        Self {
+            ctx,
+            frames,
+            generation: 0,
+        }
+    }
+
+    fn create(ctx: &ActiveContext, frame_index: usize, capacity: usize) -> FrameMaterials {
+        let buffer = |name: &str, size: usize| {
+            ctx.create_buffer(&BufferDescriptor {
+                name: format!("{}-{}", name, frame_index).into(),
+                size: (capacity * size) as u64,
+                memory_type: MemoryType::HostVisible,
+                usage: BufferUsage::Storage,
+            })
+        };
+        FrameMaterials {
+            solid: buffer("solid-materials", std::mem::size_of::<SolidMaterial>()),
+            pbr: buffer("pbr-materials", std::mem::size_of::<PbrFactors>()),
+            capacity,
+            version: None,
+        }
+    }
+
+    /// Copies the registry into the buffers of the frame (which are not in use by the gpu
+    /// anymore), if it changed since they were written
+    pub fn write(&mut self, frame_index: usize, registry: &MaterialRegistry) {
+        let needed = registry.capacity().max(1);
+        if self.frames[frame_index].capacity < needed {
+            let frame = Self::create(&self.ctx, frame_index, needed.next_power_of_two());
+            let old = std::mem::replace(&mut self.frames[frame_index], frame);
+            self.ctx.drop_buffer(old.solid);
+            self.ctx.drop_buffer(old.pbr);
+            self.generation += 1;
+        }
+
+        let frame = &mut self.frames[frame_index];
+        if frame.version != Some(registry.version()) {
+            unsafe {
+                self.ctx.write_to_buffer_raw(
+                    &frame.solid,
+                    bytemuck::cast_slice(registry.solid_materials()),
+                );
+                self.ctx
+                    .write_to_buffer_raw(&frame.pbr, bytemuck::cast_slice(registry.pbr_factors()));
+            }
+            frame.version = Some(registry.version());
+        }
+    }
+
+    pub fn solid(&self, frame_index: usize) -> &BufferHandle {
+        &self.frames[frame_index].solid
+    }
+
+    pub fn pbr(&self, frame_index: usize) -> &BufferHandle {
+        &self.frames[frame_index].pbr
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Drop for MaterialBuffers {
+    fn drop(&mut self) {
+        for frame in self.frames.drain(..) {
+            self.ctx.drop_buffer(frame.solid);
+            self.ctx.drop_buffer(frame.pbr);
+        }
+    }
+}
+
+pub(crate) fn init(app: &mut App) {
+    app.register_asset::<Material>();
+    app.insert_resource(MaterialRegistry::new());
+    app.add_system(
+        app::stages::UPDATE,
+        MaterialRegistry::update_system.into_system(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use app::HandleId;
+    use glam::Vec4;
+
+    use super::*;
+
+    fn handle(label: &str) -> AssetHandle<Material> {
+        AssetHandle::weak(HandleId::LabelId(label.into()))
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut registry = MaterialRegistry::new();
+        let solid = registry.insert(&handle("solid"), &Material::BRONZE);
+        let pbr = registry.insert(
+            &handle("pbr"),
+            &Material::pbr(Default::default(), Default::default()),
+        );
+        assert_eq!(solid, MaterialSlot::Solid(0));
+        assert_eq!(pbr, MaterialSlot::Pbr(1));
+        assert_eq!(registry.pbr_factors()[1].base_color, Vec4::ONE);
+
+        // Editing a material keeps its slot
+        let version = registry.version();
+        let edited = registry.insert(&handle("solid"), &Material::YELLOW_RUBBER);
+        assert_eq!(edited, solid);
+        assert!(registry.version() > version);
+
+        registry.remove(&handle("solid"));
+        assert_eq!(registry.slot(&handle("solid")), None);
+        let reused = registry.insert(&handle("other"), &Material::BRONZE);
+        assert_eq!(reused, MaterialSlot::Solid(0));
+        assert_eq!(registry.capacity(), 2);
+    }
+}
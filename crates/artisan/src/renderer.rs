@@ -1,9 +1,15 @@
-use std::{cell::Ref, ops::Deref, sync::Arc};
+use std::{
+    cell::{Cell, Ref},
+    collections::HashMap,
+    ops::Range,
+    path::PathBuf,
+    rc::Rc,
+    sync::Arc,
+};
 
-use app::{App, AssetDescendant, Assets, IntoMutatingSystem, Resources, Timing, World};
-use bytemuck::{Pod, Zeroable};
+use app::{App, AssetEvent, AssetHandle, Assets, Events, IntoMutatingSystem, Resources, World};
+#[cfg(not(any(feature = "soft", feature = "recording")))]
 use gfx::context::ContextBuilder as GfxContextBuilder;
-use glam::Vec3A;
 use render::{
     context::GpuBuilder,
     graph::{
@@ -16,38 +22,124 @@ use render::{
     prelude::*,
     resource::{
         frame::Extent2D,
-        pipeline::{
-            DepthDescriptor, PipelineShaders, PipelineStates, Primitive, Rasterizer,
-            RenderContext as PipelineRenderContext,
-        },
+        glue::{GlueBottle, Mixture},
         render_pass::{LoadOp, StoreOp},
+        texture::{Sampler, SamplerDescriptor, WrapMode},
     },
+    util::format::TextureFormat,
 };
 
 use crate::{
+    batch::{Batcher, InstanceBuffers, InstanceData},
+    bounds::{Aabb, CullingStats, Frustum},
     camera::{Camera, CameraBuffer},
-    components::{ModelComponent, Transform},
-    material::{Material, SolidMaterial},
-    mesh::{Mesh, Model, Vertex},
-    pipelines::ShaderAsset,
+    components::{ModelComponent, Tint, Transform},
+    debug::DebugDraw,
+    light::{self, AmbientLight, Environment, LightArray, TimeOfDay, LIGHT_STRIDE, MAX_LIGHTS},
+    material::{Material, PbrTextures},
+    mesh::{Mesh, Model},
+    pipelines::{self, LoadedPipeline, PipelineInstance},
+    post::{PostChain, PostProcessSettings},
+    registry::{MaterialBuffers, MaterialRegistry, MaterialSlot},
+    shadow::{self, ShadowSettings, ShadowUniform},
+    text::FontAtlas,
+    texture::TextureAsset,
 };
 
-const LIGHT_POSITION: Vec3A = glam::const_vec3a!([10.0, 10.0, 10.0]);
 const MAT4_SIZE: u32 = std::mem::size_of::<glam::Mat4>() as _;
-const MATERIAL_SIZE: u32 = std::mem::size_of::<SolidMaterial>() as _;
 
-#[derive(Copy, Clone)]
-#[repr(C)]
-struct Light {
-    light_position: Vec3A,
-    view_position: Vec3A,
+/// Options of the renderer, that can be changed at runtime
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    /// Entities that share a model are drawn with a single instanced draw per mesh part,
+    /// otherwise every entity is drawn on its own
+    pub instancing: bool,
+    /// Entities outside of the view frustum of the camera are not drawn
+    pub culling: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            instancing: true,
+            culling: true,
+        }
+    }
+}
+
+/// Where the renderer draws to, this is read once when the renderer is initialized
+#[derive(Debug, Clone)]
+pub enum RenderTarget {
+    /// The window, or the `SoftSurface` resource of the software backend, screenshots are taken
+    /// before fxaa and the overlays
+    Surface,
+    /// An image of the given size, that is never presented but can be saved with `Screenshot`
+    Offscreen(Extent2D),
+}
+
+impl Default for RenderTarget {
+    fn default() -> Self {
+        RenderTarget::Surface
+    }
+}
+
+/// Where the pipelines and shaders of the renderer are loaded from, this is read whenever the
+/// render graph is built
+#[derive(Debug, Clone, Default)]
+pub struct RendererAssets {
+    /// The paths of the pipelines start with `assets/`, so this is the root of the repository.
+    /// A relative root is resolved against the working directory
+    pub root: PathBuf,
 }
 
-unsafe impl Zeroable for Light {}
-unsafe impl Pod for Light {}
+/// Multisampling of the scene, that can be changed at runtime. The render graph is built again
+/// with a new sample count, which recreates its attachments and pipelines
+#[derive(Debug, Clone)]
+pub struct MsaaSettings {
+    /// Samples per texel of the scene color and depth, 1 disables multisampling. Counts that
+    /// the adapter does not support fall back to the next lower one
+    pub samples: u8,
+}
 
+impl Default for MsaaSettings {
+    fn default() -> Self {
+        Self { samples: 4 }
+    }
+}
+
+/// The requested sample count, or the next lower one that the graph supports
+fn supported_samples<G: GraphBuilder>(graph_builder: &G, requested: u8) -> u8 {
+    let samples = (1..=requested.max(1))
+        .rev()
+        .find(|samples| graph_builder.supports_samples(*samples))
+        .unwrap_or(1);
+    if samples != requested {
+        log::warn!(
+            "[Artisan] {} samples are not supported, falling back to {}",
+            requested,
+            samples
+        );
+    }
+    samples
+}
+
+#[cfg(not(any(feature = "soft", feature = "recording")))]
 pub type ActiveContextBuilder = GfxContextBuilder;
+#[cfg(feature = "soft")]
+pub type ActiveContextBuilder = soft::ContextBuilder;
+#[cfg(all(feature = "recording", not(feature = "soft")))]
+pub type ActiveContextBuilder = recording::ContextBuilder;
 pub type ActiveContext = <ActiveContextBuilder as GpuBuilder>::Context;
+pub type ActiveGraph = <ActiveContext as GpuContext>::Graph;
+type SurfaceHandle = <ActiveContext as GpuContext>::SurfaceHandle;
+/// Glue of every frame in flight, with the mixture it was bottled from
+type FrameGlue = (Arc<Mixture<ActiveContext>>, Arc<Vec<Glue<ActiveContext>>>);
+/// Glue of every frame in flight for each set of pbr textures, with the mixture it was bottled from
+type MaterialGlue = (
+    Arc<Mixture<ActiveContext>>,
+    HashMap<PbrTextures, Arc<Vec<Glue<ActiveContext>>>>,
+);
+type TextureHandle = <ActiveContext as GpuContext>::TextureHandle;
 
 // struct RendererState {
 //     vertex_buffer: Buffer<GfxContext>,
@@ -55,45 +147,203 @@ pub type ActiveContext = <ActiveContextBuilder as GpuBuilder>::Context;
 //     glue_drops: Vec<Glue<GfxContext>>,
 // }
 
+fn window_extent(window_state: &window::WindowState) -> Extent2D {
+    let window_size = window_state.window.inner_size();
+    Extent2D {
+        width: window_size.width,
+        height: window_size.height,
+    }
+}
+
+fn window_surface(
+    ctx_builder: &mut ActiveContextBuilder,
+    resources: &Resources,
+) -> (SurfaceHandle, Extent2D) {
+    let window_state = resources
+        .get::<window::WindowState>()
+        .expect("[Artisan] failed to load window");
+
+    let extent = window_extent(&window_state);
+    let surface = ctx_builder.create_surface(&window_state.window, extent.clone());
+    (surface, extent)
+}
+
+#[cfg(not(any(feature = "soft", feature = "recording")))]
+fn create_surface(
+    ctx_builder: &mut ActiveContextBuilder,
+    resources: &Resources,
+) -> (SurfaceHandle, Extent2D) {
+    window_surface(ctx_builder, resources)
+}
+
+/// Without a window, the software backend renders into a `SoftSurface` resource (if present)
+#[cfg(feature = "soft")]
+fn create_surface(
+    ctx_builder: &mut ActiveContextBuilder,
+    resources: &Resources,
+) -> (SurfaceHandle, Extent2D) {
+    match resources.get::<soft::SoftSurface>() {
+        Ok(surface) => (surface.clone(), surface.extent()),
+        Err(_) => window_surface(ctx_builder, resources),
+    }
+}
+
+/// Same for the recording backend, with a `RecordingSurface` resource
+#[cfg(all(feature = "recording", not(feature = "soft")))]
+fn create_surface(
+    ctx_builder: &mut ActiveContextBuilder,
+    resources: &Resources,
+) -> (SurfaceHandle, Extent2D) {
+    match resources.get::<recording::RecordingSurface>() {
+        Ok(surface) => (surface.clone(), surface.extent()),
+        Err(_) => window_surface(ctx_builder, resources),
+    }
+}
+
+/// The surface of a graph that is built again, at the current size of the window
+#[cfg(not(any(feature = "soft", feature = "recording")))]
+fn current_surface(surface: &SurfaceHandle, resources: &Resources) -> (SurfaceHandle, Extent2D) {
+    let window_state = resources
+        .get::<window::WindowState>()
+        .expect("[Artisan] failed to load window");
+    let extent = window_extent(&window_state);
+    ((surface.0.clone(), extent.clone()), extent)
+}
+
+/// Surfaces of the software and recording backends know their size
+#[cfg(any(feature = "soft", feature = "recording"))]
+fn current_surface(surface: &SurfaceHandle, _resources: &Resources) -> (SurfaceHandle, Extent2D) {
+    (surface.clone(), surface.extent())
+}
+
+/// What the render graph is built from, to build it again once the `MsaaSettings` change
+struct GraphSource {
+    surface: SurfaceHandle,
+    target: RenderTarget,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    /// Samples that were requested when the graph was built
+    samples: u8,
+}
+
 pub fn init(app: &mut App) {
+    // Targets inserted before the renderer are kept, eg. to render without presenting
+    if !app.get_resources().contains::<RenderTarget>() {
+        app.insert_resource(RenderTarget::default());
+    }
+    let target = app.get_res::<RenderTarget>().clone();
     let (ctx, surface, initial_aspect_ratio) = {
         let resources = app.get_resources();
-        let window_state = resources
-            .get::<window::WindowState>()
-            .expect("[Artisan] failed to load window");
-
-        let mut ctx_builder = GfxContextBuilder::new();
-        let window_size = window_state.window.inner_size();
-        let initial_aspect_ratio = window_size.width as f32 / window_size.height as f32;
-        let surface = ctx_builder.create_surface(
-            &window_state.window,
-            Extent2D {
-                width: window_size.width,
-                height: window_size.height,
-            },
-        );
+        let mut ctx_builder = ActiveContextBuilder::new();
+        let (surface, surface_extent) = create_surface(&mut ctx_builder, resources);
+        let extent = match &target {
+            RenderTarget::Surface => surface_extent,
+            RenderTarget::Offscreen(extent) => extent.clone(),
+        };
+        let initial_aspect_ratio = extent.width as f32 / extent.height as f32;
         let ctx = Arc::new(ctx_builder.build());
         (ctx, surface, initial_aspect_ratio)
     };
+    #[cfg(feature = "soft")]
+    crate::software::register_shaders(&ctx);
     let resources = Arc::new(GpuResources::new(ctx.clone()));
 
     // Initialize shader asset
-    crate::pipelines::init(app, ctx.clone());
+    crate::pipelines::init(app, ctx.clone(), resources.clone());
+    // And textures
+    crate::texture::init(app, resources.clone());
+    // And fonts
+    crate::font::init(app);
+    // Layout and interaction of the ui, before the frame is rendered
+    crate::ui::init(app);
+    // Materials are referenced by their slot in the material buffers
+    crate::registry::init(app);
+    // The sun and the ambient light
+    crate::light::init(app);
     // Add Context as Resource
     app.insert_resource::<Arc<ActiveContext>>(ctx.clone());
+    app.insert_resource(RenderSettings::default());
+    app.insert_resource(CullingStats::default());
+    // Settings inserted before the renderer are kept, eg. to change the resolution
+    if !app.get_resources().contains::<ShadowSettings>() {
+        app.insert_resource(ShadowSettings::default());
+    }
+    if !app.get_resources().contains::<MsaaSettings>() {
+        app.insert_resource(MsaaSettings::default());
+    }
+    if !app.get_resources().contains::<RendererAssets>() {
+        app.insert_resource(RendererAssets::default());
+    }
+    // Settings inserted before the renderer are kept, eg. to start without fxaa
+    if !app.get_resources().contains::<PostProcessSettings>() {
+        app.insert_resource(PostProcessSettings::default());
+    }
+    if !app.get_resources().contains::<PostChain>() {
+        app.insert_resource(PostChain::default());
+    }
+    app.insert_resource(DebugDraw::default());
+    // Texts and labels share their glyphs, across rebuilds of the graph
+    app.insert_resource(FontAtlas::default());
 
     // And mesh and model asset
     app.register_asset::<Mesh>();
     app.register_asset::<Model>();
 
-    {
-        let mut graph_builder = ctx.create_graph(surface);
+    let (graph, screenshot_attachment) = build_graph(
+        app.get_resources(),
+        ctx.create_graph(surface.clone()),
+        &target,
+        resources.clone(),
+        initial_aspect_ratio,
+    );
+    // Swapchain images can not be read back
+    crate::screenshot::init(app, screenshot_attachment);
+    app.insert_resource(graph);
+    let samples = app.get_res::<MsaaSettings>().samples;
+    app.insert_resource(GraphSource {
+        surface,
+        target,
+        gpu: resources,
+        samples,
+    });
+
+    app.add_mut_system(frame_render.into_mut_system());
+}
+
+/// Adds every pass of the renderer to the graph and builds it
+///
+/// Returns the attachment that screenshots read back
+fn build_graph(
+    resources: &Resources,
+    mut graph_builder: <ActiveContext as GpuContext>::GraphBuilder,
+    target: &RenderTarget,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    initial_aspect_ratio: f32,
+) -> (ActiveGraph, <ActiveGraph as Graph>::AttachmentIndex) {
+    let ctx = resources
+        .get::<Arc<ActiveContext>>()
+        .expect("[Artisan] failed to get context")
+        .clone();
+    let shadow_settings = resources
+        .get::<ShadowSettings>()
+        .expect("[Artisan] failed to get shadow settings")
+        .clone();
+    let shadow_resolution = shadow_settings.resolution;
+    let requested_samples = resources
+        .get::<MsaaSettings>()
+        .expect("[Artisan] failed to get msaa settings")
+        .samples;
+
+    let screenshot_attachment = {
+        if let RenderTarget::Offscreen(extent) = target {
+            graph_builder.render_offscreen(extent.clone());
+        }
         let frames_in_flight = graph_builder.get_swapchain_image_count();
+        let samples = supported_samples(&graph_builder, requested_samples);
+        let camera = resources
+            .get::<Camera>()
+            .expect("[Artisan] failed to get camera");
 
-        let initial_camera = {
-            let camera = app.get_res::<Camera>();
-            camera.to_buffer(initial_aspect_ratio)
-        };
+        let initial_camera = camera.to_buffer(initial_aspect_ratio);
 
         let camera_buffer = SwapBuffer::new(
             ctx.clone(),
@@ -102,200 +352,1194 @@ pub fn init(app: &mut App) {
             initial_camera,
         );
 
-        let initial_light = {
-            let camera = app.get_res::<Camera>();
-            Light {
-                light_position: LIGHT_POSITION,
-                view_position: camera.eye.into(),
-            }
+        let initial_environment = {
+            let ambient = resources
+                .get::<AmbientLight>()
+                .expect("[Artisan] failed to get ambient light");
+            Environment::new(camera.eye, &ambient, 0)
         };
+        drop(camera);
 
-        let light_buffer =
-            SwapBuffer::new(ctx, "Light Uniform".into(), frames_in_flight, initial_light);
-
-        // Which is the equivalent of a DescriptorSetLayout
-        let parts = mixture![
-            0: "camera_buffer" in Vertex: CameraBuffer,
-            1: "light" in Fragment: Light
-            // 1: "camera" in Vertex: ShaderCamera,
-            // 2: "material" in Fragment: [dynamic Material],
-            // 2: "albedo" in Fragment: sampler
-        ];
-
-        let mixture = Arc::new(resources.stir(parts));
-
-        // TODO: load that from file or something
-        let vertex_shader = app
-            .load_asset("assets/shaders/solid.vert")
-            .expect("failed to load solid vertex");
-        let fragment_shader = app
-            .load_asset("assets/shaders/solid.frag")
-            .expect("failed to load solid fragment");
-
-        // log::info!("Vertex shader handle: {:?}", vertex_shader);
-
-        let mut glue_drops = Vec::with_capacity(frames_in_flight);
-
-        for i in 0..frames_in_flight {
-            let mut glue_bottle = resources.bottle(&mixture);
-            glue_bottle.write_buffer(
-                PartIndex::Name("camera_buffer".into()),
-                camera_buffer.get(i as u32),
-                None,
-            );
-            glue_bottle.write_buffer(PartIndex::Binding(1), light_buffer.get(i as _), None);
-            glue_drops.push(glue_bottle.apply());
-        }
+        let environment_buffer = SwapBuffer::new(
+            ctx.clone(),
+            "Environment Uniform".into(),
+            frames_in_flight,
+            initial_environment,
+        );
+        let lights_buffer = SwapBuffer::new(
+            ctx.clone(),
+            "Lights Uniform".into(),
+            frames_in_flight,
+            light::light_array(&[]),
+        );
+        let shadow_buffer = Arc::new(SwapBuffer::new(
+            ctx.clone(),
+            "Shadow Uniform".into(),
+            frames_in_flight,
+            ShadowUniform::new(glam::Mat4::IDENTITY, shadow_resolution, &shadow_settings),
+        ));
+        // The shadow pass only knows the size of the shadow map, the main pass updates this
+        let aspect_ratio = Rc::new(Cell::new(initial_aspect_ratio));
+        let mut instance_buffers = InstanceBuffers::new(ctx.clone(), frames_in_flight);
+        let mut material_buffers = MaterialBuffers::new(ctx.clone(), frames_in_flight);
+        let mut material_generation = material_buffers.generation();
+        let mut shadow_instance_buffers = InstanceBuffers::new(ctx, frames_in_flight);
+        let shadow_sampler = gpu.create_sampler(&SamplerDescriptor {
+            wrap: WrapMode::ClampToEdge,
+            ..SamplerDescriptor::NEAREST
+        });
+
+        let shadow_pipe = pipelines::load(resources, "assets/shaders/shadow.pipe");
+
+        let solid_pipe = pipelines::load(resources, "assets/shaders/solid.pipe");
+        let instanced_pipe = pipelines::load(resources, "assets/shaders/instanced.pipe");
+        let pbr_pipe = pipelines::load(resources, "assets/shaders/pbr.pipe");
+        let mut solid_glue: Option<FrameGlue> = None;
+        let mut instanced_glue: Option<FrameGlue> = None;
+        let mut pbr_glue: Option<MaterialGlue> = None;
+        let default_textures = DefaultTextures::new(&gpu);
+        let mut shadow_glue: Option<FrameGlue> = None;
 
         let backbuffer = graph_builder.get_backbuffer_attachment();
+        // Lit colors of the main pass, that are post processed into the backbuffer
+        let hdr_attachment = graph_builder.add_attachment(GraphAttachment::new(
+            "Hdr Color",
+            AttachmentSize::SWAPCHAIN,
+            crate::post::HDR_FORMAT,
+        ));
+        // Multisampled scenes are drawn into their own color, that is resolved into the hdr color
+        let scene_attachment = if samples > 1 {
+            let mut desc = GraphAttachment::new(
+                "Multisampled Color",
+                AttachmentSize::SWAPCHAIN,
+                crate::post::HDR_FORMAT,
+            );
+            desc.samples = samples;
+            Some(graph_builder.add_attachment(desc))
+        } else {
+            None
+        };
         // Depth attachment
-        let depth_attachment = graph_builder.add_attachment(GraphAttachment::new(
+        let mut depth_desc = GraphAttachment::new(
             "Depth Attachment",
             AttachmentSize::SWAPCHAIN,
             graph_builder.default_depth_format(),
+        );
+        depth_desc.samples = samples;
+        let depth_attachment = graph_builder.add_attachment(depth_desc);
+        // Depth of the scene as seen from the sun
+        let shadow_attachment = graph_builder.add_attachment(GraphAttachment::new(
+            "Shadow Map",
+            AttachmentSize::Absolute(shadow_resolution, shadow_resolution),
+            graph_builder.default_depth_format(),
         ));
 
         {
-            let mut builder = graph_builder.build_pass_node("main_pass".into());
-            builder.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
-            builder.set_depth(depth_attachment, LoadOp::Clear, StoreOp::DontCare);
-            let resources = resources;
+            let mut builder = graph_builder.build_pass_node("shadow_pass".into());
+            builder.set_depth(shadow_attachment, LoadOp::Clear, StoreOp::Store);
+            let init_gpu = gpu.clone();
+            let shadow_buffer = shadow_buffer.clone();
+            let aspect_ratio = aspect_ratio.clone();
             builder.init(Box::new(move |render_pass| {
-                let resources = resources.clone();
-                let vertex_shader = vertex_shader.clone_strong().unwrap();
-                let fragment_shader = fragment_shader.clone_strong().unwrap();
-                let mixture = mixture.clone();
-                let pipeline = AssetDescendant::<ShaderAsset, _>::new(
-                    move |assets| {
-                        let (buffer_descriptor, attributes) = Vertex::get_layout();
-                        let desc = GraphicsPipelineDescriptor {
-                            name: "simple_pipeline".into(),
-                            mixtures: vec![&mixture],
-                            push_constants: vec![
-                                (ShaderType::Vertex, 0..MAT4_SIZE),
-                                (ShaderType::Fragment, MAT4_SIZE..MAT4_SIZE + MATERIAL_SIZE),
-                            ],
-                            shaders: PipelineShaders {
-                                vertex: &assets[0].0,
-                                fragment: &assets[1].0,
-                                geometry: None,
-                            },
-                            rasterizer: Rasterizer::FILL,
-                            vertex_buffers: vec![buffer_descriptor],
-                            attributes,
-                            primitive: Primitive::TriangleList,
-                            blend_targets: vec![true],
-                            depth: Some(DepthDescriptor::LESS),
-                            pipeline_states: PipelineStates::DYNAMIC,
-                        };
+                Box::new(PipelineInstance::new(
+                    &shadow_pipe,
+                    init_gpu.clone(),
+                    render_pass,
+                ))
+            }));
+            let gpu = gpu.clone();
+            builder.callback(Box::new(move |frame, pass, world, resources| {
+                let pipeline = match pass.get(resources) {
+                    Some(pipeline) => pipeline,
+                    None => return Ok(None),
+                };
+                let culling = resources
+                    .get::<RenderSettings>()
+                    .map(|settings| settings.culling)
+                    .unwrap_or(true);
 
-                        Arc::new(resources.create_graphics_pipeline(
-                            desc,
-                            PipelineRenderContext::RenderPass((render_pass.deref(), 0)),
-                        ))
-                    },
-                    vec![vertex_shader, fragment_shader],
-                );
+                let FrameData {
+                    cmd,
+                    frame_index,
+                    viewport,
+                    ..
+                } = frame;
 
-                Box::new(pipeline)
-            }));
-            builder.callback(Box::new(move |frame, pipeline, world, resources| {
-                match pipeline.get(resources) {
-                    Some(pipeline) => {
-                        let FrameData {
-                            cmd,
-                            frame_index,
-                            viewport,
-                        } = frame;
-
-                        {
-                            // Query needed resources
-                            let (camera, timing): (Ref<Camera>, Ref<Timing>) =
-                                resources.query::<(Ref<Camera>, Ref<Timing>)>()?;
-                            // Calculate new camera
-                            let camera_data = {
-                                let aspect_ratio =
-                                    viewport.rect.width as f32 / viewport.rect.height as f32;
-                                camera.to_buffer(aspect_ratio)
-                            };
-                            camera_buffer.write(camera_data);
-                            camera_buffer.frame(frame_index);
-                            // Update Light Buffer
-                            let angle = timing.total_elapsed() * 0.1;
-                            let light_position =
-                                glam::vec3a(angle.sin() * 700.0, 1500.0, angle.cos() * 700.0);
-                            let light_data = Light {
-                                light_position,
-                                view_position: camera.eye.into(),
-                            };
-                            light_buffer.write(light_data);
-                            light_buffer.frame(frame_index);
+                let frustum = {
+                    let (camera, time, settings): (
+                        Ref<Camera>,
+                        Ref<TimeOfDay>,
+                        Ref<ShadowSettings>,
+                    ) = resources.query::<(Ref<Camera>, Ref<TimeOfDay>, Ref<ShadowSettings>)>()?;
+                    let corners = camera.frustum_corners(aspect_ratio.get(), settings.distance);
+                    let light_dir = time.sun_direction();
+                    let light_space = shadow::light_space(&corners, light_dir, shadow_resolution);
+                    shadow_buffer.write(ShadowUniform::new(
+                        light_space,
+                        shadow_resolution,
+                        &settings,
+                    ));
+                    shadow_buffer.frame(frame_index);
+
+                    Frustum::from_view_projection(&light_space)
+                };
+
+                let glue_drops = frame_glue(&mut shadow_glue, &pipeline.mixture, || {
+                    (0..frames_in_flight)
+                        .map(|i| {
+                            let mut glue_bottle = gpu.bottle(&pipeline.mixture);
+                            glue_bottle.write_buffer(
+                                PartIndex::Name("shadow".into()),
+                                shadow_buffer.get(i as u32),
+                                None,
+                            );
+                            glue_bottle.apply()
+                        })
+                        .collect()
+                });
+
+                cmd.bind_graphics_pipeline(&pipeline);
+                cmd.set_viewport(0, viewport.clone());
+                cmd.set_scissor(0, viewport.rect);
+                cmd.snort_glue(0, &pipeline, &glue_drops[frame_index as usize]);
+
+                let meshes = resources.get::<Assets<Mesh>>()?;
+                let models = resources.get::<Assets<Model>>()?;
+
+                // Everything that might cast a shadow into the view of the camera
+                let mut stats = CullingStats::default();
+                let frustum = if culling { Some(&frustum) } else { None };
+                let visible = visible_instances(world, &models, &meshes, frustum, &mut stats);
+                let mut batcher = Batcher::new();
+                for (model, instance) in visible {
+                    batcher.push(model, instance);
+                }
+                let batches = batcher.finish();
+                let instance_buffer =
+                    shadow_instance_buffers.write(frame_index as usize, &batches.instances);
+                cmd.bind_vertex_buffer(1, instance_buffer, BufferRange::WHOLE);
+
+                for batch in batches.batches.iter() {
+                    if let Some(model) = models.try_get(&batch.key) {
+                        for (local_transform, mesh) in model.meshes.iter() {
+                            if let Some(mesh) = meshes.try_get(mesh) {
+                                draw_depth(
+                                    cmd,
+                                    &pipeline,
+                                    &mesh,
+                                    local_transform,
+                                    batch.instances.clone(),
+                                );
+                            }
                         }
+                    }
+                }
+                // Keep both alive, until the frame is finished
+                Ok(Some(Box::new((pipeline, glue_drops))))
+            }));
+            graph_builder.add_node(Node::PassNode(builder.build()))
+        }
 
-                        cmd.bind_graphics_pipeline(pipeline.as_ref());
+        {
+            let mut builder = graph_builder.build_pass_node("main_pass".into());
+            // The debug pass draws on top and resolves the multisampled color
+            builder.add_output(
+                scene_attachment.unwrap_or(hdr_attachment),
+                LoadOp::Clear,
+                StoreOp::Store,
+            );
+            builder.set_depth(depth_attachment, LoadOp::Clear, StoreOp::Store);
+            builder.add_sampled(shadow_attachment);
+            let init_gpu = gpu.clone();
+            let debug_gpu = gpu.clone();
+            let post_gpu = gpu.clone();
+            let text_gpu = gpu.clone();
+            let world_text_gpu = gpu.clone();
+            let ui_gpu = gpu.clone();
+            let shadow_aspect_ratio = aspect_ratio;
+            let uniforms = FrameUniforms {
+                camera: camera_buffer,
+                environment: environment_buffer,
+                lights: lights_buffer,
+                shadow: shadow_buffer,
+                shadow_sampler,
+            };
+            builder.init(Box::new(move |render_pass| {
+                let instance = |pipe, render_pass| {
+                    PipelineInstance::new(pipe, init_gpu.clone(), render_pass).with_samples(samples)
+                };
+                Box::new(MainPass {
+                    solid: instance(&solid_pipe, render_pass.clone()),
+                    instanced: instance(&instanced_pipe, render_pass.clone()),
+                    pbr: instance(&pbr_pipe, render_pass),
+                })
+            }));
+            builder.callback(Box::new(move |frame, pass, world, resources| {
+                let (instancing, culling) = resources
+                    .get::<RenderSettings>()
+                    .map(|settings| (settings.instancing, settings.culling))
+                    .unwrap_or((true, true));
+                let registry = resources.get::<MaterialRegistry>()?;
+                material_buffers.write(frame.frame_index as usize, &registry);
+                // Glue of replaced material buffers has to be bottled again, frames in flight
+                // keep the old one alive
+                if material_buffers.generation() != material_generation {
+                    material_generation = material_buffers.generation();
+                    solid_glue = None;
+                    instanced_glue = None;
+                    pbr_glue = None;
+                }
+                let solid = pass.solid.get(resources);
+                let instanced = pass.instanced.get(resources);
+                let pbr = pass.pbr.get(resources);
+                // Until the instanced pipeline is loaded (or if it failed) entities are drawn
+                // one by one
+                let solid = match (instanced, solid) {
+                    (Some(pipeline), _) if instancing => {
+                        Some((pipeline, &mut instanced_glue, true))
+                    }
+                    (_, Some(pipeline)) => Some((pipeline, &mut solid_glue, false)),
+                    _ => None,
+                };
+                if solid.is_none() && pbr.is_none() {
+                    return Ok(None);
+                }
+
+                let FrameData {
+                    cmd,
+                    frame_index,
+                    viewport,
+                    sampled,
+                    ..
+                } = frame;
+
+                let frustum = {
+                    // Query needed resources
+                    let (camera, time, ambient): (Ref<Camera>, Ref<TimeOfDay>, Ref<AmbientLight>) =
+                        resources.query::<(Ref<Camera>, Ref<TimeOfDay>, Ref<AmbientLight>)>()?;
+                    // Calculate new camera
+                    let aspect_ratio = viewport.rect.width as f32 / viewport.rect.height as f32;
+                    shadow_aspect_ratio.set(aspect_ratio);
+                    let camera_data = camera.to_buffer(aspect_ratio);
+                    uniforms.camera.write(camera_data);
+                    uniforms.camera.frame(frame_index);
+                    // Gather the lights of the entities
+                    let lights = light::gather(world, Some(&time.sun()), camera.eye);
+                    let environment = Environment::new(camera.eye, &ambient, lights.len());
+                    uniforms.environment.write(environment);
+                    uniforms.environment.frame(frame_index);
+                    uniforms.lights.write(light::light_array(&lights));
+                    uniforms.lights.frame(frame_index);
+
+                    camera.frustum(aspect_ratio)
+                };
+                let shadow_map = sampled[0];
 
-                        // TODO: Viewport
-                        cmd.set_viewport(0, viewport.clone());
-                        cmd.set_scissor(0, viewport.rect);
+                let meshes = resources.get::<Assets<Mesh>>()?;
+                let models = resources.get::<Assets<Model>>()?;
 
-                        cmd.snort_glue(0, &pipeline, &glue_drops[frame_index as usize]);
+                // Cull before recording any draws, entities without loaded meshes are skipped
+                let mut stats = CullingStats::default();
+                let frustum = if culling { Some(&frustum) } else { None };
+                let visible = visible_instances(world, &models, &meshes, frustum, &mut stats);
+                if let Ok(mut culling_stats) = resources.get_mut::<CullingStats>() {
+                    *culling_stats = stats;
+                }
+
+                // Pbr materials are always drawn from the instance buffer
+                let mut batcher = Batcher::new();
+                for (model, instance) in visible.iter() {
+                    batcher.push(model.as_weak(), *instance);
+                }
+                let batches = batcher.finish();
+                let instance_buffer =
+                    instance_buffers.write(frame_index as usize, &batches.instances);
 
-                        let meshes = resources.get::<Assets<Mesh>>()?;
-                        let models = resources.get::<Assets<Model>>()?;
+                let mut keep_alive: Vec<Box<dyn std::any::Any>> = Vec::new();
+                if let Some((pipeline, glue, is_instanced)) = solid {
+                    let glue_drops = frame_glue(glue, &pipeline.mixture, || {
+                        (0..frames_in_flight)
+                            .map(|i| {
+                                let mut glue_bottle = gpu.bottle(&pipeline.mixture);
+                                uniforms.write(&mut glue_bottle, i as u32, shadow_map);
+                                glue_bottle.write_buffer(
+                                    PartIndex::Name("materials".into()),
+                                    material_buffers.solid(i),
+                                    None,
+                                );
+                                glue_bottle.apply()
+                            })
+                            .collect()
+                    });
 
-                        for (_e, (model, transform)) in
-                            world.query::<(&ModelComponent, &Transform)>().iter()
-                        {
-                            let model_matrix = transform.into_model();
+                    cmd.bind_graphics_pipeline(&pipeline);
 
-                            if let Some(model) = models.try_get(&model) {
+                    // TODO: Viewport
+                    cmd.set_viewport(0, viewport.clone());
+                    cmd.set_scissor(0, viewport.rect.clone());
+
+                    cmd.snort_glue(0, &pipeline, &glue_drops[frame_index as usize]);
+
+                    if is_instanced {
+                        cmd.bind_vertex_buffer(1, instance_buffer, BufferRange::WHOLE);
+                        for batch in batches.batches.iter() {
+                            if let Some(model) = models.try_get(&batch.key) {
                                 for (local_transform, mesh) in model.meshes.iter() {
-                                    if let Some(mesh) = meshes.try_get(&mesh) {
-                                        let model_matrix = model_matrix * *local_transform;
-                                        let vertex_push_data: &[u32] =
-                                            bytemuck::cast_slice(bytemuck::bytes_of(&model_matrix));
-                                        cmd.push_constants(
+                                    if let Some(mesh) = meshes.try_get(mesh) {
+                                        draw_mesh(
+                                            cmd,
                                             &pipeline,
-                                            ShaderType::Vertex,
-                                            0,
-                                            vertex_push_data,
+                                            &registry,
+                                            &mesh,
+                                            local_transform,
+                                            batch.instances.clone(),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    } else {
+                        for (model, instance) in visible.iter() {
+                            if let Some(model) = models.try_get(model) {
+                                for (local_transform, mesh) in model.meshes.iter() {
+                                    if let Some(mesh) = meshes.try_get(mesh) {
+                                        let model_matrix = instance.model * *local_transform;
+                                        draw_mesh(
+                                            cmd,
+                                            &pipeline,
+                                            &registry,
+                                            &mesh,
+                                            &model_matrix,
+                                            0..1,
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    // Keep both alive, until the frame is finished
+                    keep_alive.push(Box::new((pipeline, glue_drops)));
+                }
+
+                if let Some(pipeline) = pbr {
+                    let materials = resources.get::<Assets<Material>>()?;
+                    let textures = resources.get::<Assets<TextureAsset>>()?;
+                    // Glue of a reloaded pipeline has to be bottled again
+                    let mixture = &pipeline.mixture;
+                    if matches!(&pbr_glue, Some((cached, _)) if !Arc::ptr_eq(cached, mixture)) {
+                        pbr_glue = None;
+                    }
+                    // Texture handles are hashed by their id, not their ref count
+                    #[allow(clippy::mutable_key_type)]
+                    let (_, cache) =
+                        pbr_glue.get_or_insert_with(|| (mixture.clone(), HashMap::new()));
+                    // Glue of loaded or replaced textures is bottled again (glue of textures
+                    // that were still loading uses the defaults), the old one is kept until the
+                    // frame is finished
+                    if let Ok(events) = resources.get::<Events<AssetEvent<TextureAsset>>>() {
+                        for event in events.iter() {
+                            let handle = event.get_handle();
+                            let replaced: Vec<PbrTextures> = cache
+                                .keys()
+                                .filter(|textures| textures.contains(handle))
+                                .map(PbrTextures::as_weak)
+                                .collect();
+                            for textures in replaced {
+                                keep_alive.extend(
+                                    cache
+                                        .remove(&textures)
+                                        .map(|glue| Box::new(glue) as Box<dyn std::any::Any>),
+                                );
+                            }
+                        }
+                    }
+
+                    cmd.bind_graphics_pipeline(&pipeline);
+                    cmd.set_viewport(0, viewport.clone());
+                    cmd.set_scissor(0, viewport.rect);
+                    cmd.bind_vertex_buffer(1, instance_buffer, BufferRange::WHOLE);
+
+                    for batch in batches.batches.iter() {
+                        let model = match models.try_get(&batch.key) {
+                            Some(model) => model,
+                            None => continue,
+                        };
+                        for (local_transform, mesh) in model.meshes.iter() {
+                            let mesh = match meshes.try_get(mesh) {
+                                Some(mesh) => mesh,
+                                None => continue,
+                            };
+                            for part in mesh.parts.iter() {
+                                // The slot is only updated a frame after the asset
+                                let (slot, material) = match (
+                                    registry.slot(&part.material),
+                                    materials.try_get(&part.material),
+                                ) {
+                                    (Some(MaterialSlot::Pbr(slot)), Some(material)) => {
+                                        (slot, material)
+                                    }
+                                    _ => continue,
+                                };
+                                let material = match &*material {
+                                    Material::Pbr(material) => material,
+                                    Material::Solid(_) => continue,
+                                };
+                                let glue_drops = match cache.get(&material.textures) {
+                                    Some(glue) => glue.clone(),
+                                    None => {
+                                        let assets: Vec<_> = material
+                                            .textures
+                                            .iter()
+                                            .map(|texture| {
+                                                texture.and_then(|t| textures.try_get(t))
+                                            })
+                                            .collect();
+                                        let glue = Arc::new(
+                                            (0..frames_in_flight)
+                                                .map(|i| {
+                                                    let mut glue_bottle =
+                                                        gpu.bottle(&pipeline.mixture);
+                                                    uniforms.write(
+                                                        &mut glue_bottle,
+                                                        i as u32,
+                                                        shadow_map,
+                                                    );
+                                                    glue_bottle.write_buffer(
+                                                        PartIndex::Name("materials".into()),
+                                                        material_buffers.pbr(i),
+                                                        None,
+                                                    );
+                                                    for (binding, asset) in
+                                                        assets.iter().enumerate()
+                                                    {
+                                                        glue_bottle.write_texture(
+                                                            PartIndex::Name(
+                                                                PBR_TEXTURES[binding].into(),
+                                                            ),
+                                                            default_textures
+                                                                .get(binding, asset.as_deref()),
+                                                            &default_textures.sampler,
+                                                        );
+                                                    }
+                                                    glue_bottle.apply()
+                                                })
+                                                .collect::<Vec<_>>(),
                                         );
+                                        // Until a texture is loaded its default is used
+                                        cache.insert(material.textures.as_weak(), glue.clone());
+                                        glue
+                                    }
+                                };
+                                cmd.snort_glue(0, &pipeline, &glue_drops[frame_index as usize]);
 
-                                        for part in mesh.parts.iter() {
-                                            let Material::Solid(solid) = &part.material;
-                                            let fragment_push_data: &[u32] =
-                                                bytemuck::cast_slice(bytemuck::bytes_of(solid));
-                                            // log::info!("Push Data is: \n{:#?}", push_data);
-                                            cmd.push_constants(
-                                                &pipeline,
-                                                ShaderType::Fragment,
-                                                MAT4_SIZE,
-                                                fragment_push_data,
-                                            );
-
-                                            cmd.render(part);
-                                        }
+                                let vertex_push_data: &[u32] =
+                                    bytemuck::cast_slice(bytemuck::bytes_of(local_transform));
+                                cmd.push_constants(
+                                    &pipeline,
+                                    ShaderType::Vertex,
+                                    0,
+                                    vertex_push_data,
+                                );
+                                cmd.push_constants(
+                                    &pipeline,
+                                    ShaderType::Fragment,
+                                    MAT4_SIZE,
+                                    &[slot],
+                                );
+                                if instancing {
+                                    part.draw(cmd, batch.instances.clone());
+                                } else {
+                                    for instance in batch.instances.clone() {
+                                        part.draw(cmd, instance..instance + 1);
                                     }
                                 }
+                                keep_alive.push(Box::new(glue_drops));
                             }
                         }
-                        Ok(Some(Box::new(pipeline.clone())))
                     }
-                    None => Ok(None),
+                    keep_alive.push(Box::new(pipeline));
                 }
+                Ok(Some(Box::new(keep_alive)))
             }));
-            graph_builder.add_node(Node::PassNode(builder.build()))
+            graph_builder.add_node(Node::PassNode(builder.build()));
+            crate::text::init_world(
+                resources,
+                &mut graph_builder,
+                world_text_gpu,
+                scene_attachment.unwrap_or(hdr_attachment),
+                depth_attachment,
+                samples,
+            );
+            crate::debug::init(
+                resources,
+                &mut graph_builder,
+                debug_gpu,
+                scene_attachment.unwrap_or(hdr_attachment),
+                depth_attachment,
+                scene_attachment.map(|_| hdr_attachment),
+                samples,
+            );
+            let tone_mapped = crate::post::init(
+                resources,
+                &mut graph_builder,
+                post_gpu,
+                hdr_attachment,
+                backbuffer,
+            );
+            crate::text::init(resources, &mut graph_builder, text_gpu, backbuffer);
+            crate::ui::render::init(resources, &mut graph_builder, ui_gpu, backbuffer);
+            match target {
+                RenderTarget::Surface => tone_mapped,
+                RenderTarget::Offscreen(_) => backbuffer,
+            }
         }
-        app.insert_resource(graph_builder.build());
     };
+    let graph = graph_builder
+        .build()
+        .expect("[Artisan] failed to build render graph");
+    (graph, screenshot_attachment)
+}
 
-    app.add_mut_system(frame_render.into_mut_system());
+/// Builds the graph again once the samples of the `MsaaSettings` changed, the old graph waits
+/// for its frames in flight when it is dropped
+fn rebuild_graph(resources: &Resources) {
+    let samples = resources
+        .get::<MsaaSettings>()
+        .expect("[Artisan] failed to get msaa settings")
+        .samples;
+    let mut source = resources
+        .get_mut::<GraphSource>()
+        .expect("[Artisan] failed to get graph source");
+    if source.samples == samples {
+        return;
+    }
+    source.samples = samples;
+
+    let ctx = resources
+        .get::<Arc<ActiveContext>>()
+        .expect("[Artisan] failed to get context")
+        .clone();
+    let (surface, surface_extent) = current_surface(&source.surface, resources);
+    let extent = match &source.target {
+        RenderTarget::Surface => surface_extent,
+        RenderTarget::Offscreen(extent) => extent.clone(),
+    };
+    let (graph, screenshot_attachment) = build_graph(
+        resources,
+        ctx.create_graph(surface),
+        &source.target,
+        source.gpu.clone(),
+        extent.width as f32 / extent.height as f32,
+    );
+    crate::screenshot::retarget(resources, screenshot_attachment);
+    *resources
+        .get_mut::<ActiveGraph>()
+        .expect("[Artisan] failed to get graph") = graph;
+}
+
+/// Pipelines of the main pass
+struct MainPass {
+    solid: PipelineInstance,
+    instanced: PipelineInstance,
+    pbr: PipelineInstance,
+}
+
+/// Uniforms of the main pass, that are part of the glue of every pipeline
+struct FrameUniforms {
+    camera: SwapBuffer<ActiveContext, CameraBuffer>,
+    environment: SwapBuffer<ActiveContext, Environment>,
+    /// Bound element by element, the array support of mixtures binds an uniform per light
+    lights: SwapBuffer<ActiveContext, LightArray>,
+    shadow: Arc<SwapBuffer<ActiveContext, ShadowUniform>>,
+    shadow_sampler: Sampler<ActiveContext>,
+}
+
+impl FrameUniforms {
+    fn write<'a>(
+        &'a self,
+        glue_bottle: &mut GlueBottle<'a, ActiveContext>,
+        frame_index: u32,
+        shadow_map: &'a TextureHandle,
+    ) {
+        glue_bottle.write_buffer(
+            PartIndex::Name("camera_buffer".into()),
+            self.camera.get(frame_index),
+            None,
+        );
+        glue_bottle.write_buffer(
+            PartIndex::Name("environment".into()),
+            self.environment.get(frame_index),
+            None,
+        );
+        for i in 0..MAX_LIGHTS as u64 {
+            glue_bottle.write_array(
+                PartIndex::Name("lights".into()),
+                i..i + 1,
+                self.lights.get(frame_index),
+                Some(i * LIGHT_STRIDE),
+            );
+        }
+        glue_bottle.write_buffer(
+            PartIndex::Name("shadow".into()),
+            self.shadow.get(frame_index),
+            None,
+        );
+        // The shadow map has an absolute size, so its texture stays the same
+        glue_bottle.write_texture(
+            PartIndex::Name("shadow_map".into()),
+            shadow_map,
+            &self.shadow_sampler,
+        );
+    }
+}
+
+/// Names of the texture bindings of `pbr.pipe`, in the order of `PbrTextures::iter`
+const PBR_TEXTURES: [&str; 5] = [
+    "base_color_map",
+    "metallic_roughness_map",
+    "normal_map",
+    "occlusion_map",
+    "emissive_map",
+];
+
+/// Bound in place of the textures that a pbr material does not have (or that are still loading)
+struct DefaultTextures {
+    white: Texture<ActiveContext>,
+    /// Points along the normal of the vertex
+    normal: Texture<ActiveContext>,
+    sampler: Sampler<ActiveContext>,
+}
+
+impl DefaultTextures {
+    fn new(resources: &GpuResources<ActiveContext>) -> Self {
+        let texture = |name: &'static str, texel: [u8; 4]| {
+            let desc = TextureDescriptor {
+                name: name.into(),
+                extent: Extent2D {
+                    width: 1,
+                    height: 1,
+                },
+                format: TextureFormat::Rgba8Unorm,
+                mip_levels: 1,
+                storage: false,
+            };
+            resources.create_texture(desc, &[&texel])
+        };
+        Self {
+            white: texture("Default White", [255; 4]),
+            normal: texture("Default Normal", [128, 128, 255, 255]),
+            sampler: resources.create_sampler(&SamplerDescriptor::LINEAR),
+        }
+    }
+
+    /// The texture of a binding of `pbr.pipe`, or its default if the material has none
+    fn get<'a>(&'a self, binding: usize, texture: Option<&'a TextureAsset>) -> &'a TextureHandle {
+        match texture {
+            Some(texture) => texture.0.get_handle(),
+            None if PBR_TEXTURES[binding] == "normal_map" => self.normal.get_handle(),
+            None => self.white.get_handle(),
+        }
+    }
+}
+
+/// The glue of the cache, unless the mixture changed (eg. the pipeline was reloaded)
+fn frame_glue(
+    cache: &mut Option<FrameGlue>,
+    mixture: &Arc<Mixture<ActiveContext>>,
+    bottle: impl FnOnce() -> Vec<Glue<ActiveContext>>,
+) -> Arc<Vec<Glue<ActiveContext>>> {
+    match cache {
+        Some((cached, drops)) if Arc::ptr_eq(cached, mixture) => drops.clone(),
+        _ => {
+            let drops = Arc::new(bottle());
+            *cache = Some((mixture.clone(), drops.clone()));
+            drops
+        }
+    }
+}
+
+/// Entities with a loaded model inside of the frustum (if any), counted in stats
+fn visible_instances(
+    world: &World,
+    models: &Assets<Model>,
+    meshes: &Assets<Mesh>,
+    frustum: Option<&Frustum>,
+    stats: &mut CullingStats,
+) -> Vec<(AssetHandle<Model>, InstanceData)> {
+    world
+        .query::<(&ModelComponent, &Transform, Option<&Tint>)>()
+        .iter()
+        .filter_map(|(_e, (model, transform, tint))| {
+            let model_matrix = transform.into_model();
+            let model_data = models.try_get(model)?;
+            let bounds = model_bounds(&model_data, meshes, &model_matrix);
+            if bounds.is_empty() {
+                return None;
+            }
+            if matches!(frustum, Some(frustum) if !frustum.intersects(&bounds)) {
+                stats.culled += 1;
+                return None;
+            }
+            stats.drawn += 1;
+            let instance = InstanceData {
+                model: model_matrix,
+                tint: tint.map(|t| t.0).unwrap_or(glam::Vec4::ONE),
+            };
+            Some((model.as_weak(), instance))
+        })
+        .collect()
+}
+
+/// World space bounds of the loaded meshes of a model
+fn model_bounds(model: &Model, meshes: &Assets<Mesh>, transform: &glam::Mat4) -> Aabb {
+    model
+        .meshes
+        .iter()
+        .filter_map(|(local_transform, mesh)| {
+            let mesh = meshes.try_get(mesh)?;
+            Some(mesh.bounds().transform(&(*transform * *local_transform)))
+        })
+        .fold(Aabb::EMPTY, |bounds, mesh| bounds.union(&mesh))
+}
+
+/// Pushes the transform (vertex) and the material (fragment) before drawing each part
+/// Parts are drawn once their material is in the registry, pbr materials have their own pipeline
+fn draw_mesh(
+    cmd: &mut <ActiveContext as GpuContext>::CommandEncoder,
+    pipeline: &LoadedPipeline,
+    registry: &MaterialRegistry,
+    mesh: &Mesh,
+    transform: &glam::Mat4,
+    instances: Range<u32>,
+) {
+    let vertex_push_data: &[u32] = bytemuck::cast_slice(bytemuck::bytes_of(transform));
+    cmd.push_constants(pipeline, ShaderType::Vertex, 0, vertex_push_data);
+
+    for part in mesh.parts.iter() {
+        if let Some(MaterialSlot::Solid(slot)) = registry.slot(&part.material) {
+            cmd.push_constants(pipeline, ShaderType::Fragment, MAT4_SIZE, &[slot]);
+            part.draw(cmd, instances.clone());
+        }
+    }
+}
+
+/// Only pushes the transform, the shadow pass does not need materials
+fn draw_depth(
+    cmd: &mut <ActiveContext as GpuContext>::CommandEncoder,
+    pipeline: &LoadedPipeline,
+    mesh: &Mesh,
+    transform: &glam::Mat4,
+    instances: Range<u32>,
+) {
+    let vertex_push_data: &[u32] = bytemuck::cast_slice(bytemuck::bytes_of(transform));
+    cmd.push_constants(pipeline, ShaderType::Vertex, 0, vertex_push_data);
+    for part in mesh.parts.iter() {
+        part.draw(cmd, instances.clone());
+    }
 }
 
 fn frame_render(world: &mut World, resources: &mut Resources) {
+    rebuild_graph(resources);
     let mut graph = resources
-        .get_mut::<<ActiveContext as GpuContext>::Graph>()
+        .get_mut::<ActiveGraph>()
         .expect("[Artisan] failed to get graph");
 
+    crate::screenshot::capture(&mut graph, resources);
     graph.execute(world, resources);
+    crate::screenshot::save(resources);
+    crate::debug::finish_frame(resources);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use render::prelude::{PartType, ShaderType};
+
+    use super::*;
+    use crate::{
+        light::LightUniform,
+        mesh::{SurfaceVertex, Vertex},
+        pipe::Pipeline,
+        pipelines::tests::validated_pipe,
+    };
+
+    #[test]
+    fn solid_pipe_matches_renderer() {
+        let (pipe, _) = validated_pipe("solid");
+
+        let sizes: Vec<(u32, usize)> = pipe
+            .parts()
+            .unwrap()
+            .iter()
+            .map(|part| match part.type_info {
+                PartType::Uniform(size) => (part.binding, size),
+                PartType::Sampler | PartType::StorageBuffer => (part.binding, 0),
+                _ => panic!("unexpected part {:?}", part),
+            })
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (0, size_of::<CameraBuffer>()),
+                (1, size_of::<Environment>()),
+                (2, size_of::<ShadowUniform>()),
+                (3, 0),
+                (4, 0),
+                (5, size_of::<LightUniform>()),
+            ]
+        );
+        assert_eq!(pipe.parts().unwrap()[4].name, "materials");
+        assert_eq!(pipe.parts().unwrap()[5].name, "lights");
+        assert_eq!(pipe.parts().unwrap()[5].array_size, MAX_LIGHTS);
+
+        // Only the slot of the material is pushed
+        let push_constants = pipe.push_constants();
+        assert!(matches!(push_constants[0].0, ShaderType::Vertex));
+        assert_eq!(push_constants[0].1, 0..MAT4_SIZE);
+        assert!(matches!(push_constants[1].0, ShaderType::Fragment));
+        assert_eq!(push_constants[1].1, MAT4_SIZE..MAT4_SIZE + 4);
+
+        let (buffer, attributes) = Vertex::get_layout();
+        assert_eq!(pipe.vertex_buffers()[0].stride, buffer.stride);
+        assert_eq!(pipe.attributes().len(), attributes.len());
+    }
+
+    #[test]
+    fn instanced_pipe_matches_instance_data() {
+        let (pipe, _) = validated_pipe("instanced");
+        let buffers = pipe.vertex_buffers();
+        assert_eq!(buffers[0].stride as usize, size_of::<Vertex>());
+        assert_eq!(buffers[1].stride as usize, crate::batch::INSTANCE_SIZE);
+        // 2 vertex attributes, 4 matrix columns and the tint
+        assert_eq!(pipe.attributes().len(), 7);
+    }
+
+    #[test]
+    fn shadow_pipe_matches_renderer() {
+        let (pipe, parts) = validated_pipe("shadow");
+        assert_eq!(parts.len(), 1);
+        assert!(matches!(
+            parts[0].type_info,
+            PartType::Uniform(size) if size == size_of::<ShadowUniform>()
+        ));
+        assert_eq!(pipe.push_constants()[0].1, 0..MAT4_SIZE);
+        assert_eq!(
+            pipe.vertex_buffers()[1].stride as usize,
+            crate::batch::INSTANCE_SIZE
+        );
+        assert!(pipe.blend_targets.is_empty());
+    }
+
+    #[test]
+    fn pbr_pipe_matches_renderer() {
+        let (pipe, parts) = validated_pipe("pbr");
+
+        let samplers: Vec<&str> = parts
+            .iter()
+            .filter(|part| matches!(part.type_info, PartType::Sampler))
+            .map(|part| part.name.as_str())
+            .skip(1)
+            .collect();
+        assert_eq!(samplers, PBR_TEXTURES);
+
+        let materials = parts.iter().find(|part| part.name == "materials").unwrap();
+        assert!(matches!(materials.type_info, PartType::StorageBuffer));
+        let lights = parts.iter().find(|part| part.name == "lights").unwrap();
+        assert_eq!(lights.array_size, MAX_LIGHTS);
+        assert_eq!(pipe.push_constants()[1].1, MAT4_SIZE..MAT4_SIZE + 4);
+
+        let buffers = pipe.vertex_buffers();
+        assert_eq!(buffers[1].stride as usize, crate::batch::INSTANCE_SIZE);
+        assert_eq!(buffers[2].stride as usize, size_of::<SurfaceVertex>());
+        // The attributes of instanced.pipe, the uv and the tangent
+        assert_eq!(pipe.attributes().len(), 9);
+    }
+
+    /// Renders a rotated cube, with nothing that changes between frames
+    #[cfg(any(feature = "soft", feature = "recording"))]
+    fn reference_scene(app: &mut App, extent: Extent2D) {
+        use app::AssetServer;
+
+        use crate::{factory, light::TimeOfDay, mesh::MeshPart};
+
+        app.insert_resource(RenderTarget::Offscreen(extent));
+        // Tests run in the directory of the crate (and in parallel, so it is not changed)
+        app.insert_resource(RendererAssets {
+            root: std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../.."),
+        });
+        app.insert_resource(MsaaSettings { samples: 1 });
+        app.insert_resource(ShadowSettings {
+            resolution: 256,
+            ..Default::default()
+        });
+        // Nothing may change between the frames
+        app.insert_resource(TimeOfDay {
+            speed: 0.0,
+            ..Default::default()
+        });
+        app.insert_resource(window::input::Input::default());
+        app.add_plugin(crate::init_artisan);
+        app.add_plugin(|app| {
+            let ctx = app.get_res::<Arc<ActiveContext>>().clone();
+            let server = app.get_res::<AssetServer>().clone();
+            let material = server.add_loaded_asset("reference-material", Material::BRONZE);
+            let part = MeshPart::from_data("reference-cube", &factory::unit_cube(), material, &ctx);
+            let mesh = server.add_loaded_asset("reference-mesh", Mesh::new("cube", vec![part]));
+            let mut model = Model::new();
+            model.add_mesh(glam::Mat4::IDENTITY, mesh);
+            let model = server.add_loaded_asset("reference-model", model);
+            // The camera looks along x, at a yaw of 0
+            let position = app.get_res::<Camera>().eye + glam::vec3(3.0, 0.0, 0.0);
+            let rotation = crate::components::Rotation::new(glam::vec3(1.0, 1.0, 0.0), 0.6);
+            app.get_world_mut().spawn((
+                ModelComponent(model),
+                Transform::new(position, rotation, glam::Vec3::ONE),
+            ));
+        });
+    }
+
+    /// Pipelines are compiled on the pool, without it they are compiled in place as soon as
+    /// their shaders are loaded, so that the frames do not depend on timing
+    #[cfg(any(feature = "soft", feature = "recording"))]
+    fn without_pool(resources: &mut Resources) -> app::AssetServer {
+        resources.remove::<tasks::AsyncComputePool>();
+        resources.get::<app::AssetServer>().unwrap().clone()
+    }
+
+    /// Renders frames until everything is loaded and the cube is drawn the same in two frames in
+    /// a row, loads are waited for (see `without_pool`)
+    #[cfg(feature = "soft")]
+    fn settled_frame(
+        frame: &mut dyn FnMut() -> render::graph::readback::ReadbackImage,
+        server: &app::AssetServer,
+        extent: &Extent2D,
+    ) -> render::graph::readback::ReadbackImage {
+        // Uploads take a frame once everything is loaded
+        const MAX_FRAMES: usize = 4;
+        let mut last = frame();
+        while server.wait_for_loads() {
+            last = frame();
+        }
+        for _ in 0..MAX_FRAMES {
+            let image = frame();
+            // The cube covers the center of the frame
+            let center = ((extent.height / 2 * extent.width + extent.width / 2) * 4) as usize;
+            let drawn = image.data[..4] != image.data[center..center + 4];
+            if drawn && image.data == last.data {
+                return image;
+            }
+            last = image;
+        }
+        panic!("the frames did not settle");
+    }
+
+    /// Renders a cube with the software backend and compares it with `snapshots/cube.png`, the
+    /// reference is only written if `UPDATE_SNAPSHOTS` is set, a missing one fails the test
+    #[cfg(feature = "soft")]
+    #[test]
+    fn renders_reference_image() {
+        use app::SequentialExecutor;
+        use ecs::schedule::executor::ScheduleExecutor;
+        use render::graph::readback::ReadbackImage;
+
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let reference = root.join("snapshots/cube.png");
+
+        let extent = Extent2D {
+            width: 64,
+            height: 48,
+        };
+        let mut app = App::new();
+        app.insert_resource(soft::SoftSurface::new(extent.clone()));
+        reference_scene(&mut app, extent.clone());
+        app.set_runner(move |mut resources, mut world, mut scheduler| {
+            let server = without_pool(&mut resources);
+            let backbuffer = crate::screenshot::attachment(&resources);
+            let mut frame = || -> ReadbackImage {
+                let readback = resources
+                    .get_mut::<ActiveGraph>()
+                    .unwrap()
+                    .read_attachment(backbuffer);
+                SequentialExecutor::execute(&mut scheduler, &mut world, &mut resources);
+                readback
+                    .take()
+                    .expect("the readback was not completed")
+                    .expect("failed to read the backbuffer back")
+            };
+
+            let last = settled_frame(&mut frame, &server, &extent);
+            let rgba = last.to_rgba8().expect("the backbuffer is not 8 bit color");
+            let actual = soft::Frame {
+                width: last.extent.width,
+                height: last.extent.height,
+                pixels: bytemuck::cast_slice(&rgba).to_vec(),
+            };
+            if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+                std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+                image::save_buffer(
+                    &reference,
+                    &rgba,
+                    actual.width,
+                    actual.height,
+                    image::ColorType::Rgba8,
+                )
+                .expect("failed to write the reference image");
+                return;
+            }
+            assert!(
+                reference.exists(),
+                "the reference image {:?} is missing, rerun with UPDATE_SNAPSHOTS set to write it",
+                reference
+            );
+            let expected = image::open(&reference)
+                .expect("failed to read the reference image")
+                .to_rgba8();
+            let expected = soft::Frame {
+                width: expected.width(),
+                height: expected.height(),
+                pixels: bytemuck::cast_slice(expected.as_raw()).to_vec(),
+            };
+            let differing = actual
+                .differing_pixels(&expected, 4)
+                .expect("the size of the frame changed");
+            assert!(
+                differing * 100 <= actual.pixels.len(),
+                "{} pixels differ from {:?}, rerun with UPDATE_SNAPSHOTS set to update it",
+                differing,
+                reference
+            );
+        });
+        app.run();
+    }
+
+    /// Changing the samples rebuilds the graph, going back to a single sample renders the same
+    /// image as before
+    #[cfg(feature = "soft")]
+    #[test]
+    fn msaa_is_changed_at_runtime() {
+        use app::SequentialExecutor;
+        use ecs::schedule::executor::ScheduleExecutor;
+        use render::graph::readback::ReadbackImage;
+
+        let extent = Extent2D {
+            width: 64,
+            height: 48,
+        };
+        let mut app = App::new();
+        app.insert_resource(soft::SoftSurface::new(extent.clone()));
+        reference_scene(&mut app, extent.clone());
+        app.set_runner(move |mut resources, mut world, mut scheduler| {
+            let server = without_pool(&mut resources);
+            let samples = Cell::new(1);
+            let mut frame = || -> ReadbackImage {
+                // The graph is built again during the frame, which drops the readbacks of the old
+                // one, so a frame is rendered before reading back
+                let changed = {
+                    let mut msaa = resources.get_mut::<MsaaSettings>().unwrap();
+                    let changed = msaa.samples != samples.get();
+                    msaa.samples = samples.get();
+                    changed
+                };
+                if changed {
+                    SequentialExecutor::execute(&mut scheduler, &mut world, &mut resources);
+                }
+                let backbuffer = crate::screenshot::attachment(&resources);
+                let readback = resources
+                    .get_mut::<ActiveGraph>()
+                    .unwrap()
+                    .read_attachment(backbuffer);
+                SequentialExecutor::execute(&mut scheduler, &mut world, &mut resources);
+                readback
+                    .take()
+                    .expect("the readback was not completed")
+                    .expect("failed to read the backbuffer back")
+            };
+            let aliased = settled_frame(&mut frame, &server, &extent);
+            samples.set(4);
+            let multisampled = settled_frame(&mut frame, &server, &extent);
+            assert!(
+                aliased.data != multisampled.data,
+                "the edges of the cube were not smoothed"
+            );
+            samples.set(1);
+            let rebuilt = settled_frame(&mut frame, &server, &extent);
+            assert!(
+                aliased.data == rebuilt.data,
+                "the graph differs after going back to a single sample"
+            );
+        });
+        app.run();
+    }
+
+    /// Runs the renderer on the recording backend and compares the command stream of a frame
+    /// with `snapshots/renderer.ron`, the snapshot is only written if `UPDATE_SNAPSHOTS` is set
+    #[cfg(all(feature = "recording", not(feature = "soft")))]
+    #[test]
+    fn records_renderer_commands() {
+        use app::SequentialExecutor;
+        use ecs::schedule::executor::ScheduleExecutor;
+        use recording::{graph::FRAMES_IN_FLIGHT, CommandLog, RecordingSurface};
+
+        // Uploads take a frame once everything is loaded
+        const MAX_FRAMES: usize = 4;
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let snapshot = root.join("snapshots/renderer.ron");
+
+        let extent = Extent2D {
+            width: 64,
+            height: 48,
+        };
+        let surface = RecordingSurface::new(extent.clone());
+        let mut app = App::new();
+        app.insert_resource(surface.clone());
+        reference_scene(&mut app, extent);
+        app.set_runner(move |mut resources, mut world, mut scheduler| {
+            let server = without_pool(&mut resources);
+            let mut frame = || -> CommandLog {
+                SequentialExecutor::execute(&mut scheduler, &mut world, &mut resources);
+                surface
+                    .last_log()
+                    .expect("the graph did not present a frame")
+            };
+
+            // Passes are skipped until their pipelines are loaded (see `without_pool`), frames in
+            // flight use different framebuffers, so the log is compared with the same frame index
+            let mut logs: Vec<CommandLog> = (0..FRAMES_IN_FLIGHT).map(|_| frame()).collect();
+            while server.wait_for_loads() {
+                logs.push(frame());
+            }
+            let settled = (0..MAX_FRAMES).any(|_| {
+                let log = frame();
+                let same = log.draw_count() > 0 && log == logs[logs.len() - FRAMES_IN_FLIGHT];
+                logs.push(log);
+                same
+            });
+            assert!(settled, "the frames did not settle");
+
+            let log = logs.pop().unwrap();
+            log.assert_no_misuse();
+            log.normalized().assert_snapshot(&snapshot);
+        });
+        app.run();
+    }
+
+    #[test]
+    fn simple_pipe_parses() {
+        let pipe = Pipeline::parse(include_str!("../../../assets/shaders/simple.pipe")).unwrap();
+        assert!(pipe.push_constants().is_empty());
+        assert_eq!(pipe.parts().unwrap().len(), 1);
+
+        // Without an interface it is derived from the shaders
+        let pipe = Pipeline::parse(
+            r#"Pipeline(
+                name: "Derived",
+                shaders: (vertex: "simple.vert", fragment: "simple.frag"),
+                primitive: TriangleList,
+                blend_targets: [false],
+            )"#,
+        )
+        .unwrap();
+        assert!(pipe.parts().is_none());
+    }
 }
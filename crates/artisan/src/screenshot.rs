@@ -0,0 +1,143 @@
+//! Captures of the backbuffer, saved as png files
+//!
+//! Swapchain images can not be read back, so screenshots of the window are taken from the tone
+//! mapped color, before fxaa and the overlays. Rendering offscreen (see `RenderTarget`) captures
+//! the final frame.
+
+use std::path::PathBuf;
+
+use app::{
+    core::anyhow::{anyhow, Context, Result},
+    App, Events, Resources,
+};
+use render::graph::{
+    readback::{Readback, ReadbackImage},
+    Graph,
+};
+
+use crate::renderer::ActiveGraph;
+
+type AttachmentIndex = <ActiveGraph as Graph>::AttachmentIndex;
+
+/// Saves the next rendered frame as a png file at the path
+#[derive(Debug, Clone)]
+pub struct Screenshot {
+    pub path: PathBuf,
+}
+
+impl Screenshot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+/// Screenshots whose frame is still in flight
+struct Screenshots {
+    attachment: AttachmentIndex,
+    pending: Vec<(PathBuf, Readback)>,
+}
+
+pub(crate) fn init(app: &mut App, attachment: AttachmentIndex) {
+    app.add_event::<Screenshot>();
+    app.insert_resource(Screenshots {
+        attachment,
+        pending: Vec::new(),
+    });
+}
+
+/// Screenshots read the attachment of a rebuilt graph from now on
+pub(crate) fn retarget(resources: &Resources, attachment: AttachmentIndex) {
+    resources
+        .get_mut::<Screenshots>()
+        .expect("[Artisan] failed to get screenshots")
+        .attachment = attachment;
+}
+
+/// The attachment that screenshots read back
+pub(crate) fn attachment(resources: &Resources) -> AttachmentIndex {
+    resources
+        .get::<Screenshots>()
+        .expect("[Artisan] failed to get screenshots")
+        .attachment
+}
+
+/// Requests a readback of the backbuffer for every screenshot, before the graph is executed
+pub(crate) fn capture(graph: &mut ActiveGraph, resources: &Resources) {
+    let events = resources
+        .get::<Events<Screenshot>>()
+        .expect("[Artisan] failed to get screenshot events");
+    let mut screenshots = resources
+        .get_mut::<Screenshots>()
+        .expect("[Artisan] failed to get screenshots");
+    for Screenshot { path } in events.iter() {
+        let readback = graph.read_attachment(screenshots.attachment);
+        screenshots.pending.push((path.clone(), readback));
+    }
+}
+
+/// Saves the screenshots whose readback is completed
+pub(crate) fn save(resources: &Resources) {
+    let mut screenshots = resources
+        .get_mut::<Screenshots>()
+        .expect("[Artisan] failed to get screenshots");
+    screenshots.pending.retain(|(path, readback)| {
+        let result = match readback.take() {
+            Some(result) => result,
+            None => return true,
+        };
+        let saved = result
+            .map_err(|e| anyhow!(e))
+            .and_then(|image| encode_png(&image))
+            .and_then(|png| {
+                std::fs::write(path, png).with_context(|| format!("failed to write {:?}", path))
+            });
+        match saved {
+            Ok(()) => log::info!("[Artisan] saved screenshot {:?}", path),
+            Err(e) => log::error!("[Artisan] failed to save screenshot {:?}: {:#}", path, e),
+        }
+        false
+    });
+}
+
+fn encode_png(image: &ReadbackImage) -> Result<Vec<u8>> {
+    let rgba = image
+        .to_rgba8()
+        .ok_or_else(|| anyhow!("the format {:?} is not 8 bit color", image.format))?;
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png).encode(
+        &rgba,
+        image.extent.width,
+        image.extent.height,
+        image::ColorType::Rgba8,
+    )?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use render::{resource::frame::Extent2D, util::format::TextureFormat};
+
+    use super::*;
+
+    #[test]
+    fn encodes_backbuffers_as_png() {
+        let image = ReadbackImage {
+            extent: Extent2D {
+                width: 2,
+                height: 1,
+            },
+            format: TextureFormat::Bgra8Srgb,
+            data: vec![0, 0, 255, 255, 255, 0, 0, 128],
+        };
+        let png = encode_png(&image).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (2, 1));
+        assert_eq!(decoded.into_raw(), vec![255, 0, 0, 255, 0, 0, 255, 128]);
+
+        let depth = ReadbackImage {
+            format: TextureFormat::Depth32Sfloat,
+            ..image
+        };
+        assert!(encode_png(&depth).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Shadows of the sun, its depth is rendered into a shadow map before the main pass
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+/// Options of the shadow map, the resolution is only read when the renderer is initialized
+#[derive(Debug, Clone)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels
+    pub resolution: u32,
+    /// Subtracted from the depth of a fragment before comparing it, against shadow acne
+    pub bias: f32,
+    /// The shadow map is sampled `(2 * pcf_radius + 1)^2` times per fragment, 0 disables filtering
+    pub pcf_radius: i32,
+    /// Only the view volume of the camera up to this distance casts and receives shadows
+    pub distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            bias: 0.005,
+            pcf_radius: 1,
+            distance: 100.0,
+        }
+    }
+}
+
+/// Shadow uniform of the shadow pass and the main pass
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct ShadowUniform {
+    pub light_space: Mat4,
+    pub bias: f32,
+    /// Size of a texel in uv coordinates
+    pub texel_size: f32,
+    pub pcf_radius: i32,
+    _padding: f32,
+}
+
+impl ShadowUniform {
+    /// The resolution is the one of the shadow map, the settings might have changed since
+    pub fn new(light_space: Mat4, resolution: u32, settings: &ShadowSettings) -> Self {
+        Self {
+            light_space,
+            bias: settings.bias,
+            texel_size: 1.0 / resolution as f32,
+            pcf_radius: settings.pcf_radius,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Orthographic view projection of the sun, that contains the corners of the camera frustum
+///
+/// The projection is fitted around the bounding sphere of the corners, so that its size does not
+/// change when the camera rotates, and moves in whole texels, so that shadow edges do not
+/// shimmer when the camera moves.
+pub fn light_space(corners: &[Vec3; 8], light_dir: Vec3, resolution: u32) -> Mat4 {
+    let center = corners.iter().fold(Vec3::ZERO, |sum, c| sum + *c) / corners.len() as f32;
+    let radius = corners
+        .iter()
+        .map(|c| c.distance(center))
+        .fold(0.0f32, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let light_dir = light_dir.normalize();
+    let up = if light_dir.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let view = Mat4::look_at_rh(Vec3::ZERO, light_dir, up);
+
+    let texel = 2.0 * radius / resolution as f32;
+    let mut center = view.transform_point3(center);
+    center.x = (center.x / texel).floor() * texel;
+    center.y = (center.y / texel).floor() * texel;
+
+    // Casters between the sun and the sphere still have to be rendered
+    let near = -center.z - radius * 2.0;
+    let far = -center.z + radius;
+    let projection = Mat4::orthographic_rh(
+        center.x - radius,
+        center.x + radius,
+        center.y - radius,
+        center.y + radius,
+        near,
+        far,
+    );
+    projection * view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(center: Vec3, size: f32) -> [Vec3; 8] {
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let offset = Vec3::new(
+                if i & 1 == 0 { -size } else { size },
+                if i & 2 == 0 { -size } else { size },
+                if i & 4 == 0 { -size } else { size },
+            );
+            *corner = center + offset;
+        }
+        corners
+    }
+
+    #[test]
+    fn light_space_contains_corners() {
+        let corners = cube(Vec3::new(5.0, 0.0, -20.0), 10.0);
+        for light_dir in [Vec3::new(-0.3, -1.0, 0.2), -Vec3::Y].iter() {
+            let matrix = light_space(&corners, *light_dir, 1024);
+            for corner in corners.iter() {
+                let projected = matrix.project_point3(*corner);
+                assert!(projected.x.abs() <= 1.0 && projected.y.abs() <= 1.0);
+                assert!((0.0..=1.0).contains(&projected.z), "{:?}", projected);
+            }
+            // Closer to the sun is less deep
+            let center = Vec3::new(5.0, 0.0, -20.0);
+            let towards_sun = matrix.project_point3(center - *light_dir);
+            assert!(towards_sun.z < matrix.project_point3(center).z);
+        }
+    }
+
+    #[test]
+    fn light_space_moves_in_texels() {
+        let light_dir = Vec3::new(0.0, -1.0, 0.0);
+        let first = light_space(&cube(Vec3::new(0.03, 0.0, 0.0), 8.0), light_dir, 256);
+        let second = light_space(&cube(Vec3::new(0.04, 0.0, 0.0), 8.0), light_dir, 256);
+        // Moving by less than a texel (2 * 8 * sqrt(3) / 256) does not move the projection
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,558 @@
+//! Rust versions of the shaders in assets/shaders, used by the software backend
+
+use glam::{Mat3, Mat4, Vec3, Vec4};
+use soft::{ShaderEnv, SoftContext, SoftShader, Varyings, VertexOutput};
+
+use crate::{
+    light::{Environment, LightUniform, DIRECTIONAL, MAX_LIGHTS, SPOT},
+    material::{PbrFactors, SolidMaterial},
+    post::{PostUniform, LUT_SIZE},
+    shadow::ShadowUniform,
+};
+
+const MAT4_SIZE: u32 = std::mem::size_of::<Mat4>() as _;
+
+fn vec3(values: &[f32]) -> Vec3 {
+    glam::vec3(values[0], values[1], values[2])
+}
+
+/// simple.vert
+fn simple_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let view_projection: Mat4 = env.uniform(0, 0);
+    let in_pos = vec3(&attributes[0]);
+
+    let mut output = VertexOutput::new((view_projection * in_pos.extend(1.0)).into());
+    output.varyings[0..3].copy_from_slice(&attributes[1][0..3]);
+    output
+}
+
+/// simple.frag
+fn simple_fragment(_env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let pass_normal = vec3(&varyings[0..3]);
+    (0.5 * pass_normal + Vec3::splat(0.5)).extend(1.0).into()
+}
+
+/// solid.vert
+fn solid_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let view_projection: Mat4 = env.uniform(0, 0);
+    let model: Mat4 = env.push_constant(0);
+    let in_pos = vec3(&attributes[0]);
+    let in_normal = vec3(&attributes[1]);
+
+    let world_position = model * in_pos.extend(1.0);
+    let pass_normal = normal_matrix(&model) * in_normal;
+
+    let mut output = VertexOutput::new((view_projection * world_position).into());
+    output.varyings[0..3].copy_from_slice(&<[f32; 3]>::from(pass_normal));
+    output.varyings[3..6].copy_from_slice(&<[f32; 3]>::from(world_position.truncate()));
+    output
+}
+
+/// The mat4 at locations 2 to 5
+fn instance_model(attributes: &[[f32; 4]]) -> Mat4 {
+    Mat4::from_cols(
+        attributes[2].into(),
+        attributes[3].into(),
+        attributes[4].into(),
+        attributes[5].into(),
+    )
+}
+
+/// mat3(transpose(inverse(model)))
+fn normal_matrix(model: &Mat4) -> Mat3 {
+    let inverse = model.inverse().transpose();
+    Mat3::from_cols(
+        inverse.x_axis.truncate(),
+        inverse.y_axis.truncate(),
+        inverse.z_axis.truncate(),
+    )
+}
+
+/// instanced.vert
+fn instanced_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let view_projection: Mat4 = env.uniform(0, 0);
+    let local: Mat4 = env.push_constant(0);
+    let in_pos = vec3(&attributes[0]);
+    let in_normal = vec3(&attributes[1]);
+    let instance_model = instance_model(attributes);
+
+    let model = instance_model * local;
+    let world_position = model * in_pos.extend(1.0);
+    let pass_normal = normal_matrix(&model) * in_normal;
+
+    let mut output = VertexOutput::new((view_projection * world_position).into());
+    output.varyings[0..3].copy_from_slice(&<[f32; 3]>::from(pass_normal));
+    output.varyings[3..6].copy_from_slice(&<[f32; 3]>::from(world_position.truncate()));
+    output.varyings[6..10].copy_from_slice(&attributes[6]);
+    output
+}
+
+/// pbr.vert, instanced.vert with texture coordinates and tangents
+fn pbr_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let mut output = instanced_vertex(env, attributes);
+    let local: Mat4 = env.push_constant(0);
+    let model = instance_model(attributes) * local;
+    let in_tangent = Vec4::from(attributes[8]);
+    let tangent = model.transform_vector3(in_tangent.truncate());
+
+    output.varyings[10..12].copy_from_slice(&attributes[7][0..2]);
+    output.varyings[12..16].copy_from_slice(&<[f32; 4]>::from(tangent.extend(in_tangent.w)));
+    output
+}
+
+/// shadow.vert
+fn shadow_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let shadow: ShadowUniform = env.uniform(0, 0);
+    let local: Mat4 = env.push_constant(0);
+    let in_pos = vec3(&attributes[0]);
+
+    let position = shadow.light_space * instance_model(attributes) * local * in_pos.extend(1.0);
+    VertexOutput::new(position.into())
+}
+
+/// shadow.frag, never called as the shadow pass has no color attachment
+fn shadow_fragment(_env: &ShaderEnv, _varyings: &Varyings) -> [f32; 4] {
+    [0.0; 4]
+}
+
+/// sunlight() of solid.frag
+fn sunlight(env: &ShaderEnv, position: Vec3) -> f32 {
+    let shadow: ShadowUniform = env.uniform(0, 2);
+    let projected = shadow.light_space.project_point3(position);
+    let uv = glam::vec2(projected.x, projected.y) * 0.5 + glam::Vec2::splat(0.5);
+    // Outside of the shadow map everything is lit
+    if projected.z > 1.0 || uv.x < 0.0 || uv.y < 0.0 || uv.x > 1.0 || uv.y > 1.0 {
+        return 1.0;
+    }
+    let depth = projected.z - shadow.bias;
+    let radius = shadow.pcf_radius;
+    let mut lit = 0.0;
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            let offset = glam::vec2(x as f32, y as f32) * shadow.texel_size;
+            let closest = env.sample(0, 3, (uv + offset).into())[0];
+            if depth <= closest {
+                lit += 1.0;
+            }
+        }
+    }
+    lit / ((2 * radius + 1) * (2 * radius + 1)) as f32
+}
+
+/// The environment and the lights of the fragment shaders, lights are bound at binding
+fn lights(env: &ShaderEnv, binding: u32) -> (Environment, Vec<LightUniform>) {
+    let environment: Environment = env.uniform(0, 1);
+    let lights: [LightUniform; MAX_LIGHTS] = env.uniform(0, binding);
+    let count = (environment.light_count as usize).min(MAX_LIGHTS);
+    (environment, lights[..count].to_vec())
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// incoming() of solid.frag, the direction towards the light and its color at the position,
+/// multiplied with the sunlight if the shadow map applies
+fn incoming(light: &LightUniform, position: Vec3, sun: f32) -> (Vec3, Vec3) {
+    let shadow = 1.0 + light.color.w * (sun - 1.0);
+    let color = light.color.truncate() * shadow;
+    if light.kind == DIRECTIONAL {
+        return (-light.direction.truncate(), color);
+    }
+    let to_light = light.position.truncate() - position;
+    let dist = to_light.length();
+    let light_dir = to_light / dist;
+    // Inverse square falloff, that fades out smoothly at the range
+    let window = (1.0 - (dist / light.position.w).powi(4)).clamp(0.0, 1.0);
+    let mut attenuation = window * window / (dist * dist + 1.0);
+    if light.kind == SPOT {
+        let cos_angle = (-light_dir).dot(light.direction.truncate());
+        attenuation *= smoothstep(light.outer_cos, light.inner_cos, cos_angle);
+    }
+    (light_dir, color * attenuation)
+}
+
+const PI: f32 = std::f32::consts::PI;
+
+/// Trowbridge-Reitz normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    n_dot_v / (n_dot_v * (1.0 - k) + k)
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - cos_theta).powf(5.0)
+}
+
+/// pbr.frag
+fn pbr_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let slot: u32 = env.push_constant(MAT4_SIZE);
+    let material: PbrFactors = env.load(0, 9, slot as usize);
+    let (environment, lights) = lights(env, 10);
+    let pass_normal = vec3(&varyings[0..3]);
+    let pass_fragment_position = vec3(&varyings[3..6]);
+    let pass_tint = Vec4::new(varyings[6], varyings[7], varyings[8], varyings[9]);
+    let pass_uv = [varyings[10], varyings[11]];
+    let pass_tangent = Vec4::new(varyings[12], varyings[13], varyings[14], varyings[15]);
+    let sample = |binding: u32| Vec4::from(env.sample(0, binding, pass_uv));
+
+    // material
+    let base_color = material.base_color * sample(4) * pass_tint;
+    let metallic_roughness = sample(5);
+    let metallic = material.metallic * metallic_roughness.z;
+    let roughness = (material.roughness * metallic_roughness.y).clamp(0.04, 1.0);
+    let occlusion = 1.0 + material.occlusion_strength * (sample(7).x - 1.0);
+    let emissive = material.emissive.truncate() * sample(8).truncate();
+    // normal mapping
+    let normal = pass_normal.normalize();
+    let tangent = pass_tangent.truncate();
+    let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+    let bitangent = normal.cross(tangent) * pass_tangent.w;
+    let mut mapped = sample(6).truncate() * 2.0 - Vec3::ONE;
+    mapped.x *= material.normal_scale;
+    mapped.y *= material.normal_scale;
+    let normal = (Mat3::from_cols(tangent, bitangent, normal) * mapped).normalize();
+    // cook-torrance
+    let view_dir = (environment.view_position.truncate() - pass_fragment_position).normalize();
+    let n_dot_v = normal.dot(view_dir).max(0.0001);
+    let albedo = base_color.truncate();
+    let f0 = Vec3::splat(0.04).lerp(albedo, metallic);
+    // shadow
+    let sun = sunlight(env, pass_fragment_position);
+
+    let mut direct = Vec3::ZERO;
+    for light in lights.iter() {
+        let (light_dir, radiance) = incoming(light, pass_fragment_position, sun);
+        // A white light of intensity 1 lights a white surface like in solid.frag
+        let radiance = PI * radiance;
+        let halfway_dir = (view_dir + light_dir).normalize();
+        let n_dot_l = normal.dot(light_dir).max(0.0);
+        let f = fresnel_schlick(halfway_dir.dot(view_dir).max(0.0), f0);
+        let d = distribution_ggx(normal.dot(halfway_dir).max(0.0), roughness);
+        let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+        let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+        let diffuse = (Vec3::ONE - f) * (1.0 - metallic) * albedo / PI;
+        direct += (diffuse + specular) * radiance * n_dot_l;
+    }
+    // ambient
+    let ambient = environment.ambient.truncate() * albedo * occlusion;
+
+    (ambient + direct + emissive).extend(base_color.w).into()
+}
+
+fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
+    incident - 2.0 * normal.dot(incident) * normal
+}
+
+/// solid.frag
+fn solid_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let slot: u32 = env.push_constant(MAT4_SIZE);
+    let material: SolidMaterial = env.load(0, 4, slot as usize);
+    let (environment, lights) = lights(env, 5);
+    let pass_normal = vec3(&varyings[0..3]);
+    let pass_fragment_position = vec3(&varyings[3..6]);
+
+    // ambient
+    let ambient = environment.ambient.truncate() * material.ambient.truncate();
+    let normal = pass_normal.normalize();
+    let view_dir = (environment.view_position.truncate() - pass_fragment_position).normalize();
+    // shadow
+    let sun = sunlight(env, pass_fragment_position);
+
+    let mut lit = Vec3::ZERO;
+    for light in lights.iter() {
+        let (light_dir, radiance) = incoming(light, pass_fragment_position, sun);
+        // diffuse
+        let diff = light_dir.dot(normal).max(0.0);
+        let diffuse = diff * material.diffuse.truncate();
+        // specular
+        let reflect_dir = reflect(-light_dir, normal);
+        let spec = view_dir.dot(reflect_dir).max(0.0).powf(material.shininess);
+        let specular = spec * material.specular.truncate();
+        lit += radiance * (diffuse + specular);
+    }
+
+    let color: Vec4 = (ambient + lit).extend(material.specular.w);
+    color.into()
+}
+
+/// instanced.frag, solid.frag multiplied with the tint
+fn instanced_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let color = Vec4::from(solid_fragment(env, varyings));
+    let tint = Vec4::new(varyings[6], varyings[7], varyings[8], varyings[9]);
+    (color * tint).into()
+}
+
+/// post.vert
+fn post_vertex(_env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let [x, y, _, _] = attributes[0];
+    let mut output = VertexOutput::new([x, y, 0.0, 1.0]);
+    output.varyings[0..2].copy_from_slice(&[x * 0.5 + 0.5, y * 0.5 + 0.5]);
+    output
+}
+
+/// bright() of bloom.frag
+fn bright(color: Vec3, threshold: f32) -> Vec3 {
+    let luminance = color.dot(glam::vec3(0.2126, 0.7152, 0.0722));
+    color * (luminance - threshold).max(0.0) / luminance.max(0.0001)
+}
+
+/// bloom.frag
+fn bloom_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let post: PostUniform = env.uniform(0, 0);
+    let texel_size: glam::Vec2 = glam::Vec2::from(env.push_constant::<[f32; 2]>(0));
+    let uv = glam::vec2(varyings[0], varyings[1]);
+
+    let mut sum = Vec3::ZERO;
+    let mut total = 0.0;
+    for x in -1i32..=1 {
+        for y in -1i32..=1 {
+            let weight = ((2 - x.abs()) * (2 - y.abs())) as f32;
+            let offset = glam::vec2(x as f32, y as f32) * 2.0 * texel_size;
+            let hdr = Vec4::from(env.sample(0, 1, (uv + offset).into())).truncate();
+            sum += weight * bright(hdr * post.exposure, post.bloom_threshold);
+            total += weight;
+        }
+    }
+    (sum / total).extend(1.0).into()
+}
+
+/// aces() of tonemap.frag
+fn aces(x: Vec3) -> Vec3 {
+    let curve = |x: f32| ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0);
+    glam::vec3(curve(x.x), curve(x.y), curve(x.z))
+}
+
+fn to_srgb(linear: f32) -> f32 {
+    if linear < 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// grade() of tonemap.frag
+fn grade(env: &ShaderEnv, color: Vec3) -> Vec3 {
+    let size = LUT_SIZE as f32;
+    let coord = glam::vec3(to_srgb(color.x), to_srgb(color.y), to_srgb(color.z)) * (size - 1.0);
+    let slice = coord.z.floor();
+    let next = (slice + 1.0).min(size - 1.0);
+    let uv =
+        (glam::vec2(coord.x, coord.y) + glam::Vec2::splat(0.5)) / glam::vec2(size * size, size);
+    let lut = |slice: f32| {
+        let uv = uv + glam::vec2(slice / size, 0.0);
+        Vec4::from(env.sample_lod(0, 3, uv.into(), 0.0)).truncate()
+    };
+    lut(slice).lerp(lut(next), coord.z - slice)
+}
+
+/// tonemap.frag, the input attachment is sampled at the center of the fragment
+fn tonemap_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let post: PostUniform = env.uniform(0, 0);
+    let uv = [varyings[0], varyings[1]];
+
+    let mut color = Vec4::from(env.sample(0, 1, uv)).truncate() * post.exposure;
+    color += Vec4::from(env.sample(0, 2, uv)).truncate() * post.bloom_intensity;
+    color = if post.tonemapping != 0 {
+        aces(color)
+    } else {
+        color.clamp(Vec3::ZERO, Vec3::ONE)
+    };
+    if post.color_grading != 0 {
+        color = grade(env, color);
+    }
+    color.extend(1.0).into()
+}
+
+/// luma() of fxaa.frag
+fn luma(rgb: Vec3) -> f32 {
+    rgb.dot(glam::vec3(0.299, 0.587, 0.114)).sqrt()
+}
+
+/// fxaa.frag
+fn fxaa_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    const SPAN_MAX: f32 = 8.0;
+    const REDUCE_MUL: f32 = 1.0 / 8.0;
+    const REDUCE_MIN: f32 = 1.0 / 128.0;
+    let post: PostUniform = env.uniform(0, 0);
+    let texel_size: glam::Vec2 = glam::Vec2::from(env.push_constant::<[f32; 2]>(0));
+    let uv = glam::vec2(varyings[0], varyings[1]);
+    let fetch = |offset: glam::Vec2| {
+        Vec4::from(env.sample(0, 1, (uv + offset * texel_size).into())).truncate()
+    };
+
+    let middle = fetch(glam::Vec2::ZERO);
+    if post.fxaa == 0 {
+        return middle.extend(1.0).into();
+    }
+    let nw = luma(fetch(glam::vec2(-1.0, -1.0)));
+    let ne = luma(fetch(glam::vec2(1.0, -1.0)));
+    let sw = luma(fetch(glam::vec2(-1.0, 1.0)));
+    let se = luma(fetch(glam::vec2(1.0, 1.0)));
+    let m = luma(middle);
+    let luma_min = m.min(nw.min(ne).min(sw.min(se)));
+    let luma_max = m.max(nw.max(ne).max(sw.max(se)));
+
+    let dir = glam::vec2((sw + se) - (nw + ne), (nw + sw) - (ne + se));
+    let reduce = ((nw + ne + sw + se) * 0.25 * REDUCE_MUL).max(REDUCE_MIN);
+    let scale = 1.0 / (dir.x.abs().min(dir.y.abs()) + reduce);
+    let dir = (dir * scale).clamp(glam::Vec2::splat(-SPAN_MAX), glam::Vec2::splat(SPAN_MAX));
+
+    let a = 0.5 * (fetch(dir * (1.0 / 3.0 - 0.5)) + fetch(dir * (2.0 / 3.0 - 0.5)));
+    let b = a * 0.5 + 0.25 * (fetch(dir * -0.5) + fetch(dir * 0.5));
+    let luma_b = luma(b);
+    if luma_b < luma_min || luma_b > luma_max {
+        a.extend(1.0).into()
+    } else {
+        b.extend(1.0).into()
+    }
+}
+
+/// debug.vert
+fn debug_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let view_projection: Mat4 = env.push_constant(0);
+    let mut output = VertexOutput::new((view_projection * vec3(&attributes[0]).extend(1.0)).into());
+    output.varyings[0..4].copy_from_slice(&attributes[1]);
+    output
+}
+
+/// debug.frag
+fn debug_fragment(_env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    [varyings[0], varyings[1], varyings[2], varyings[3]]
+}
+
+/// text.vert
+fn text_vertex(_env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let mut output = VertexOutput::new(attributes[0]);
+    output.varyings[0..2].copy_from_slice(&attributes[1][0..2]);
+    output.varyings[2..6].copy_from_slice(&attributes[2]);
+    output
+}
+
+/// text.frag
+fn text_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let coverage = env.sample(0, 0, [varyings[0], varyings[1]])[0];
+    [
+        varyings[2],
+        varyings[3],
+        varyings[4],
+        varyings[5] * coverage,
+    ]
+}
+
+/// widget.vert
+fn widget_vertex(env: &ShaderEnv, attributes: &[[f32; 4]]) -> VertexOutput {
+    let extent: [f32; 2] = env.push_constant(0);
+    let position = [
+        attributes[0][0] / extent[0] * 2.0 - 1.0,
+        attributes[0][1] / extent[1] * 2.0 - 1.0,
+        0.0,
+        1.0,
+    ];
+    let mut output = VertexOutput::new(position);
+    output.varyings[0..3].copy_from_slice(&attributes[1][0..3]);
+    output.varyings[3..7].copy_from_slice(&attributes[2]);
+    output
+}
+
+/// widget.frag
+fn widget_fragment(env: &ShaderEnv, varyings: &Varyings) -> [f32; 4] {
+    let color = [varyings[3], varyings[4], varyings[5], varyings[6]];
+    let kind = varyings[2];
+    if kind < 0.5 {
+        return color;
+    }
+    let texel = env.sample(0, 0, [varyings[0], varyings[1]]);
+    if kind < 1.5 {
+        [color[0], color[1], color[2], color[3] * texel[0]]
+    } else {
+        (Vec4::from(color) * Vec4::from(texel)).into()
+    }
+}
+
+/// Registers the rust versions of the shaders on the software context
+///
+/// These are copies of the GLSL in assets/shaders and nothing keeps the two in sync: the
+/// reference image tests run these functions and never the real shaders. A change to a shader
+/// has to be made here as well, a new shader needs a new function (`pipes_have_software_shaders`
+/// only checks that every shader of a `.pipe` has one)
+pub(crate) fn register_shaders(ctx: &SoftContext) {
+    ctx.register_shader("simple.vert", SoftShader::vertex(simple_vertex));
+    ctx.register_shader("simple.frag", SoftShader::fragment(simple_fragment));
+    ctx.register_shader("solid.vert", SoftShader::vertex(solid_vertex));
+    ctx.register_shader("solid.frag", SoftShader::fragment(solid_fragment));
+    ctx.register_shader("instanced.vert", SoftShader::vertex(instanced_vertex));
+    ctx.register_shader("instanced.frag", SoftShader::fragment(instanced_fragment));
+    ctx.register_shader("pbr.vert", SoftShader::vertex(pbr_vertex));
+    ctx.register_shader("pbr.frag", SoftShader::fragment(pbr_fragment));
+    ctx.register_shader("shadow.vert", SoftShader::vertex(shadow_vertex));
+    ctx.register_shader("shadow.frag", SoftShader::fragment(shadow_fragment));
+    ctx.register_shader("post.vert", SoftShader::vertex(post_vertex));
+    ctx.register_shader("bloom.frag", SoftShader::fragment(bloom_fragment));
+    ctx.register_shader("tonemap.frag", SoftShader::fragment(tonemap_fragment));
+    ctx.register_shader("fxaa.frag", SoftShader::fragment(fxaa_fragment));
+    ctx.register_shader("debug.vert", SoftShader::vertex(debug_vertex));
+    ctx.register_shader("debug.frag", SoftShader::fragment(debug_fragment));
+    ctx.register_shader("text.vert", SoftShader::vertex(text_vertex));
+    ctx.register_shader("text.frag", SoftShader::fragment(text_fragment));
+    ctx.register_shader("widget.vert", SoftShader::vertex(widget_vertex));
+    ctx.register_shader("widget.frag", SoftShader::fragment(widget_fragment));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use render::prelude::{GpuContext, ShaderSource, ShaderType};
+    use soft::MissingShader;
+
+    use super::*;
+    use crate::pipe::Pipeline;
+
+    #[test]
+    fn pipes_have_software_shaders() {
+        let ctx = SoftContext::default();
+        register_shaders(&ctx);
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/shaders");
+        let mut missing = Vec::new();
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("pipe") {
+                continue;
+            }
+            let pipe = Pipeline::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let shaders = &pipe.shaders;
+            let stages = [
+                (Some(&shaders.vertex), ShaderType::Vertex),
+                (Some(&shaders.fragment), ShaderType::Fragment),
+                (shaders.geometry.as_ref(), ShaderType::Geometry),
+            ];
+            for (name, shader_type) in stages.iter() {
+                let name = match name {
+                    Some(name) => name.as_str(),
+                    None => continue,
+                };
+                let source = ShaderSource::GlslSource {
+                    source: "",
+                    shader_type: shader_type.clone(),
+                    name: Some(name),
+                };
+                if let Err(e) = ctx.compile_shader(source) {
+                    assert!(e.downcast_ref::<MissingShader>().is_some(), "{}", e);
+                    missing.push(format!("{} ({:?})", name, path.file_name().unwrap()));
+                }
+            }
+        }
+        assert!(missing.is_empty(), "no software version of {:?}", missing);
+    }
+}
@@ -0,0 +1,607 @@
+//! Text in screen space (`Text`) and world space (`Text2d`)
+//!
+//! Texts are laid out every frame, their glyphs are rasterized into the `FontAtlas` the first time
+//! they are used, which the labels of the ui share. World space texts are drawn into the scene
+//! before the debug pass, so they are hidden behind it and post processed with it. Screen space
+//! texts are drawn after post processing, directly into the backbuffer, so they are neither tone
+//! mapped nor hidden by the scene.
+
+use std::{hash::Hash, sync::Arc};
+
+use app::{AssetHandle, Assets, Resources, World};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec4};
+use render::{
+    graph::{builder::GraphBuilder, node::Node, nodes::callbacks::FrameData},
+    prelude::*,
+    resource::{
+        frame::Extent2D,
+        glue::Mixture,
+        render_pass::{LoadOp, StoreOp},
+        texture::{SamplerDescriptor, WrapMode},
+    },
+    util::format::TextureFormat,
+};
+use rusttype::{GlyphId, Scale};
+
+use crate::{
+    batch::InstanceBuffers,
+    camera::Camera,
+    components::Transform,
+    font::{AtlasFull, Font, GlyphAtlas, ATLAS_SIZE},
+    pipelines::{self, PipelineInstance},
+    renderer::ActiveContext,
+};
+
+type ActiveGraphBuilder = <ActiveContext as GpuContext>::GraphBuilder;
+type AttachmentIndex = <ActiveGraphBuilder as GraphBuilder>::AttachmentIndex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// How a text is drawn, sizes are in pixels
+pub struct TextStyle {
+    pub font: AssetHandle<Font>,
+    /// Distance between the ascent and descent of the font
+    pub size: f32,
+    /// Linear color, the alpha is multiplied with the coverage of the glyphs
+    pub color: Vec4,
+    /// Of the lines inside of the box of the text
+    pub align: TextAlign,
+    /// Lines are wrapped between words to stay below this width
+    pub max_width: Option<f32>,
+}
+
+impl TextStyle {
+    pub fn new(font: AssetHandle<Font>, size: f32, color: Vec4) -> Self {
+        Self {
+            font,
+            size,
+            color,
+            align: TextAlign::Left,
+            max_width: None,
+        }
+    }
+}
+
+/// Text in screen space, with the top left corner of its box at position (in pixels from the top
+/// left of the screen)
+pub struct Text {
+    pub text: String,
+    pub style: TextStyle,
+    pub position: Vec2,
+}
+
+/// Text in world space, the box of the text is centered at the `Transform` of the entity and lies
+/// in its xy plane (y up). A pixel of the style is one unit, scale the transform to size it
+pub struct Text2d {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+/// A glyph with the position of the pen on its baseline, relative to the top left of the text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    pub glyph: GlyphId,
+    pub position: Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<PlacedGlyph>,
+    /// Of the box around all lines, which is max_width wide if there is one
+    pub size: Vec2,
+}
+
+/// Places the characters of a word after the pen (and kerns them against the last glyph), returns
+/// the pen position after the word
+fn place_word(
+    font: &rusttype::Font,
+    scale: Scale,
+    word: &str,
+    mut pen: f32,
+    last: &mut Option<GlyphId>,
+    glyphs: &mut Vec<(GlyphId, f32)>,
+) -> f32 {
+    for c in word.chars() {
+        let glyph = font.glyph(c).id();
+        if let Some(last) = *last {
+            pen += font.pair_kerning(scale, last, glyph);
+        }
+        glyphs.push((glyph, pen));
+        pen += font.glyph(glyph).scaled(scale).h_metrics().advance_width;
+        *last = Some(glyph);
+    }
+    pen
+}
+
+/// Lays out the lines of the text, which are wrapped between words to stay below max_width (single
+/// words that are wider overflow it)
+pub fn layout(
+    font: &rusttype::Font,
+    text: &str,
+    size: f32,
+    align: TextAlign,
+    max_width: Option<f32>,
+) -> TextLayout {
+    let scale = Scale::uniform(size);
+    let v_metrics = font.v_metrics(scale);
+    let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+
+    // Glyphs of every line with their pen positions, and the width of the line
+    let mut lines: Vec<(Vec<(GlyphId, f32)>, f32)> = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = Vec::new();
+        let mut width = 0.0;
+        let mut last = None;
+        for (i, word) in paragraph.split(' ').enumerate() {
+            // The space in front of the word is dropped if it starts a new line
+            let spaced = if i > 0 {
+                format!(" {}", word)
+            } else {
+                word.to_owned()
+            };
+            let mut placed = Vec::new();
+            let mut word_last = last;
+            let mut end = place_word(font, scale, &spaced, width, &mut word_last, &mut placed);
+            if matches!(max_width, Some(max) if end > max) && !line.is_empty() {
+                lines.push((std::mem::take(&mut line), width));
+                placed.clear();
+                word_last = None;
+                end = place_word(font, scale, word, 0.0, &mut word_last, &mut placed);
+            }
+            line.extend(placed);
+            last = word_last;
+            width = end;
+        }
+        lines.push((line, width));
+    }
+
+    let widest = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+    let box_width = max_width.unwrap_or(widest);
+    let mut glyphs = Vec::new();
+    for (i, (line, width)) in lines.iter().enumerate() {
+        let offset = match align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (box_width - width) * 0.5,
+            TextAlign::Right => box_width - width,
+        };
+        let baseline = v_metrics.ascent + i as f32 * line_height;
+        glyphs.extend(line.iter().map(|(glyph, pen)| PlacedGlyph {
+            glyph: *glyph,
+            position: glam::vec2(offset + pen, baseline),
+        }));
+    }
+    TextLayout {
+        glyphs,
+        size: glam::vec2(box_width, lines.len() as f32 * line_height),
+    }
+}
+
+/// Vertex of `text.pipe`, positions are in clip space
+#[derive(Debug, Clone, Copy, PartialEq, Zeroable, Pod)]
+#[repr(C)]
+pub struct TextVertex {
+    pub position: [f32; 4],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Where the quads of a laid out text end up
+enum Placement {
+    /// Top left corner in pixels
+    Screen(Vec2),
+    /// Model matrix and the center of the box
+    World(Mat4, Vec2),
+}
+
+/// A laid out text of a frame
+struct TextDraw {
+    font: AssetHandle<Font>,
+    size: f32,
+    color: Vec4,
+    glyphs: Vec<PlacedGlyph>,
+    placement: Placement,
+}
+
+/// Texts with a loaded font, of `Text2d` in world space and of `Text` otherwise
+fn gather(world: &World, fonts: &Assets<Font>, world_space: bool) -> Vec<TextDraw> {
+    let mut draws = Vec::new();
+    let mut push = |text: &str, style: &TextStyle, placement: &dyn Fn(Vec2) -> Placement| {
+        if let Some(font) = fonts.try_get(&style.font) {
+            let layout = layout(&font.0, text, style.size, style.align, style.max_width);
+            draws.push(TextDraw {
+                font: style.font.as_weak(),
+                size: style.size,
+                color: style.color,
+                glyphs: layout.glyphs,
+                placement: placement(layout.size),
+            });
+        }
+    };
+    if !world_space {
+        for (_e, text) in world.query::<&Text>().iter() {
+            push(&text.text, &text.style, &|_| {
+                Placement::Screen(text.position)
+            });
+        }
+        return draws;
+    }
+    for (_e, (text, transform)) in world.query::<(&Text2d, &Transform)>().iter() {
+        push(&text.text, &text.style, &|size| {
+            Placement::World(transform.into_model(), size * 0.5)
+        });
+    }
+    draws
+}
+
+/// Glyphs of a laid out text, in a font and size
+pub(crate) struct GlyphRun<'a> {
+    pub font: &'a AssetHandle<Font>,
+    pub size: f32,
+    pub glyphs: &'a [PlacedGlyph],
+}
+
+/// Rasterizes the glyphs of every run, the atlas grows until they fit
+pub(crate) fn rasterize(
+    atlas: &mut GlyphAtlas<AssetHandle<Font>>,
+    runs: &[GlyphRun],
+    fonts: &Assets<Font>,
+) {
+    loop {
+        let inserted: Result<(), AtlasFull> = runs.iter().try_for_each(|run| {
+            let font = match fonts.try_get(run.font) {
+                Some(font) => font,
+                None => return Ok(()),
+            };
+            for placed in run.glyphs.iter() {
+                atlas.insert(run.font.as_weak(), &font.0, placed.glyph, run.size)?;
+            }
+            Ok(())
+        });
+        if inserted.is_ok() {
+            return;
+        }
+        if !atlas.grow() {
+            log::warn!("[Text] glyph atlas is full, some glyphs are not drawn");
+            return;
+        }
+    }
+}
+
+/// Uploads the texels of the atlas into a new texture (textures of older generations may still be
+/// in use by frames in flight)
+fn create_atlas_texture<K: Hash + Eq>(
+    gpu: &GpuResources<ActiveContext>,
+    atlas: &GlyphAtlas<K>,
+) -> Texture<ActiveContext> {
+    gpu.create_texture(
+        TextureDescriptor {
+            name: "Glyph Atlas".into(),
+            extent: Extent2D {
+                width: atlas.size(),
+                height: atlas.size(),
+            },
+            format: TextureFormat::R8Unorm,
+            mip_levels: 1,
+            storage: false,
+        },
+        &[atlas.texels()],
+    )
+}
+
+/// The glyph atlas of every text and label, with the texture of its current generation
+pub(crate) struct FontAtlas {
+    pub atlas: GlyphAtlas<AssetHandle<Font>>,
+    texture: Option<(u64, Arc<Texture<ActiveContext>>)>,
+}
+
+impl Default for FontAtlas {
+    fn default() -> Self {
+        Self {
+            atlas: GlyphAtlas::new(ATLAS_SIZE),
+            texture: None,
+        }
+    }
+}
+
+impl FontAtlas {
+    /// The texture of the current generation, uploaded by the first pass that needs it
+    pub fn texture(&mut self, gpu: &GpuResources<ActiveContext>) -> Arc<Texture<ActiveContext>> {
+        let generation = self.atlas.generation();
+        match &self.texture {
+            Some((uploaded, texture)) if *uploaded == generation => texture.clone(),
+            _ => {
+                let texture = Arc::new(create_atlas_texture(gpu, &self.atlas));
+                self.texture = Some((generation, texture.clone()));
+                texture
+            }
+        }
+    }
+}
+
+/// Two triangles per glyph that is in the atlas
+fn vertices(
+    atlas: &GlyphAtlas<AssetHandle<Font>>,
+    draws: &[TextDraw],
+    extent: Vec2,
+    view_projection: &Mat4,
+) -> Vec<TextVertex> {
+    let atlas_size = atlas.size() as f32;
+    let mut vertices = Vec::new();
+    for draw in draws.iter() {
+        let color = draw.color.into();
+        for placed in draw.glyphs.iter() {
+            let glyph = match atlas.get(draw.font.as_weak(), placed.glyph, draw.size) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let min = placed.position.round()
+                + glam::vec2(glyph.offset[0] as f32, glyph.offset[1] as f32);
+            let size = glam::vec2(glyph.size[0] as f32, glyph.size[1] as f32);
+            let uv_min = glam::vec2(glyph.min[0] as f32, glyph.min[1] as f32) / atlas_size;
+            let uv_size = size / atlas_size;
+
+            let corner = |x: f32, y: f32| {
+                let point = min + size * glam::vec2(x, y);
+                let position = match &draw.placement {
+                    Placement::Screen(origin) => {
+                        let ndc = (*origin + point) / extent * 2.0 - Vec2::ONE;
+                        glam::vec4(ndc.x, ndc.y, 0.0, 1.0)
+                    }
+                    Placement::World(model, center) => {
+                        let local = point - *center;
+                        *view_projection * *model * glam::vec4(local.x, -local.y, 0.0, 1.0)
+                    }
+                };
+                TextVertex {
+                    position: position.into(),
+                    uv: (uv_min + uv_size * glam::vec2(x, y)).into(),
+                    color,
+                }
+            };
+            let (a, b, c, d) = (
+                corner(0.0, 0.0),
+                corner(1.0, 0.0),
+                corner(0.0, 1.0),
+                corner(1.0, 1.0),
+            );
+            vertices.extend_from_slice(&[a, b, c, c, b, d]);
+        }
+    }
+    vertices
+}
+
+/// The atlas texture of a generation of the atlas, with its glue
+struct AtlasTexture {
+    generation: u64,
+    mixture: Arc<Mixture<ActiveContext>>,
+    texture: Arc<Texture<ActiveContext>>,
+    glue: Arc<Glue<ActiveContext>>,
+}
+
+/// Where a text pass draws to
+enum TextTarget {
+    /// Screen space texts, on top of the backbuffer
+    Screen(AttachmentIndex),
+    /// World space texts, into the color of the scene and tested against its depth
+    World {
+        scene: AttachmentIndex,
+        depth: AttachmentIndex,
+        samples: u8,
+    },
+}
+
+/// Adds the world text pass, that draws into the scene before the debug pass
+pub(crate) fn init_world(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    scene: AttachmentIndex,
+    depth: AttachmentIndex,
+    samples: u8,
+) {
+    let target = TextTarget::World {
+        scene,
+        depth,
+        samples,
+    };
+    add_pass(resources, graph_builder, gpu, target);
+}
+
+/// Adds the text pass, that draws on top of the backbuffer
+pub(crate) fn init(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    backbuffer: AttachmentIndex,
+) {
+    add_pass(
+        resources,
+        graph_builder,
+        gpu,
+        TextTarget::Screen(backbuffer),
+    );
+}
+
+fn add_pass(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    target: TextTarget,
+) {
+    let (name, path) = match target {
+        TextTarget::Screen(_) => ("text_pass", "assets/shaders/text.pipe"),
+        TextTarget::World { .. } => ("world_text_pass", "assets/shaders/text_world.pipe"),
+    };
+    let pipe = pipelines::load(resources, path);
+    let frames_in_flight = graph_builder.get_swapchain_image_count();
+    let ctx = resources
+        .get::<Arc<ActiveContext>>()
+        .expect("[Artisan] failed to get context")
+        .clone();
+    let mut vertex_buffers =
+        InstanceBuffers::new(ctx, frames_in_flight).with_name("text-vertex-buffer");
+    let mut atlas_texture: Option<AtlasTexture> = None;
+    let sampler = gpu.create_sampler(&SamplerDescriptor {
+        wrap: WrapMode::ClampToEdge,
+        ..SamplerDescriptor::LINEAR
+    });
+
+    let mut builder = graph_builder.build_pass_node(name.into());
+    let (world_space, samples) = match target {
+        TextTarget::Screen(backbuffer) => {
+            builder.add_output(backbuffer, LoadOp::Load, StoreOp::Store);
+            (false, 1)
+        }
+        TextTarget::World {
+            scene,
+            depth,
+            samples,
+        } => {
+            // The debug pass draws on top and resolves the scene
+            builder.add_output(scene, LoadOp::Load, StoreOp::Store);
+            builder.set_depth(depth, LoadOp::Load, StoreOp::Store);
+            (true, samples)
+        }
+    };
+    let init_gpu = gpu.clone();
+    builder.init(Box::new(move |render_pass| {
+        Box::new(PipelineInstance::new(&pipe, init_gpu.clone(), render_pass).with_samples(samples))
+    }));
+    builder.callback(Box::new(move |frame, instance, world, resources| {
+        let FrameData {
+            cmd,
+            frame_index,
+            viewport,
+            ..
+        } = frame;
+        let fonts = resources.get::<Assets<Font>>()?;
+        let draws = gather(world, &fonts, world_space);
+        if draws.is_empty() {
+            return Ok(None);
+        }
+        let pipeline = match instance.get(resources) {
+            Some(pipeline) => pipeline,
+            None => return Ok(None),
+        };
+
+        let runs: Vec<GlyphRun> = draws
+            .iter()
+            .map(|draw| GlyphRun {
+                font: &draw.font,
+                size: draw.size,
+                glyphs: &draw.glyphs,
+            })
+            .collect();
+        let mut font_atlas = resources.get_mut::<FontAtlas>()?;
+        rasterize(&mut font_atlas.atlas, &runs, &fonts);
+        let extent = glam::vec2(viewport.rect.width as f32, viewport.rect.height as f32);
+        let view_projection = resources
+            .get::<Camera>()?
+            .view_projection(extent.x / extent.y);
+        let vertices = vertices(&font_atlas.atlas, &draws, extent, &view_projection);
+        if vertices.is_empty() {
+            return Ok(None);
+        }
+
+        // Frames in flight keep the texture of their generation alive
+        let outdated = !matches!(
+            &atlas_texture,
+            Some(texture) if texture.generation == font_atlas.atlas.generation()
+                && Arc::ptr_eq(&texture.mixture, &pipeline.mixture)
+        );
+        if outdated {
+            let texture = font_atlas.texture(&gpu);
+            let mut glue_bottle = gpu.bottle(&pipeline.mixture);
+            glue_bottle.write_texture(
+                PartIndex::Name("atlas".into()),
+                texture.get_handle(),
+                &sampler,
+            );
+            atlas_texture = Some(AtlasTexture {
+                generation: font_atlas.atlas.generation(),
+                mixture: pipeline.mixture.clone(),
+                glue: Arc::new(glue_bottle.apply()),
+                texture,
+            });
+        }
+        let texture = atlas_texture.as_ref().unwrap();
+
+        let vertex_buffer = vertex_buffers.write(frame_index as usize, &vertices);
+        cmd.bind_graphics_pipeline(&pipeline);
+        cmd.set_viewport(0, viewport.clone());
+        cmd.set_scissor(0, viewport.rect);
+        cmd.snort_glue(0, &pipeline, &texture.glue);
+        cmd.bind_vertex_buffer(0, vertex_buffer, BufferRange::WHOLE);
+        cmd.draw(0..vertices.len() as u32, 0..1);
+
+        // Keep everything alive, until the frame is finished
+        Ok(Some(Box::new((
+            pipeline,
+            texture.texture.clone(),
+            texture.glue.clone(),
+        ))))
+    }));
+    graph_builder.add_node(Node::PassNode(builder.build()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipelines::tests::validated_pipe;
+
+    fn font() -> rusttype::Font<'static> {
+        let bytes = include_bytes!("../../../assets/fonts/FiraCode-Regular.ttf");
+        rusttype::Font::try_from_vec(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn lines_are_wrapped_and_aligned() {
+        let font = font();
+        let size = 20.0;
+        // Fira code is monospaced
+        let advance = font
+            .glyph('a')
+            .scaled(Scale::uniform(size))
+            .h_metrics()
+            .advance_width;
+
+        let single = layout(&font, "ab cd", size, TextAlign::Left, None);
+        assert_eq!(single.glyphs.len(), 5);
+        assert_eq!(single.size.x, 5.0 * advance);
+        assert_eq!(single.glyphs[4].position.x, 4.0 * advance);
+
+        // The second word does not fit, so it starts the next line without its space
+        let wrapped = layout(&font, "ab cd", size, TextAlign::Right, Some(3.5 * advance));
+        assert_eq!(wrapped.glyphs.len(), 4);
+        assert_eq!(wrapped.size.x, 3.5 * advance);
+        assert_eq!(wrapped.size.y, 2.0 * single.size.y);
+        let (first, second) = (wrapped.glyphs[0].position, wrapped.glyphs[2].position);
+        assert!((first.x - 1.5 * advance).abs() < 1e-3);
+        assert!((second.x - 1.5 * advance).abs() < 1e-3);
+        assert!(second.y > first.y);
+
+        let centered = layout(&font, "a\nabc", size, TextAlign::Center, None);
+        assert!((centered.glyphs[0].position.x - advance).abs() < 1e-3);
+        assert_eq!(centered.glyphs[1].position.x, 0.0);
+    }
+
+    #[test]
+    fn text_pipes_match_vertex() {
+        for (name, depth_tested) in [("text", false), ("text_world", true)].iter() {
+            let (pipe, parts) = validated_pipe(name);
+            assert_eq!(
+                pipe.vertex_buffers()[0].stride as usize,
+                std::mem::size_of::<TextVertex>()
+            );
+            assert_eq!(parts.len(), 1);
+            assert_eq!(parts[0].name, "atlas");
+            assert_eq!(pipe.depth.is_some(), *depth_tested);
+        }
+    }
+}
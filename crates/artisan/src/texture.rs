@@ -0,0 +1,269 @@
+use std::{any::TypeId, convert::TryInto, sync::Arc};
+
+use app::core::anyhow::{anyhow, ensure, Context, Result};
+use app::{App, AssetLoader};
+use render::{
+    prelude::{GpuResources, Texture, TextureDescriptor},
+    resource::{
+        frame::Extent2D,
+        texture::{mip_extent, mip_level_count},
+    },
+    util::format::TextureFormat,
+};
+
+use crate::renderer::ActiveContext;
+
+/// A decoded image, with at least the first mip level
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    pub extent: Extent2D,
+    pub format: TextureFormat,
+    /// Tightly packed texels of every level that is stored in the file
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl ImageData {
+    /// Decodes png and jpeg files (with the image crate) and uncompressed ktx2 files
+    pub fn decode(bytes: &[u8], extension: &str) -> Result<Self> {
+        match extension {
+            "ktx2" => Self::decode_ktx2(bytes),
+            _ => {
+                let image = image::load_from_memory(bytes)
+                    .context("[ImageData] failed to decode image")?
+                    .to_rgba8();
+                let (width, height) = image.dimensions();
+                Ok(Self {
+                    extent: Extent2D { width, height },
+                    format: TextureFormat::Rgba8Srgb,
+                    levels: vec![image.into_raw()],
+                })
+            }
+        }
+    }
+
+    fn decode_ktx2(bytes: &[u8]) -> Result<Self> {
+        const IDENTIFIER: [u8; 12] = [
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        // Identifier, 9 u32 fields and the dfd, kvd, sgd index
+        const LEVEL_INDEX: usize = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+
+        ensure!(
+            bytes.len() >= LEVEL_INDEX && bytes[..12] == IDENTIFIER,
+            "[ImageData] not a ktx2 file"
+        );
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        let vk_format = u32_at(12);
+        let format = match vk_format {
+            9 => TextureFormat::R8Unorm,
+            16 => TextureFormat::Rg8Unorm,
+            37 => TextureFormat::Rgba8Unorm,
+            43 => TextureFormat::Rgba8Srgb,
+            44 => TextureFormat::Bgra8Unorm,
+            50 => TextureFormat::Bgra8Srgb,
+            97 => TextureFormat::Rgba16Sfloat,
+            100 => TextureFormat::R32Sfloat,
+            109 => TextureFormat::Rgba32Sfloat,
+            _ => return Err(anyhow!("[ImageData] unsupported ktx2 format {}", vk_format)),
+        };
+        let extent = Extent2D {
+            width: u32_at(20),
+            height: u32_at(24).max(1),
+        };
+        ensure!(extent.width > 0, "[ImageData] ktx2 file without a width");
+        let (depth, layers, faces) = (u32_at(28), u32_at(32), u32_at(36));
+        ensure!(
+            depth <= 1 && layers <= 1 && faces == 1,
+            "[ImageData] only 2d ktx2 textures are supported"
+        );
+        let level_count = u32_at(40).max(1);
+        ensure!(
+            u32_at(44) == 0,
+            "[ImageData] supercompressed ktx2 files are not supported"
+        );
+        ensure!(
+            level_count <= mip_level_count(&extent),
+            "[ImageData] ktx2 file has more levels than its extent allows"
+        );
+
+        let levels = (0..level_count as usize)
+            .map(|level| {
+                let index = LEVEL_INDEX + level * 24;
+                ensure!(
+                    bytes.len() >= index + 24,
+                    "[ImageData] truncated ktx2 level index"
+                );
+                let (offset, length) = (u64_at(index) as usize, u64_at(index + 8) as usize);
+                let mip = mip_extent(&extent, level as u32);
+                let expected = mip.width as usize * mip.height as usize * format.texel_size();
+                ensure!(
+                    length == expected,
+                    "[ImageData] ktx2 level {} has {} bytes instead of {}",
+                    level,
+                    length,
+                    expected
+                );
+                offset
+                    .checked_add(length)
+                    .and_then(|end| bytes.get(offset..end))
+                    .map(|level| level.to_vec())
+                    .ok_or_else(|| anyhow!("[ImageData] ktx2 level {} is out of bounds", level))
+            })
+            .collect::<Result<Vec<Vec<u8>>>>()?;
+
+        Ok(Self {
+            extent,
+            format,
+            levels,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TextureAsset(pub Texture<ActiveContext>);
+
+impl TextureAsset {
+    /// Uploads the image, images without a mip chain get one generated on upload
+    pub fn from_image(
+        resources: &GpuResources<ActiveContext>,
+        name: impl Into<String>,
+        image: ImageData,
+    ) -> Self {
+        let mip_levels = match image.levels.len() {
+            1 => mip_level_count(&image.extent),
+            count => count as u32,
+        };
+        let desc = TextureDescriptor {
+            name: name.into().into(),
+            extent: image.extent,
+            format: image.format,
+            mip_levels,
+            storage: false,
+        };
+        let levels: Vec<&[u8]> = image.levels.iter().map(|l| l.as_slice()).collect();
+        Self(resources.create_texture(desc, &levels))
+    }
+
+    /// Memory of all mip levels
+    fn size_bytes(&self) -> usize {
+        let desc = self.0.desc();
+        (0..desc.mip_levels)
+            .map(|level| desc.level_size(level))
+            .sum()
+    }
+}
+
+pub struct ImageLoader {
+    resources: Arc<GpuResources<ActiveContext>>,
+}
+
+impl AssetLoader for ImageLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        ctx: app::LoadContext<'a>,
+    ) -> app::BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let extension = ctx
+                .path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            let image = ImageData::decode(bytes, extension)
+                .context(format!("[ImageLoader] failed to load {:?}", ctx.path))?;
+            let texture =
+                TextureAsset::from_image(&self.resources, ctx.path.to_string_lossy(), image);
+
+            ctx.send_asset(texture).await;
+
+            Ok(())
+        })
+    }
+
+    fn ext(&self) -> &[&str] {
+        &["png", "jpg", "jpeg", "ktx2"]
+    }
+
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<TextureAsset>()
+    }
+}
+
+pub(crate) fn init(app: &mut App, resources: Arc<GpuResources<ActiveContext>>) {
+    app.register_asset::<TextureAsset>();
+    app.set_asset_size_bytes::<TextureAsset>(TextureAsset::size_bytes);
+    app.add_asset_loader(ImageLoader { resources });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uncompressed ktx2 file with the given levels
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+        let mut header: Vec<u8> = vec![
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        let fields = [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0];
+        fields
+            .iter()
+            .for_each(|f| header.extend_from_slice(&f.to_le_bytes()));
+        // No dfd, kvd or sgd
+        header.extend_from_slice(&[0u8; 4 * 4 + 2 * 8]);
+
+        let mut offset = (header.len() + levels.len() * 24) as u64;
+        for level in levels {
+            for value in [offset, level.len() as u64, level.len() as u64].iter() {
+                header.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += level.len() as u64;
+        }
+        levels.iter().for_each(|l| header.extend_from_slice(l));
+        header
+    }
+
+    #[test]
+    fn decodes_images() {
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png)
+            .encode(
+                &[255, 0, 0, 255, 0, 0, 255, 255],
+                2,
+                1,
+                image::ColorType::Rgba8,
+            )
+            .unwrap();
+        let image = ImageData::decode(&png, "png").unwrap();
+        assert_eq!((image.extent.width, image.extent.height), (2, 1));
+        assert_eq!(image.format, TextureFormat::Rgba8Srgb);
+        assert_eq!(image.levels, vec![vec![255, 0, 0, 255, 0, 0, 255, 255]]);
+
+        let (level0, level1) = ([7u8; 16], [9u8; 4]);
+        let file = ktx2(37, 2, 2, &[&level0, &level1]);
+        let image = ImageData::decode(&file, "ktx2").unwrap();
+        assert_eq!(image.format, TextureFormat::Rgba8Unorm);
+        assert_eq!(image.levels, vec![level0.to_vec(), level1.to_vec()]);
+
+        // Block compressed formats (here BC7) are rejected
+        assert!(ImageData::decode(&ktx2(145, 4, 4, &[&[0u8; 16]]), "ktx2").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_ktx2_levels() {
+        // The level is too short for its extent
+        assert!(ImageData::decode(&ktx2(37, 2, 2, &[&[0u8; 12]]), "ktx2").is_err());
+        // More levels than a 2x2 texture has
+        let levels: [&[u8]; 3] = [&[0u8; 16], &[0u8; 4], &[0u8; 4]];
+        assert!(ImageData::decode(&ktx2(37, 2, 2, &levels), "ktx2").is_err());
+
+        // An offset that overflows the end of the level
+        let mut file = ktx2(37, 1, 1, &[&[0u8; 4]]);
+        let index = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+        file[index..index + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(ImageData::decode(&file, "ktx2").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Hit testing, focus and the input of the widgets
+
+use app::{Entity, World};
+use glam::Vec2;
+use window::{
+    events::{MouseButton, VirtualKeyCode},
+    input::Input,
+};
+
+use super::{Interaction, List, Node, Slider, UiState};
+
+/// Pixels a list scrolls per line of the mouse wheel, or per key press
+const SCROLL_STEP: f32 = 24.0;
+/// Share of the range a focused slider moves per key press
+const SLIDER_STEP: f32 = 0.05;
+
+/// What the ui reacts to in a frame
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct UiInput {
+    pub cursor: Vec2,
+    /// Scrolled lines, positive when scrolling up
+    pub scroll: f32,
+    /// State of the left mouse button
+    pub pressed: bool,
+    pub just_pressed: bool,
+    pub just_released: bool,
+    /// Moves the focus to the next interactive node
+    pub next_focus: bool,
+    /// Clicks the focused node
+    pub activate: bool,
+    /// Arrow keys pressed this frame, x points right and y down
+    pub arrows: Vec2,
+}
+
+impl UiInput {
+    pub fn from_input(input: &Input) -> Self {
+        let key = |key| {
+            if input.is_just_pressed(key) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        Self {
+            cursor: input.mouse_pos,
+            scroll: input.scroll_delta.y,
+            pressed: input.is_mouse_pressed(MouseButton::Left),
+            just_pressed: input.is_mouse_just_pressed(MouseButton::Left),
+            just_released: input.is_mouse_just_released(MouseButton::Left),
+            next_focus: input.is_just_pressed(VirtualKeyCode::Tab),
+            // Space moves the camera
+            activate: input.is_just_pressed(VirtualKeyCode::Return),
+            arrows: glam::vec2(
+                key(VirtualKeyCode::Right) - key(VirtualKeyCode::Left),
+                key(VirtualKeyCode::Down) - key(VirtualKeyCode::Up),
+            ),
+        }
+    }
+}
+
+fn scroll(world: &World, entity: Entity, pixels: f32) {
+    if let Ok(mut list) = world.get_mut::<List>(entity) {
+        list.scroll = (list.scroll + pixels).clamp(0.0, list.max_scroll);
+    }
+}
+
+/// Updates the hovered, pressed and focused node and the widgets they belong to
+pub(crate) fn interact(world: &World, state: &mut UiState, input: &UiInput) {
+    let interactive: Vec<Entity> = state
+        .order
+        .iter()
+        .copied()
+        .filter(|entity| world.get::<Interaction>(*entity).is_ok())
+        .collect();
+    // Despawned (or hidden) nodes lose the focus
+    let placed = |entity: &Entity| interactive.contains(entity);
+    state.focused = state.focused.filter(placed);
+    state.pressed = state.pressed.filter(placed);
+
+    let hit = |entity: Entity| {
+        world
+            .get::<Node>(entity)
+            .map(|node| node.rect.intersect(&node.clip).contains(input.cursor))
+            .unwrap_or(false)
+    };
+    // Later nodes are drawn on top
+    state.hovered = interactive.iter().rev().copied().find(|e| hit(*e));
+
+    let mut clicked = None;
+    if input.just_pressed {
+        state.pressed = state.hovered;
+        state.focused = state.hovered;
+    }
+    if let Some(pressed) = state.pressed {
+        if input.pressed {
+            // Sliders follow the cursor while they are dragged
+            let rect = world.get::<Node>(pressed).map(|node| node.rect);
+            if let (Ok(mut slider), Ok(rect)) = (world.get_mut::<Slider>(pressed), rect) {
+                if rect.size.x > 0.0 {
+                    let fraction = ((input.cursor.x - rect.min.x) / rect.size.x).clamp(0.0, 1.0);
+                    slider.value = slider.min + fraction * (slider.max - slider.min);
+                }
+            }
+        } else {
+            if input.just_released && state.hovered == Some(pressed) {
+                clicked = Some(pressed);
+            }
+            state.pressed = None;
+        }
+    }
+
+    if input.next_focus && !interactive.is_empty() {
+        let next = state
+            .focused
+            .and_then(|focused| interactive.iter().position(|e| *e == focused))
+            .map(|i| (i + 1) % interactive.len())
+            .unwrap_or(0);
+        state.focused = Some(interactive[next]);
+    }
+    if let Some(focused) = state.focused {
+        if input.activate {
+            clicked = Some(focused);
+        }
+        if input.arrows.x != 0.0 {
+            if let Ok(mut slider) = world.get_mut::<Slider>(focused) {
+                let step = input.arrows.x * SLIDER_STEP * (slider.max - slider.min);
+                slider.value = (slider.value + step).clamp(slider.min, slider.max);
+            }
+        }
+        if input.arrows.y != 0.0 {
+            scroll(world, focused, input.arrows.y * SCROLL_STEP);
+        }
+    }
+    if input.scroll != 0.0 {
+        let list = state
+            .order
+            .iter()
+            .rev()
+            .copied()
+            .find(|e| world.get::<List>(*e).is_ok() && hit(*e));
+        if let Some(list) = list {
+            scroll(world, list, -input.scroll * SCROLL_STEP);
+        }
+    }
+
+    if let Some(clicked) = clicked {
+        for (_e, (node, list)) in world.query::<(&Node, &mut List)>().iter() {
+            if node.children.contains(&clicked) {
+                list.selected = Some(clicked);
+            }
+        }
+    }
+    for (entity, interaction) in world.query::<&mut Interaction>().iter() {
+        *interaction = Interaction {
+            hovered: state.hovered == Some(entity),
+            pressed: state.pressed == Some(entity),
+            clicked: clicked == Some(entity),
+            focused: state.focused == Some(entity),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{layout::Rect, Style};
+
+    fn interactive(world: &mut World, rect: Rect) -> Entity {
+        let mut node = Node::new(Style::default());
+        node.rect = rect;
+        node.clip = Rect::new(Vec2::ZERO, glam::vec2(100.0, 100.0));
+        world.spawn((node, Interaction::default()))
+    }
+
+    #[test]
+    fn clicks_focus_the_top_most_node() {
+        let mut world = World::new();
+        let full = Rect::new(Vec2::ZERO, glam::vec2(100.0, 100.0));
+        let below = interactive(&mut world, full);
+        let above = interactive(&mut world, Rect::new(Vec2::ZERO, glam::vec2(50.0, 50.0)));
+        let mut state = UiState {
+            order: vec![below, above],
+            ..Default::default()
+        };
+        let interaction = |entity| *world.get::<Interaction>(entity).unwrap();
+
+        let mut input = UiInput {
+            cursor: glam::vec2(10.0, 10.0),
+            pressed: true,
+            just_pressed: true,
+            ..Default::default()
+        };
+        interact(&world, &mut state, &input);
+        assert_eq!(state.hovered(), Some(above));
+        assert!(state.wants_mouse());
+        assert!(interaction(above).pressed && interaction(above).focused);
+        assert!(!interaction(below).hovered);
+
+        // Released over the node that was pressed
+        input = UiInput {
+            cursor: glam::vec2(20.0, 20.0),
+            just_released: true,
+            ..Default::default()
+        };
+        interact(&world, &mut state, &input);
+        assert!(interaction(above).clicked);
+
+        // The click is only there for a frame, the focus moves with tab
+        input.just_released = false;
+        input.next_focus = true;
+        interact(&world, &mut state, &input);
+        assert!(!interaction(above).clicked);
+        assert_eq!(state.focused(), Some(below));
+
+        // Outside of the clip of the nodes
+        input.cursor = glam::vec2(150.0, 10.0);
+        input.next_focus = false;
+        interact(&world, &mut state, &input);
+        assert!(!state.wants_mouse());
+    }
+}
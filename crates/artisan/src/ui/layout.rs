@@ -0,0 +1,455 @@
+//! Flexbox like layout of the nodes
+//!
+//! The preferred size of every node is measured bottom up (from the content of its widget or its
+//! children), then the nodes are placed top down. Children in the flow are placed one after
+//! another along the main axis of their parent, free space is shared by their grow factors or
+//! distributed by the justification of the parent.
+
+use std::collections::{HashMap, HashSet};
+
+use app::{Assets, Entity, World};
+use glam::Vec2;
+
+use super::{Align, Dimension, Direction, Icon, Justify, Label, List, Node, Slider};
+use crate::{font::Font, text, texture::TextureAsset};
+
+/// Size of sliders without a size
+const SLIDER_SIZE: [f32; 2] = [160.0, 20.0];
+
+/// An axis aligned rectangle, in pixels from the top left of the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub min: Vec2,
+    pub size: Vec2,
+}
+
+impl Rect {
+    pub fn new(min: Vec2, size: Vec2) -> Self {
+        Self { min, size }
+    }
+
+    pub fn max(&self) -> Vec2 {
+        self.min + self.size
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmplt(self.max()).all()
+    }
+
+    /// The overlap of both, which is empty if they do not overlap
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let min = self.min.max(other.min);
+        let max = self.max().min(other.max());
+        Rect::new(min, (max - min).max(Vec2::ZERO))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.x <= 0.0 || self.size.y <= 0.0
+    }
+}
+
+/// Component of the vector along the main axis
+fn main(v: Vec2, direction: Direction) -> f32 {
+    match direction {
+        Direction::Row => v.x,
+        Direction::Column => v.y,
+    }
+}
+
+fn cross(v: Vec2, direction: Direction) -> f32 {
+    match direction {
+        Direction::Row => v.y,
+        Direction::Column => v.x,
+    }
+}
+
+/// The vector with the components along the main and cross axis
+fn along(direction: Direction, main: f32, cross: f32) -> Vec2 {
+    match direction {
+        Direction::Row => glam::vec2(main, cross),
+        Direction::Column => glam::vec2(cross, main),
+    }
+}
+
+fn resolve(dimension: Dimension, available: f32, preferred: f32) -> f32 {
+    match dimension {
+        Dimension::Auto => preferred,
+        Dimension::Px(px) => px,
+        Dimension::Percent(percent) => available * percent / 100.0,
+    }
+}
+
+/// Sizes of the widgets, labels are laid out on the way
+fn measure_widgets(
+    world: &World,
+    fonts: Option<&Assets<Font>>,
+    textures: Option<&Assets<TextureAsset>>,
+) -> HashMap<Entity, Vec2> {
+    let mut contents = HashMap::new();
+    for (entity, label) in world.query::<&mut Label>().iter() {
+        let font = fonts.and_then(|fonts| fonts.try_get(&label.style.font));
+        let size = match font {
+            Some(font) => {
+                let style = &label.style;
+                let layout = text::layout(
+                    &font.0,
+                    &label.text,
+                    style.size,
+                    style.align,
+                    style.max_width,
+                );
+                label.glyphs = layout.glyphs;
+                layout.size
+            }
+            // Until the font is loaded
+            None => {
+                label.glyphs.clear();
+                Vec2::ZERO
+            }
+        };
+        contents.insert(entity, size);
+    }
+    for (entity, icon) in world.query::<&Icon>().iter() {
+        let texture = textures.and_then(|textures| textures.try_get(&icon.image));
+        let size = texture
+            .map(|texture| {
+                let extent = &texture.0.desc().extent;
+                glam::vec2(extent.width as f32, extent.height as f32)
+            })
+            .unwrap_or(Vec2::ZERO);
+        contents.insert(entity, size);
+    }
+    for (entity, _slider) in world.query::<&Slider>().iter() {
+        contents.insert(entity, SLIDER_SIZE.into());
+    }
+    contents
+}
+
+/// Preferred size of the node (without its margin), percentages are treated as auto
+fn measure(
+    world: &World,
+    entity: Entity,
+    contents: &HashMap<Entity, Vec2>,
+    preferred: &mut HashMap<Entity, Vec2>,
+) -> Vec2 {
+    let node = match world.get::<Node>(entity) {
+        Ok(node) => node,
+        Err(_) => return Vec2::ZERO,
+    };
+    let style = &node.style;
+    let direction = style.direction;
+    let (mut main_size, mut cross_size, mut count) = (0.0, 0.0f32, 0);
+    for child in node.children.iter() {
+        let size = measure(world, *child, contents, preferred);
+        match world.get::<Node>(*child) {
+            Ok(child) if child.style.position.is_none() => {
+                let size = size + child.style.margin.size();
+                main_size += main(size, direction);
+                cross_size = cross_size.max(cross(size, direction));
+                count += 1;
+            }
+            _ => (),
+        }
+    }
+    main_size += style.gap * (count as f32 - 1.0).max(0.0);
+    let children = along(direction, main_size, cross_size);
+    let content = contents.get(&entity).copied().unwrap_or(children);
+
+    let auto = content + style.padding.size();
+    let size = glam::vec2(
+        resolve(style.width, auto.x, auto.x),
+        resolve(style.height, auto.y, auto.y),
+    );
+    preferred.insert(entity, size);
+    size
+}
+
+/// A child in the flow of its parent
+struct FlowChild {
+    entity: Entity,
+    size: Vec2,
+    /// Margin before and after the child
+    start: Vec2,
+    end: Vec2,
+    grow: f32,
+}
+
+/// Places the node and its children, every placed node is pushed to order
+fn arrange(
+    world: &World,
+    entity: Entity,
+    rect: Rect,
+    clip: Rect,
+    preferred: &HashMap<Entity, Vec2>,
+    order: &mut Vec<Entity>,
+) {
+    let (style, children, content) = match world.get_mut::<Node>(entity) {
+        Ok(mut node) => {
+            node.rect = rect;
+            node.clip = clip;
+            (node.style.clone(), node.children.clone(), node.content())
+        }
+        Err(_) => return,
+    };
+    order.push(entity);
+
+    let is_list = world.get::<List>(entity).is_ok();
+    let child_clip = if is_list {
+        clip.intersect(&content)
+    } else {
+        clip
+    };
+
+    let direction = style.direction;
+    let mut flow = Vec::new();
+    let mut absolute = Vec::new();
+    for child in children.iter() {
+        let child_style = match world.get::<Node>(*child) {
+            Ok(node) => node.style.clone(),
+            Err(_) => continue,
+        };
+        let child_preferred = preferred.get(child).copied().unwrap_or(Vec2::ZERO);
+        let mut size = glam::vec2(
+            resolve(child_style.width, content.size.x, child_preferred.x),
+            resolve(child_style.height, content.size.y, child_preferred.y),
+        );
+        if let Some(position) = child_style.position {
+            absolute.push((*child, Rect::new(content.min + position, size)));
+            continue;
+        }
+        let margin = child_style.margin;
+        let cross_dimension = match direction {
+            Direction::Row => child_style.height,
+            Direction::Column => child_style.width,
+        };
+        if style.align == Align::Stretch && cross_dimension == Dimension::Auto {
+            let stretched = cross(content.size, direction) - cross(margin.size(), direction);
+            size = along(direction, main(size, direction), stretched.max(0.0));
+        }
+        flow.push(FlowChild {
+            entity: *child,
+            size,
+            start: glam::vec2(margin.left, margin.top),
+            end: glam::vec2(margin.right, margin.bottom),
+            grow: child_style.grow,
+        });
+    }
+
+    let gaps = style.gap * (flow.len() as f32 - 1.0).max(0.0);
+    let used = flow
+        .iter()
+        .map(|child| main(child.size + child.start + child.end, direction))
+        .sum::<f32>()
+        + gaps;
+    let mut free = (main(content.size, direction) - used).max(0.0);
+    let total_grow = flow.iter().map(|child| child.grow).sum::<f32>();
+    if free > 0.0 && total_grow > 0.0 {
+        for child in flow.iter_mut() {
+            let grown = main(child.size, direction) + free * child.grow / total_grow;
+            child.size = along(direction, grown, cross(child.size, direction));
+        }
+        free = 0.0;
+    }
+
+    let (mut pen, spacing) = match style.justify {
+        Justify::Start => (0.0, 0.0),
+        Justify::Center => (free * 0.5, 0.0),
+        Justify::End => (free, 0.0),
+        Justify::SpaceBetween if flow.len() > 1 => (0.0, free / (flow.len() - 1) as f32),
+        Justify::SpaceBetween => (0.0, 0.0),
+    };
+    if let Ok(mut list) = world.get_mut::<List>(entity) {
+        list.max_scroll = (used - main(content.size, direction)).max(0.0);
+        list.scroll = list.scroll.clamp(0.0, list.max_scroll);
+        pen -= list.scroll;
+    }
+
+    for child in flow.iter() {
+        pen += main(child.start, direction);
+        let cross_free = cross(
+            content.size - child.size - child.start - child.end,
+            direction,
+        );
+        let cross_offset = cross(child.start, direction)
+            + match style.align {
+                Align::Start | Align::Stretch => 0.0,
+                Align::Center => cross_free * 0.5,
+                Align::End => cross_free,
+            };
+        let min = content.min + along(direction, pen, cross_offset);
+        let child_rect = Rect::new(min, child.size);
+        arrange(
+            world,
+            child.entity,
+            child_rect,
+            child_clip,
+            preferred,
+            order,
+        );
+        pen += main(child.size + child.end, direction) + style.gap + spacing;
+    }
+    // Placed nodes are drawn on top of the flow
+    for (child, child_rect) in absolute {
+        arrange(world, child, child_rect, child_clip, preferred, order);
+    }
+}
+
+/// Lays out every tree of nodes against the screen, returns the nodes in the order they are drawn
+/// (roots in the order of their entities)
+pub(crate) fn layout(
+    world: &World,
+    screen: Vec2,
+    fonts: Option<&Assets<Font>>,
+    textures: Option<&Assets<TextureAsset>>,
+) -> Vec<Entity> {
+    let contents = measure_widgets(world, fonts, textures);
+    layout_nodes(world, screen, &contents)
+}
+
+fn layout_nodes(world: &World, screen: Vec2, contents: &HashMap<Entity, Vec2>) -> Vec<Entity> {
+    let mut children = HashSet::new();
+    let mut roots = Vec::new();
+    for (entity, node) in world.query::<&Node>().iter() {
+        children.extend(node.children.iter().copied());
+        roots.push(entity);
+    }
+    roots.retain(|root| !children.contains(root));
+    roots.sort_by_key(|root| root.id());
+
+    let mut preferred = HashMap::new();
+    let mut order = Vec::new();
+    let screen_rect = Rect::new(Vec2::ZERO, screen);
+    for root in roots {
+        let size = measure(world, root, contents, &mut preferred);
+        let style = world.get::<Node>(root).map(|node| node.style.clone());
+        let style = match style {
+            Ok(style) => style,
+            Err(_) => continue,
+        };
+        let size = glam::vec2(
+            resolve(style.width, screen.x, size.x),
+            resolve(style.height, screen.y, size.y),
+        );
+        let min =
+            style.position.unwrap_or(Vec2::ZERO) + glam::vec2(style.margin.left, style.margin.top);
+        let rect = Rect::new(min, size);
+        arrange(world, root, rect, screen_rect, &preferred, &mut order);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{Edges, Style};
+
+    fn node(world: &mut World, style: Style, children: Vec<Entity>) -> Entity {
+        world.spawn((Node::new(style).with_children(children),))
+    }
+
+    fn rect(world: &World, entity: Entity) -> Rect {
+        world.get::<Node>(entity).unwrap().rect()
+    }
+
+    #[test]
+    fn children_grow_and_are_justified() {
+        let mut world = World::new();
+        let fixed = Style::size(Dimension::Px(100.0), Dimension::Px(20.0));
+        let a = node(&mut world, fixed.clone(), vec![]);
+        let b = node(
+            &mut world,
+            Style {
+                grow: 1.0,
+                margin: Edges::all(5.0),
+                ..Default::default()
+            },
+            vec![],
+        );
+        let row = node(
+            &mut world,
+            Style {
+                width: Dimension::Percent(50.0),
+                padding: Edges::all(10.0),
+                gap: 10.0,
+                align: Align::Stretch,
+                ..Default::default()
+            },
+            vec![a, b],
+        );
+        let c = node(&mut world, fixed.clone(), vec![]);
+        let column = node(
+            &mut world,
+            Style {
+                direction: Direction::Column,
+                width: Dimension::Px(200.0),
+                height: Dimension::Px(100.0),
+                justify: Justify::End,
+                align: Align::Center,
+                position: Some(glam::vec2(0.0, 100.0)),
+                ..Default::default()
+            },
+            vec![c],
+        );
+
+        let order = layout_nodes(&world, glam::vec2(800.0, 600.0), &HashMap::new());
+        // Parents come before their children
+        assert_eq!(order, vec![row, a, b, column, c]);
+        assert_eq!(
+            rect(&world, row),
+            Rect::new(Vec2::ZERO, glam::vec2(400.0, 40.0))
+        );
+        assert_eq!(
+            rect(&world, a),
+            Rect::new(glam::vec2(10.0, 10.0), glam::vec2(100.0, 20.0))
+        );
+        // Takes the free space and is stretched, both inside of its margin
+        assert_eq!(
+            rect(&world, b),
+            Rect::new(glam::vec2(125.0, 15.0), glam::vec2(260.0, 10.0))
+        );
+        assert_eq!(
+            rect(&world, c),
+            Rect::new(glam::vec2(50.0, 180.0), glam::vec2(100.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn lists_scroll_and_clip_their_children() {
+        let mut world = World::new();
+        let items: Vec<Entity> = (0..5)
+            .map(|_| {
+                let style = Style::size(Dimension::Percent(100.0), Dimension::Px(30.0));
+                node(&mut world, style, vec![])
+            })
+            .collect();
+        let list = world.spawn((
+            Node::new(Style {
+                direction: Direction::Column,
+                width: Dimension::Px(100.0),
+                height: Dimension::Px(100.0),
+                gap: 10.0,
+                ..Default::default()
+            })
+            .with_children(items.clone()),
+            List {
+                scroll: 1000.0,
+                ..Default::default()
+            },
+        ));
+
+        layout_nodes(&world, glam::vec2(800.0, 600.0), &HashMap::new());
+        // 5 items with 4 gaps, of which 100 pixels are visible
+        let scroll = world.get::<List>(list).unwrap().scroll;
+        assert_eq!(world.get::<List>(list).unwrap().max_scroll(), 90.0);
+        assert_eq!(scroll, 90.0);
+        let first = world.get::<Node>(items[0]).unwrap();
+        assert_eq!(first.rect().min, glam::vec2(0.0, -90.0));
+        assert!(first.clip().intersect(&first.rect()).is_empty());
+        let last = world.get::<Node>(items[4]).unwrap();
+        assert_eq!(
+            last.rect(),
+            Rect::new(glam::vec2(0.0, 70.0), glam::vec2(100.0, 30.0))
+        );
+        assert_eq!(last.clip(), Rect::new(Vec2::ZERO, glam::vec2(100.0, 100.0)));
+    }
+}
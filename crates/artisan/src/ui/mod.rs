@@ -0,0 +1,394 @@
+//! Retained mode game ui
+//!
+//! The ui is built from entities with a `Node`, which are laid out like a (simplified) flexbox.
+//! Nodes list their children, every node that is not a child of another one is a root that is
+//! laid out against the screen. Widgets are components next to the node: `Panel`, `Button`,
+//! `Label`, `Icon`, `Slider` and `List`. Nodes with an `Interaction` are hit tested against the
+//! cursor and can be focused, the `UiState` resource tells which node is hovered and focused.
+//!
+//! Layout and interaction run after all stages, so the game reads the interactions of the last
+//! frame during its update. The ui pass draws on top of everything else.
+
+mod interaction;
+mod layout;
+pub(crate) mod render;
+
+use app::{App, AssetHandle, Entity, IntoMutatingSystem, Resources, World};
+use glam::{Vec2, Vec4};
+
+use crate::{
+    text::{PlacedGlyph, TextStyle},
+    texture::TextureAsset,
+};
+
+pub use layout::Rect;
+
+/// Size of a node along an axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    /// Fits the content (children or the widget)
+    Auto,
+    Px(f32),
+    /// Of the content box of the parent (or the screen)
+    Percent(f32),
+}
+
+/// Main axis, along which the children are placed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+/// Placement of the children along the main axis, if they do not fill it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+}
+
+/// Placement of the children along the cross axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    /// Children with an auto size fill the cross axis
+    Stretch,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Edges {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl Edges {
+    pub const ZERO: Self = Self::all(0.0);
+
+    pub const fn all(value: f32) -> Self {
+        Self {
+            left: value,
+            right: value,
+            top: value,
+            bottom: value,
+        }
+    }
+
+    pub const fn symmetric(horizontal: f32, vertical: f32) -> Self {
+        Self {
+            left: horizontal,
+            right: horizontal,
+            top: vertical,
+            bottom: vertical,
+        }
+    }
+
+    /// Sum of both sides along x and y
+    pub fn size(&self) -> Vec2 {
+        glam::vec2(self.left + self.right, self.top + self.bottom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub direction: Direction,
+    pub width: Dimension,
+    pub height: Dimension,
+    pub padding: Edges,
+    pub margin: Edges,
+    /// Space between the children
+    pub gap: f32,
+    pub justify: Justify,
+    pub align: Align,
+    /// Share of the free space of the parent along its main axis
+    pub grow: f32,
+    /// Takes the node out of the flow, it is placed at this offset of the content box of its
+    /// parent (or the screen for roots)
+    pub position: Option<Vec2>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            direction: Direction::Row,
+            width: Dimension::Auto,
+            height: Dimension::Auto,
+            padding: Edges::ZERO,
+            margin: Edges::ZERO,
+            gap: 0.0,
+            justify: Justify::Start,
+            align: Align::Start,
+            grow: 0.0,
+            position: None,
+        }
+    }
+}
+
+impl Style {
+    pub fn size(width: Dimension, height: Dimension) -> Self {
+        Self {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+}
+
+/// A node of the ui tree, children are drawn on top of their parent (and in their order)
+#[derive(Debug)]
+pub struct Node {
+    pub style: Style,
+    pub children: Vec<Entity>,
+    /// Computed by the layout, in pixels from the top left of the screen
+    rect: Rect,
+    /// Visible part of the rect, lists clip their children
+    clip: Rect,
+}
+
+impl Node {
+    pub fn new(style: Style) -> Self {
+        Self {
+            style,
+            children: Vec::new(),
+            rect: Rect::default(),
+            clip: Rect::default(),
+        }
+    }
+
+    pub fn with_children(mut self, children: Vec<Entity>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Where the node was placed by the last layout
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// The rect without the padding
+    pub fn content(&self) -> Rect {
+        let padding = self.style.padding;
+        Rect::new(
+            self.rect.min + glam::vec2(padding.left, padding.top),
+            (self.rect.size - padding.size()).max(Vec2::ZERO),
+        )
+    }
+}
+
+/// Background of a node
+#[derive(Debug, Clone, Copy)]
+pub struct Panel {
+    pub color: Vec4,
+}
+
+/// Background of a node that changes with its `Interaction`
+#[derive(Debug, Clone, Copy)]
+pub struct Button {
+    pub color: Vec4,
+    pub hovered_color: Vec4,
+    pub pressed_color: Vec4,
+}
+
+impl Button {
+    /// Lightens the color when hovered and darkens it when pressed
+    pub fn new(color: Vec4) -> Self {
+        let shade = |factor: f32| (color.truncate() * factor).extend(color.w);
+        Self {
+            color,
+            hovered_color: shade(1.25),
+            pressed_color: shade(0.75),
+        }
+    }
+}
+
+/// Text of a node, which is sized to fit it
+pub struct Label {
+    pub text: String,
+    pub style: TextStyle,
+    /// Laid out by the ui layout, relative to the content box of the node
+    glyphs: Vec<PlacedGlyph>,
+}
+
+impl Label {
+    pub fn new(text: impl Into<String>, style: TextStyle) -> Self {
+        Self {
+            text: text.into(),
+            style,
+            glyphs: Vec::new(),
+        }
+    }
+}
+
+/// A texture that fills the content box of a node, nodes with an auto size take its size
+pub struct Icon {
+    pub image: AssetHandle<TextureAsset>,
+    pub tint: Vec4,
+}
+
+/// A value that is dragged along the width of the node, or changed by 5% with the left and right
+/// keys while focused. Needs an `Interaction`
+#[derive(Debug, Clone, Copy)]
+pub struct Slider {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub track_color: Vec4,
+    pub handle_color: Vec4,
+}
+
+impl Slider {
+    pub fn new(min: f32, max: f32, value: f32) -> Self {
+        Self {
+            value,
+            min,
+            max,
+            track_color: glam::vec4(0.2, 0.2, 0.2, 0.9),
+            handle_color: glam::vec4(0.8, 0.8, 0.8, 1.0),
+        }
+    }
+
+    /// Of the value between min and max
+    pub fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            ((self.value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Children are scrolled along the main axis (with the mouse wheel, or the up and down keys while
+/// focused) and clipped to the node. Clicking a child with an `Interaction` selects it
+#[derive(Debug, Default, Clone, Copy)]
+pub struct List {
+    /// Pixels the children are moved towards the start
+    pub scroll: f32,
+    pub selected: Option<Entity>,
+    /// How far the children can be scrolled, computed by the layout
+    max_scroll: f32,
+}
+
+impl List {
+    pub fn max_scroll(&self) -> f32 {
+        self.max_scroll
+    }
+}
+
+/// State of a node that is hit tested against the cursor, reset every frame
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Interaction {
+    pub hovered: bool,
+    /// The left mouse button was pressed on the node and is held
+    pub pressed: bool,
+    /// Released on the node (or activated with enter or space while focused) this frame
+    pub clicked: bool,
+    pub focused: bool,
+}
+
+/// Nodes the input goes to
+#[derive(Debug, Default)]
+pub struct UiState {
+    hovered: Option<Entity>,
+    focused: Option<Entity>,
+    /// Pressed with the left mouse button, until it is released
+    pressed: Option<Entity>,
+    /// Every node in the order they are drawn
+    order: Vec<Entity>,
+}
+
+impl UiState {
+    /// The top most interactive node under the cursor
+    pub fn hovered(&self) -> Option<Entity> {
+        self.hovered
+    }
+
+    pub fn focused(&self) -> Option<Entity> {
+        self.focused
+    }
+
+    /// Focus moves to the clicked node, or with tab to the next one
+    pub fn focus(&mut self, node: Option<Entity>) {
+        self.focused = node;
+    }
+
+    /// Whether the cursor is over the ui (or a node is dragged), the game should ignore it then
+    pub fn wants_mouse(&self) -> bool {
+        self.hovered.is_some() || self.pressed.is_some()
+    }
+}
+
+/// A node with a background
+pub fn panel(style: Style, color: Vec4) -> (Node, Panel) {
+    (Node::new(style), Panel { color })
+}
+
+pub fn label(text: impl Into<String>, style: TextStyle) -> (Node, Label) {
+    (Node::new(Style::default()), Label::new(text, style))
+}
+
+/// A clickable node, its content (eg. a label or an icon) are its children
+pub fn button(style: Style, color: Vec4) -> (Node, Button, Interaction) {
+    (Node::new(style), Button::new(color), Interaction::default())
+}
+
+pub fn icon(image: AssetHandle<TextureAsset>, width: f32, height: f32) -> (Node, Icon) {
+    (
+        Node::new(Style::size(Dimension::Px(width), Dimension::Px(height))),
+        Icon {
+            image,
+            tint: Vec4::ONE,
+        },
+    )
+}
+
+pub fn slider(style: Style, min: f32, max: f32, value: f32) -> (Node, Slider, Interaction) {
+    (
+        Node::new(style),
+        Slider::new(min, max, value),
+        Interaction::default(),
+    )
+}
+
+pub fn list(style: Style) -> (Node, List, Interaction) {
+    (Node::new(style), List::default(), Interaction::default())
+}
+
+/// Appends the child to the children of the parent
+pub fn add_child(world: &World, parent: Entity, child: Entity) {
+    if let Ok(mut node) = world.get_mut::<Node>(parent) {
+        node.children.push(child);
+    }
+}
+
+/// Lays out the nodes and updates their interactions
+fn ui_system(world: &mut World, resources: &mut Resources) {
+    let screen = match resources.get::<window::WindowState>() {
+        Ok(window) => glam::vec2(window.size.width as f32, window.size.height as f32),
+        Err(_) => return,
+    };
+    let mut state = resources
+        .get_mut::<UiState>()
+        .expect("[Ui] failed to get ui state");
+    {
+        let fonts = resources.get::<app::Assets<crate::font::Font>>().ok();
+        let textures = resources.get::<app::Assets<TextureAsset>>().ok();
+        state.order = layout::layout(world, screen, fonts.as_deref(), textures.as_deref());
+    }
+    if let Ok(input) = resources.get::<window::input::Input>() {
+        interaction::interact(world, &mut state, &interaction::UiInput::from_input(&input));
+    }
+}
+
+pub(crate) fn init(app: &mut App) {
+    app.insert_resource(UiState::default());
+    app.add_mut_system(ui_system.into_mut_system());
+}
@@ -0,0 +1,392 @@
+//! The ui pass, that draws the nodes on top of the backbuffer
+//!
+//! Every node is turned into quads on the cpu, clipped to the visible part of the node. Quads are
+//! batched by the texture they sample, which is either the glyph atlas of the labels or the
+//! texture of an icon.
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use app::{AssetEvent, AssetHandle, Assets, Entity, Events, Resources, World};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec4};
+use render::{
+    graph::{builder::GraphBuilder, node::Node as GraphNode, nodes::callbacks::FrameData},
+    prelude::*,
+    resource::{
+        glue::Mixture,
+        render_pass::{LoadOp, StoreOp},
+        texture::{SamplerDescriptor, WrapMode},
+    },
+};
+
+use super::{Button, Icon, Interaction, Label, Node, Panel, Rect, Slider, UiState};
+use crate::{
+    batch::InstanceBuffers,
+    font::{Font, GlyphAtlas},
+    pipelines::{self, PipelineInstance},
+    renderer::ActiveContext,
+    text::{self, FontAtlas, GlyphRun},
+    texture::TextureAsset,
+};
+
+type ActiveGraphBuilder = <ActiveContext as GpuContext>::GraphBuilder;
+type AttachmentIndex = <ActiveGraphBuilder as GraphBuilder>::AttachmentIndex;
+
+/// How the fragment shader uses the texture, stored in the z of the uv
+const SOLID: f32 = 0.0;
+const GLYPH: f32 = 1.0;
+const IMAGE: f32 = 2.0;
+/// Height of the track of a slider and width of its handle, relative to the height of the node
+const TRACK_HEIGHT: f32 = 0.3;
+const HANDLE_WIDTH: f32 = 0.5;
+
+/// Vertex of `widget.pipe`, positions are in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Zeroable, Pod)]
+#[repr(C)]
+pub struct UiVertex {
+    pub position: [f32; 2],
+    /// Texture coordinates and how the texture is used
+    pub uv: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// The texture a batch samples
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum BatchTexture {
+    Atlas,
+    Image(AssetHandle<TextureAsset>),
+}
+
+struct Batch {
+    texture: BatchTexture,
+    vertices: Range<u32>,
+}
+
+/// Quads of every node, in the order they are drawn
+#[derive(Default)]
+struct UiMesh {
+    vertices: Vec<UiVertex>,
+    batches: Vec<Batch>,
+}
+
+impl UiMesh {
+    /// Adds the visible part of a quad, uv is the part of the texture mapped onto the rect. Quads
+    /// without a texture continue the last batch
+    fn quad(
+        &mut self,
+        rect: Rect,
+        uv: Rect,
+        color: Vec4,
+        kind: f32,
+        texture: Option<BatchTexture>,
+        clip: &Rect,
+    ) {
+        let visible = rect.intersect(clip);
+        if visible.is_empty() {
+            return;
+        }
+        let start = self.vertices.len() as u32;
+        match (self.batches.last_mut(), texture) {
+            (Some(batch), None) => batch.vertices.end += 6,
+            (Some(batch), Some(texture)) if batch.texture == texture => batch.vertices.end += 6,
+            (_, texture) => self.batches.push(Batch {
+                texture: texture.unwrap_or(BatchTexture::Atlas),
+                vertices: start..start + 6,
+            }),
+        }
+
+        // The uv is cut off in the same proportion as the rect
+        let color = color.into();
+        let corner = |x: f32, y: f32| {
+            let position = visible.min + visible.size * glam::vec2(x, y);
+            let uv = uv.min + (position - rect.min) / rect.size * uv.size;
+            UiVertex {
+                position: position.into(),
+                uv: [uv.x, uv.y, kind],
+                color,
+            }
+        };
+        let (a, b, c, d) = (
+            corner(0.0, 0.0),
+            corner(1.0, 0.0),
+            corner(0.0, 1.0),
+            corner(1.0, 1.0),
+        );
+        self.vertices.extend_from_slice(&[a, b, c, c, b, d]);
+    }
+
+    fn solid(&mut self, rect: Rect, color: Vec4, clip: &Rect) {
+        self.quad(rect, Rect::default(), color, SOLID, None, clip);
+    }
+}
+
+/// Quads of the nodes, with the glyphs of the labels that are in the atlas
+fn build(
+    world: &World,
+    order: &[Entity],
+    atlas: &GlyphAtlas<AssetHandle<Font>>,
+    textures: &Assets<TextureAsset>,
+) -> UiMesh {
+    let mut mesh = UiMesh::default();
+    let atlas_size = atlas.size() as f32;
+    for entity in order.iter().copied() {
+        let (rect, content, clip) = match world.get::<Node>(entity) {
+            Ok(node) => (node.rect(), node.content(), node.clip()),
+            Err(_) => continue,
+        };
+        if let Ok(panel) = world.get::<Panel>(entity) {
+            mesh.solid(rect, panel.color, &clip);
+        }
+        if let Ok(button) = world.get::<Button>(entity) {
+            let interaction = world
+                .get::<Interaction>(entity)
+                .map(|interaction| *interaction)
+                .unwrap_or_default();
+            let color = if interaction.pressed {
+                button.pressed_color
+            } else if interaction.hovered || interaction.focused {
+                button.hovered_color
+            } else {
+                button.color
+            };
+            mesh.solid(rect, color, &clip);
+        }
+        if let Ok(slider) = world.get::<Slider>(entity) {
+            let track_height = content.size.y * TRACK_HEIGHT;
+            let track = Rect::new(
+                content.min + glam::vec2(0.0, (content.size.y - track_height) * 0.5),
+                glam::vec2(content.size.x, track_height),
+            );
+            mesh.solid(track, slider.track_color, &clip);
+            let handle_width = content.size.y * HANDLE_WIDTH;
+            let handle = Rect::new(
+                content.min + glam::vec2((content.size.x - handle_width) * slider.fraction(), 0.0),
+                glam::vec2(handle_width, content.size.y),
+            );
+            mesh.solid(handle, slider.handle_color, &clip);
+        }
+        if let Ok(icon) = world.get::<Icon>(entity) {
+            if textures.try_get(&icon.image).is_some() {
+                let uv = Rect::new(Vec2::ZERO, Vec2::ONE);
+                let texture = BatchTexture::Image(icon.image.as_weak());
+                mesh.quad(content, uv, icon.tint, IMAGE, Some(texture), &clip);
+            }
+        }
+        if let Ok(label) = world.get::<Label>(entity) {
+            let style = &label.style;
+            for placed in label.glyphs.iter() {
+                let glyph = match atlas.get(style.font.as_weak(), placed.glyph, style.size) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                let offset = glam::vec2(glyph.offset[0] as f32, glyph.offset[1] as f32);
+                let size = glam::vec2(glyph.size[0] as f32, glyph.size[1] as f32);
+                let min = (content.min + placed.position).round() + offset;
+                let uv = Rect::new(
+                    glam::vec2(glyph.min[0] as f32, glyph.min[1] as f32) / atlas_size,
+                    size / atlas_size,
+                );
+                let rect = Rect::new(min, size);
+                mesh.quad(
+                    rect,
+                    uv,
+                    style.color,
+                    GLYPH,
+                    Some(BatchTexture::Atlas),
+                    &clip,
+                );
+            }
+        }
+    }
+    mesh
+}
+
+/// Glue of every texture the quads sample, bottled for a mixture
+struct UiGlue {
+    mixture: Arc<Mixture<ActiveContext>>,
+    atlas_generation: u64,
+    atlas: Arc<Texture<ActiveContext>>,
+    atlas_glue: Arc<Glue<ActiveContext>>,
+    images: HashMap<AssetHandle<TextureAsset>, Arc<Glue<ActiveContext>>>,
+}
+
+/// Adds the ui pass, that draws on top of the backbuffer
+pub(crate) fn init(
+    resources: &Resources,
+    graph_builder: &mut ActiveGraphBuilder,
+    gpu: Arc<GpuResources<ActiveContext>>,
+    backbuffer: AttachmentIndex,
+) {
+    let pipe = pipelines::load(resources, "assets/shaders/widget.pipe");
+    let frames_in_flight = graph_builder.get_swapchain_image_count();
+    let ctx = resources
+        .get::<Arc<ActiveContext>>()
+        .expect("[Artisan] failed to get context")
+        .clone();
+    let mut vertex_buffers =
+        InstanceBuffers::new(ctx, frames_in_flight).with_name("ui-vertex-buffer");
+    let mut cache: Option<UiGlue> = None;
+    let sampler = gpu.create_sampler(&SamplerDescriptor {
+        wrap: WrapMode::ClampToEdge,
+        ..SamplerDescriptor::LINEAR
+    });
+
+    let mut builder = graph_builder.build_pass_node("ui_pass".into());
+    builder.add_output(backbuffer, LoadOp::Load, StoreOp::Store);
+    let init_gpu = gpu.clone();
+    builder.init(Box::new(move |render_pass| {
+        Box::new(PipelineInstance::new(&pipe, init_gpu.clone(), render_pass))
+    }));
+    builder.callback(Box::new(move |frame, instance, world, resources| {
+        let FrameData {
+            cmd,
+            frame_index,
+            viewport,
+            ..
+        } = frame;
+        let state = resources.get::<UiState>()?;
+        if state.order.is_empty() {
+            return Ok(None);
+        }
+        let pipeline = match instance.get(resources) {
+            Some(pipeline) => pipeline,
+            None => return Ok(None),
+        };
+        let fonts = resources.get::<Assets<Font>>()?;
+        let textures = resources.get::<Assets<TextureAsset>>()?;
+
+        let mut font_atlas = resources.get_mut::<FontAtlas>()?;
+        {
+            let mut labels = world.query::<&Label>();
+            let runs: Vec<GlyphRun> = labels
+                .iter()
+                .map(|(_e, label)| GlyphRun {
+                    font: &label.style.font,
+                    size: label.style.size,
+                    glyphs: &label.glyphs,
+                })
+                .collect();
+            text::rasterize(&mut font_atlas.atlas, &runs, &fonts);
+        }
+        let mesh = build(world, &state.order, &font_atlas.atlas, &textures);
+        if mesh.vertices.is_empty() {
+            return Ok(None);
+        }
+
+        if !matches!(&cache, Some(cached) if Arc::ptr_eq(&cached.mixture, &pipeline.mixture)) {
+            cache = None;
+        }
+        // Frames in flight keep the atlas of their generation alive
+        let cached = match cache.take() {
+            Some(cached) if cached.atlas_generation == font_atlas.atlas.generation() => cached,
+            cached => {
+                let texture = font_atlas.texture(&gpu);
+                let mut glue_bottle = gpu.bottle(&pipeline.mixture);
+                glue_bottle.write_texture(
+                    PartIndex::Name("image".into()),
+                    texture.get_handle(),
+                    &sampler,
+                );
+                UiGlue {
+                    mixture: pipeline.mixture.clone(),
+                    atlas_generation: font_atlas.atlas.generation(),
+                    atlas_glue: Arc::new(glue_bottle.apply()),
+                    atlas: texture,
+                    images: cached.map(|cached| cached.images).unwrap_or_default(),
+                }
+            }
+        };
+        let cached = cache.insert(cached);
+        // Reloaded textures have to be bottled again
+        if let Ok(events) = resources.get::<Events<AssetEvent<TextureAsset>>>() {
+            if events.iter().any(|e| !e.is_created()) {
+                cached.images.clear();
+            }
+        }
+
+        let extent = [viewport.rect.width as f32, viewport.rect.height as f32];
+        let vertex_buffer = vertex_buffers.write(frame_index as usize, &mesh.vertices);
+        cmd.bind_graphics_pipeline(&pipeline);
+        cmd.set_viewport(0, viewport.clone());
+        cmd.set_scissor(0, viewport.rect);
+        cmd.push_constants(
+            &pipeline,
+            ShaderType::Vertex,
+            0,
+            bytemuck::cast_slice(&extent),
+        );
+        cmd.bind_vertex_buffer(0, vertex_buffer, BufferRange::WHOLE);
+        let mut keep_alive = vec![cached.atlas_glue.clone()];
+        for batch in mesh.batches.iter() {
+            let glue = match &batch.texture {
+                BatchTexture::Atlas => cached.atlas_glue.clone(),
+                BatchTexture::Image(handle) => {
+                    let texture = match textures.try_get(handle) {
+                        Some(texture) => texture,
+                        None => continue,
+                    };
+                    let mixture = &pipeline.mixture;
+                    let glue = cached.images.entry(handle.as_weak()).or_insert_with(|| {
+                        let mut glue_bottle = gpu.bottle(mixture);
+                        glue_bottle.write_texture(
+                            PartIndex::Name("image".into()),
+                            texture.0.get_handle(),
+                            &sampler,
+                        );
+                        Arc::new(glue_bottle.apply())
+                    });
+                    keep_alive.push(glue.clone());
+                    glue.clone()
+                }
+            };
+            cmd.snort_glue(0, &pipeline, &glue);
+            cmd.draw(batch.vertices.clone(), 0..1);
+        }
+
+        // Keep everything alive, until the frame is finished
+        Ok(Some(Box::new((pipeline, cached.atlas.clone(), keep_alive))))
+    }));
+    graph_builder.add_node(GraphNode::PassNode(builder.build()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipelines::tests::validated_pipe;
+
+    #[test]
+    fn quads_are_clipped_and_batched() {
+        let mut mesh = UiMesh::default();
+        let clip = Rect::new(Vec2::ZERO, glam::vec2(100.0, 100.0));
+        let uv = Rect::new(Vec2::ZERO, Vec2::ONE);
+        mesh.solid(
+            Rect::new(glam::vec2(200.0, 0.0), Vec2::ONE),
+            Vec4::ONE,
+            &clip,
+        );
+        assert!(mesh.vertices.is_empty());
+
+        // The right half is cut off, and so is the right half of the texture
+        let rect = Rect::new(glam::vec2(50.0, 0.0), glam::vec2(100.0, 10.0));
+        let atlas = Some(BatchTexture::Atlas);
+        mesh.quad(rect, uv, Vec4::ONE, GLYPH, atlas, &clip);
+        assert_eq!(mesh.vertices[5].position, [100.0, 10.0]);
+        assert_eq!(mesh.vertices[5].uv, [0.5, 1.0, GLYPH]);
+
+        mesh.solid(rect, Vec4::ONE, &clip);
+        assert_eq!(mesh.batches.len(), 1);
+        assert_eq!(mesh.batches[0].vertices, 0..12);
+    }
+
+    #[test]
+    fn widget_pipe_matches_vertex() {
+        let (pipe, parts) = validated_pipe("widget");
+        assert_eq!(
+            pipe.vertex_buffers()[0].stride as usize,
+            std::mem::size_of::<UiVertex>()
+        );
+        assert_eq!(pipe.push_constants()[0].1, 0..8);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].name, "image");
+    }
+}
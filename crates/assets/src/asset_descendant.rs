@@ -1,60 +1,399 @@
-use std::ops::Deref;
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    ops::Deref,
+    sync::Arc,
+};
 
-use ecs::prelude::{Events, Resources};
+use core::anyhow::{anyhow, Result};
+use ecs::prelude::{Events, ManualEventReader, Resources};
+use tasks::{futures::future, task::Task, AsyncComputePool};
 
-use crate::prelude::{Asset, AssetEvent, AssetHandle, Assets};
+use crate::{
+    assets::AssetStore,
+    handle::HandleId,
+    prelude::{Asset, AssetEvent, AssetHandle, AssetServer, Assets},
+};
 
-// TODO: Async ?
-pub struct AssetDescendant<A: Asset, T> {
-    cb: Box<dyn Fn(Vec<&A>) -> T + Send + Sync>,
-    handles: Vec<AssetHandle<A>>,
-    value: Option<T>,
+/// Access to the assets a descendant depends on, handed to the callback of an [`AssetDescendant`]
+///
+/// The sources are the live stores and not a snapshot: an asset that is updated (or unloaded)
+/// while the callback runs on the pool can be seen in its new state. The update marks the
+/// descendant as dirty, so it is computed again with consistent sources once the running
+/// computation is done.
+pub struct DescendantSources {
+    stores: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
 }
 
-impl<A: Asset, T> AssetDescendant<A, T> {
-    pub fn new(
-        cb: impl Fn(Vec<&A>) -> T + Send + Sync + 'static,
-        handles: Vec<AssetHandle<A>>,
-    ) -> Self {
-        assert!(
-            handles.iter().all(|h| h.is_strong()),
-            "[AssetDescendant] (new) expected to receive only strong handles"
-        );
+impl DescendantSources {
+    pub fn get<A: Asset>(&self, handle: &AssetHandle<A>) -> Result<impl Deref<Target = A> + '_> {
+        let store = self
+            .stores
+            .get(&TypeId::of::<A>())
+            .and_then(|s| s.downcast_ref::<AssetStore<A>>())
+            .ok_or_else(|| {
+                anyhow!(
+                    "[DescendantSources] {} is not a dependency of this descendant",
+                    type_name::<A>()
+                )
+            })?;
+        store
+            .get(&handle.id)
+            .ok_or_else(|| anyhow!("[DescendantSources] asset {:?} is not loaded", handle))
+    }
+}
+
+/// Type erased functions of a dependency, monomorphized for the asset type in `depends_on`
+struct Dependency {
+    id: HandleId,
+    type_id: TypeId,
+    store: fn(&Resources) -> Option<Box<dyn Any + Send + Sync>>,
+    is_loaded: fn(&Resources, HandleId) -> bool,
+    /// Reads the update events of the dependency, every event is only seen once
+    is_updated: Box<dyn FnMut(&Resources) -> bool + Send + Sync>,
+}
+
+fn store<A: Asset>(resources: &Resources) -> Option<Box<dyn Any + Send + Sync>> {
+    let assets = resources.get::<Assets<A>>().ok()?;
+    Some(Box::new(assets.store()))
+}
+
+fn is_loaded<A: Asset>(resources: &Resources, id: HandleId) -> bool {
+    resources
+        .get::<Assets<A>>()
+        .map(|assets| assets.contains(id))
+        .unwrap_or(false)
+}
+
+fn is_updated<A: Asset>(id: HandleId) -> Box<dyn FnMut(&Resources) -> bool + Send + Sync> {
+    let mut reader = ManualEventReader::<AssetEvent<A>>::default();
+    Box::new(move |resources| {
+        resources
+            .get::<Events<AssetEvent<A>>>()
+            .map(|events| {
+                reader
+                    .iter(&events)
+                    .filter(|e| e.is_updated())
+                    .any(|e| e.get_handle().id == id)
+            })
+            .unwrap_or(false)
+    })
+}
+
+type DescendantCallback<T> = dyn Fn(&DescendantSources) -> Result<T> + Send + Sync;
+
+pub struct AssetDescendantBuilder {
+    dependencies: Vec<Dependency>,
+}
+
+impl AssetDescendantBuilder {
+    pub fn new() -> Self {
         Self {
-            cb: Box::new(cb),
-            handles,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// The value will be recomputed whenever this asset is updated
+    pub fn depends_on<A: Asset>(mut self, handle: &AssetHandle<A>) -> Self {
+        self.dependencies.push(Dependency {
+            id: handle.id,
+            type_id: TypeId::of::<A>(),
+            store: store::<A>,
+            is_loaded: is_loaded::<A>,
+            is_updated: is_updated::<A>(handle.id),
+        });
+        self
+    }
+
+    /// The callback is run on the `AsyncComputePool` (if present), it should keep strong handles
+    /// to its dependencies, so that they stay loaded
+    pub fn build<T: Send + 'static>(
+        self,
+        cb: impl Fn(&DescendantSources) -> Result<T> + Send + Sync + 'static,
+    ) -> AssetDescendant<T> {
+        AssetDescendant {
+            cb: Arc::new(cb),
+            dependencies: self.dependencies,
             value: None,
+            task: None,
+            dirty: true,
+            error: None,
+        }
+    }
+}
+
+impl Default for AssetDescendantBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cached value derived from (possibly multiple types of) assets, that is recomputed when one of
+/// them is updated
+///
+/// While recomputing (or if the recompute failed) the last good value is kept
+pub struct AssetDescendant<T> {
+    cb: Arc<DescendantCallback<T>>,
+    dependencies: Vec<Dependency>,
+    value: Option<T>,
+    task: Option<Task<Result<T>>>,
+    dirty: bool,
+    error: Option<core::anyhow::Error>,
+}
+
+impl<T: Send + 'static> AssetDescendant<T> {
+    /// Returns `true` while a recompute is running
+    pub fn is_computing(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// The error of the last recompute, if it failed, or of a dependency that failed to load
+    pub fn error(&self) -> Option<&core::anyhow::Error> {
+        self.error.as_ref()
+    }
+
+    fn finish(&mut self, result: Result<T>) {
+        match result {
+            Ok(value) => {
+                self.value = Some(value);
+                self.error = None;
+            }
+            Err(e) => {
+                log::error!(
+                    "[AssetDescendant] failed to compute {}, keeping the last value: {:?}",
+                    type_name::<T>(),
+                    e
+                );
+                self.error = Some(e);
+            }
         }
     }
 
+    /// The first dependency that failed to load, the value can't be computed without it
+    fn load_error(&self, resources: &Resources) -> Option<core::anyhow::Error> {
+        let server = resources.get::<AssetServer>().ok()?;
+        self.dependencies
+            .iter()
+            .filter(|d| !(d.is_loaded)(resources, d.id))
+            .find_map(|d| {
+                let error = server.load_error(d.id)?;
+                Some(anyhow!(
+                    "[AssetDescendant] dependency {:?} failed to load: {}",
+                    d.id,
+                    error
+                ))
+            })
+    }
+
     pub fn get(&mut self, resources: &Resources) -> Option<&T> {
-        // If an asset changes, we invalidate the cache
-        let events = resources
-            .get::<Events<AssetEvent<A>>>()
-            .expect("[AssetDescendant] failed to get asset events");
+        // Every dependency has to read its events, so no short circuiting
+        let mut updated = false;
+        for dependency in self.dependencies.iter_mut() {
+            updated |= (dependency.is_updated)(resources);
+        }
+        if updated {
+            self.dirty = true;
+        }
 
-        if events
+        if let Some(task) = self.task.as_mut() {
+            if let Some(result) = future::block_on(future::poll_once(task)) {
+                self.task = None;
+                self.finish(result);
+            }
+        }
+
+        let loaded = self
+            .dependencies
             .iter()
-            .filter(|a| a.is_updated())
-            .map(|e| e.get_handle())
-            .any(|h| self.handles.contains(h))
-        {
-            self.value = None
+            .all(|d| (d.is_loaded)(resources, d.id));
+        if !loaded {
+            if let Some(e) = self.load_error(resources) {
+                self.error = Some(e);
+            }
         }
+        if self.dirty && self.task.is_none() && loaded {
+            self.dirty = false;
 
-        if self.value.is_none() {
-            // check if all assets are present
-            let assets = resources
-                .get::<Assets<A>>()
-                .expect("[AssetDescendant] failed to get assets store");
+            let mut stores = HashMap::new();
+            for dependency in self.dependencies.iter() {
+                if let Some(store) = (dependency.store)(resources) {
+                    stores.insert(dependency.type_id, store);
+                }
+            }
+            let sources = DescendantSources { stores };
+            let cb = self.cb.clone();
 
-            let assets = assets.query(self.handles.as_slice());
-            if let Ok(assets) = assets {
-                let result = (self.cb)(assets.iter().map(|a| a.deref()).collect());
-                self.value = Some(result);
+            match resources.get::<AsyncComputePool>() {
+                Ok(pool) => self.task = Some(pool.spawn(async move { cb(&sources) })),
+                // Without a pool we will compute in place
+                Err(_) => {
+                    let result = cb(&sources);
+                    self.finish(result);
+                }
             }
         }
 
         self.value.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{AssetServer, AssetStats};
+    use std::{thread, time::Duration};
+    use tasks::sync_channel::unbounded;
+
+    struct Number(i32);
+    struct Factor(i32);
+
+    fn setup() -> (AssetServer, Resources) {
+        let server = AssetServer::new(tasks::ComputePool::default());
+        let mut resources = Resources::new();
+        resources.insert(server.register_asset::<Number>()).unwrap();
+        resources.insert(server.register_asset::<Factor>()).unwrap();
+        resources
+            .insert(Events::<AssetEvent<Number>>::new())
+            .unwrap();
+        resources
+            .insert(Events::<AssetEvent<Factor>>::new())
+            .unwrap();
+        resources.insert(AssetStats::default()).unwrap();
+        (server, resources)
+    }
+
+    fn frame(resources: &Resources) {
+        Assets::<Number>::update_system(
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+        );
+        Assets::<Factor>::update_system(
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+        );
+        // make this frames events visible
+        resources
+            .get_mut::<Events<AssetEvent<Number>>>()
+            .unwrap()
+            .update();
+        resources
+            .get_mut::<Events<AssetEvent<Factor>>>()
+            .unwrap()
+            .update();
+    }
+
+    #[test]
+    fn multiple_types_and_last_good_value() {
+        let (server, resources) = setup();
+        let number = server.add_loaded_asset("number", Number(3));
+        let factor = server.add_loaded_asset("factor", Factor(2));
+        let number_handle = number.clone_strong().unwrap();
+
+        let mut product = AssetDescendantBuilder::new()
+            .depends_on(&number)
+            .depends_on(&factor)
+            .build(move |sources| {
+                let number = sources.get(&number)?;
+                let factor = sources.get(&factor)?;
+                if factor.0 == 0 {
+                    return Err(anyhow!("factor must not be zero"));
+                }
+                Ok(number.0 * factor.0)
+            });
+
+        assert_eq!(product.get(&resources), None);
+        frame(&resources);
+        assert_eq!(product.get(&resources), Some(&6));
+
+        // A failing recompute keeps the old value
+        future::block_on(server.update_asset(&number_handle, Number(4)));
+        let factor_handle = AssetHandle::<Factor>::weak(HandleId::LabelId("factor".into()));
+        future::block_on(server.update_asset(&factor_handle, Factor(0)));
+        frame(&resources);
+        assert_eq!(product.get(&resources), Some(&6));
+        assert!(product.error().is_some());
+
+        future::block_on(server.update_asset(&factor_handle, Factor(5)));
+        frame(&resources);
+        assert_eq!(product.get(&resources), Some(&20));
+        assert!(product.error().is_none());
+    }
+
+    #[test]
+    fn pooled_recompute_keeps_last_value() {
+        let (server, mut resources) = setup();
+        resources.insert(AsyncComputePool::default()).unwrap();
+        let number = server.add_loaded_asset("number", Number(3));
+        let factor = server.add_loaded_asset("factor", Factor(2));
+        let number_handle = number.clone_strong().unwrap();
+        let factor_handle = factor.clone_strong().unwrap();
+
+        // Every computation waits until it is let through
+        let (gate, wait) = unbounded::<()>();
+        let mut product = AssetDescendantBuilder::new()
+            .depends_on(&number)
+            .depends_on(&factor)
+            .build(move |sources| {
+                wait.recv()?;
+                let factor = sources.get(&factor)?;
+                if factor.0 == 0 {
+                    return Err(anyhow!("factor must not be zero"));
+                }
+                Ok(sources.get(&number)?.0 * factor.0)
+            });
+        // Lets the running computation through
+        let finish = |product: &mut AssetDescendant<i32>, resources: &Resources| {
+            gate.send(()).unwrap();
+            for _ in 0..100 {
+                let value = product.get(resources).copied();
+                if !product.is_computing() {
+                    return value;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("computation did not finish");
+        };
+
+        frame(&resources);
+        assert_eq!(product.get(&resources), None);
+        assert!(product.is_computing());
+        assert_eq!(finish(&mut product, &resources), Some(6));
+
+        // The old value is kept while computing and after the computation failed
+        future::block_on(server.update_asset(&factor_handle, Factor(0)));
+        frame(&resources);
+        assert_eq!(product.get(&resources), Some(&6));
+        assert!(product.is_computing());
+        assert_eq!(finish(&mut product, &resources), Some(6));
+        assert!(product.error().is_some());
+
+        future::block_on(server.update_asset(&number_handle, Number(4)));
+        future::block_on(server.update_asset(&factor_handle, Factor(5)));
+        frame(&resources);
+        assert_eq!(product.get(&resources), Some(&6));
+        assert_eq!(finish(&mut product, &resources), Some(20));
+        assert!(product.error().is_none());
+    }
+
+    #[test]
+    fn failed_dependency_is_reported() {
+        let (server, mut resources) = setup();
+        resources.insert(server.clone()).unwrap();
+        let number = server.load_asset::<Number>("does/not/exist.number");
+        let mut double = AssetDescendantBuilder::new()
+            .depends_on(&number)
+            .build(move |sources| Ok(sources.get(&number)?.0 * 2));
+
+        for _ in 0..100 {
+            frame(&resources);
+            if double.get(&resources).is_none() && double.error().is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let error = double.error().expect("the failed load has to be reported");
+        assert!(error.to_string().contains("failed to load"));
+    }
+}
@@ -8,10 +8,12 @@ use crate::{
     file_spy::FileSpy,
     handle::{AssetHandle, AssetHandleUntyped, HandleId, LabelId},
     loader::{AssetLoader, LoadContext},
+    path::AssetPath,
 };
 use core::anyhow::{self, Result};
 use core::thiserror::{self, Error};
 
+use dashmap::{DashMap, DashSet};
 use ecs::prelude::Res;
 use std::{
     any::TypeId,
@@ -19,9 +21,9 @@ use std::{
     io::Read,
     ops::Deref,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
-use tasks::{futures::future, lock::RwLock, task_pool::TaskPool};
+use tasks::{futures::future, lock::RwLock, sync_lock::Mutex, task::Task, task_pool::TaskPool};
 
 #[derive(Debug, Error)]
 pub enum LoadAssetError {
@@ -35,6 +37,41 @@ pub enum LoadAssetError {
     LoaderError(#[from] anyhow::Error),
 }
 
+/// Strong handles of the files in a folder, with the asset type of their loader
+type FolderFiles = Mutex<Vec<(TypeId, AssetHandleUntyped)>>;
+
+/// Keeps every file of a loaded folder alive, files that are created in the folder later on are
+/// added for as long as the folder handle (or one of its clones) exists
+#[derive(Clone)]
+pub struct FolderHandle {
+    path: PathBuf,
+    files: Arc<FolderFiles>,
+}
+
+impl FolderHandle {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.lock().is_empty()
+    }
+
+    /// Weak handles of the files that are loaded as `A`
+    pub fn handles<A: Asset>(&self) -> Vec<AssetHandle<A>> {
+        self.files
+            .lock()
+            .iter()
+            .filter(|(type_id, _)| *type_id == TypeId::of::<A>())
+            .map(|(_, handle)| AssetHandle::weak(handle.id))
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct AssetServer {
     task_pool: TaskPool,
@@ -44,6 +81,16 @@ pub struct AssetServer {
     // Ref Counter Channels
     pub(crate) ref_counter: RefCounterMap,
     loaders: Arc<RwLock<Vec<Arc<dyn AssetLoader>>>>,
+    /// Files that are loaded (or currently loading), the entry is removed once the asset is unloaded
+    requested: Arc<DashSet<HandleId>>,
+    /// Files whose loader is still running
+    loading: Arc<DashSet<HandleId>>,
+    /// The tasks of the loads, finished ones are dropped by `update_system`
+    tasks: Arc<Mutex<Vec<Task<()>>>>,
+    /// Errors of the files that failed to load, by their path (so that sub assets find them too)
+    failed: Arc<DashMap<u64, String>>,
+    /// Watched folders, new files are added to the handle as long as it is alive
+    folders: Arc<Mutex<Vec<(PathBuf, Weak<FolderFiles>)>>>,
     /// the spy
     file_spy: Arc<FileSpy>,
 }
@@ -56,6 +103,11 @@ impl AssetServer {
             receivers: Default::default(),
             ref_counter: Default::default(),
             loaders: Default::default(),
+            requested: Default::default(),
+            loading: Default::default(),
+            tasks: Default::default(),
+            failed: Default::default(),
+            folders: Default::default(),
             file_spy: Default::default(),
         }
     }
@@ -64,8 +116,11 @@ impl AssetServer {
         let (tx, rx) = asset_pipe();
         self.senders.add_pipe::<A>(tx);
         self.receivers.add_pipe::<A>(rx.clone());
-        self.ref_counter.add_pipe::<A>();
-        Assets::new(rx, self.ref_counter.get_pipe::<A>().unwrap().1.clone())
+        Assets::new(
+            rx,
+            self.ref_counter.get_counter::<A>(),
+            self.requested.clone(),
+        )
     }
 
     pub async fn add_loader(&self, loader: impl AssetLoader + 'static) {
@@ -79,65 +134,175 @@ impl AssetServer {
 
     pub fn add_loaded_asset<A: Asset>(&self, label: impl AsRef<str>, asset: A) -> AssetHandle<A> {
         let id = HandleId::LabelId(label.into());
-        let ref_pipe = self
-            .ref_counter
-            .get_pipe::<A>()
-            .expect("[AssetServer] (add_loaded_asset) failed to get ref sender");
-        let handle: AssetHandle<A> = AssetHandle::strong(id, ref_pipe.0.clone());
-        future::block_on(self.senders.get_pipe::<A>().send((id, Box::new(asset))))
-            .expect("[AssetServer] (add_loaded_asset) failed to send asset");
+        let handle: AssetHandle<A> = AssetHandle::strong(id, self.ref_counter.get_counter::<A>());
+        future::block_on(self.senders.send(id, asset));
         handle
     }
 
     pub async fn update_asset<A: Asset>(&self, handle: &AssetHandle<A>, asset: A) {
-        self.senders
-            .get_pipe::<A>()
-            .send((handle.id, Box::new(asset)))
-            .await
-            .expect(&format!(
-                "[AssetServer] failed to update asset: {:?}",
-                handle
-            ))
+        self.senders.send(handle.id, asset).await
     }
 
+    /// Loads the asset at `path`
+    ///
+    /// A path that is loaded (or loading) already is not read again, the call only returns
+    /// another handle to it. Changes of the file are picked up by the file spy
     pub fn load_asset<A: Asset>(&self, path: impl AsRef<str>) -> AssetHandle<A> {
         self.load_asset_untyped(path, TypeId::of::<A>()).typed()
     }
 
+    /// Loads the asset at `path`, a label can be appended to request a sub asset, eg.
+    /// `"models/firetruck.gltf#Mesh/Ladder"`. Files are only read if they are not loaded already,
+    /// or if the requested sub asset was unloaded on its own
     pub fn load_asset_untyped(&self, path: impl AsRef<str>, type_id: TypeId) -> AssetHandleUntyped {
-        let path_buf = {
-            let mut buf = PathBuf::new(); // from(env!("CARGO_MANIFEST_DIR"));
-            buf.push(path.as_ref());
-            buf
+        let asset_path = AssetPath::parse(path.as_ref());
+        let path_buf = asset_path.path().to_path_buf();
+        let file = HandleId::from_path(&path_buf);
+        let id = HandleId::from_asset_path(asset_path);
+        let handle = self.strong_untyped(id, type_id);
+
+        // A running load of the file brings its sub assets along
+        let missing = !self.requested.contains(&file)
+            || (!self.requested.contains(&id) && !self.loading.contains(&file));
+        if missing {
+            self.requested.insert(id);
+            self.file_spy.watch_asset(path_buf.clone());
+            self.load_internal(path_buf);
+        }
+        handle
+    }
+
+    /// Why the asset of `id` (or the file it is a sub asset of) failed to load, None if it is
+    /// loaded, still loading or was never requested
+    pub fn load_error(&self, id: HandleId) -> Option<String> {
+        let path_id = id.path_id()?;
+        self.failed.get(&path_id).map(|error| error.clone())
+    }
+
+    /// Loads every file in `path` (and its subfolders) that has a registered loader
+    ///
+    /// The folder is watched recursively, files that are added later on will be loaded
+    /// automatically and announced through an `AssetEvent::Created`. The returned handle keeps
+    /// all of them loaded
+    pub fn load_folder(&self, path: impl AsRef<Path>) -> Result<FolderHandle, LoadAssetError> {
+        let path = path.as_ref();
+        let mut files = Vec::new();
+        collect_files(path, &mut files)?;
+
+        self.file_spy.watch_folder(path);
+
+        let handles = files
+            .into_iter()
+            .filter_map(|file| {
+                let type_id = self.loader_type(&file)?;
+                Some((type_id, self.load_untyped_internal(file, type_id)))
+            })
+            .collect();
+        let folder = FolderHandle {
+            path: path.to_path_buf(),
+            files: Arc::new(Mutex::new(handles)),
         };
+        self.folders
+            .lock()
+            .push((folder.path.clone(), Arc::downgrade(&folder.files)));
+        Ok(folder)
+    }
+
+    fn load_untyped_internal(&self, path: PathBuf, type_id: TypeId) -> AssetHandleUntyped {
+        let id = self.load_internal(path);
+        self.strong_untyped(id, type_id)
+    }
+
+    /// Loads a file that was created in a watched folder, the handle goes to every live folder
+    /// handle that contains it. Files that no handle would be created for are skipped
+    fn load_created(&self, path: PathBuf, type_id: TypeId) {
+        let id = HandleId::from_path(&path);
+        let mut folders = self.folders.lock();
+        folders.retain(|(_, files)| files.strong_count() > 0);
+        let owners: Vec<Arc<FolderFiles>> = folders
+            .iter()
+            .filter(|(folder, _)| path.starts_with(folder))
+            .filter_map(|(_, files)| files.upgrade())
+            .collect();
+        drop(folders);
+        if owners.is_empty() && !self.requested.contains(&id) {
+            return;
+        }
+
+        log::info!("[AssetServer] (update_system) found new asset {:?}", path);
+        // The handles exist before the asset can arrive
+        for files in owners {
+            let mut files = files.lock();
+            if !files.iter().any(|(_, handle)| handle.id == id) {
+                files.push((type_id, self.strong_untyped(id, type_id)));
+            }
+        }
+        self.load_internal(path);
+    }
 
-        self.file_spy.watch_asset(path_buf.clone());
+    fn strong_untyped(&self, id: HandleId, type_id: TypeId) -> AssetHandleUntyped {
+        AssetHandleUntyped::strong(id, self.ref_counter.get_counter_from_type(type_id))
+    }
 
-        let id = self.load_internal(path_buf);
-        let ref_pipe = self
-            .ref_counter
-            .get_pipe_from_type(type_id)
-            .expect("[AssetServer] (add_loaded_asset) failed to get ref sender");
-        AssetHandleUntyped::strong(id, ref_pipe.0.clone())
+    /// Returns the asset type of the loader that is responsible for `path`
+    fn loader_type(&self, path: &Path) -> Option<TypeId> {
+        let extension = path.extension()?.to_str()?;
+        let loaders = future::block_on(self.loaders.read());
+        loaders
+            .iter()
+            .find(|l| l.ext().contains(&extension))
+            .map(|l| l.asset_type())
     }
 
     fn load_internal(&self, path: impl AsRef<Path> + Send + 'static) -> HandleId {
         let id = HandleId::from_path(path.as_ref());
+        self.requested.insert(id);
+        self.loading.insert(id);
+        let path_id = id.path_id();
+        if let Some(path_id) = path_id {
+            self.failed.remove(&path_id);
+        }
         let server = self.clone();
-        {
-            let task = self.task_pool.spawn(async move {
-                let path = path.as_ref();
-                if let Err(e) = server.load_async(path, id).await {
-                    log::error!("[AssetServer] (load_async) failed to load asset {:?}", path);
-                    log::error!("{}", e);
+        let requested = self.requested.clone();
+        let loading = self.loading.clone();
+        let failed = self.failed.clone();
+        let task = self.task_pool.spawn(async move {
+            let path = path.as_ref();
+            let result = server.load_async(path, id).await;
+            loading.remove(&id);
+            if let Err(e) = result {
+                log::error!("[AssetServer] (load_async) failed to load asset {:?}", path);
+                log::error!("{}", e);
+                if let Some(path_id) = path_id {
+                    failed.insert(path_id, e.to_string());
                 }
-            });
-            // AAAAAAAAnd then we don't care about it anymore
-            task.detach();
-        }
+                // so that the next request will try again
+                requested.remove(&id);
+            }
+        });
+        // Kept so that `wait_for_loads` can block on it
+        self.tasks.lock().push(task);
         id
     }
 
+    /// Blocks until every load has finished, including the ones that were started by loaders
+    /// (eg. the shaders of a pipeline). Returns false if nothing was loading
+    ///
+    /// The loaded assets arrive with the next `Assets::update_system`
+    pub fn wait_for_loads(&self) -> bool {
+        let mut waited = false;
+        loop {
+            let tasks = std::mem::take(&mut *self.tasks.lock());
+            if tasks.is_empty() {
+                return waited;
+            }
+            for task in tasks {
+                future::block_on(task);
+            }
+            waited = true;
+        }
+    }
+
     async fn load_async(
         self,
         path: impl AsRef<Path>,
@@ -148,6 +313,7 @@ impl AssetServer {
             loaders,
             senders,
             ref_counter,
+            requested,
             ..
         } = self;
 
@@ -173,36 +339,84 @@ impl AssetServer {
                 ext: extension.into(),
             })?;
 
-        let load_context = LoadContext::new(senders, ref_counter, path, handle);
+        let load_context = LoadContext::new(senders, ref_counter, requested, path, handle);
         loader.load(&bytes, load_context).await?;
 
         Ok(())
     }
 
     pub fn update_system(server: Res<Self>) {
+        {
+            let mut tasks = server.tasks.lock();
+            let running = std::mem::take(&mut *tasks)
+                .into_iter()
+                .filter_map(|mut task| {
+                    future::block_on(future::poll_once(&mut task)).map_or(Some(task), |_| None)
+                });
+            tasks.extend(running);
+        }
+
+        let mut changed = Vec::new();
         while let Ok(event) = server.file_spy.rx().try_recv() {
             match event {
-                Ok(event) => {
-                    if let notify::Event {
-                        kind: notify::EventKind::Modify(_),
-                        paths,
-                        ..
-                    } = event
-                    {
-                        for path in paths {
-                            // TODO: factor out
-                            let path = path
-                                .strip_prefix(std::env::current_dir().expect(
-                                    "[AssetServer] (update_system) failed to get working dir",
-                                ))
-                                .expect("[AssetServer] (update_system) Failed to strip prefix")
-                                .to_path_buf();
-                            let _ = server.load_internal(path);
-                        }
+                Ok(notify::Event { kind, paths, .. }) => {
+                    let created = match kind {
+                        notify::EventKind::Modify(_) => false,
+                        notify::EventKind::Create(notify::event::CreateKind::Folder) => continue,
+                        notify::EventKind::Create(_) => true,
+                        _ => continue,
+                    };
+                    for path in paths {
+                        collapse_event(&mut changed, relative_path(path), created);
                     }
                 }
                 Err(e) => log::warn!("[AssetServer] (update_system) notify got an error: {}", e),
             }
         }
+
+        for (path, created) in changed {
+            // Folders are watched as a whole, so we will also see files we can't load
+            let type_id = match server.loader_type(&path) {
+                Some(type_id) => type_id,
+                None => continue,
+            };
+            if created {
+                server.load_created(path, type_id);
+            } else if server.requested.contains(&HandleId::from_path(&path)) {
+                // Only files that were requested are reloaded, the others have no handles
+                server.load_internal(path);
+            }
+        }
+    }
+}
+
+/// Adds a file event to the changes of this frame, so that every path is loaded once. Editors
+/// tend to report a new file as created and modified, such a file counts as created
+pub(crate) fn collapse_event(changed: &mut Vec<(PathBuf, bool)>, path: PathBuf, created: bool) {
+    match changed.iter_mut().find(|(changed, _)| *changed == path) {
+        Some((_, was_created)) => *was_created |= created,
+        None => changed.push((path, created)),
+    }
+}
+
+/// notify reports absolute paths, but assets are identified by the path relative to the working dir
+pub(crate) fn relative_path(path: PathBuf) -> PathBuf {
+    let current_dir =
+        std::env::current_dir().expect("[AssetServer] (relative_path) failed to get working dir");
+    match path.strip_prefix(current_dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
     }
+    Ok(())
 }
@@ -1,36 +1,98 @@
-use std::{any::type_name, hash::BuildHasherDefault};
+use std::{
+    any::type_name,
+    collections::{HashMap, VecDeque},
+    hash::BuildHasherDefault,
+    sync::Arc,
+};
 
 use crate::{
     asset::Asset,
-    channels::{AssetPipeReceiver, RefEvent, RefReceiver},
+    channels::{AssetPipeReceiver, RefCounter},
     handle::{AssetHandle, HandleId},
     prelude::AssetEvent,
+    stats::{AssetStats, AssetTypeStats},
 };
 use core::anyhow::anyhow;
-use dashmap::{mapref::one::Ref, DashMap};
-use ecs::prelude::{Events, Res, ResMut};
+use dashmap::{mapref::one::Ref, DashMap, DashSet};
+use ecs::prelude::{Events, ResMut};
 use hash_hasher::{HashBuildHasher, HashHasher};
 
 type Hasher = BuildHasherDefault<HashHasher>;
+/// The store is shared, so that derived data can be computed from assets on other threads
+pub(crate) type AssetStore<A> = Arc<DashMap<HandleId, A, Hasher>>;
+
+/// Decides what happens to an asset once its last strong handle is dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnloadPolicy {
+    /// Unload the asset in the same frame
+    Immediate,
+    /// Keep the asset around for a number of frames, so that it can be picked up again
+    Delayed { frames: u64 },
+    /// Keep unreferenced assets as long as the memory of all assets of this type stays
+    /// within `budget` bytes, the ones that were released first are unloaded first
+    Lru { budget: usize },
+}
+
+impl Default for UnloadPolicy {
+    fn default() -> Self {
+        Self::Immediate
+    }
+}
+
+fn default_size_bytes<A: Asset>(_: &A) -> usize {
+    std::mem::size_of::<A>()
+}
 
 /// Simple Collection for Assets
 pub struct Assets<A: Asset> {
-    store: DashMap<HandleId, A, Hasher>,
-    ref_count: DashMap<HandleId, u32, Hasher>,
+    store: AssetStore<A>,
     receiver: AssetPipeReceiver,
-    ref_receiver: RefReceiver,
+    ref_counter: Arc<RefCounter>,
+    // Unloading
+    policy: UnloadPolicy,
+    size_bytes: fn(&A) -> usize,
+    sizes: HashMap<HandleId, usize, Hasher>,
+    /// Sum of the sizes
+    total_bytes: usize,
+    /// Unreferenced assets together with the frame they were released in (oldest first)
+    unused: VecDeque<(HandleId, u64)>,
+    frame: u64,
+    ref_errors: usize,
+    /// Paths the asset server considers as loaded, shared so that unloaded paths will be read again
+    requested: Arc<DashSet<HandleId>>,
 }
 
 impl<A: Asset> Assets<A> {
-    pub(crate) fn new(receiver: AssetPipeReceiver, ref_receiver: RefReceiver) -> Self {
+    pub(crate) fn new(
+        receiver: AssetPipeReceiver,
+        ref_counter: Arc<RefCounter>,
+        requested: Arc<DashSet<HandleId>>,
+    ) -> Self {
         Self {
-            store: DashMap::with_hasher(HashBuildHasher::default()),
-            ref_count: DashMap::with_hasher(HashBuildHasher::default()),
+            store: Arc::new(DashMap::with_hasher(HashBuildHasher::default())),
             receiver,
-            ref_receiver,
+            ref_counter,
+            policy: Default::default(),
+            size_bytes: default_size_bytes::<A>,
+            sizes: HashMap::with_hasher(HashBuildHasher::default()),
+            total_bytes: 0,
+            unused: VecDeque::new(),
+            frame: 0,
+            ref_errors: 0,
+            requested,
         }
     }
 
+    /// Sets what happens to assets of this type once they are no longer referenced
+    pub fn set_unload_policy(&mut self, policy: UnloadPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets the function used to estimate the memory of a single asset, defaults to `size_of::<A>()`
+    pub fn set_size_bytes(&mut self, size_bytes: fn(&A) -> usize) {
+        self.size_bytes = size_bytes;
+    }
+
     pub fn query<'a>(
         &'a self,
         handles: &[AssetHandle<A>],
@@ -55,39 +117,346 @@ impl<A: Asset> Assets<A> {
             .expect("[Assets] failed to retrieve asset, maybe you want to use try_get?")
     }
 
-    // TODO: Asset Lifetime
-    pub fn update_system(assets: Res<Self>, mut events: ResMut<Events<AssetEvent<A>>>) {
+    pub(crate) fn contains(&self, id: HandleId) -> bool {
+        self.store.contains_key(&id)
+    }
+
+    pub(crate) fn store(&self) -> AssetStore<A> {
+        self.store.clone()
+    }
+
+    fn destroy(&mut self, id: HandleId, events: &mut Events<AssetEvent<A>>) {
+        // A new handle could have been created since the asset was released
+        if !self.ref_counter.remove_unused(id) {
+            return;
+        }
+        if let Some(size) = self.sizes.remove(&id) {
+            self.total_bytes -= size;
+        }
+        self.requested.remove(&id);
+        if self.store.remove(&id).is_some() {
+            log::info!(
+                "Destroying Asset {:?} of Asset Type {}",
+                id,
+                type_name::<A>()
+            );
+            events.send(AssetEvent::Destroyed(AssetHandle::weak(id)));
+        }
+    }
+
+    fn unload_unused(&mut self, events: &mut Events<AssetEvent<A>>) {
+        loop {
+            let evict = match (self.policy, self.unused.front()) {
+                (_, None) => false,
+                (UnloadPolicy::Immediate, Some(_)) => true,
+                (UnloadPolicy::Delayed { frames }, Some((_, released))) => {
+                    released + frames <= self.frame
+                }
+                (UnloadPolicy::Lru { budget }, Some(_)) => self.total_bytes > budget,
+            };
+            if !evict {
+                break;
+            }
+            if let Some((id, _)) = self.unused.pop_front() {
+                self.destroy(id, events);
+            }
+        }
+    }
+
+    pub fn update_system(
+        mut assets: ResMut<Self>,
+        mut events: ResMut<Events<AssetEvent<A>>>,
+        mut stats: ResMut<AssetStats>,
+    ) {
+        let assets = &mut *assets;
+        assets.frame += 1;
+
+        let mut orphans = Vec::new();
         while let Some((id, asset)) = assets.receiver.try_receive() {
-            if let Ok(asset) = asset.downcast() {
+            if let Ok(asset) = asset.downcast::<A>() {
+                let size = (assets.size_bytes)(&asset);
+                assets.total_bytes += size;
+                if let Some(previous) = assets.sizes.insert(id, size) {
+                    assets.total_bytes -= previous;
+                }
                 match assets.store.insert(id, *asset) {
                     Some(_) => events.send(AssetEvent::Updated(AssetHandle::weak(id))),
                     None => events.send(AssetEvent::Created(AssetHandle::weak(id))),
                 }
+                // The last handle might have been dropped (and released) while it was loading
+                if assets.ref_counter.count(id) == 0 {
+                    orphans.push(id);
+                }
             }
         }
-        // Ref Counter events
-        while let Ok(e) = assets.ref_receiver.try_recv() {
-            match e {
-                RefEvent::Increase { id } => {
-                    let mut count = assets.ref_count.entry(id).or_insert(0);
-                    *count.value_mut() += 1
-                }
-                RefEvent::Decrease { id } => {
-                    let mut count = assets
-                        .ref_count
-                        .get_mut(&id)
-                        .expect("[Assets] (update_system) Decrease on non existing handle");
-                    *count.value_mut() -= 1;
-                    if *count.value() == 0u32 {
-                        log::info!(
-                            "Destroying Asset {:?} of Asset Type {}",
-                            id,
-                            type_name::<A>()
-                        );
-                        assets.store.remove(&id);
-                    }
-                }
+        // Ref Counter
+        let counter = &assets.ref_counter;
+        assets.unused.retain(|(id, _)| counter.count(*id) == 0);
+        for id in assets.ref_counter.take_released() {
+            // The count might have been increased again in the meantime
+            if assets.ref_counter.count(id) == 0 {
+                assets.unused.push_back((id, assets.frame));
             }
         }
+        for id in orphans {
+            let queued = assets.unused.iter().any(|(unused, _)| *unused == id);
+            if !queued && assets.ref_counter.count(id) == 0 {
+                assets.unused.push_back((id, assets.frame));
+            }
+        }
+        for id in assets.ref_counter.take_inconsistent() {
+            log::error!(
+                "[Assets] (update_system) handle {:?} of Asset Type {} was released more often than acquired",
+                id,
+                type_name::<A>()
+            );
+            assets.ref_errors += 1;
+        }
+
+        assets.unload_unused(&mut events);
+
+        let unreferenced_bytes = assets
+            .unused
+            .iter()
+            .filter_map(|(id, _)| assets.sizes.get(id))
+            .sum();
+        stats.update::<A>(AssetTypeStats {
+            name: type_name::<A>(),
+            loaded: assets.store.len(),
+            unreferenced: assets.unused.len(),
+            bytes: assets.total_bytes,
+            unreferenced_bytes,
+            ref_errors: assets.ref_errors,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::AssetServer;
+    use ecs::prelude::Resources;
+    use tasks::futures::future;
+
+    struct Number(i32);
+
+    fn setup() -> (AssetServer, Resources) {
+        let server = AssetServer::new(tasks::ComputePool::default());
+        let mut resources = Resources::new();
+        resources.insert(server.register_asset::<Number>()).unwrap();
+        resources
+            .insert(Events::<AssetEvent<Number>>::new())
+            .unwrap();
+        resources.insert(AssetStats::default()).unwrap();
+        (server, resources)
+    }
+
+    fn frame(resources: &Resources) {
+        Assets::<Number>::update_system(
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+        );
+        resources
+            .get_mut::<Events<AssetEvent<Number>>>()
+            .unwrap()
+            .update();
+    }
+
+    fn is_loaded(resources: &Resources, handle: &AssetHandle<Number>) -> bool {
+        resources
+            .get::<Assets<Number>>()
+            .unwrap()
+            .try_get(handle)
+            .is_some()
+    }
+
+    fn stats(resources: &Resources) -> AssetTypeStats {
+        *resources
+            .get::<AssetStats>()
+            .unwrap()
+            .get::<Number>()
+            .unwrap()
+    }
+
+    #[test]
+    fn drop_last_handle_unloads() {
+        let (server, resources) = setup();
+        let handle = server.add_loaded_asset("one", Number(1));
+        let weak = handle.as_weak();
+        frame(&resources);
+        assert_eq!(resources.get::<Assets<Number>>().unwrap().get(&weak).0, 1);
+
+        drop(handle);
+        frame(&resources);
+        assert!(!is_loaded(&resources, &weak));
+        let events = resources.get::<Events<AssetEvent<Number>>>().unwrap();
+        assert!(events.iter().any(|e| e.is_destroyed()));
+    }
+
+    #[test]
+    fn drop_before_asset_arrives() {
+        let (server, resources) = setup();
+        let weak = server.add_loaded_asset("one", Number(1)).as_weak();
+        frame(&resources);
+        assert!(!is_loaded(&resources, &weak));
+    }
+
+    #[test]
+    fn asset_arriving_after_destroy_is_unloaded() {
+        let (server, resources) = setup();
+        let handle = AssetHandle::<Number>::strong(
+            HandleId::LabelId("one".into()),
+            server.ref_counter.get_counter::<Number>(),
+        );
+        let weak = handle.as_weak();
+        drop(handle);
+        frame(&resources);
+
+        // eg. a load that finishes after its handle is gone
+        future::block_on(server.update_asset(&weak, Number(1)));
+        frame(&resources);
+        assert!(!is_loaded(&resources, &weak));
+        assert_eq!(stats(&resources).loaded, 0);
+    }
+
+    #[test]
+    fn unregistered_assets_are_dropped() {
+        let (server, resources) = setup();
+        let handle = server.add_loaded_asset("other", 1u32);
+        future::block_on(server.update_asset(&handle, 2u32));
+        frame(&resources);
+        assert!(resources.get::<Assets<u32>>().is_err());
+    }
+
+    #[test]
+    fn clone_keeps_asset_alive() {
+        let (server, resources) = setup();
+        let handle = server.add_loaded_asset("one", Number(1));
+        let clone = handle.clone_strong().unwrap();
+        drop(handle);
+        frame(&resources);
+        assert!(is_loaded(&resources, &clone));
+
+        drop(clone);
+        frame(&resources);
+        assert!(!is_loaded(
+            &resources,
+            &AssetHandle::weak(HandleId::LabelId("one".into()))
+        ));
+    }
+
+    #[test]
+    fn handles_outlive_store() {
+        let (server, resources) = setup();
+        let handle = server.add_loaded_asset("one", Number(1));
+        frame(&resources);
+        drop(resources);
+        // must neither panic nor report an error
+        drop(handle);
+    }
+
+    #[test]
+    fn untyped_conversion_keeps_count() {
+        let (server, resources) = setup();
+        let id = HandleId::LabelId("one".into());
+        let counter = server.ref_counter.get_counter::<Number>();
+        let untyped = crate::handle::AssetHandleUntyped::strong(id, counter.clone());
+        let typed = untyped.typed::<Number>();
+        assert_eq!(counter.count(id), 1);
+        drop(typed);
+        assert_eq!(counter.count(id), 0);
+        frame(&resources);
+        assert_eq!(stats(&resources).ref_errors, 0);
+    }
+
+    #[test]
+    fn over_release_is_reported() {
+        let (server, resources) = setup();
+        let counter = server.ref_counter.get_counter::<Number>();
+        counter.decrease(HandleId::LabelId("never acquired".into()));
+        frame(&resources);
+        assert_eq!(stats(&resources).ref_errors, 1);
+    }
+
+    #[test]
+    fn delayed_unload_can_be_picked_up() {
+        let (server, resources) = setup();
+        resources
+            .get_mut::<Assets<Number>>()
+            .unwrap()
+            .set_unload_policy(UnloadPolicy::Delayed { frames: 2 });
+        let handle = server.add_loaded_asset("one", Number(1));
+        let mut weak = handle.as_weak();
+        frame(&resources);
+
+        drop(handle);
+        frame(&resources);
+        assert!(is_loaded(&resources, &weak));
+        assert_eq!(stats(&resources).unreferenced, 1);
+
+        // picked up again, survives the delay
+        weak.make_strong(&server);
+        frame(&resources);
+        frame(&resources);
+        assert!(is_loaded(&resources, &weak));
+
+        drop(weak);
+        for _ in 0..3 {
+            frame(&resources);
+        }
+        assert!(!is_loaded(
+            &resources,
+            &AssetHandle::weak(HandleId::LabelId("one".into()))
+        ));
+    }
+
+    #[test]
+    fn lru_keeps_within_budget() {
+        let (server, resources) = setup();
+        {
+            let mut assets = resources.get_mut::<Assets<Number>>().unwrap();
+            assets.set_size_bytes(|_| 10);
+            assets.set_unload_policy(UnloadPolicy::Lru { budget: 20 });
+        }
+        let handles: Vec<_> = (0..3)
+            .map(|i| server.add_loaded_asset(i.to_string(), Number(i)))
+            .collect();
+        let weak: Vec<_> = handles.iter().map(|h| h.as_weak()).collect();
+        frame(&resources);
+        assert_eq!(stats(&resources).bytes, 30);
+
+        drop(handles);
+        frame(&resources);
+        // the first released one is gone, the rest fits into the budget
+        assert!(!is_loaded(&resources, &weak[0]));
+        assert!(is_loaded(&resources, &weak[1]));
+        assert!(is_loaded(&resources, &weak[2]));
+    }
+
+    #[test]
+    fn lru_budget_follows_updated_sizes() {
+        let (server, resources) = setup();
+        {
+            let mut assets = resources.get_mut::<Assets<Number>>().unwrap();
+            assets.set_size_bytes(|number| number.0 as usize);
+            assets.set_unload_policy(UnloadPolicy::Lru { budget: 10 });
+        }
+        let big = server.add_loaded_asset("big", Number(8));
+        let other = server.add_loaded_asset("other", Number(6)).as_weak();
+        frame(&resources);
+        // over budget, the released one goes
+        assert!(!is_loaded(&resources, &other));
+
+        // the replaced asset no longer counts with its old size
+        let small = server.add_loaded_asset("big", Number(2));
+        let other = server.add_loaded_asset("other", Number(6));
+        frame(&resources);
+        let weak = other.as_weak();
+        drop(other);
+        frame(&resources);
+        assert!(is_loaded(&resources, &weak));
+        assert_eq!(stats(&resources).bytes, 8);
+        drop((big, small));
     }
 }
@@ -1,13 +1,16 @@
-use std::{any::TypeId, ops::Deref, sync::Arc};
+use std::{
+    any::{type_name, TypeId},
+    sync::Arc,
+};
 
 use dashmap::{mapref::one::Ref, DashMap};
-use tasks::channel::{Receiver, Sender};
-
-use crate::{
-    handle::{AssetHandleUntyped, HandleId},
-    prelude::{Asset, AssetHandle},
+use tasks::{
+    channel::{Receiver, Sender},
+    sync_lock::Mutex,
 };
 
+use crate::{asset::Asset, handle::HandleId};
+
 type AssetPair = (HandleId, Box<dyn Asset>);
 
 #[derive(Clone, Debug)]
@@ -28,9 +31,33 @@ impl AssetSenderMap {
         self.0.insert(type_id, pipe);
     }
 
-    pub fn get_pipe<A: Asset>(&self) -> Ref<TypeId, AssetPipeSender> {
+    /// The pipe of an asset type, None if the type was never registered
+    pub fn get_pipe<A: Asset>(&self) -> Option<Ref<TypeId, AssetPipeSender>> {
         let type_id = std::any::TypeId::of::<A>();
-        self.0.get(&type_id).unwrap()
+        self.0.get(&type_id)
+    }
+
+    /// Sends an asset to the `Assets` of its type, the asset is dropped (and an error logged) if
+    /// the type was never registered or its `Assets` are gone
+    pub async fn send<A: Asset>(&self, id: HandleId, asset: A) {
+        let pipe = match self.get_pipe::<A>() {
+            Some(pipe) => pipe.value().clone(),
+            None => {
+                log::error!(
+                    "[AssetSenderMap] (send) asset type {} is not registered, dropping {:?}",
+                    type_name::<A>(),
+                    id
+                );
+                return;
+            }
+        };
+        if pipe.send((id, Box::new(asset))).await.is_err() {
+            log::error!(
+                "[AssetSenderMap] (send) assets of type {} are gone, dropping {:?}",
+                type_name::<A>(),
+                id
+            );
+        }
     }
 }
 
@@ -56,9 +83,9 @@ impl AssetReceiverMap {
         self.0.insert(type_id, pipe);
     }
 
-    pub fn get_pipe<A: Asset>(&self) -> Ref<TypeId, AssetPipeReceiver> {
+    pub fn get_pipe<A: Asset>(&self) -> Option<Ref<TypeId, AssetPipeReceiver>> {
         let type_id = std::any::TypeId::of::<A>();
-        self.0.get(&type_id).unwrap()
+        self.0.get(&type_id)
     }
 }
 
@@ -68,39 +95,72 @@ pub fn asset_pipe() -> (AssetPipeSender, AssetPipeReceiver) {
 }
 
 /// ****************************************
-/// region: RefCounter Channels
-
-#[derive(Debug, Clone)]
-pub enum RefEvent {
-    Increase { id: HandleId },
-    Decrease { id: HandleId },
+/// region: RefCounter
+
+/// Shared reference counts of all handles of one asset type
+///
+/// Handles update the counts directly, so none of the handle operations can fail. Counts that
+/// drop to zero are collected and picked up by `Assets::update_system`
+#[derive(Debug, Default)]
+pub struct RefCounter {
+    counts: DashMap<HandleId, u32>,
+    released: Mutex<Vec<HandleId>>,
+    /// Ids that were decreased more often than increased
+    inconsistent: Mutex<Vec<HandleId>>,
 }
 
-pub type RefReceiver = Receiver<RefEvent>;
-pub type RefSender = Sender<RefEvent>;
+impl RefCounter {
+    pub fn increase(&self, id: HandleId) {
+        *self.counts.entry(id).or_insert(0) += 1;
+    }
+
+    pub fn decrease(&self, id: HandleId) {
+        let released = match self.counts.get_mut(&id) {
+            Some(mut count) if *count > 0 => {
+                *count -= 1;
+                *count == 0
+            }
+            _ => {
+                self.inconsistent.lock().push(id);
+                return;
+            }
+        };
+        if released {
+            self.released.lock().push(id);
+        }
+    }
+
+    /// Current count of strong handles for `id`
+    pub fn count(&self, id: HandleId) -> u32 {
+        self.counts.get(&id).map(|c| *c).unwrap_or(0)
+    }
 
-pub fn ref_pipe() -> (RefSender, RefReceiver) {
-    tasks::channel::unbounded()
+    /// Removes the entry of `id`, if no new handle has been created in the meantime
+    pub(crate) fn remove_unused(&self, id: HandleId) -> bool {
+        self.counts.remove_if(&id, |_, count| *count == 0).is_some()
+            || !self.counts.contains_key(&id)
+    }
+
+    pub(crate) fn take_released(&self) -> Vec<HandleId> {
+        std::mem::take(&mut *self.released.lock())
+    }
+
+    pub(crate) fn take_inconsistent(&self) -> Vec<HandleId> {
+        std::mem::take(&mut *self.inconsistent.lock())
+    }
 }
 
 #[derive(Debug, Clone, Default)]
-pub struct RefCounterMap(Arc<DashMap<TypeId, (RefSender, RefReceiver)>>);
+pub struct RefCounterMap(Arc<DashMap<TypeId, Arc<RefCounter>>>);
 
 impl RefCounterMap {
-    pub fn add_pipe<A: Asset>(&self) {
-        let type_id = TypeId::of::<A>();
-        self.0.insert(type_id, ref_pipe());
+    pub fn get_counter<A: Asset>(&self) -> Arc<RefCounter> {
+        self.get_counter_from_type(TypeId::of::<A>())
     }
 
-    pub fn get_pipe<A: Asset>(&self) -> Option<impl Deref<Target = (RefSender, RefReceiver)> + '_> {
-        let type_id = TypeId::of::<A>();
-        self.get_pipe_from_type(type_id)
-    }
-
-    pub fn get_pipe_from_type(
-        &self,
-        type_id: TypeId,
-    ) -> Option<impl Deref<Target = (RefSender, RefReceiver)> + '_> {
-        self.0.get(&type_id)
+    /// Gets the counter of an asset type, the counter is created if the type was not registered
+    /// (yet), so that handles can be created in any order
+    pub fn get_counter_from_type(&self, type_id: TypeId) -> Arc<RefCounter> {
+        self.0.entry(type_id).or_default().clone()
     }
 }
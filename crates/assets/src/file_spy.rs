@@ -30,12 +30,27 @@ impl FileSpy {
         &self.rx
     }
 
+    /// A path that can't be watched (eg. because it does not exist) is only logged, its load
+    /// will report the actual error
     pub(crate) fn watch_asset<P: AsRef<Path>>(&self, path: P) {
-        log::debug!("Will watch: {:?}", path.as_ref());
-        self.watcher
+        let path = path.as_ref();
+        log::debug!("Will watch: {:?}", path);
+        if let Err(e) = self
+            .watcher
             .lock()
             .watch(path, notify::RecursiveMode::NonRecursive)
-            .expect("[FileSpy] (watch_asset) failed to watch path");
+        {
+            log::warn!("[FileSpy] (watch_asset) failed to watch {:?}: {}", path, e);
+        }
+    }
+
+    /// Watches a whole folder (including subfolders), so that newly created files will also be reported
+    pub(crate) fn watch_folder<P: AsRef<Path>>(&self, path: P) {
+        log::debug!("Will watch folder: {:?}", path.as_ref());
+        self.watcher
+            .lock()
+            .watch(path, notify::RecursiveMode::Recursive)
+            .expect("[FileSpy] (watch_folder) failed to watch path");
     }
 }
 
@@ -3,14 +3,10 @@ use std::{
     fmt::Debug,
     hash::{Hash, Hasher},
     path::Path,
+    sync::Arc,
 };
 
-use crate::{
-    asset::Asset,
-    channels::{RefEvent, RefSender},
-    path::AssetPath,
-    prelude::AssetServer,
-};
+use crate::{asset::Asset, channels::RefCounter, path::AssetPath, prelude::AssetServer};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub enum HandleId {
@@ -32,6 +28,14 @@ impl HandleId {
     pub fn from_path(path: &Path) -> Self {
         Self::AssetPathId(AssetPath::from_path(path).into())
     }
+
+    /// Identifies the file of the asset, shared by the file and its sub assets
+    pub(crate) fn path_id(&self) -> Option<u64> {
+        match self {
+            Self::AssetPathId(id) => Some(id.path_id),
+            Self::LabelId(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
@@ -76,7 +80,7 @@ impl LabelId {
 
 #[derive(Debug)]
 pub enum HandleType {
-    Strong(RefSender),
+    Strong(Arc<RefCounter>),
     Weak,
 }
 
@@ -117,13 +121,11 @@ impl<A: Asset> AssetHandle<A> {
         }
     }
 
-    pub fn strong(id: HandleId, sender: RefSender) -> Self {
-        sender
-            .try_send(RefEvent::Increase { id })
-            .expect("[AssetHandle] (strong) failed to send increase");
+    pub fn strong(id: HandleId, counter: Arc<RefCounter>) -> Self {
+        counter.increase(id);
         Self {
             id,
-            handle_type: HandleType::Strong(sender),
+            handle_type: HandleType::Strong(counter),
             _marker: Default::default(),
         }
     }
@@ -138,13 +140,9 @@ impl<A: Asset> AssetHandle<A> {
 
     pub fn make_strong(&mut self, server: &AssetServer) {
         if !self.is_strong() {
-            let ref_pipe = server
-                .ref_counter
-                .get_pipe::<A>()
-                .expect("[AssetHandle] (make_strong) failed to get sender");
-            let sender = ref_pipe.0.clone();
-            sender.try_send(RefEvent::Increase { id: self.id });
-            self.handle_type = HandleType::Strong(sender);
+            let counter = server.ref_counter.get_counter::<A>();
+            counter.increase(self.id);
+            self.handle_type = HandleType::Strong(counter);
         }
     }
 
@@ -161,17 +159,7 @@ impl<A: Asset> AssetHandle<A> {
     /// Returns None if the Handle is not strong
     pub fn clone_strong(&self) -> Option<Self> {
         match &self.handle_type {
-            HandleType::Strong(sender) => {
-                let sender = sender.clone();
-                sender
-                    .try_send(RefEvent::Increase { id: self.id })
-                    .expect("[AssetHandle] (clone_strong) failed to send ref event");
-                Some(Self {
-                    id: self.id,
-                    handle_type: HandleType::Strong(sender),
-                    _marker: Default::default(),
-                })
-            }
+            HandleType::Strong(counter) => Some(Self::strong(self.id, counter.clone())),
             HandleType::Weak => None,
         }
     }
@@ -179,8 +167,8 @@ impl<A: Asset> AssetHandle<A> {
 
 impl<A: Asset> Drop for AssetHandle<A> {
     fn drop(&mut self) {
-        if let HandleType::Strong(sender) = &self.handle_type {
-            let _ = sender.try_send(RefEvent::Decrease { id: self.id });
+        if let HandleType::Strong(counter) = &self.handle_type {
+            counter.decrease(self.id);
         }
     }
 }
@@ -199,13 +187,11 @@ impl AssetHandleUntyped {
         }
     }
 
-    pub fn strong(id: HandleId, sender: RefSender) -> Self {
-        sender
-            .try_send(RefEvent::Increase { id })
-            .expect("[AssetHandle] (strong) failed to send increase");
+    pub fn strong(id: HandleId, counter: Arc<RefCounter>) -> Self {
+        counter.increase(id);
         Self {
             id,
-            handle_type: HandleType::Strong(sender),
+            handle_type: HandleType::Strong(counter),
         }
     }
 
@@ -217,25 +203,20 @@ impl AssetHandleUntyped {
         matches!(self.handle_type, HandleType::Weak)
     }
 
-    pub fn typed<A: Asset>(self) -> AssetHandle<A> {
-        match &self.handle_type {
-            HandleType::Strong(sender) => {
-                // first we will need to send an increase event, so that the drop function of self will not destroy the asset
-                sender
-                    .try_send(RefEvent::Increase { id: self.id })
-                    .expect("[AssetHandleUntyped] (typed) failed to send ref event");
-
-                AssetHandle::strong(self.id, sender.clone())
-            }
-            HandleType::Weak => AssetHandle::weak(self.id),
+    /// Converts into a typed handle, a strong handle keeps its reference (the count is not touched)
+    pub fn typed<A: Asset>(mut self) -> AssetHandle<A> {
+        AssetHandle {
+            id: self.id,
+            handle_type: std::mem::replace(&mut self.handle_type, HandleType::Weak),
+            _marker: Default::default(),
         }
     }
 }
 
 impl Drop for AssetHandleUntyped {
     fn drop(&mut self) {
-        if let HandleType::Strong(sender) = &self.handle_type {
-            let _ = sender.try_send(RefEvent::Decrease { id: self.id });
+        if let HandleType::Strong(counter) = &self.handle_type {
+            counter.decrease(self.id);
         }
     }
 }
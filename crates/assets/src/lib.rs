@@ -11,32 +11,42 @@ mod file_spy;
 pub mod handle;
 pub mod loader;
 pub mod path;
+pub mod stats;
 
 pub use def::*;
 
 pub mod prelude {
     pub use crate::{
         asset::Asset,
-        asset_descendant::AssetDescendant,
-        asset_server::AssetServer,
-        assets::Assets,
+        asset_descendant::{AssetDescendant, AssetDescendantBuilder, DescendantSources},
+        asset_server::{AssetServer, FolderHandle},
+        assets::{Assets, UnloadPolicy},
         def::BoxedFuture,
         events::AssetEvent,
-        handle::AssetHandle,
+        handle::{AssetHandle, AssetHandleUntyped, HandleId},
         loader::{AssetLoader, LoadContext},
+        stats::AssetStats,
     };
 }
 
 #[cfg(test)]
 mod tests {
     use core::anyhow;
-    use std::{cell::RefCell, thread, time::Duration};
+    use std::{
+        fs,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
 
-    use assets::Assets;
+    use ecs::prelude::{Events, Resources};
 
     use crate::loader::AssetLoader;
 
-    use super::*;
+    use super::{prelude::*, *};
     struct SimpleAsset {
         number: i32,
     }
@@ -54,11 +64,7 @@ mod tests {
                 let input = String::from_utf8_lossy(bytes);
                 // now we parse it to an intn
                 let number = input.trim().parse::<i32>()?;
-                ctx.server
-                    .channels
-                    .get_channel::<SimpleAsset>()
-                    .send(ctx.handle.typed(), SimpleAsset { number })
-                    .await;
+                ctx.send_asset(SimpleAsset { number }).await;
                 Ok(())
             })
         }
@@ -66,37 +72,220 @@ mod tests {
         fn ext(&self) -> &[&str] {
             &["dat"]
         }
+
+        fn asset_type(&self) -> std::any::TypeId {
+            std::any::TypeId::of::<SimpleAsset>()
+        }
+    }
+
+    /// Counts how often a file was read, the number is also sent doubled as `#Double`
+    struct CountingLoader(Arc<AtomicUsize>);
+
+    impl AssetLoader for CountingLoader {
+        fn load<'a>(
+            &'a self,
+            bytes: &'a [u8],
+            ctx: loader::LoadContext<'a>,
+        ) -> BoxedFuture<'a, anyhow::Result<()>> {
+            Box::pin(async move {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                let number = String::from_utf8_lossy(bytes).trim().parse::<i32>()?;
+                ctx.send_asset(SimpleAsset { number }).await;
+                ctx.add_asset_with_label("Double", SimpleAsset { number: 2 * number })
+                    .await;
+                Ok(())
+            })
+        }
+
+        fn ext(&self) -> &[&str] {
+            &["cnt"]
+        }
+
+        fn asset_type(&self) -> std::any::TypeId {
+            std::any::TypeId::of::<SimpleAsset>()
+        }
     }
 
     #[test]
     fn use_case() {
         let task_pool = tasks::ComputePool::default();
 
-        let mut server = asset_server::AssetServer::new(task_pool);
-        // NOTE(luca): We need to use ref cell here so simulate the resource system
-        // (although that should probably also not rely on refcell 😟)
-        let simple_assets = RefCell::new(server.register_asset::<SimpleAsset>());
-        server.add_loader(SimpleLoader {});
-
-        // println!("{:?}", path_buf);
-        let handle = match server.load_asset("../../assets/file.dat") {
-            Ok(h) => h,
-            Err(e) => {
-                println!("failed to load file.dat, with error: {}", e);
-                panic!()
-            }
-        };
+        let server = asset_server::AssetServer::new(task_pool);
+        // NOTE(luca): the resources simulate what the app would do
+        let mut resources = Resources::new();
+        resources
+            .insert(server.register_asset::<SimpleAsset>())
+            .unwrap();
+        resources
+            .insert(Events::<AssetEvent<SimpleAsset>>::new())
+            .unwrap();
+        resources.insert(AssetStats::default()).unwrap();
+        server.add_loader_sync(SimpleLoader {});
 
-        while simple_assets.borrow().try_get(&handle).is_none() {
-            Assets::<SimpleAsset>::update_system(simple_assets.borrow());
+        let handle = server.load_asset("../../assets/file.dat");
+
+        while resources
+            .get::<Assets<SimpleAsset>>()
+            .unwrap()
+            .try_get(&handle)
+            .is_none()
+        {
+            Assets::<SimpleAsset>::update_system(
+                resources.get_mut().unwrap(),
+                resources.get_mut().unwrap(),
+                resources.get_mut().unwrap(),
+            );
             thread::sleep(Duration::from_millis(200u64))
         }
         // And now we check if everything is correct
         {
-            let simple_assets = simple_assets.borrow();
+            let simple_assets = resources.get::<Assets<SimpleAsset>>().unwrap();
             let my_asset = simple_assets.try_get(&handle);
             assert!(my_asset.is_some());
             assert_eq!(my_asset.unwrap().number, 1023);
         }
     }
+
+    #[test]
+    fn folders_load_files_with_a_loader() {
+        let dir = std::env::temp_dir().join(format!("assets_folder_{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("one.dat"), "1").unwrap();
+        fs::write(dir.join("nested/two.dat"), "2").unwrap();
+        fs::write(dir.join("notes.txt"), "no loader").unwrap();
+
+        let server = asset_server::AssetServer::new(tasks::ComputePool::default());
+        let mut resources = Resources::new();
+        resources
+            .insert(server.register_asset::<SimpleAsset>())
+            .unwrap();
+        resources
+            .insert(Events::<AssetEvent<SimpleAsset>>::new())
+            .unwrap();
+        resources.insert(AssetStats::default()).unwrap();
+        server.add_loader_sync(SimpleLoader {});
+        resources.insert(server.clone()).unwrap();
+
+        let folder = server.load_folder(&dir).unwrap();
+        assert_eq!(folder.len(), 2);
+        let update = |resources: &Resources| {
+            AssetServer::update_system(resources.get().unwrap());
+            Assets::<SimpleAsset>::update_system(
+                resources.get_mut().unwrap(),
+                resources.get_mut().unwrap(),
+                resources.get_mut().unwrap(),
+            );
+        };
+        let numbers = |resources: &Resources| {
+            let assets = resources.get::<Assets<SimpleAsset>>().unwrap();
+            let mut numbers: Vec<i32> = folder
+                .handles::<SimpleAsset>()
+                .iter()
+                .filter_map(|handle| assets.try_get(handle).map(|asset| asset.number))
+                .collect();
+            numbers.sort_unstable();
+            numbers
+        };
+        for _ in 0..50 {
+            update(&resources);
+            if numbers(&resources).len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(numbers(&resources), vec![1, 2]);
+
+        // Files that are created later on are owned by the folder as well
+        fs::write(dir.join("nested/three.dat"), "3").unwrap();
+        for _ in 0..50 {
+            update(&resources);
+            if numbers(&resources).len() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(numbers(&resources), vec![1, 2, 3]);
+
+        let working_dir = std::env::current_dir().unwrap();
+        assert_eq!(
+            asset_server::relative_path(working_dir.join("models/tree.gltf")),
+            std::path::Path::new("models/tree.gltf")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_events_are_collapsed_per_path() {
+        let mut changed = Vec::new();
+        asset_server::collapse_event(&mut changed, "new.dat".into(), true);
+        asset_server::collapse_event(&mut changed, "new.dat".into(), false);
+        asset_server::collapse_event(&mut changed, "old.dat".into(), false);
+        asset_server::collapse_event(&mut changed, "old.dat".into(), false);
+        assert_eq!(
+            changed,
+            vec![("new.dat".into(), true), ("old.dat".into(), false)]
+        );
+    }
+
+    #[test]
+    fn paths_are_read_once_and_sub_assets_again() {
+        let dir = std::env::temp_dir().join(format!("assets_labels_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("number.cnt").to_string_lossy().into_owned();
+        fs::write(&path, "3").unwrap();
+
+        let server = asset_server::AssetServer::new(tasks::ComputePool::default());
+        let mut resources = Resources::new();
+        resources
+            .insert(server.register_asset::<SimpleAsset>())
+            .unwrap();
+        resources
+            .insert(Events::<AssetEvent<SimpleAsset>>::new())
+            .unwrap();
+        resources.insert(AssetStats::default()).unwrap();
+        let reads = Arc::new(AtomicUsize::new(0));
+        server.add_loader_sync(CountingLoader(reads.clone()));
+
+        let number = |resources: &Resources, handle: &AssetHandle<SimpleAsset>| {
+            let assets = resources.get::<Assets<SimpleAsset>>().unwrap();
+            assets.try_get(handle).map(|asset| asset.number)
+        };
+        let wait_for = |resources: &Resources, handle: &AssetHandle<SimpleAsset>| {
+            for _ in 0..50 {
+                Assets::<SimpleAsset>::update_system(
+                    resources.get_mut().unwrap(),
+                    resources.get_mut().unwrap(),
+                    resources.get_mut().unwrap(),
+                );
+                if number(resources, handle).is_some() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            number(resources, handle)
+        };
+
+        let file = server.load_asset::<SimpleAsset>(&path);
+        let again = server.load_asset::<SimpleAsset>(&path);
+        let double = server.load_asset::<SimpleAsset>(format!("{}#Double", path));
+        assert_eq!(wait_for(&resources, &file), Some(3));
+        assert_eq!(wait_for(&resources, &double), Some(6));
+        assert_eq!(file, again);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // The file stays loaded, so only the sub asset is gone
+        let weak = double.as_weak();
+        drop(double);
+        Assets::<SimpleAsset>::update_system(
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+            resources.get_mut().unwrap(),
+        );
+        assert_eq!(number(&resources, &weak), None);
+
+        let double = server.load_asset::<SimpleAsset>(format!("{}#Double", path));
+        assert_eq!(wait_for(&resources, &double), Some(6));
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,4 +1,6 @@
-use std::{borrow::Cow, path::Path};
+use std::{any::TypeId, borrow::Cow, path::Path, sync::Arc};
+
+use dashmap::DashSet;
 
 use crate::{
     channels::{AssetSenderMap, RefCounterMap},
@@ -12,6 +14,8 @@ use crate::{
 pub struct LoadContext<'a> {
     pub senders: AssetSenderMap,
     pub ref_map: RefCounterMap,
+    /// Ids the asset server considers as loaded, sub assets are added as they are sent
+    requested: Arc<DashSet<HandleId>>,
     pub path: &'a Path,
     pub handle: HandleId,
 }
@@ -20,23 +24,22 @@ impl<'a> LoadContext<'a> {
     pub fn new(
         senders: AssetSenderMap,
         ref_map: RefCounterMap,
+        requested: Arc<DashSet<HandleId>>,
         path: &'a Path,
         handle: HandleId,
     ) -> Self {
         Self {
             senders,
             ref_map,
+            requested,
             path,
             handle,
         }
     }
 
+    /// Sends the asset of the loaded path, it is dropped if its type was never registered
     pub async fn send_asset<A: Asset>(&self, asset: A) {
-        self.senders
-            .get_pipe::<A>()
-            .value()
-            .send((self.handle, Box::new(asset)))
-            .await;
+        self.senders.send(self.handle, asset).await;
     }
 
     pub async fn add_asset_with_label<A: Asset>(
@@ -48,19 +51,12 @@ impl<'a> LoadContext<'a> {
             AssetPath::new(Cow::Borrowed(self.path), Some(Cow::Borrowed(label.into())));
 
         let id = HandleId::from_asset_path(asset_path);
+        self.requested.insert(id);
 
-        self.senders
-            .get_pipe::<A>()
-            .value()
-            .send((id, Box::new(asset)))
-            .await;
-
-        let ref_pipe = self
-            .ref_map
-            .get_pipe::<A>()
-            .expect("[LoadContext] (add_asset_with_label) failed to get ref_sender");
-
-        AssetHandle::strong(id, ref_pipe.0.clone())
+        // The handle has to exist before the asset can arrive
+        let handle = AssetHandle::strong(id, self.ref_map.get_counter::<A>());
+        self.senders.send(id, asset).await;
+        handle
     }
 }
 
@@ -74,4 +70,8 @@ pub trait AssetLoader: Send + Sync {
     //maybe we'll need something like
     // (async) fn free(&self, ...)
     fn ext(&self) -> &[&str];
+    /// The type of the asset that is sent for the loaded path itself (labeled sub assets
+    /// excluded), this is used to create handles for files that were not requested with a
+    /// concrete type, eg. through [`AssetServer::load_folder`](crate::asset_server::AssetServer::load_folder)
+    fn asset_type(&self) -> TypeId;
 }
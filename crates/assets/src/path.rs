@@ -18,6 +18,18 @@ impl<'a> AssetPath<'a> {
         }
     }
 
+    /// Parses a path of the form `"models/firetruck.gltf#Mesh/Ladder"`, everything after the
+    /// first `#` is treated as the label of a sub asset
+    pub fn parse(asset_path: &'a str) -> Self {
+        match asset_path.split_once('#') {
+            Some((path, label)) => Self {
+                path: Cow::Borrowed(Path::new(path)),
+                label: Some(Cow::Borrowed(label)),
+            },
+            None => Self::from_path(Path::new(asset_path)),
+        }
+    }
+
     /// Get a reference to the asset path's path.
     pub fn path(&self) -> &Cow<'a, Path> {
         &self.path
@@ -33,3 +45,31 @@ impl<'a> AssetPath<'a> {
         self.label = label;
     }
 }
+
+impl<'a> From<&'a str> for AssetPath<'a> {
+    fn from(asset_path: &'a str) -> Self {
+        Self::parse(asset_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_label() {
+        let asset_path = AssetPath::parse("models/firetruck.gltf#Mesh/Ladder");
+        assert_eq!(
+            asset_path.path().as_ref(),
+            Path::new("models/firetruck.gltf")
+        );
+        assert_eq!(asset_path.label().as_deref(), Some("Mesh/Ladder"));
+
+        let asset_path = AssetPath::parse("models/firetruck.gltf");
+        assert_eq!(
+            asset_path.path().as_ref(),
+            Path::new("models/firetruck.gltf")
+        );
+        assert!(asset_path.label().is_none());
+    }
+}
@@ -0,0 +1,44 @@
+use std::{any::TypeId, collections::HashMap};
+
+use crate::asset::Asset;
+
+/// Usage statistics of a single asset type
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssetTypeStats {
+    pub name: &'static str,
+    /// Number of assets currently in the store
+    pub loaded: usize,
+    /// Number of assets without a strong handle, that are kept alive by the unload policy
+    pub unreferenced: usize,
+    /// Estimated memory of all loaded assets
+    pub bytes: usize,
+    /// Estimated memory of the unreferenced assets
+    pub unreferenced_bytes: usize,
+    /// Number of inconsistencies found in the reference counting (released handles that were never acquired)
+    pub ref_errors: usize,
+}
+
+/// Resource that reports counts and memory per asset type, updated by `Assets::update_system`
+#[derive(Debug, Default)]
+pub struct AssetStats {
+    types: HashMap<TypeId, AssetTypeStats>,
+}
+
+impl AssetStats {
+    pub fn get<A: Asset>(&self) -> Option<&AssetTypeStats> {
+        self.types.get(&TypeId::of::<A>())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AssetTypeStats> {
+        self.types.values()
+    }
+
+    /// Estimated memory of all loaded assets of all types
+    pub fn total_bytes(&self) -> usize {
+        self.iter().map(|s| s.bytes).sum()
+    }
+
+    pub(crate) fn update<A: Asset>(&mut self, stats: AssetTypeStats) {
+        self.types.insert(TypeId::of::<A>(), stats);
+    }
+}
@@ -1,11 +1,13 @@
 //! This event system aims the be easily usable with the ecs system in place
 
-use std::cell::RefMut;
+use std::{cell::RefMut, marker::PhantomData};
 
 pub trait Event = 'static;
 
 pub struct EventBuffer<T: Event> {
     buffer: Vec<T>,
+    /// Number of events sent before the first one of this buffer
+    start: usize,
 }
 
 enum EventState {
@@ -17,6 +19,8 @@ pub struct Events<T: Event> {
     state: EventState,
     alpha: EventBuffer<T>,
     beta: EventBuffer<T>,
+    /// Number of events sent so far
+    count: usize,
 }
 
 impl<T: Event> Default for Events<T> {
@@ -29,8 +33,15 @@ impl<T: Event> Events<T> {
     pub fn new() -> Self {
         Self {
             state: EventState::Alpha,
-            alpha: EventBuffer { buffer: Vec::new() },
-            beta: EventBuffer { buffer: Vec::new() },
+            alpha: EventBuffer {
+                buffer: Vec::new(),
+                start: 0,
+            },
+            beta: EventBuffer {
+                buffer: Vec::new(),
+                start: 0,
+            },
+            count: 0,
         }
     }
 
@@ -42,35 +53,36 @@ impl<T: Event> Events<T> {
     }
 
     pub fn send(&mut self, instance: T) {
+        self.count += 1;
         let buffer = self.get_buffer_mut();
         buffer.buffer.push(instance);
     }
 
-    // NOTE(luca): If we feel like we actually need an event reader
-    // pub fn get_reader(&self) -> EventReader<T> {
-    //     EventReader {
-    //         last_count: 0,
-    //         _marker: PhantomData,
-    //     }
-    // }
+    fn visible(&self) -> &EventBuffer<T> {
+        match self.state {
+            EventState::Alpha => &self.beta,
+            EventState::Beta => &self.alpha,
+        }
+    }
 
     /// For now we will enable only last frame iteration
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
-        match self.state {
-            EventState::Alpha => self.beta.buffer.iter(),
-            EventState::Beta => self.alpha.buffer.iter(),
-        }
+        self.visible().buffer.iter()
     }
 
     pub fn update(&mut self) {
+        let buffer = EventBuffer {
+            buffer: Vec::new(),
+            start: self.count,
+        };
         match self.state {
             EventState::Alpha => {
                 self.state = EventState::Beta;
-                self.beta = EventBuffer { buffer: Vec::new() }
+                self.beta = buffer;
             }
             EventState::Beta => {
                 self.state = EventState::Alpha;
-                self.alpha = EventBuffer { buffer: Vec::new() }
+                self.alpha = buffer;
             }
         }
     }
@@ -80,9 +92,39 @@ impl<T: Event> Events<T> {
     }
 }
 
+/// Iterates the events of the last frame like `Events::iter`, but only returns every event once,
+/// no matter how often it is called during the frame
+pub struct ManualEventReader<T: Event> {
+    /// Number of events that were seen
+    last_count: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Event> Default for ManualEventReader<T> {
+    fn default() -> Self {
+        Self {
+            last_count: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Event> ManualEventReader<T> {
+    /// The events of the last frame that were not returned before
+    pub fn iter<'a>(&mut self, events: &'a Events<T>) -> impl DoubleEndedIterator<Item = &'a T> {
+        let visible = events.visible();
+        let seen = self
+            .last_count
+            .saturating_sub(visible.start)
+            .min(visible.buffer.len());
+        self.last_count = visible.start + visible.buffer.len();
+        visible.buffer[seen..].iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Events;
+    use super::{Events, ManualEventReader};
 
     #[derive(Debug, PartialEq)]
     struct NumberEvent(i32);
@@ -107,4 +149,23 @@ mod tests {
             assert_eq!(iter.next(), None);
         }
     }
+
+    #[test]
+    fn reader_returns_events_once() {
+        let mut events = Events::new();
+        let mut reader = ManualEventReader::default();
+
+        events.send(NumberEvent(2));
+        events.update();
+        assert_eq!(reader.iter(&events).next(), Some(&NumberEvent(2)));
+        assert_eq!(reader.iter(&events).next(), None);
+
+        // Events sent this frame become visible with the next update
+        events.send(NumberEvent(3));
+        assert_eq!(reader.iter(&events).next(), None);
+        events.update();
+        assert_eq!(reader.iter(&events).next(), Some(&NumberEvent(3)));
+        events.update();
+        assert_eq!(reader.iter(&events).next(), None);
+    }
 }
@@ -9,14 +9,14 @@ pub mod schedule;
 pub mod system;
 
 pub mod prelude {
-    pub use crate::event::{Event, Events};
+    pub use crate::event::{Event, Events, ManualEventReader};
     pub use crate::resource::{ResourceQuery, Resources};
     pub use crate::schedule::{executor::SequentialExecutor, scheduler::Scheduler};
     pub use crate::system::{
         into_system::{IntoFunctionSystem, IntoMutatingSystem},
         System,
     };
-    pub use hecs::{QueryBorrow, World};
+    pub use hecs::{Entity, QueryBorrow, World};
     // Our resource ref type (should be replace sometime)
     pub use std::cell::{Ref as Res, RefMut as ResMut};
 }
@@ -77,6 +77,15 @@ impl Resources {
         Ok(store.borrow_mut())
     }
 
+    /// Removes the resource of type `T`, None if there is none
+    pub fn remove<T: Resource>(&mut self) -> Option<T> {
+        self.storage
+            .remove(&TypeId::of::<T>())?
+            .downcast::<RefCell<T>>()
+            .ok()
+            .map(|store| store.into_inner())
+    }
+
     pub fn contains<T: Resource>(&self) -> bool {
         let type_id = TypeId::of::<T>();
         self.storage.contains_key(&type_id)
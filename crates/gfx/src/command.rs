@@ -28,6 +28,10 @@ impl<B: Backend> GfxCommand<B> {
     pub(crate) fn into_inner(self) -> B::CommandBuffer {
         self.command
     }
+
+    pub(crate) fn inner_mut(&mut self) -> &mut B::CommandBuffer {
+        &mut self.command
+    }
 }
 
 impl<B: Backend> CommandEncoder<GfxContext<B>> for GfxCommand<B> {
@@ -160,4 +164,47 @@ impl<B: Backend> CommandEncoder<GfxContext<B>> for GfxCommand<B> {
             self.command.copy_buffer(&src.0, &dst.0, regions);
         }
     }
+
+    fn bind_compute_pipeline(
+        &mut self,
+        pipeline: &<GfxContext<B> as GpuContext>::ComputePipelineHandle,
+    ) {
+        unsafe {
+            self.command.bind_compute_pipeline(&pipeline.0);
+        }
+    }
+
+    fn compute_push_constants(
+        &mut self,
+        pipeline: &<GfxContext<B> as GpuContext>::ComputePipelineHandle,
+        offset: u32,
+        data: &[u32],
+    ) {
+        unsafe {
+            self.command
+                .push_compute_constants(&pipeline.1, offset, data)
+        }
+    }
+
+    fn snort_compute_glue(
+        &mut self,
+        set_idx: usize,
+        pipeline: &<GfxContext<B> as GpuContext>::ComputePipelineHandle,
+        glue: &Glue<GfxContext<B>>,
+    ) {
+        unsafe {
+            self.command.bind_compute_descriptor_sets(
+                &pipeline.1,
+                set_idx,
+                vec![&glue.handle.handle.1],
+                Vec::<&u32>::new(),
+            )
+        }
+    }
+
+    fn dispatch(&mut self, groups: [u32; 3]) {
+        unsafe {
+            self.command.dispatch(groups);
+        }
+    }
 }
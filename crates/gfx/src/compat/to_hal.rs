@@ -1,4 +1,6 @@
-use gfx_hal::image::{Access, Extent, Layout};
+use gfx_hal::image::{
+    Access, Extent, Filter as HalFilter, Layout, SamplerDesc, WrapMode as HalWrapMode,
+};
 use gfx_hal::memory::Dependencies;
 use gfx_hal::pass::{
     Attachment as HalAttachment, AttachmentId, AttachmentLoadOp as HalAttachmentLoadOp,
@@ -22,6 +24,8 @@ use gfx_hal::{
     pso::DescriptorType,
 };
 use gfx_hal::{format::Format, image::Tiling};
+use render::graph::compile::AttachmentLayout;
+use render::resource::texture::{Filter, SamplerDescriptor, WrapMode};
 use render::resource::{
     buffer::BufferCopy,
     render_pass::{
@@ -236,6 +240,20 @@ impl ToHalType for TextureLayout {
     }
 }
 
+impl ToHalType for AttachmentLayout {
+    type Target = Layout;
+
+    fn convert(self) -> Self::Target {
+        match self {
+            AttachmentLayout::Undefined => Layout::Undefined,
+            AttachmentLayout::ColorAttachment => Layout::ColorAttachmentOptimal,
+            AttachmentLayout::DepthStencilAttachment => Layout::DepthStencilAttachmentOptimal,
+            AttachmentLayout::ShaderReadOnly => Layout::ShaderReadOnlyOptimal,
+            AttachmentLayout::Present => Layout::Present,
+        }
+    }
+}
+
 impl ToHalType for LoadOp {
     type Target = HalAttachmentLoadOp;
 
@@ -313,7 +331,7 @@ impl ToHalType for Attachment {
     fn convert(self) -> Self::Target {
         HalAttachment {
             format: Some(self.format.convert()),
-            samples: 1,
+            samples: self.samples,
             ops: AttachmentOps::new(self.load_op.convert(), self.store_op.convert()),
             stencil_ops: AttachmentOps::DONT_CARE,
             layouts: self.layouts.start.convert()..self.layouts.end.convert(),
@@ -455,7 +473,7 @@ impl ToHalType for ShaderType {
     }
 }
 
-pub(in crate) fn get_descriptor_type(part: &MixturePart) -> DescriptorType {
+pub(crate) fn get_descriptor_type(part: &MixturePart) -> DescriptorType {
     match part.type_info {
         render::resource::glue::PartType::Uniform(_) => DescriptorType::Buffer {
             ty: gfx_hal::pso::BufferDescriptorType::Uniform,
@@ -466,6 +484,15 @@ pub(in crate) fn get_descriptor_type(part: &MixturePart) -> DescriptorType {
         render::resource::glue::PartType::Sampler => DescriptorType::Image {
             ty: gfx_hal::pso::ImageDescriptorType::Sampled { with_sampler: true },
         },
+        render::resource::glue::PartType::StorageBuffer => DescriptorType::Buffer {
+            ty: gfx_hal::pso::BufferDescriptorType::Storage { read_only: false },
+            format: gfx_hal::pso::BufferDescriptorFormat::Structured {
+                dynamic_offset: part.is_dynamic,
+            },
+        },
+        render::resource::glue::PartType::StorageImage => DescriptorType::Image {
+            ty: gfx_hal::pso::ImageDescriptorType::Storage { read_only: false },
+        },
     }
 }
 
@@ -516,3 +543,41 @@ impl ToHalType for IndexType {
         }
     }
 }
+
+impl ToHalType for Filter {
+    type Target = HalFilter;
+
+    fn convert(self) -> Self::Target {
+        match self {
+            Filter::Nearest => HalFilter::Nearest,
+            Filter::Linear => HalFilter::Linear,
+        }
+    }
+}
+
+impl ToHalType for WrapMode {
+    type Target = HalWrapMode;
+
+    fn convert(self) -> Self::Target {
+        match self {
+            WrapMode::Repeat => HalWrapMode::Tile,
+            WrapMode::MirroredRepeat => HalWrapMode::Mirror,
+            WrapMode::ClampToEdge => HalWrapMode::Clamp,
+        }
+    }
+}
+
+impl ToHalType for SamplerDescriptor {
+    type Target = SamplerDesc;
+
+    fn convert(self) -> Self::Target {
+        let wrap = self.wrap.convert();
+        SamplerDesc {
+            min_filter: self.min_filter.convert(),
+            mag_filter: self.mag_filter.convert(),
+            mip_filter: self.mip_filter.convert(),
+            wrap_mode: (wrap, wrap, wrap),
+            ..SamplerDesc::new(HalFilter::Linear, wrap)
+        }
+    }
+}
@@ -17,8 +17,11 @@ use gfx_hal::{device::Device, Backend};
 use parking_lot::Mutex;
 use render::resource::frame::{Extent2D, Extent3D};
 use render::resource::glue::Mixture;
-use render::resource::pipeline::{GraphicsPipelineDescriptor, RenderContext, ShaderSource};
+use render::resource::pipeline::{
+    ComputePipelineDescriptor, GraphicsPipelineDescriptor, RenderContext, ShaderSource,
+};
 use render::resource::render_pass::RenderPassDescriptor;
+use render::resource::texture::{SamplerDescriptor, TextureDescriptor};
 
 use core::anyhow;
 use render::{
@@ -30,6 +33,7 @@ use std::borrow::Borrow;
 use std::{ops::Deref, sync::Arc};
 
 use super::pool::{LayoutHandle, Pool, SetHandle};
+use crate::{texture::GfxTexture, transfer::Transfer};
 
 #[derive(Debug)]
 pub(crate) struct Queues<B: Backend> {
@@ -61,6 +65,8 @@ where
     // pub(crate) swapper: Swapper<B>,nn
     // Pool -> Descriptor Sets
     pub(crate) pool: Pool<B>,
+    // Single shot submissions, eg. uploads
+    pub(crate) transfer: Transfer<B>,
 }
 
 impl<B: Backend> GfxContext<B> {
@@ -74,6 +80,7 @@ impl<B: Backend> GpuContext for GfxContext<B> {
     type SurfaceHandle = (Arc<Mutex<B::Surface>>, Extent2D);
     type BufferHandle = (B::Buffer, AllocationIndex);
     type PipelineHandle = (B::GraphicsPipeline, B::PipelineLayout);
+    type ComputePipelineHandle = (B::ComputePipeline, B::PipelineLayout);
     type RenderPassHandle = B::RenderPass;
     type ShaderCode = Vec<u32>;
     type ImageView = B::ImageView;
@@ -81,6 +88,8 @@ impl<B: Backend> GpuContext for GfxContext<B> {
     type CommandBuffer = B::CommandBuffer;
     type DescriptorLayout = LayoutHandle<B>;
     type DescriptorSet = SetHandle<B>;
+    type TextureHandle = GfxTexture<B>;
+    type SamplerHandle = B::Sampler;
     type CommandEncoder = GfxCommand<B>;
     type SwapchainImage =
         <<B as gfx_hal::Backend>::Surface as PresentationSurface<B>>::SwapchainImage;
@@ -117,11 +126,49 @@ impl<B: Backend> GpuContext for GfxContext<B> {
         self.heapy.write(&buffer.1, data);
     }
 
+    fn upload_buffer(&self, buffer: &Self::BufferHandle, offset: u64, data: &[u8]) {
+        self.transfer.upload(&buffer.0, offset, data);
+    }
+
+    fn flush_uploads(&self) {
+        self.transfer.flush();
+    }
+
+    /// Uploads into the buffer may still be in flight, so it is destroyed once they finished
     fn drop_buffer(&self, buffer: Self::BufferHandle) {
+        self.transfer.drop_buffer(buffer.0, buffer.1);
+    }
+
+    fn create_texture(&self, desc: &TextureDescriptor) -> Self::TextureHandle {
+        GfxTexture::<B>::create(&self.device, &self.heapy, &self.transfer, desc)
+    }
+
+    fn upload_texture(&self, texture: &Self::TextureHandle, levels: &[&[u8]]) {
+        use gfx_hal::adapter::PhysicalDevice;
+        let alignment = self
+            .adapter
+            .physical_device
+            .limits()
+            .optimal_buffer_copy_offset_alignment;
+        texture.upload(&self.device, &self.heapy, &self.transfer, alignment, levels);
+    }
+
+    fn drop_texture(&self, texture: Self::TextureHandle) {
+        texture.destroy(&self.device, &self.heapy);
+    }
+
+    fn create_sampler(&self, desc: &SamplerDescriptor) -> Self::SamplerHandle {
+        unsafe {
+            self.device
+                .create_sampler(&desc.clone().convert())
+                .expect("[GfxContext] (create_sampler) creation failed")
+        }
+    }
+
+    fn drop_sampler(&self, sampler: Self::SamplerHandle) {
         unsafe {
-            self.device.destroy_buffer(buffer.0);
+            self.device.destroy_sampler(sampler);
         }
-        self.heapy.deallocate(buffer.1);
     }
 
     fn create_render_pass(&self, desc: &RenderPassDescriptor) -> Self::RenderPassHandle {
@@ -175,6 +222,20 @@ impl<B: Backend> GpuContext for GfxContext<B> {
         }
     }
 
+    fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<Self>,
+    ) -> Self::ComputePipelineHandle {
+        self.plumber.create_compute_pipeline(desc)
+    }
+
+    fn drop_compute_pipeline(&self, pipeline: Self::ComputePipelineHandle) {
+        unsafe {
+            self.device.destroy_compute_pipeline(pipeline.0);
+            self.device.destroy_pipeline_layout(pipeline.1);
+        }
+    }
+
     // fn get_surface_format(&self) -> TextureFormat {
     //     self.swapper.get_surface_format()
     // }
@@ -183,6 +244,10 @@ impl<B: Backend> GpuContext for GfxContext<B> {
         self.plumber.compile_shader(source)
     }
 
+    fn shader_spirv<'a>(&self, code: &'a Self::ShaderCode) -> Option<&'a [u32]> {
+        Some(code)
+    }
+
     fn create_framebuffer<I>(
         &self,
         rp: &Self::RenderPassHandle,
@@ -291,7 +356,7 @@ impl<B: Backend> Drop for GfxContext<B> {
 }
 
 // Helper functions, mainly maps between our enums and gfx_hal's ones
-fn get_buffer_usage(desc: &BufferDescriptor) -> gfx_hal::buffer::Usage {
+pub(crate) fn get_buffer_usage(desc: &BufferDescriptor) -> gfx_hal::buffer::Usage {
     use gfx_hal::buffer::Usage;
     use render::resource::buffer::MemoryType;
     let usage = match desc.usage {
@@ -299,6 +364,7 @@ fn get_buffer_usage(desc: &BufferDescriptor) -> gfx_hal::buffer::Usage {
         BufferUsage::Vertex => Usage::VERTEX,
         BufferUsage::Index => Usage::INDEX,
         BufferUsage::Staging => Usage::TRANSFER_SRC,
+        BufferUsage::Storage => Usage::STORAGE | Usage::VERTEX,
     };
 
     if desc.memory_type == MemoryType::DeviceLocal {
@@ -13,6 +13,7 @@ use crate::{
     heapy::Heapy,
     plumber::Plumber,
     pool::Pool,
+    transfer::Transfer,
 };
 
 pub struct GfxBuilder<B: Backend> {
@@ -117,11 +118,14 @@ impl<B: Backend> GpuBuilder for GfxBuilder<B> {
             let (device, queue_groups) = unsafe {
                 use gfx_hal::adapter::PhysicalDevice;
 
+                // Arrays of uniforms (eg. the lights) are indexed in loops
+                let features = adapter.physical_device.features()
+                    & gfx_hal::Features::SHADER_UNIFORM_BUFFER_ARRAY_DYNAMIC_INDEXING;
                 let gpu = adapter
                     .physical_device
                     .open(
                         &[(graphics_family, &[1.0; 1]), (compute_family, &[1.0; 1])],
-                        gfx_hal::Features::empty(),
+                        features,
                     )
                     .expect("failed to open device");
 
@@ -163,15 +167,18 @@ impl<B: Backend> GpuBuilder for GfxBuilder<B> {
         // );
 
         let pool = Pool::<B>::new(device.clone());
+        let heapy = Arc::new(heapy);
+        let transfer = Transfer::<B>::new(device.clone(), queues.clone(), heapy.clone());
 
         GfxContext {
             instance,
             adapter,
             device,
             queues,
-            heapy: Arc::new(heapy),
+            heapy,
             plumber,
             pool,
+            transfer,
         }
     }
 }
@@ -12,14 +12,11 @@ use render::{
         nodes::pass::{PassAttachment, PassNode},
     },
     prelude::MemoryType,
-    resource::frame::Extent2D,
+    resource::{frame::Extent2D, texture::TextureDescriptor},
 };
 use uuid::Uuid;
 
-use crate::{
-    compat::ToHalType,
-    heapy::{AllocationIndex, Heapy},
-};
+use crate::{compat::ToHalType, heapy::Heapy, texture::GfxTexture};
 
 use super::builder::GfxGraphBuilder;
 
@@ -29,10 +26,10 @@ pub enum AttachmentIndex {
     Custom(Uuid),
 }
 
+/// The image is a texture, so that passes can sample it (see PassNodeBuilder::add_sampled)
 pub(crate) struct GfxGraphAttachment<B: Backend> {
     pub(crate) desc: GraphAttachment,
-    pub(crate) image: (B::Image, AllocationIndex),
-    pub(crate) image_view: B::ImageView,
+    pub(crate) texture: GfxTexture<B>,
 }
 
 pub trait NodeIterator<B: Backend> = Iterator<Item: Borrow<PassNode<GfxGraphBuilder<B>>>>;
@@ -45,15 +42,11 @@ impl<B: Backend> GfxGraphAttachment<B> {
         dimension: Extent2D,
         nodes: I,
     ) -> Self {
-        let (image, image_view) = Self::build(&desc, device, heapy, dimension, nodes);
-        Self {
-            desc,
-            image,
-            image_view,
-        }
+        let texture = Self::build(&desc, device, heapy, dimension, nodes);
+        Self { desc, texture }
     }
 
-    /// Should be called whenever swapchain dimensions change
+    /// Should be called whenever swapchain dimensions change, absolute sized attachments are kept
     pub fn rebuild<I: NodeIterator<B>>(
         &mut self,
         device: &B::Device,
@@ -61,9 +54,9 @@ impl<B: Backend> GfxGraphAttachment<B> {
         dimension: Extent2D,
         nodes: I,
     ) {
-        let (image, image_view) = Self::build(&self.desc, device, heapy, dimension, nodes);
-        self.image = image;
-        self.image_view = image_view;
+        if let AttachmentSize::Relative(_, _) = self.desc.size {
+            self.texture = Self::build(&self.desc, device, heapy, dimension, nodes);
+        }
     }
 
     fn build<I: NodeIterator<B>>(
@@ -72,29 +65,45 @@ impl<B: Backend> GfxGraphAttachment<B> {
         heapy: &Heapy<B>,
         dimension: Extent2D,
         nodes: I,
-    ) -> ((B::Image, AllocationIndex), B::ImageView) {
-        // Figure out usage
+    ) -> GfxTexture<B> {
+        // The offscreen backbuffer is referenced by the nodes like the swapchain image
+        let index = if desc.is_backbuffer {
+            AttachmentIndex::Backbuffer
+        } else {
+            AttachmentIndex::Custom(desc.id)
+        };
+
+        // Figure out usage, every attachment can be read back (see Graph::read_attachment)
         let usage = {
-            let mut res = Usage::empty();
+            let mut res = Usage::TRANSFER_SRC;
             nodes.for_each(|n| {
                 let node = n.borrow();
 
                 let mut check_array = |a: &Vec<PassAttachment<AttachmentIndex>>, usage: Usage| {
-                    if a.iter()
-                        .map(|a| a.index)
-                        .any(|i| i == AttachmentIndex::Custom(desc.id))
-                    {
+                    if a.iter().map(|a| a.index).any(|i| i == index) {
                         res |= usage
                     }
                 };
 
                 check_array(&node.output_attachments, Usage::COLOR_ATTACHMENT);
-                check_array(&node.input_attachments, Usage::INPUT_ATTACHMENT);
+                // Input attachments are bound like sampled textures (see FrameData::inputs)
+                check_array(
+                    &node.input_attachments,
+                    Usage::INPUT_ATTACHMENT | Usage::SAMPLED,
+                );
+
+                if node.resolve_attachments.iter().any(|r| r.target == index) {
+                    res |= Usage::COLOR_ATTACHMENT;
+                }
+
+                if node.sampled_attachments.contains(&index) {
+                    res |= Usage::SAMPLED;
+                }
 
                 if node
                     .depth_attachment
                     .clone()
-                    .filter(|p| p.index == AttachmentIndex::Custom(desc.id))
+                    .filter(|p| p.index == index)
                     .is_some()
                 {
                     res |= Usage::DEPTH_STENCIL_ATTACHMENT;
@@ -109,16 +118,9 @@ impl<B: Backend> GfxGraphAttachment<B> {
             usage
         );
 
-        let image = unsafe {
-            // Calculate Size
-            let (width, height) = match desc.size {
-                AttachmentSize::Relative(scale_x, scale_y) => (
-                    (dimension.width as f32 * scale_x) as u32,
-                    (dimension.height as f32 * scale_y) as u32,
-                ),
-                AttachmentSize::Absolute(width, height) => (width, height),
-            };
-            let kind = Kind::D2(width, height, 1, 1);
+        let extent = desc.size.extent(&dimension);
+        let (image, allocation) = unsafe {
+            let kind = Kind::D2(extent.width, extent.height, 1, desc.samples);
 
             let mut image = device
                 .create_image(
@@ -146,11 +148,12 @@ impl<B: Backend> GfxGraphAttachment<B> {
 
         // TODO: bind image memory
 
-        let image_view = unsafe {
+        let view = unsafe {
             let kind = ViewKind::D2;
             let swizzle = Swizzle::NO;
             let aspects = if usage.contains(Usage::DEPTH_STENCIL_ATTACHMENT) {
-                if desc.format.has_stencil() {
+                // Views that are sampled can only have a single aspect
+                if desc.format.has_stencil() && !usage.contains(Usage::SAMPLED) {
                     Aspects::STENCIL | Aspects::DEPTH
                 } else {
                     Aspects::DEPTH
@@ -166,10 +169,21 @@ impl<B: Backend> GfxGraphAttachment<B> {
                 layer_count: None,
             };
             device
-                .create_image_view(&image.0, kind, desc.format.convert(), swizzle, range)
+                .create_image_view(&image, kind, desc.format.convert(), swizzle, range)
                 .expect("[GfxGraph] (build attachment) failed to create image view")
         };
 
-        (image, image_view)
+        GfxTexture {
+            image,
+            allocation,
+            view,
+            desc: TextureDescriptor {
+                name: desc.name.clone(),
+                extent,
+                format: desc.format,
+                mip_levels: 1,
+                storage: false,
+            },
+        }
     }
 }
@@ -9,7 +9,12 @@ use gfx_hal::{
 };
 use parking_lot::{Mutex, RwLock};
 use render::{
-    graph::{attachment::GraphAttachment, builder::GraphBuilder, node::Node},
+    graph::{
+        attachment::{AttachmentSize, GraphAttachment},
+        builder::GraphBuilder,
+        compile::{compile, AttachmentLayout, CompiledGraph, GraphError},
+        node::Node,
+    },
     resource::frame::Extent2D,
     util::format::TextureFormat,
 };
@@ -96,6 +101,7 @@ impl<B: Backend> GfxGraphBuilder<B> {
             depth_format,
             surface_format,
             surface_extent: RwLock::new(extent),
+            offscreen: None,
             frames_in_flight: 3u32,
             command_pool: ManuallyDrop::new(Mutex::new(command_pool)),
         };
@@ -133,10 +139,26 @@ impl<B: Backend> GraphBuilder for GfxGraphBuilder<B> {
             .map(|a| AttachmentIndex::Custom(a.id))
     }
 
+    fn attachment_name(&self, index: Self::AttachmentIndex) -> std::borrow::Cow<'static, str> {
+        match index {
+            AttachmentIndex::Backbuffer => "backbuffer".into(),
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|a| a.id == id)
+                .map(|a| a.name.clone())
+                .unwrap_or_else(|| "unknown".into()),
+        }
+    }
+
     fn get_backbuffer_attachment(&self) -> Self::AttachmentIndex {
         AttachmentIndex::Backbuffer
     }
 
+    fn render_offscreen(&mut self, extent: Extent2D) {
+        self.data.offscreen = Some(extent);
+    }
+
     fn get_surface_format(&self) -> TextureFormat {
         self.data.surface_format
     }
@@ -149,11 +171,20 @@ impl<B: Backend> GraphBuilder for GfxGraphBuilder<B> {
         self.data.frames_in_flight as _
     }
 
-    fn build(self) -> Self::Graph {
+    fn supports_samples(&self, samples: u8) -> bool {
+        // The limits are bit sets of the supported counts, which are powers of two
+        let limits = self.data.adapter.physical_device.limits();
+        samples.is_power_of_two()
+            && limits.framebuffer_color_sample_counts & samples != 0
+            && limits.framebuffer_depth_sample_counts & samples != 0
+    }
+
+    fn build(mut self) -> Result<Self::Graph, GraphError> {
+        let nodes = std::mem::take(&mut self.nodes);
+        // Culled nodes are dropped, since into_builder is not supported by this backend yet
+        let CompiledGraph { nodes, .. } = compile(&self, nodes)?;
         let GfxGraphBuilder {
-            attachments,
-            nodes,
-            data,
+            attachments, data, ..
         } = self;
 
         let frames_in_flight = data.frames_in_flight;
@@ -166,40 +197,69 @@ impl<B: Backend> GraphBuilder for GfxGraphBuilder<B> {
             }
         }
 
-        let dimensions = data.surface_extent.read().clone();
+        let dimensions = data.target_extent();
+        let create_attachment = |desc: GraphAttachment| {
+            GfxGraphAttachment::create(
+                desc,
+                data.device.deref(),
+                data.heapy.deref(),
+                dimensions.clone(),
+                nodes.iter().filter_map(|n| match &n.node {
+                    Node::PassNode(n) => Some(n),
+                    Node::ComputeNode(_) => None,
+                }),
+            )
+        };
 
-        let attachments: Vec<GfxGraphAttachment<B>> = attachments
-            .into_iter()
-            .map(|a| {
-                GfxGraphAttachment::create(
-                    a,
-                    data.device.deref(),
-                    data.heapy.deref(),
-                    dimensions.clone(),
-                    nodes.iter().map(|n| match n {
-                        Node::PassNode(n) => n,
-                    }),
-                )
-            })
-            .collect();
+        let attachments: Vec<GfxGraphAttachment<B>> =
+            attachments.into_iter().map(create_attachment).collect();
+        let backbuffer = data.offscreen.as_ref().map(|extent| {
+            let mut desc = GraphAttachment::new(
+                "backbuffer",
+                AttachmentSize::Absolute(extent.width, extent.height),
+                data.surface_format,
+            );
+            desc.is_backbuffer = true;
+            create_attachment(desc)
+        });
+
+        // Layout of every attachment at the end of a frame, which is where readbacks copy from
+        let mut final_layouts: Vec<(AttachmentIndex, AttachmentLayout)> = Vec::new();
+        for node in nodes.iter() {
+            if let Node::PassNode(n) = &node.node {
+                for (a, layouts) in n.attachment_uses().iter().zip(node.layouts.iter()) {
+                    final_layouts.retain(|(index, _)| *index != a.index);
+                    final_layouts.push((a.index, layouts.end));
+                }
+            }
+        }
 
         // TODO: Build attachments
         // Build nodes
         let nodes: Vec<GfxNode<B>> = nodes
             .into_iter()
             .map(|n| {
-                super::nodes::build_node(data.device.deref(), n, &attachments, data.surface_format)
+                super::nodes::build_node(
+                    data.device.deref(),
+                    n,
+                    &attachments,
+                    data.surface_format,
+                    data.offscreen.is_some(),
+                )
             })
             .collect();
 
-        GfxGraph {
+        Ok(GfxGraph {
             attachments,
+            backbuffer,
+            final_layouts,
+            readbacks: Vec::new(),
             nodes,
             data,
             should_configure_swapchain: AtomicBool::new(true),
             current_frame: RwLock::new((0, FrameStatus::Inactive)),
             frames: Mutex::new(frames),
-        }
+        })
     }
 
     fn build_pass_node<U: render::graph::nodes::callbacks::UserData>(
@@ -24,7 +24,12 @@ use gfx_hal::{
 };
 use parking_lot::{Mutex, MutexGuard, RwLock};
 use render::{
-    graph::{nodes::callbacks::FrameData, Graph},
+    graph::{
+        compile::AttachmentLayout,
+        nodes::{callbacks::FrameData, compute::BarrierTracker},
+        readback::{Readback, ReadbackError},
+        Graph,
+    },
     prelude::CommandEncoder,
     resource::{
         frame::{Clear, Extent2D},
@@ -32,6 +37,7 @@ use render::{
     },
     util::format::TextureFormat,
 };
+use uuid::Uuid;
 
 use crate::{
     command::GfxCommand,
@@ -44,11 +50,13 @@ pub mod attachment;
 use self::{
     attachment::{AttachmentIndex, GfxGraphAttachment},
     builder::GfxGraphBuilder,
-    nodes::GfxNode,
+    nodes::{attachment_layout, record_barriers, GfxNode},
+    readback::PendingReadback,
 };
 
 pub mod builder;
 pub mod nodes;
+mod readback;
 
 #[derive(Debug)]
 enum FrameStatus {
@@ -62,6 +70,7 @@ struct FrameSynchronization<B: Backend> {
     rendering_complete: B::Semaphore,
     in_use_command: Option<B::CommandBuffer>,
     pass_data: Vec<Box<dyn Any>>,
+    readbacks: Vec<PendingReadback<B>>,
 }
 
 impl<B: Backend> FrameSynchronization<B> {
@@ -75,6 +84,14 @@ impl<B: Backend> FrameSynchronization<B> {
                 .expect("[Swapper] failed to create simple sync primitive"),
             in_use_command: None,
             pass_data: Vec::new(),
+            readbacks: Vec::new(),
+        }
+    }
+
+    /// Completes the readbacks of the frame, once its submission fence was signaled
+    unsafe fn finish_readbacks(&mut self, device: &B::Device, heapy: &Heapy<B>) {
+        for readback in self.readbacks.drain(..) {
+            readback.finish(device, heapy);
         }
     }
 }
@@ -91,13 +108,30 @@ pub struct GraphData<B: Backend> {
     depth_format: TextureFormat,
     surface_format: TextureFormat,
     surface_extent: RwLock<Extent2D>,
+    /// Size of the backbuffer when rendering offscreen, the swapchain is not used at all then
+    offscreen: Option<Extent2D>,
     frames_in_flight: u32,
     // Pool
     command_pool: ManuallyDrop<Mutex<B::CommandPool>>,
 }
 
+impl<B: Backend> GraphData<B> {
+    /// Size of the backbuffer, which relative attachments are based on
+    fn target_extent(&self) -> Extent2D {
+        self.offscreen
+            .clone()
+            .unwrap_or_else(|| self.surface_extent.read().clone())
+    }
+}
+
 pub struct GfxGraph<B: Backend> {
     attachments: Vec<GfxGraphAttachment<B>>,
+    /// Replaces the swapchain images when rendering offscreen
+    backbuffer: Option<GfxGraphAttachment<B>>,
+    /// Layout of the attachments at the end of a frame
+    final_layouts: Vec<(AttachmentIndex, AttachmentLayout)>,
+    /// Copies requested for the next execution
+    readbacks: Vec<(AttachmentIndex, Readback)>,
     nodes: Vec<GfxNode<B>>,
 
     data: GraphData<B>,
@@ -112,6 +146,10 @@ type SwapchainImage<B> = <<B as Backend>::Surface as PresentationSurface<B>>::Sw
 
 impl<B: Backend> GfxGraph<B> {
     fn configure_swapchain(&self) -> anyhow::Result<()> {
+        // Offscreen graphs never present, so the swapchain is not needed
+        if self.data.offscreen.is_some() {
+            return Ok(());
+        }
         if self.should_configure_swapchain.load(Ordering::Relaxed) {
             // First we need to wait for all frames to finish
             let wait_timeout_ns = 1_000_000_000;
@@ -159,7 +197,8 @@ impl<B: Backend> GfxGraph<B> {
         Ok(())
     }
 
-    pub fn new_frame(&self) -> anyhow::Result<(u32, SwapchainImage<B>)> {
+    /// Offscreen graphs do not acquire a swapchain image
+    pub fn new_frame(&self) -> anyhow::Result<(u32, Option<SwapchainImage<B>>)> {
         self.configure_swapchain()?;
 
         let frame_idx = {
@@ -189,6 +228,7 @@ impl<B: Backend> GfxGraph<B> {
 
             // And free the frame data
             this_frame.pass_data = Vec::new();
+            this_frame.finish_readbacks(&self.data.device, &self.data.heapy);
 
             // Now we also need to delete the command buffer in use
             if this_frame.in_use_command.is_some() {
@@ -197,13 +237,17 @@ impl<B: Backend> GfxGraph<B> {
             }
         };
 
+        if self.data.offscreen.is_some() {
+            return Ok((frame_idx, None));
+        }
+
         // Acquire Image
         Ok(unsafe {
             // We refuse to wait more than a second, to avoid hanging.
             let acquire_timeout_ns = 1_000_000_000;
 
             match self.data.surface.lock().acquire_image(acquire_timeout_ns) {
-                Ok((image, _)) => Ok((frame_idx, image)),
+                Ok((image, _)) => Ok((frame_idx, Some(image))),
                 Err(e) => {
                     self.should_configure_swapchain
                         .store(true, Ordering::Relaxed);
@@ -220,7 +264,8 @@ impl<B: Backend> Graph for GfxGraph<B> {
     type Builder = GfxGraphBuilder<B>;
 
     fn execute(&mut self, world: &World, resources: &Resources) {
-        {
+        // Offscreen graphs do not follow the window
+        if self.data.offscreen.is_none() {
             // Check for resize events
             let resize_events = resources
                 .get::<app::Events<window::events::WindowResize>>()
@@ -242,8 +287,9 @@ impl<B: Backend> Graph for GfxGraph<B> {
                         self.data.device.deref(),
                         self.data.heapy.deref(),
                         new_dimension.clone(),
-                        self.nodes.iter().map(|n| match n {
-                            GfxNode::PassNode(n) => &n.graph_node,
+                        self.nodes.iter().filter_map(|n| match n {
+                            GfxNode::PassNode(n) => Some(&n.graph_node),
+                            GfxNode::ComputeNode(_) => None,
                         }),
                     );
                 }
@@ -262,6 +308,8 @@ impl<B: Backend> Graph for GfxGraph<B> {
                 self.new_frame().unwrap()
             }
         };
+        // Only taken once there is a frame, until then they stay requested
+        let readbacks = std::mem::take(&mut self.readbacks);
 
         let command = {
             // create the command buffer
@@ -274,63 +322,152 @@ impl<B: Backend> Graph for GfxGraph<B> {
 
             // Command Encoder Abstraction
             let mut gfx_command = GfxCommand::<B>::new(command);
-            let extent = self.data.surface_extent.read().clone();
-            let extent3d = Extent {
-                width: extent.width,
-                height: extent.height,
-                depth: 1,
+            let extent = self.data.target_extent();
+            let backbuffer: &B::ImageView = match (&self.backbuffer, &swapchain_image) {
+                (Some(backbuffer), _) => &backbuffer.texture.view,
+                (None, Some(image)) => image.borrow(),
+                (None, None) => panic!("[GfxGraph] (execute) no swapchain image was acquired"),
+            };
+
+            let viewport = Viewport {
+                rect: Rect {
+                    x: 0,
+                    y: 0,
+                    width: extent.width as i16,
+                    height: extent.height as i16,
+                },
+                depth: 0.0..1.0,
             };
 
-            // TODO: Execute all passes
+            // Barriers are only needed around compute nodes, pass nodes are synchronized by
+            // their render pass
+            let mut tracker = BarrierTracker::new();
             for node in self.nodes.iter() {
-                // TODO: Expand to diffrent nodes
-                let GfxNode::PassNode(node) = node;
+                let node = match node {
+                    GfxNode::PassNode(node) => node,
+                    GfxNode::ComputeNode(node) => {
+                        record_barriers::<B>(gfx_command.inner_mut(), tracker.compute_node(node));
+
+                        let frame_data = FrameData {
+                            cmd: &mut gfx_command,
+                            frame_index: index,
+                            viewport: viewport.clone(),
+                            sampled: Vec::new(),
+                            inputs: Vec::new(),
+                        };
+                        match node
+                            .callbacks
+                            .borrow_mut()
+                            .run(frame_data, world, resources)
+                        {
+                            Ok(Some(data)) => {
+                                let mut frames = self.frames.lock();
+                                frames.get_mut(index as usize).unwrap().pass_data.push(data);
+                            }
+                            Ok(None) => (),
+                            Err(e) => {
+                                panic!(
+                                    "[GfxGraph] execution failed during compute node: {}, with error: {}",
+                                    node.name, e
+                                )
+                            }
+                        }
+                        continue;
+                    }
+                };
+                record_barriers::<B>(gfx_command.inner_mut(), tracker.external());
+
+                // The render area is the size of the attachments, which might not be the surface
+                let attachment = |id: Uuid| {
+                    self.attachments
+                        .iter()
+                        .find(|a| a.desc.id == id)
+                        .expect("[GfxGraph] (execute) failed to load custom attachment")
+                };
+                let pass_extent = match node.graph_node.first_attachment() {
+                    Some(AttachmentIndex::Custom(id)) => attachment(id).texture.desc.extent.clone(),
+                    _ => extent.clone(),
+                };
+                let pass_viewport = Viewport {
+                    rect: Rect {
+                        x: 0,
+                        y: 0,
+                        width: pass_extent.width as i16,
+                        height: pass_extent.height as i16,
+                    },
+                    depth: 0.0..1.0,
+                };
+
                 let framebuffer = unsafe {
                     let mut attachments = node.graph_node.output_attachments.clone();
                     attachments.extend(node.graph_node.input_attachments.clone());
                     if let Some(a) = &node.graph_node.depth_attachment {
                         attachments.push(a.clone())
                     };
-                    let attachments = attachments.iter().map(|a| match a.index {
-                        AttachmentIndex::Backbuffer => swapchain_image.borrow(),
-                        AttachmentIndex::Custom(id) => {
-                            &self
-                                .attachments
-                                .iter()
-                                .find(|a| a.desc.id == id)
-                                .expect("[GfxGraph] (execute) failed to load custom attachment")
-                                .image_view
-                        }
-                    });
+                    let resolves = node.graph_node.resolve_attachments.iter();
+                    let attachments = attachments
+                        .iter()
+                        .map(|a| a.index)
+                        .chain(resolves.map(|r| r.target))
+                        .map(|index| match index {
+                            AttachmentIndex::Backbuffer => backbuffer,
+                            AttachmentIndex::Custom(id) => &attachment(id).texture.view,
+                        });
+                    let pass_extent3d = Extent {
+                        width: pass_extent.width,
+                        height: pass_extent.height,
+                        depth: 1,
+                    };
                     self.data
                         .device
-                        .create_framebuffer(&node.render_pass, attachments, extent3d)
+                        .create_framebuffer(&node.render_pass, attachments, pass_extent3d)
                         .expect("[GfxGraph] failed to create viewport")
                 };
 
-                let viewport = Viewport {
-                    rect: Rect {
-                        x: 0,
-                        y: 0,
-                        width: extent.width as i16,
-                        height: extent.height as i16,
-                    },
-                    depth: 0.0..1.0,
-                };
-
+                // Clear values are indexed by attachment, like the render pass attachments
+                let graph_node = &node.graph_node;
+                let clear_values: Vec<Clear> = graph_node
+                    .output_attachments
+                    .iter()
+                    .chain(graph_node.input_attachments.iter())
+                    .map(|_| Clear::Color(0.2, 0.5, 0.1, 1.0))
+                    .chain(
+                        graph_node
+                            .depth_attachment
+                            .iter()
+                            .map(|_| Clear::Depth(1.0, 0)),
+                    )
+                    .chain(
+                        graph_node
+                            .resolve_attachments
+                            .iter()
+                            .map(|_| Clear::Color(0.2, 0.5, 0.1, 1.0)),
+                    )
+                    .collect();
                 gfx_command.begin_render_pass(
                     &node.render_pass,
                     &framebuffer,
-                    viewport.clone().rect,
-                    // TODO: Clear Values
-                    vec![Clear::Color(0.2, 0.5, 0.1, 1.0), Clear::Depth(1.0, 0)],
+                    pass_viewport.rect.clone(),
+                    clear_values,
                 );
 
                 // Execute Callback
+                let texture = |a: &AttachmentIndex| match a {
+                    AttachmentIndex::Custom(id) => &attachment(*id).texture,
+                    AttachmentIndex::Backbuffer => {
+                        panic!("[GfxGraph] the backbuffer can not be sampled")
+                    }
+                };
                 let frame_data = FrameData {
                     cmd: &mut gfx_command,
                     frame_index: index,
-                    viewport: viewport.clone(),
+                    viewport: pass_viewport,
+                    sampled: graph_node.sampled_attachments.iter().map(texture).collect(),
+                    inputs: graph_node
+                        .input_attachments
+                        .iter()
+                        .map(|a| texture(&a.index))
+                        .collect(),
                 };
                 match node
                     .graph_node
@@ -354,6 +491,53 @@ impl<B: Backend> Graph for GfxGraph<B> {
                 // and end render pass
                 gfx_command.end_render_pass()
             }
+            // Results of compute nodes at the end are used by the next frame
+            record_barriers::<B>(gfx_command.inner_mut(), tracker.external());
+
+            let offscreen = self.data.offscreen.is_some();
+            for (attachment_index, readback) in readbacks {
+                let attachment = match attachment_index {
+                    AttachmentIndex::Backbuffer => {
+                        self.backbuffer.as_ref().ok_or(ReadbackError::Backbuffer)
+                    }
+                    AttachmentIndex::Custom(id) => self
+                        .attachments
+                        .iter()
+                        .find(|a| a.desc.id == id)
+                        .ok_or_else(|| ReadbackError::UnknownAttachment(id.to_string())),
+                };
+                let attachment = match attachment {
+                    Ok(attachment) if attachment.desc.samples > 1 => {
+                        readback.complete(Err(ReadbackError::Multisampled));
+                        continue;
+                    }
+                    Ok(attachment) => attachment,
+                    Err(e) => {
+                        readback.complete(Err(e));
+                        continue;
+                    }
+                };
+                let layout = self
+                    .final_layouts
+                    .iter()
+                    .find(|(i, _)| *i == attachment_index)
+                    .map(|(_, layout)| *layout)
+                    .unwrap_or(AttachmentLayout::Undefined);
+                let pending = PendingReadback::record(
+                    self.data.device.deref(),
+                    self.data.heapy.deref(),
+                    gfx_command.inner_mut(),
+                    attachment,
+                    attachment_layout(layout, offscreen),
+                    readback,
+                );
+                let mut frames = self.frames.lock();
+                frames
+                    .get_mut(index as usize)
+                    .unwrap()
+                    .readbacks
+                    .push(pending);
+            }
             // self.nodes.iter().
             // cb(&mut gfx_command);
 
@@ -385,35 +569,48 @@ impl<B: Backend> Graph for GfxGraph<B> {
         let mut graphics_queue = self.data.queues.graphics.lock();
 
         unsafe {
+            // Nothing waits for offscreen frames, besides the fence
+            let signal_semaphores = match swapchain_image {
+                Some(_) => vec![&this_frame.rendering_complete],
+                None => Vec::new(),
+            };
             let submission = Submission {
                 command_buffers: vec![&command],
                 wait_semaphores: None,
-                signal_semaphores: vec![&this_frame.rendering_complete],
+                signal_semaphores,
             };
             graphics_queue.submit(submission, Some(&this_frame.submission_fence));
         }
 
         this_frame.in_use_command = Some(command);
 
-        unsafe {
-            let surface = &mut self.data.surface.lock();
-
-            // NOTE(luca): Currently we do not think about suboptimal swapchains
-            let result = graphics_queue.present(
-                surface,
-                swapchain_image,
-                Some(&this_frame.rendering_complete),
-            );
-
-            if let Err(_e) = result {
-                // log::warn!("Recovarable Error happened");
-                // log::warn!("{:#?}", e);
-                self.should_configure_swapchain
-                    .store(true, Ordering::Relaxed);
+        if let Some(swapchain_image) = swapchain_image {
+            unsafe {
+                let surface = &mut self.data.surface.lock();
+
+                // NOTE(luca): Currently we do not think about suboptimal swapchains
+                let result = graphics_queue.present(
+                    surface,
+                    swapchain_image,
+                    Some(&this_frame.rendering_complete),
+                );
+
+                if let Err(_e) = result {
+                    // log::warn!("Recovarable Error happened");
+                    // log::warn!("{:#?}", e);
+                    self.should_configure_swapchain
+                        .store(true, Ordering::Relaxed);
+                }
             }
         }
     }
 
+    fn read_attachment(&mut self, index: Self::AttachmentIndex) -> Readback {
+        let readback = Readback::new();
+        self.readbacks.push((index, readback.clone()));
+        readback
+    }
+
     fn into_builder(self) -> Self::Builder {
         todo!()
     }
@@ -426,5 +623,8 @@ impl<B: Backend> Drop for GfxGraph<B> {
             .device
             .wait_idle()
             .expect("[Graph] failed to wait_idle");
+        for frame in self.frames.lock().iter_mut() {
+            unsafe { frame.finish_readbacks(&self.data.device, &self.data.heapy) };
+        }
     }
 }
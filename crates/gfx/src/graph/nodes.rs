@@ -1,11 +1,23 @@
 use gfx_hal::Backend;
-use render::graph::nodes::pass::PassNode;
+use render::graph::{
+    compile::{AttachmentLayout, CompiledNode},
+    nodes::{
+        compute::{ComputeNode, ComputeResource, ResourceAccess, ResourceBarrier},
+        pass::{PassAttachment, PassNode},
+    },
+};
 use uuid::Uuid;
 
 use gfx_hal::{
+    buffer::{self, SubRange},
+    command::CommandBuffer,
     device::Device,
-    image::Layout,
-    pass::{Attachment, AttachmentOps, AttachmentRef, SubpassDependency, SubpassDesc},
+    image::{self, Layout},
+    memory::{Barrier, Dependencies},
+    pass::{
+        Attachment, AttachmentOps, AttachmentRef, SubpassDependency, SubpassDesc, ATTACHMENT_UNUSED,
+    },
+    pso::PipelineStage,
 };
 use render::{
     graph::node::Node,
@@ -14,7 +26,7 @@ use render::{
 };
 use std::{ops::Range, sync::Arc};
 
-use crate::compat::ToHalType;
+use crate::{compat::ToHalType, context::GfxContext};
 
 use super::{
     attachment::{AttachmentIndex, GfxGraphAttachment},
@@ -29,6 +41,7 @@ use super::{
 
 pub(crate) enum GfxNode<B: Backend> {
     PassNode(GfxPassNode<B>),
+    ComputeNode(ComputeNode<GfxGraphBuilder<B>>),
 }
 
 pub struct GfxPassNode<B: Backend> {
@@ -38,14 +51,107 @@ pub struct GfxPassNode<B: Backend> {
 
 pub(super) fn build_node<B: Backend>(
     ctx: &B::Device,
-    node: Node<GfxGraphBuilder<B>>,
+    node: CompiledNode<GfxGraphBuilder<B>>,
     attachments: &[GfxGraphAttachment<B>],
     surface_format: TextureFormat,
+    offscreen: bool,
 ) -> GfxNode<B> {
-    match node {
-        Node::PassNode(n) => {
-            GfxNode::PassNode(build_pass_node(ctx, n, attachments, surface_format))
+    match node.node {
+        Node::PassNode(n) => GfxNode::PassNode(build_pass_node(
+            ctx,
+            n,
+            node.layouts,
+            attachments,
+            surface_format,
+            offscreen,
+        )),
+        Node::ComputeNode(n) => {
+            n.callbacks.borrow_mut().init();
+            GfxNode::ComputeNode(n)
+        }
+    }
+}
+
+/// Offscreen backbuffers are left as the source of readbacks instead of being presented
+pub(super) fn attachment_layout(layout: AttachmentLayout, offscreen: bool) -> Layout {
+    match layout {
+        AttachmentLayout::Present if offscreen => Layout::TransferSrcOptimal,
+        layout => layout.convert(),
+    }
+}
+
+fn stages(access: ResourceAccess) -> PipelineStage {
+    match access {
+        ResourceAccess::External => {
+            PipelineStage::TRANSFER
+                | PipelineStage::VERTEX_INPUT
+                | PipelineStage::VERTEX_SHADER
+                | PipelineStage::FRAGMENT_SHADER
+                | PipelineStage::COMPUTE_SHADER
         }
+        ResourceAccess::ComputeRead | ResourceAccess::ComputeWrite => PipelineStage::COMPUTE_SHADER,
+    }
+}
+
+fn buffer_access(access: ResourceAccess) -> buffer::Access {
+    match access {
+        ResourceAccess::External => {
+            buffer::Access::TRANSFER_WRITE
+                | buffer::Access::VERTEX_BUFFER_READ
+                | buffer::Access::INDEX_BUFFER_READ
+                | buffer::Access::UNIFORM_READ
+                | buffer::Access::SHADER_READ
+        }
+        ResourceAccess::ComputeRead => buffer::Access::SHADER_READ | buffer::Access::UNIFORM_READ,
+        ResourceAccess::ComputeWrite => buffer::Access::SHADER_READ | buffer::Access::SHADER_WRITE,
+    }
+}
+
+fn image_access(access: ResourceAccess) -> image::Access {
+    match access {
+        ResourceAccess::External => image::Access::TRANSFER_WRITE | image::Access::SHADER_READ,
+        ResourceAccess::ComputeRead => image::Access::SHADER_READ,
+        ResourceAccess::ComputeWrite => image::Access::SHADER_READ | image::Access::SHADER_WRITE,
+    }
+}
+
+/// Records the barriers inferred by the BarrierTracker of the graph
+pub(super) fn record_barriers<B: Backend>(
+    command: &mut B::CommandBuffer,
+    barriers: Vec<ResourceBarrier<GfxContext<B>>>,
+) {
+    if barriers.is_empty() {
+        return;
+    }
+
+    let mut src = PipelineStage::empty();
+    let mut dst = PipelineStage::empty();
+    let hal_barriers: Vec<Barrier<B>> = barriers
+        .iter()
+        .map(|b| {
+            src |= stages(b.states.start);
+            dst |= stages(b.states.end);
+            match &b.resource {
+                ComputeResource::Buffer(buffer) => Barrier::Buffer {
+                    states: buffer_access(b.states.start)..buffer_access(b.states.end),
+                    target: &buffer.0,
+                    range: SubRange::WHOLE,
+                    families: None,
+                },
+                // Textures stay in the same layout, there is just one for shader access
+                ComputeResource::Texture(texture) => Barrier::Image {
+                    states: (image_access(b.states.start), texture.layout())
+                        ..(image_access(b.states.end), texture.layout()),
+                    target: &texture.image,
+                    range: texture.subresource_range(),
+                    families: None,
+                },
+            }
+        })
+        .collect();
+
+    unsafe {
+        command.pipeline_barrier(src..dst, Dependencies::empty(), hal_barriers);
     }
 }
 
@@ -63,8 +169,7 @@ fn build_attachment<B: Backend>(
 
     Attachment {
         format: Some(graph_attachment.desc.format.clone().convert()),
-        // TODO: Multisampling
-        samples: 1u8,
+        samples: graph_attachment.desc.samples,
         ops: AttachmentOps::new(load.convert(), store.convert()),
         stencil_ops: AttachmentOps::DONT_CARE,
         layouts,
@@ -74,62 +179,69 @@ fn build_attachment<B: Backend>(
 fn build_pass_node<B: Backend>(
     ctx: &B::Device,
     node: PassNode<GfxGraphBuilder<B>>,
+    layouts: Vec<Range<AttachmentLayout>>,
     graph_attachments: &[GfxGraphAttachment<B>],
     surface_format: TextureFormat,
+    offscreen: bool,
 ) -> GfxPassNode<B> {
-    // ctx.create_render_pass(attachments, subpasses, dependencies);
-
     let num_of_out = node.output_attachments.len();
     let num_of_in = node.input_attachments.len();
-    let has_depth = node.depth_attachment.is_some();
-
-    let mut attachments: Vec<Attachment> =
-        Vec::with_capacity(num_of_out + num_of_in + (if has_depth { 1 } else { 0 }));
-
-    attachments.extend(node.output_attachments.iter().map(|a| match a.index {
-        AttachmentIndex::Custom(index) => build_attachment::<B>(
-            graph_attachments,
-            index,
-            a.load.clone(),
-            a.store.clone(),
-            Layout::Undefined..Layout::ShaderReadOnlyOptimal,
-        ),
-        AttachmentIndex::Backbuffer => Attachment {
-            format: Some(surface_format.clone().convert()),
-            samples: 1u8,
-            ops: AttachmentOps::new(a.load.clone().convert(), a.store.clone().convert()),
-            stencil_ops: AttachmentOps::DONT_CARE,
-            layouts: Layout::Undefined..Layout::Present,
-        },
-    }));
-
-    attachments.extend(node.input_attachments.iter().map(|a| match a.index {
-        AttachmentIndex::Custom(index) => build_attachment::<B>(
-            graph_attachments,
-            index,
-            a.load.clone(),
-            a.store.clone(),
-            Layout::ShaderReadOnlyOptimal..Layout::ShaderReadOnlyOptimal,
-        ),
-        AttachmentIndex::Backbuffer => {
-            panic!("Backbuffer as input attachment in graph is not allowed")
-        }
-    }));
-
-    if let Some(a) = &node.depth_attachment {
-        attachments.push(match a.index {
-            AttachmentIndex::Custom(index) => build_attachment::<B>(
-                graph_attachments,
-                index,
-                a.load.clone(),
-                a.store.clone(),
-                Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
-            ),
-            AttachmentIndex::Backbuffer => {
-                panic!("Backbuffer as depth attachment in graph is not allowed")
+    let num_of_depth = node.depth_attachment.iter().count();
+    // Resolve targets follow the depth attachment
+    let first_resolve = num_of_out + num_of_in + num_of_depth;
+    let resolve_targets: Vec<PassAttachment<AttachmentIndex>> = node
+        .resolve_attachments
+        .iter()
+        .map(|r| PassAttachment {
+            index: r.target,
+            load: LoadOp::DontCare,
+            store: StoreOp::Store,
+        })
+        .collect();
+    // Attachments that are read by shaders after the pass (eg. a shadow map)
+    let read_afterwards = layouts
+        .iter()
+        .any(|l| l.end == AttachmentLayout::ShaderReadOnly);
+
+    // The layouts were computed by the graph compiler, in the same order as the attachments
+    let attachments: Vec<Attachment> = node
+        .output_attachments
+        .iter()
+        .chain(node.input_attachments.iter())
+        .chain(node.depth_attachment.iter())
+        .chain(resolve_targets.iter())
+        .zip(layouts)
+        .enumerate()
+        .map(|(i, (a, layouts))| {
+            let layouts = attachment_layout(layouts.start, offscreen)
+                ..attachment_layout(layouts.end, offscreen);
+            match a.index {
+                AttachmentIndex::Custom(index) => build_attachment::<B>(
+                    graph_attachments,
+                    index,
+                    a.load.clone(),
+                    a.store.clone(),
+                    layouts,
+                ),
+                AttachmentIndex::Backbuffer => {
+                    assert!(
+                        i < num_of_out || i >= first_resolve,
+                        "Backbuffer as input or depth attachment in graph is not allowed"
+                    );
+                    Attachment {
+                        format: Some(surface_format.convert()),
+                        samples: 1u8,
+                        ops: AttachmentOps::new(
+                            a.load.clone().convert(),
+                            a.store.clone().convert(),
+                        ),
+                        stencil_ops: AttachmentOps::DONT_CARE,
+                        layouts,
+                    }
+                }
             }
         })
-    }
+        .collect();
 
     let create_attachment_ref = |range: Range<usize>, layout: Layout| -> Vec<AttachmentRef> {
         range.into_iter().map(|i| (i, layout)).collect()
@@ -144,6 +256,25 @@ fn build_pass_node<B: Backend>(
         None
     };
 
+    // Outputs without a resolve are marked as unused, if any output is resolved
+    let resolves: Vec<AttachmentRef> = if node.resolve_attachments.is_empty() {
+        Vec::new()
+    } else {
+        node.output_attachments
+            .iter()
+            .map(|output| {
+                let resolve = node
+                    .resolve_attachments
+                    .iter()
+                    .position(|r| r.source == output.index);
+                match resolve {
+                    Some(r) => (first_resolve + r, Layout::ColorAttachmentOptimal),
+                    None => (ATTACHMENT_UNUSED, Layout::ColorAttachmentOptimal),
+                }
+            })
+            .collect()
+    };
+
     let subpass = SubpassDesc {
         colors: &create_attachment_ref(0..num_of_out, Layout::ColorAttachmentOptimal),
         depth_stencil: depth_stencil.as_ref(),
@@ -151,11 +282,22 @@ fn build_pass_node<B: Backend>(
             num_of_out..(num_of_out + num_of_in),
             Layout::ShaderReadOnlyOptimal,
         ),
-        resolves: &Vec::new(),
+        resolves: &resolves,
         preserves: &Vec::new(),
     };
 
-    let dependencies: Vec<SubpassDependency> = Vec::new();
+    let mut dependencies: Vec<SubpassDependency> = Vec::new();
+    if read_afterwards {
+        dependencies.push(SubpassDependency {
+            passes: Some(0)..None,
+            stages: (PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::LATE_FRAGMENT_TESTS)
+                ..PipelineStage::FRAGMENT_SHADER,
+            accesses: (image::Access::COLOR_ATTACHMENT_WRITE
+                | image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                ..image::Access::SHADER_READ,
+            flags: Dependencies::empty(),
+        });
+    }
 
     let render_pass = Arc::new(unsafe {
         ctx.create_render_pass(attachments, &vec![subpass], dependencies)
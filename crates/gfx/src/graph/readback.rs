@@ -0,0 +1,156 @@
+use gfx_hal::{
+    buffer::{self, SubRange},
+    command::{BufferImageCopy, CommandBuffer},
+    device::Device,
+    format::Aspects,
+    image::{Access, Extent, Layout, Offset, SubresourceLayers, SubresourceRange},
+    memory::{Barrier, Dependencies},
+    pso::PipelineStage,
+    Backend,
+};
+use render::{
+    graph::readback::{Readback, ReadbackImage},
+    prelude::MemoryType,
+    resource::frame::Extent2D,
+    util::format::TextureFormat,
+};
+
+use crate::heapy::{AllocationIndex, Heapy};
+
+use super::attachment::GfxGraphAttachment;
+
+/// Copy of an attachment in host visible memory, that is read once the frame finished rendering
+#[derive(Debug)]
+pub(super) struct PendingReadback<B: Backend> {
+    readback: Readback,
+    buffer: B::Buffer,
+    allocation: AllocationIndex,
+    extent: Extent2D,
+    format: TextureFormat,
+}
+
+impl<B: Backend> PendingReadback<B> {
+    /// Records the copy of the attachment, which is in the given layout at the end of the frame
+    /// and transitioned back into it afterwards
+    pub(super) fn record(
+        device: &B::Device,
+        heapy: &Heapy<B>,
+        command: &mut B::CommandBuffer,
+        attachment: &GfxGraphAttachment<B>,
+        layout: Layout,
+        readback: Readback,
+    ) -> Self {
+        let texture = &attachment.texture;
+        let extent = texture.desc.extent.clone();
+        let format = texture.desc.format;
+        let size = (extent.width * extent.height) as u64 * format.texel_size() as u64;
+
+        // Only the depth of depth stencil attachments is copied
+        let aspects = match format {
+            TextureFormat::Depth32Sfloat | TextureFormat::Depth24PlusStencil8 => Aspects::DEPTH,
+            _ => Aspects::COLOR,
+        };
+        let range = SubresourceRange {
+            aspects,
+            level_start: 0,
+            level_count: Some(1),
+            layer_start: 0,
+            layer_count: Some(1),
+        };
+
+        unsafe {
+            let mut buffer = device
+                .create_buffer(size, buffer::Usage::TRANSFER_DST)
+                .expect("[GfxGraph] (readback) failed to create buffer");
+            let requirements = device.get_buffer_requirements(&buffer);
+            let allocation = heapy.alloc(
+                requirements.size,
+                MemoryType::HostVisible,
+                Some(requirements),
+            );
+            heapy.bind_buffer(&allocation, &mut buffer);
+
+            command.pipeline_barrier(
+                (PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                    | PipelineStage::LATE_FRAGMENT_TESTS
+                    | PipelineStage::FRAGMENT_SHADER)..PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (
+                        Access::COLOR_ATTACHMENT_WRITE | Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        layout,
+                    )
+                        ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                    target: &texture.image,
+                    range: range.clone(),
+                    families: None,
+                }],
+            );
+            command.copy_image_to_buffer(
+                &texture.image,
+                Layout::TransferSrcOptimal,
+                &buffer,
+                &[BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: extent.width,
+                    buffer_height: extent.height,
+                    image_layers: SubresourceLayers {
+                        aspects,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: Offset::ZERO,
+                    image_extent: Extent {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    },
+                }],
+            );
+
+            // Contents of attachments without a layout are not used by the next frame
+            let mut barriers = vec![Barrier::Buffer {
+                states: buffer::Access::TRANSFER_WRITE..buffer::Access::HOST_READ,
+                target: &buffer,
+                range: SubRange::WHOLE,
+                families: None,
+            }];
+            if layout != Layout::Undefined {
+                barriers.push(Barrier::Image {
+                    states: (Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                        ..(Access::empty(), layout),
+                    target: &texture.image,
+                    range,
+                    families: None,
+                });
+            }
+            command.pipeline_barrier(
+                PipelineStage::TRANSFER..(PipelineStage::HOST | PipelineStage::BOTTOM_OF_PIPE),
+                Dependencies::empty(),
+                barriers,
+            );
+
+            Self {
+                readback,
+                buffer,
+                allocation,
+                extent,
+                format,
+            }
+        }
+    }
+
+    /// Should only be called once the submission fence of the frame was signaled
+    pub(super) unsafe fn finish(self, device: &B::Device, heapy: &Heapy<B>) {
+        let size = (self.extent.width * self.extent.height) as usize * self.format.texel_size();
+        let data = heapy.read(&self.allocation, size);
+        device.destroy_buffer(self.buffer);
+        heapy.deallocate(self.allocation);
+
+        self.readback.complete(Ok(ReadbackImage {
+            extent: self.extent,
+            format: self.format,
+            data,
+        }));
+    }
+}
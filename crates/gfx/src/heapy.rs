@@ -220,6 +220,48 @@ impl<B: Backend> Heapy<B> {
         self.device.unmap_memory(page.memory_handle.deref());
     }
 
+    /// Copies the first length bytes of the allocation
+    /// Safety: Will panic if allocation is not HostVisible
+    pub(crate) unsafe fn read(&self, at: &AllocationIndex, length: usize) -> Vec<u8> {
+        if at.memory_type != MemoryType::HostVisible {
+            panic!("[Heapy] (read) tried to map un-mappable memory");
+        }
+        let pages = self.pages.read();
+        let (_, pages) = pages
+            .get(&at.memory_type)
+            .expect("[Heapy] (read) invalid index");
+        let page = pages.get(at.page).expect("[Heapy] (read) invalid index");
+        let allocation = page
+            .allocations
+            .allocations
+            .iter()
+            .find(|a| a.offset == at.offset)
+            .expect("[Heapy] (read) invalid index");
+        if (allocation.size as usize) < length {
+            panic!(
+                "[Heapy] (read) length is larger than buffer {} vs. {}",
+                allocation.size, length
+            );
+        }
+        use gfx_hal::memory::Segment;
+        let src = self
+            .device
+            .map_memory(
+                page.memory_handle.deref(),
+                Segment {
+                    offset: allocation.offset,
+                    size: Some(allocation.size),
+                },
+            )
+            .expect("[Heapy] (read) map_memory failed");
+
+        let mut data = vec![0u8; length];
+        std::ptr::copy_nonoverlapping(src, data.as_mut_ptr(), length);
+
+        self.device.unmap_memory(page.memory_handle.deref());
+        data
+    }
+
     fn get_page_info(device: &B::PhysicalDevice, props: Properties) -> PageInfo {
         let memory_properties = device.memory_properties();
 
@@ -10,6 +10,8 @@ pub mod heapy;
 mod memory_page;
 pub(crate) mod plumber;
 pub(crate) mod pool;
+pub mod texture;
+pub(crate) mod transfer;
 // pub(crate) mod swapper;
 
 mod graph;
@@ -4,13 +4,14 @@ use app::core::anyhow;
 use gfx_hal::pass::Subpass;
 use gfx_hal::pso::{
     AttributeDesc, BakedStates, BasePipeline, BlendDesc, BlendState, ColorBlendDesc, ColorMask,
-    DepthStencilDesc, EntryPoint, GraphicsPipelineDesc, InputAssemblerDesc, PipelineCreationFlags,
-    PrimitiveAssemblerDesc, ShaderStageFlags, VertexBufferDesc,
+    ComputePipelineDesc, DepthStencilDesc, EntryPoint, GraphicsPipelineDesc, InputAssemblerDesc,
+    Multisampling, PipelineCreationFlags, PrimitiveAssemblerDesc, ShaderStageFlags,
+    VertexBufferDesc,
 };
 use gfx_hal::{device::Device, Backend};
 use parking_lot::Mutex;
 use render::resource::pipeline::{
-    GraphicsPipelineDescriptor, RenderContext, ShaderSource, ShaderType,
+    ComputePipelineDescriptor, GraphicsPipelineDescriptor, RenderContext, ShaderSource, ShaderType,
 };
 use shaderc::Compiler;
 use std::borrow::Cow;
@@ -186,7 +187,17 @@ impl<B: Backend> Plumber<B> {
                     .collect(),
             },
             depth_stencil,
-            multisampling: None,
+            multisampling: if desc.samples > 1 {
+                Some(Multisampling {
+                    rasterization_samples: desc.samples,
+                    sample_shading: None,
+                    sample_mask: !0,
+                    alpha_coverage: false,
+                    alpha_to_one: false,
+                })
+            } else {
+                None
+            },
             baked_states,
             layout: &layout,
             subpass,
@@ -214,6 +225,58 @@ impl<B: Backend> Plumber<B> {
         (pipeline, layout)
     }
 
+    pub(crate) fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<GfxContext<B>>,
+    ) -> (B::ComputePipeline, B::PipelineLayout) {
+        let shader = self.create_shader_module(Some(desc.shader)).unwrap();
+
+        let layout = unsafe {
+            let name = desc.name.clone();
+            let set_layouts: Vec<&B::DescriptorSetLayout> = desc
+                .mixtures
+                .into_iter()
+                .map(|m| -> &<B as Backend>::DescriptorSetLayout { &m.gpu_layout.handle.1 })
+                .collect();
+            self.device
+                .create_pipeline_layout(
+                    set_layouts,
+                    desc.push_constants
+                        .into_iter()
+                        .map(|r| (ShaderStageFlags::COMPUTE, r)),
+                )
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "[Plumber] failed to create pipeline layout for compute pipeline: {}",
+                        name
+                    )
+                })
+        };
+
+        let hal_desc = ComputePipelineDesc::new(
+            EntryPoint {
+                entry: "main",
+                module: &shader,
+                specialization: Default::default(),
+            },
+            &layout,
+        );
+
+        let mut pipeline = unsafe {
+            self.device
+                .create_compute_pipeline(&hal_desc, None)
+                .expect("[Plumber] (create_compute_pipeline) failed to create compute pipeline")
+        };
+
+        unsafe {
+            self.device
+                .set_compute_pipeline_name(&mut pipeline, desc.name.deref());
+            self.device.destroy_shader_module(shader);
+        }
+
+        (pipeline, layout)
+    }
+
     fn compile_glsl(
         &self,
         source: &str,
@@ -265,7 +328,7 @@ impl<B: Backend> Plumber<B> {
                         "vert" => ShaderType::Vertex,
                         "frag" => ShaderType::Fragment,
                         "geometry" => ShaderType::Geometry,
-                        "compute" => ShaderType::Compute,
+                        "comp" | "compute" => ShaderType::Compute,
                         _ => panic!("[Plumber] unknown shader extension: {:#?}", extension),
                     },
                     None => panic!("[Plumber] path had no valid extension name"),
@@ -161,6 +161,12 @@ impl<B: Backend> Pool<B> {
                     render::resource::glue::Descriptor::Buffer(buffer, range) => {
                         Descriptor::Buffer(&buffer.0, range.convert())
                     }
+                    render::resource::glue::Descriptor::Texture(texture, sampler) => {
+                        Descriptor::CombinedImageSampler(&texture.view, texture.layout(), sampler)
+                    }
+                    render::resource::glue::Descriptor::StorageImage(texture) => {
+                        Descriptor::Image(&texture.view, texture.layout())
+                    }
                 };
                 DescriptorSetWrite {
                     set: &handle.1,
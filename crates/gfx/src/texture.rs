@@ -0,0 +1,302 @@
+use std::ops::Range;
+
+use gfx_hal::{
+    buffer,
+    command::{BufferImageCopy, CommandBuffer, ImageBlit},
+    device::Device,
+    format::{Aspects, Swizzle},
+    image::{
+        Access, Extent, Filter, Kind, Layout, Offset, SubresourceLayers, SubresourceRange, Tiling,
+        Usage, ViewCapabilities, ViewKind,
+    },
+    memory::{Barrier, Dependencies},
+    pso::PipelineStage,
+    Backend,
+};
+use render::{
+    prelude::MemoryType,
+    resource::texture::{mip_extent, TextureDescriptor},
+};
+
+use crate::{
+    compat::ToHalType,
+    heapy::{AllocationIndex, Heapy},
+    transfer::Transfer,
+};
+
+/// Sampled image of the gfx backend, with a view over all mip levels
+#[derive(Debug)]
+pub struct GfxTexture<B: Backend> {
+    pub(crate) image: B::Image,
+    pub(crate) allocation: AllocationIndex,
+    pub(crate) view: B::ImageView,
+    pub(crate) desc: TextureDescriptor,
+}
+
+fn levels(range: Range<u32>) -> SubresourceRange {
+    SubresourceRange {
+        aspects: Aspects::COLOR,
+        level_start: range.start as u8,
+        level_count: Some((range.end - range.start) as u8),
+        layer_start: 0,
+        layer_count: Some(1),
+    }
+}
+
+fn layers(level: u32) -> SubresourceLayers {
+    SubresourceLayers {
+        aspects: Aspects::COLOR,
+        level: level as u8,
+        layers: 0..1,
+    }
+}
+
+fn bounds(desc: &TextureDescriptor, level: u32) -> Range<Offset> {
+    let extent = mip_extent(&desc.extent, level);
+    Offset::ZERO..Offset {
+        x: extent.width as i32,
+        y: extent.height as i32,
+        z: 1,
+    }
+}
+
+impl<B: Backend> GfxTexture<B> {
+    /// Storage textures are transitioned into their layout directly, since they might never
+    /// be uploaded
+    pub(crate) fn create(
+        device: &B::Device,
+        heapy: &Heapy<B>,
+        transfer: &Transfer<B>,
+        desc: &TextureDescriptor,
+    ) -> Self {
+        let format = desc.format.convert();
+        let mut usage = Usage::SAMPLED | Usage::TRANSFER_DST | Usage::TRANSFER_SRC;
+        if desc.storage {
+            usage |= Usage::STORAGE;
+        }
+        let texture = unsafe {
+            let mut image = device
+                .create_image(
+                    Kind::D2(desc.extent.width, desc.extent.height, 1, 1),
+                    desc.mip_levels as u8,
+                    format,
+                    Tiling::Optimal,
+                    usage,
+                    ViewCapabilities::empty(),
+                )
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "[GfxTexture] failed to create image [{}]: {:?}",
+                        desc.name, e
+                    )
+                });
+            let requirements = device.get_image_requirements(&image);
+            let allocation = heapy.alloc(
+                requirements.size,
+                MemoryType::DeviceLocal,
+                Some(requirements),
+            );
+            heapy.bind_image(&allocation, &mut image);
+            device.set_image_name(&mut image, desc.name.as_ref());
+
+            let view = device
+                .create_image_view(
+                    &image,
+                    ViewKind::D2,
+                    format,
+                    Swizzle::NO,
+                    levels(0..desc.mip_levels),
+                )
+                .expect("[GfxTexture] failed to create image view");
+
+            Self {
+                image,
+                allocation,
+                view,
+                desc: desc.clone(),
+            }
+        };
+
+        if desc.storage {
+            let image = &texture.image;
+            transfer.one_shot(|cmd| unsafe {
+                cmd.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE..PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    &[Barrier::Image {
+                        states: (Access::empty(), Layout::Undefined)
+                            ..(Access::SHADER_READ | Access::SHADER_WRITE, Layout::General),
+                        target: image,
+                        range: levels(0..desc.mip_levels),
+                        families: None,
+                    }],
+                );
+            });
+        }
+        texture
+    }
+
+    /// The layout the texture is in, whenever it is accessed by shaders
+    pub(crate) fn layout(&self) -> Layout {
+        if self.desc.storage {
+            Layout::General
+        } else {
+            Layout::ShaderReadOnlyOptimal
+        }
+    }
+
+    pub(crate) fn subresource_range(&self) -> SubresourceRange {
+        levels(0..self.desc.mip_levels)
+    }
+
+    /// Copies levels through a staging buffer, generates the remaining mip levels by blitting and
+    /// leaves the whole image in its shader layout (see layout)
+    pub(crate) fn upload(
+        &self,
+        device: &B::Device,
+        heapy: &Heapy<B>,
+        transfer: &Transfer<B>,
+        copy_alignment: u64,
+        data: &[&[u8]],
+    ) {
+        let desc = &self.desc;
+        let uploaded = data.len() as u32;
+        assert!(
+            uploaded >= 1 && uploaded <= desc.mip_levels,
+            "[GfxTexture] (upload) invalid level count for {}",
+            desc.name
+        );
+
+        // Offsets into the staging buffer need to be a multiple of the texel size and 4
+        let alignment = copy_alignment.max(desc.format.texel_size() as u64).max(4);
+        let mut offsets = Vec::with_capacity(data.len());
+        let mut size = 0u64;
+        for level in data {
+            size = (size + alignment - 1) / alignment * alignment;
+            offsets.push(size);
+            size += level.len() as u64;
+        }
+        let mut staging_data = vec![0u8; size as usize];
+        for (level, offset) in data.iter().zip(offsets.iter()) {
+            let offset = *offset as usize;
+            staging_data[offset..offset + level.len()].copy_from_slice(level);
+        }
+
+        let (staging, staging_allocation) = unsafe {
+            let mut buffer = device
+                .create_buffer(size, buffer::Usage::TRANSFER_SRC)
+                .expect("[GfxTexture] (upload) failed to create staging buffer");
+            let requirements = device.get_buffer_requirements(&buffer);
+            let allocation = heapy.alloc(
+                requirements.size,
+                MemoryType::HostVisible,
+                Some(requirements),
+            );
+            heapy.bind_buffer(&allocation, &mut buffer);
+            heapy.write(&allocation, &staging_data);
+            (buffer, allocation)
+        };
+
+        let image = &self.image;
+        transfer.one_shot(|cmd| unsafe {
+            cmd.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (Access::empty(), Layout::Undefined)
+                        ..(Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+                    target: image,
+                    range: levels(0..desc.mip_levels),
+                    families: None,
+                }],
+            );
+
+            let regions: Vec<BufferImageCopy> = offsets
+                .iter()
+                .enumerate()
+                .map(|(level, offset)| {
+                    let extent = mip_extent(&desc.extent, level as u32);
+                    BufferImageCopy {
+                        buffer_offset: *offset,
+                        buffer_width: extent.width,
+                        buffer_height: extent.height,
+                        image_layers: layers(level as u32),
+                        image_offset: Offset::ZERO,
+                        image_extent: Extent {
+                            width: extent.width,
+                            height: extent.height,
+                            depth: 1,
+                        },
+                    }
+                })
+                .collect();
+            cmd.copy_buffer_to_image(&staging, image, Layout::TransferDstOptimal, regions);
+
+            // Every generated level is blitted from the previous one, which needs to be a source
+            for level in uploaded..desc.mip_levels {
+                cmd.pipeline_barrier(
+                    PipelineStage::TRANSFER..PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    &[Barrier::Image {
+                        states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                            ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                        target: image,
+                        range: levels(level - 1..level),
+                        families: None,
+                    }],
+                );
+                cmd.blit_image(
+                    image,
+                    Layout::TransferSrcOptimal,
+                    image,
+                    Layout::TransferDstOptimal,
+                    Filter::Linear,
+                    &[ImageBlit {
+                        src_subresource: layers(level - 1),
+                        src_bounds: bounds(desc, level - 1),
+                        dst_subresource: layers(level),
+                        dst_bounds: bounds(desc, level),
+                    }],
+                );
+            }
+
+            let sources = (uploaded - 1)..(desc.mip_levels - 1);
+            let barriers: Vec<Barrier<B>> = (0..desc.mip_levels)
+                .map(|level| {
+                    let from = if sources.contains(&level) {
+                        (Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                    } else {
+                        (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                    };
+                    Barrier::Image {
+                        states: from..(Access::SHADER_READ, self.layout()),
+                        target: image,
+                        range: levels(level..level + 1),
+                        families: None,
+                    }
+                })
+                .collect();
+            cmd.pipeline_barrier(
+                PipelineStage::TRANSFER
+                    ..PipelineStage::VERTEX_SHADER
+                        | PipelineStage::FRAGMENT_SHADER
+                        | PipelineStage::COMPUTE_SHADER,
+                Dependencies::empty(),
+                barriers,
+            );
+        });
+
+        unsafe {
+            device.destroy_buffer(staging);
+        }
+        heapy.deallocate(staging_allocation);
+    }
+
+    pub(crate) fn destroy(self, device: &B::Device, heapy: &Heapy<B>) {
+        unsafe {
+            device.destroy_image_view(self.view);
+            device.destroy_image(self.image);
+        }
+        heapy.deallocate(self.allocation);
+    }
+}
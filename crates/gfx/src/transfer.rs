@@ -0,0 +1,378 @@
+use std::{iter, mem::ManuallyDrop, sync::Arc};
+
+use gfx_hal::{
+    buffer::{Access, SubRange},
+    command::{CommandBuffer, CommandBufferFlags, Level},
+    device::Device,
+    memory::{Barrier, Dependencies},
+    pool::{CommandPool, CommandPoolCreateFlags},
+    prelude::CommandQueue,
+    pso::PipelineStage,
+    Backend,
+};
+use parking_lot::Mutex;
+use render::resource::buffer::{BufferCopy, BufferDescriptor, BufferUsage, MemoryType};
+
+use crate::{
+    compat::ToHalType,
+    context::{get_buffer_usage, Queues},
+    heapy::{AllocationIndex, Heapy},
+};
+
+/// Number of staging buffers in the upload ring
+const RING_SIZE: usize = 4;
+/// Size of a single staging buffer, larger uploads are split into multiple copies
+const STAGING_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+struct StagingSlot<B: Backend> {
+    buffer: B::Buffer,
+    allocation: AllocationIndex,
+    fence: B::Fence,
+    /// The copy that is (or was) executed from this slot, until the fence is signaled
+    command: Option<B::CommandBuffer>,
+}
+
+/// Slots are reused in order, every copy gets a serial so that resources used by uploads can
+/// be retired until those have finished
+#[derive(Debug)]
+struct StagingRing<S, R> {
+    slots: Vec<S>,
+    next: usize,
+    /// Serial of the next submission
+    serial: u64,
+    /// Serial of the copy of each slot, until it was waited for
+    in_flight: Vec<Option<u64>>,
+    /// Dropped resources, with the serial of the last submission before the drop
+    retired: Vec<(u64, R)>,
+}
+
+impl<S, R> StagingRing<S, R> {
+    fn new(slots: Vec<S>) -> Self {
+        Self {
+            in_flight: slots.iter().map(|_| None).collect(),
+            slots,
+            next: 0,
+            serial: 0,
+            retired: Vec::new(),
+        }
+    }
+
+    /// Index of the slot for the next copy
+    fn advance(&mut self) -> usize {
+        let next = self.next;
+        self.next = (next + 1) % self.slots.len();
+        next
+    }
+
+    fn submitted(&mut self, slot: usize) {
+        self.in_flight[slot] = Some(self.serial);
+        self.serial += 1;
+    }
+
+    fn finished(&mut self, slot: usize) {
+        self.in_flight[slot] = None;
+    }
+
+    /// Every submission with a lower serial has finished
+    fn completed(&self) -> u64 {
+        self.in_flight
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(self.serial)
+    }
+
+    /// Keeps resource until the uploads in flight have finished, returns it if there are none
+    fn retire(&mut self, resource: R) -> Option<R> {
+        if self.completed() == self.serial {
+            Some(resource)
+        } else {
+            self.retired.push((self.serial, resource));
+            None
+        }
+    }
+
+    /// Retired resources that are no longer used by any upload
+    fn release(&mut self) -> Vec<R> {
+        let completed = self.completed();
+        let (released, retired) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|(serial, _)| *serial <= completed);
+        self.retired = retired;
+        released.into_iter().map(|(_, resource)| resource).collect()
+    }
+}
+
+/// Copies of an upload into the staging buffers, uploads larger than a staging buffer are split
+fn staging_copies(offset: u64, size: usize) -> impl Iterator<Item = BufferCopy> {
+    (0..size as u64)
+        .step_by(STAGING_SIZE as usize)
+        .map(move |start| BufferCopy {
+            src_offset: 0,
+            dst_offset: offset + start,
+            size: (size as u64 - start).min(STAGING_SIZE),
+        })
+}
+
+type RetiredBuffer<B> = (<B as Backend>::Buffer, AllocationIndex);
+
+/// Submits single shot commands (eg. uploads) to the graphics queue
+#[derive(Debug)]
+pub(crate) struct Transfer<B: Backend> {
+    device: Arc<B::Device>,
+    queues: Arc<Queues<B>>,
+    heapy: Arc<Heapy<B>>,
+    pool: Mutex<ManuallyDrop<B::CommandPool>>,
+    ring: Mutex<StagingRing<StagingSlot<B>, RetiredBuffer<B>>>,
+}
+
+impl<B: Backend> Transfer<B> {
+    pub(crate) fn new(
+        device: Arc<B::Device>,
+        queues: Arc<Queues<B>>,
+        heapy: Arc<Heapy<B>>,
+    ) -> Self {
+        let pool = unsafe {
+            device
+                .create_command_pool(
+                    queues.graphics_family,
+                    CommandPoolCreateFlags::TRANSIENT | CommandPoolCreateFlags::RESET_INDIVIDUAL,
+                )
+                .expect("[Transfer] failed to create command pool")
+        };
+        let desc = BufferDescriptor {
+            name: "staging".into(),
+            size: STAGING_SIZE,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Staging,
+        };
+        let slots = (0..RING_SIZE)
+            .map(|_| unsafe {
+                let mut buffer = device
+                    .create_buffer(desc.size, get_buffer_usage(&desc))
+                    .expect("[Transfer] failed to create staging buffer");
+                let requirements = device.get_buffer_requirements(&buffer);
+                let allocation =
+                    heapy.alloc(requirements.size, desc.memory_type, Some(requirements));
+                heapy.bind_buffer(&allocation, &mut buffer);
+                device.set_buffer_name(&mut buffer, &desc.name);
+                StagingSlot {
+                    buffer,
+                    allocation,
+                    fence: device
+                        .create_fence(false)
+                        .expect("[Transfer] failed to create fence"),
+                    command: None,
+                }
+            })
+            .collect();
+
+        Self {
+            device,
+            queues,
+            heapy,
+            pool: Mutex::new(ManuallyDrop::new(pool)),
+            ring: Mutex::new(StagingRing::new(slots)),
+        }
+    }
+
+    fn record(&self, cb: impl FnOnce(&mut B::CommandBuffer)) -> B::CommandBuffer {
+        let mut command = unsafe { self.pool.lock().allocate_one(Level::Primary) };
+        unsafe {
+            command.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+        }
+        cb(&mut command);
+        unsafe {
+            command.finish();
+        }
+        command
+    }
+
+    fn submit(&self, command: &B::CommandBuffer, fence: &B::Fence) {
+        unsafe {
+            self.queues
+                .graphics
+                .lock()
+                .submit_without_semaphores(iter::once(command), Some(fence));
+        }
+    }
+
+    /// Waits for the last copy of the slot, afterwards the slot can be reused
+    fn wait_slot(&self, ring: &mut StagingRing<StagingSlot<B>, RetiredBuffer<B>>, index: usize) {
+        ring.finished(index);
+        let slot = &mut ring.slots[index];
+        if let Some(command) = slot.command.take() {
+            unsafe {
+                self.device
+                    .wait_for_fence(&slot.fence, !0)
+                    .expect("[Transfer] failed to wait for upload");
+                self.device
+                    .reset_fence(&slot.fence)
+                    .expect("[Transfer] failed to reset fence");
+                self.pool.lock().free(iter::once(command));
+            }
+        }
+    }
+
+    /// Records the commands of cb and waits until the gpu has executed them
+    pub(crate) fn one_shot(&self, cb: impl FnOnce(&mut B::CommandBuffer)) {
+        let command = self.record(cb);
+        unsafe {
+            let fence = self
+                .device
+                .create_fence(false)
+                .expect("[Transfer] (one_shot) failed to create fence");
+            self.submit(&command, &fence);
+            self.device
+                .wait_for_fence(&fence, !0)
+                .expect("[Transfer] (one_shot) failed to wait for fence");
+            self.device.destroy_fence(fence);
+
+            self.pool.lock().free(iter::once(command));
+        }
+    }
+
+    /// Copies data into buffer through the staging ring, without waiting for the copy
+    pub(crate) fn upload(&self, buffer: &B::Buffer, offset: u64, data: &[u8]) {
+        let mut ring = self.ring.lock();
+        let chunks = data.chunks(STAGING_SIZE as usize);
+        for (chunk, copy) in chunks.zip(staging_copies(offset, data.len())) {
+            let next = ring.advance();
+            self.wait_slot(&mut ring, next);
+            let slot = &mut ring.slots[next];
+
+            unsafe {
+                self.heapy.write(&slot.allocation, chunk);
+            }
+            let staging = &slot.buffer;
+            let command = self.record(|cmd| unsafe {
+                cmd.copy_buffer(staging, buffer, iter::once(copy.clone().convert()));
+                // Later submissions on the queue read the buffer as vertices, indices, uniforms or storage
+                cmd.pipeline_barrier(
+                    PipelineStage::TRANSFER
+                        ..PipelineStage::VERTEX_INPUT
+                            | PipelineStage::VERTEX_SHADER
+                            | PipelineStage::FRAGMENT_SHADER
+                            | PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Buffer {
+                        states: Access::TRANSFER_WRITE
+                            ..Access::VERTEX_BUFFER_READ
+                                | Access::INDEX_BUFFER_READ
+                                | Access::UNIFORM_READ
+                                | Access::SHADER_READ,
+                        target: buffer,
+                        range: SubRange {
+                            offset: copy.dst_offset,
+                            size: Some(copy.size),
+                        },
+                        families: None,
+                    }),
+                );
+            });
+            self.submit(&command, &slot.fence);
+            slot.command = Some(command);
+            ring.submitted(next);
+        }
+        self.release(&mut ring);
+    }
+
+    /// Waits until every upload of the staging ring has finished
+    pub(crate) fn flush(&self) {
+        let mut ring = self.ring.lock();
+        for index in 0..ring.slots.len() {
+            self.wait_slot(&mut ring, index);
+        }
+        self.release(&mut ring);
+    }
+
+    /// Destroys the buffer once the uploads that were submitted before have finished, these
+    /// may still copy into it
+    pub(crate) fn drop_buffer(&self, buffer: B::Buffer, allocation: AllocationIndex) {
+        let mut ring = self.ring.lock();
+        if let Some(buffer) = ring.retire((buffer, allocation)) {
+            self.destroy_buffer(buffer);
+        }
+    }
+
+    fn release(&self, ring: &mut StagingRing<StagingSlot<B>, RetiredBuffer<B>>) {
+        for buffer in ring.release() {
+            self.destroy_buffer(buffer);
+        }
+    }
+
+    fn destroy_buffer(&self, (buffer, allocation): RetiredBuffer<B>) {
+        unsafe {
+            self.device.destroy_buffer(buffer);
+        }
+        self.heapy.deallocate(allocation);
+    }
+}
+
+impl<B: Backend> Drop for Transfer<B> {
+    fn drop(&mut self) {
+        self.flush();
+        unsafe {
+            for slot in self.ring.get_mut().slots.drain(..) {
+                self.device.destroy_buffer(slot.buffer);
+                self.device.destroy_fence(slot.fence);
+                self.heapy.deallocate(slot.allocation);
+            }
+            let pool = ManuallyDrop::take(self.pool.get_mut());
+            self.device.destroy_command_pool(pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_wraps_around() {
+        let mut ring: StagingRing<(), ()> = StagingRing::new(vec![(); RING_SIZE]);
+        let slots: Vec<usize> = (0..RING_SIZE + 2).map(|_| ring.advance()).collect();
+        assert_eq!(slots[..RING_SIZE], (0..RING_SIZE).collect::<Vec<_>>()[..]);
+        assert_eq!(slots[RING_SIZE..], [0, 1]);
+    }
+
+    #[test]
+    fn retired_resources_wait_for_uploads() {
+        let mut ring: StagingRing<(), &str> = StagingRing::new(vec![(); 2]);
+        assert_eq!(ring.retire("idle"), Some("idle"));
+
+        let (first, second) = (ring.advance(), ring.advance());
+        ring.submitted(first);
+        assert_eq!(ring.retire("first"), None);
+        ring.submitted(second);
+        assert_eq!(ring.retire("second"), None);
+
+        // The second upload is still in flight
+        ring.finished(first);
+        assert_eq!(ring.release(), vec!["first"]);
+        // Wrapping around waits for the slot
+        let next = ring.advance();
+        assert_eq!(next, first);
+        ring.finished(second);
+        assert_eq!(ring.release(), vec!["second"]);
+    }
+
+    #[test]
+    fn large_uploads_are_split() {
+        let size = 2 * STAGING_SIZE as usize + 16;
+        let copies: Vec<(u64, u64)> = staging_copies(8, size)
+            .map(|copy| (copy.dst_offset, copy.size))
+            .collect();
+        assert_eq!(
+            copies,
+            vec![
+                (8, STAGING_SIZE),
+                (8 + STAGING_SIZE, STAGING_SIZE),
+                (8 + 2 * STAGING_SIZE, 16)
+            ]
+        );
+        assert_eq!(staging_copies(0, 4).count(), 1);
+    }
+}
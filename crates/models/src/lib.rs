@@ -7,6 +7,7 @@ use app::{App, AssetServer};
 use artisan::renderer::ActiveContext;
 use core::log;
 use loader::GltfLoader;
+use render::prelude::GpuResources;
 use std::sync::Arc;
 
 pub fn init_models(app: &mut App) {
@@ -20,5 +21,6 @@ pub fn init_models(app: &mut App) {
         return;
     }
     let ctx = app.get_res::<Arc<ActiveContext>>().clone();
-    app.add_asset_loader(GltfLoader { ctx });
+    let resources = GpuResources::new(ctx.clone());
+    app.add_asset_loader(GltfLoader { ctx, resources });
 }
@@ -1,14 +1,25 @@
-use core::anyhow::{bail, Context, Result};
-use std::sync::Arc;
+use core::anyhow::{anyhow, bail, Context, Result};
+use std::{
+    any::TypeId,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use app::{AssetHandle, AssetLoader, LoadContext};
 use artisan::{
-    material::Material,
-    mesh::{Indices, Mesh, MeshPart, Model, Vertex},
+    material::{Material, PbrFactors, PbrTextures},
+    mesh::{Indices, Mesh, MeshPart, Model, SurfaceVertex, Vertex},
     prelude::glam,
     renderer::ActiveContext,
+    texture::{ImageData, TextureAsset},
 };
 use gltf::{mesh::util::ReadIndices, Gltf};
+use render::{prelude::GpuResources, util::format::TextureFormat};
+
+/// Textures by image index and whether they hold colors (and are sampled as srgb)
+type Textures = HashMap<(usize, bool), AssetHandle<TextureAsset>>;
+type Materials = HashMap<Option<usize>, AssetHandle<Material>>;
 
 // fn load_node(
 //     node: gltf::Node,
@@ -35,10 +46,170 @@ use gltf::{mesh::util::ReadIndices, Gltf};
 // }
 //
 
+/// Uploads every image that is used by a material, once per color space it is sampled in
+async fn load_textures<'a>(
+    doc: &'a Gltf,
+    resources: &'a GpuResources<ActiveContext>,
+    buffers: &'a [Vec<u8>],
+    load_ctx: &LoadContext<'a>,
+) -> Result<Textures> {
+    let mut used = HashSet::new();
+    for material in doc.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let textures = vec![
+            pbr.base_color_texture().map(|i| (i.texture(), true)),
+            material.emissive_texture().map(|i| (i.texture(), true)),
+            pbr.metallic_roughness_texture()
+                .map(|i| (i.texture(), false)),
+            material.normal_texture().map(|t| (t.texture(), false)),
+            material.occlusion_texture().map(|t| (t.texture(), false)),
+        ];
+        for (texture, srgb) in textures.into_iter().flatten() {
+            used.insert((texture.source().index(), srgb));
+        }
+    }
+
+    let mut images: HashMap<usize, ImageData> = HashMap::new();
+    let mut textures = Textures::new();
+    for (index, srgb) in used {
+        let mut image = match images.entry(index) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let image = doc
+                    .images()
+                    .nth(index)
+                    .with_context(|| format!("image {} out of bounds", index))?;
+                let data = load_image(&image, buffers, load_ctx.path)
+                    .with_context(|| format!("failed to load image {}", index))?;
+                entry.insert(data).clone()
+            }
+        };
+        // Only 8 bit images are decoded, every other channel holds linear data
+        image.format = if srgb {
+            TextureFormat::Rgba8Srgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+        // Textures are addressable as "<file>#Texture/<image>" and "<file>#Texture/<image>/linear"
+        let label = if srgb {
+            format!("Texture/{}", index)
+        } else {
+            format!("Texture/{}/linear", index)
+        };
+        let texture = TextureAsset::from_image(resources, label.as_str(), image);
+        let handle = load_ctx.add_asset_with_label(label.as_str(), texture).await;
+        textures.insert((index, srgb), handle);
+    }
+    Ok(textures)
+}
+
+/// External images are read relative to the gltf file at path
+fn load_image(image: &gltf::Image, buffers: &[Vec<u8>], path: &Path) -> Result<ImageData> {
+    let (bytes, mime_type) = match image.source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let buffer = buffers
+                .get(view.buffer().index())
+                .context("buffer index out of bounds")?;
+            let bytes = buffer
+                .get(view.offset()..view.offset() + view.length())
+                .context("buffer view out of bounds")?;
+            (bytes.to_vec(), mime_type)
+        }
+        gltf::image::Source::Uri { uri, mime_type } => match uri.strip_prefix("data:") {
+            Some(uri) => {
+                let (mime_type, data) = uri
+                    .split_once(";base64,")
+                    .ok_or_else(|| anyhow!("image data uri is not base64"))?;
+                (base64::decode(data)?, mime_type)
+            }
+            None => {
+                let file = resolve_uri(path, uri)?;
+                let bytes =
+                    std::fs::read(&file).with_context(|| format!("failed to read {:?}", file))?;
+                // The mime type is optional for external images
+                let mime_type =
+                    mime_type.unwrap_or_else(|| match file.extension().and_then(|e| e.to_str()) {
+                        Some("png") => "image/png",
+                        Some("jpg") | Some("jpeg") => "image/jpeg",
+                        _ => "",
+                    });
+                (bytes, mime_type)
+            }
+        },
+    };
+    let extension = match mime_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        _ => bail!("unsupported image type {:?}", mime_type),
+    };
+    ImageData::decode(&bytes, extension)
+}
+
+fn load_material(material: &gltf::Material, textures: &Textures) -> Material {
+    let texture = |texture: gltf::Texture, srgb: bool| {
+        textures
+            .get(&(texture.source().index(), srgb))
+            .and_then(|handle| handle.clone_strong())
+    };
+    let pbr = material.pbr_metallic_roughness();
+    let emissive = material.emissive_factor();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    let factors = PbrFactors {
+        base_color: pbr.base_color_factor().into(),
+        emissive: glam::Vec4::new(emissive[0], emissive[1], emissive[2], 0.0),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+    };
+    let textures = PbrTextures {
+        base_color: pbr
+            .base_color_texture()
+            .and_then(|i| texture(i.texture(), true)),
+        metallic_roughness: pbr
+            .metallic_roughness_texture()
+            .and_then(|i| texture(i.texture(), false)),
+        normal: normal.and_then(|n| texture(n.texture(), false)),
+        occlusion: occlusion.and_then(|o| texture(o.texture(), false)),
+        emissive: material
+            .emissive_texture()
+            .and_then(|i| texture(i.texture(), true)),
+    };
+    Material::pbr(factors, textures)
+}
+
+/// Materials of the primitives by index, `None` is the default material of glTF
+async fn load_materials<'a>(
+    doc: &'a Gltf,
+    textures: &'a Textures,
+    load_ctx: &LoadContext<'a>,
+) -> Materials {
+    let mut materials = Materials::new();
+    for primitive in doc.meshes().flat_map(|mesh| mesh.primitives()) {
+        let material = primitive.material();
+        if materials.contains_key(&material.index()) {
+            continue;
+        }
+        // Materials are addressable as "<file>#Material/<index>" and "<file>#Material/default"
+        let label = match material.index() {
+            Some(index) => format!("Material/{}", index),
+            None => "Material/default".to_string(),
+        };
+        let handle = load_ctx
+            .add_asset_with_label(label.as_str(), load_material(&material, textures))
+            .await;
+        materials.insert(material.index(), handle);
+    }
+    materials
+}
+
 async fn load_meshes<'a>(
     doc: &'a Gltf,
     ctx: &'a ActiveContext,
     buffers: &'a [Vec<u8>],
+    materials: &'a Materials,
     load_ctx: &LoadContext<'a>,
 ) -> Result<Vec<AssetHandle<Mesh>>> {
     tasks::utilities::try_join_all(doc.meshes().map(|mesh| async move {
@@ -78,18 +249,30 @@ async fn load_meshes<'a>(
                     ReadIndices::U32(data) => Indices::U32(data.collect::<Vec<u32>>()),
                 };
 
-                let material = {
-                    let pbr = p.material().pbr_metallic_roughness();
+                let material = materials
+                    .get(&p.material().index())
+                    .and_then(|material| material.clone_strong())
+                    .context("[GltfLoader] material of the primitive was not loaded")?;
 
-                    let color = pbr.base_color_factor().into();
-
-                    Material::solid(color * 0.2, color, color, 0.1)
+                // Without uvs every texel is read from the corner of the textures
+                let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                    Some(uvs) => uvs.into_f32().collect(),
+                    None => vec![[0.0; 2]; vertices.len()],
+                };
+                let surface = match reader.read_tangents() {
+                    Some(tangents) => uvs
+                        .iter()
+                        .zip(tangents)
+                        .map(|(uv, tangent)| SurfaceVertex { uv: *uv, tangent })
+                        .collect(),
+                    None => SurfaceVertex::generate(&vertices, &uvs, Some(&indices)),
                 };
 
-                let part = MeshPart::from_data_with_indices(
+                let part = MeshPart::from_surface_data(
                     &format!("{}-primitive-{}", mesh_name, i),
                     &vertices,
-                    &indices,
+                    &surface,
+                    Some(&indices),
                     material,
                     ctx,
                 );
@@ -98,8 +281,10 @@ async fn load_meshes<'a>(
             })
             .collect::<Result<Vec<_>>>()?;
 
+        // Meshes are addressable as "<file>#Mesh/<name>"
+        let label = format!("Mesh/{}", mesh_name);
         let handle = load_ctx
-            .add_asset_with_label(mesh_name, Mesh::new(mesh_name, parts))
+            .add_asset_with_label(label.as_str(), Mesh::new(mesh_name, parts))
             .await;
 
         Ok(handle)
@@ -109,7 +294,35 @@ async fn load_meshes<'a>(
 
 const URI_STRING: &str = "data:application/octet-stream;base64,";
 
-fn load_buffers(doc: &Gltf) -> core::anyhow::Result<Vec<Vec<u8>>> {
+/// Path of a relative uri in the gltf file at path, percent encoded bytes are decoded
+fn resolve_uri(path: &Path, uri: &str) -> Result<PathBuf> {
+    if uri.contains(':') {
+        bail!("only relative uris are supported, got {:?}", uri);
+    }
+    let mut bytes = Vec::with_capacity(uri.len());
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match tail.get(..2) {
+            Some(hex) if byte == b'%' => {
+                let hex = std::str::from_utf8(hex)?;
+                bytes.push(u8::from_str_radix(hex, 16).context("invalid percent encoding")?);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let relative = String::from_utf8(bytes).context("uri is not utf8")?;
+    Ok(path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(relative))
+}
+
+/// External buffers are read relative to the gltf file at path
+fn load_buffers(doc: &Gltf, path: &Path) -> core::anyhow::Result<Vec<Vec<u8>>> {
     let mut result = Vec::new();
     for buffer in doc.buffers() {
         use gltf::buffer::Source::*;
@@ -130,7 +343,10 @@ fn load_buffers(doc: &Gltf) -> core::anyhow::Result<Vec<Vec<u8>>> {
                         )
                     }
                 } else {
-                    bail!("Actual uri's are not supported")
+                    let file = resolve_uri(path, uri)?;
+                    let data = std::fs::read(&file)
+                        .with_context(|| format!("failed to read {:?}", file))?;
+                    result.push(data);
                 }
             }
         }
@@ -172,6 +388,7 @@ fn load_model(doc: &Gltf, meshes: &[AssetHandle<Mesh>]) -> Result<Model> {
 
 pub struct GltfLoader {
     pub(crate) ctx: Arc<ActiveContext>,
+    pub(crate) resources: GpuResources<ActiveContext>,
 }
 
 impl AssetLoader for GltfLoader {
@@ -183,8 +400,13 @@ impl AssetLoader for GltfLoader {
         Box::pin(async move {
             let model: Gltf = gltf::Gltf::from_slice(bytes)?;
 
-            let buffers = load_buffers(&model).context("[GltfLoader] load_buffers failed")?;
-            let meshes = load_meshes(&model, &self.ctx, &buffers, &ctx)
+            let buffers =
+                load_buffers(&model, ctx.path).context("[GltfLoader] load_buffers failed")?;
+            let textures = load_textures(&model, &self.resources, &buffers, &ctx)
+                .await
+                .context("[GltfLoader] load_textures failed")?;
+            let materials = load_materials(&model, &textures, &ctx).await;
+            let meshes = load_meshes(&model, &self.ctx, &buffers, &materials, &ctx)
                 .await
                 .context("[GltfLoader] load_meshes failed")?;
             let model = load_model(&model, &meshes)?;
@@ -197,4 +419,8 @@ impl AssetLoader for GltfLoader {
     fn ext(&self) -> &[&str] {
         &["gltf", "glb"]
     }
+
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<Model>()
+    }
 }
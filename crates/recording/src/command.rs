@@ -0,0 +1,328 @@
+use std::{ops::Range, sync::Arc};
+
+use render::{
+    command_encoder::{CommandEncoder, IndexType},
+    context::GpuContext,
+    prelude::{BufferRange, ShaderType},
+    resource::{
+        buffer::BufferCopy,
+        frame::Clear,
+        glue::Glue,
+        pipeline::{Rect, Viewport},
+    },
+};
+
+use crate::{
+    context::RecordingContext,
+    record::{Access, Command, CommandLog},
+    resources::{Misuse, Registry, ResourceId, ResourceKind},
+};
+
+/// Command encoder of the recording backend, every call is appended to the log
+#[derive(Debug)]
+pub struct RecordingCommand {
+    registry: Arc<Registry>,
+    log: CommandLog,
+    in_render_pass: bool,
+    pipeline: Option<ResourceId>,
+    compute_pipeline: Option<ResourceId>,
+    has_index_buffer: bool,
+}
+
+impl RecordingCommand {
+    pub(crate) fn new(registry: Arc<Registry>) -> Self {
+        Self {
+            registry,
+            log: Default::default(),
+            in_render_pass: false,
+            pipeline: None,
+            compute_pipeline: None,
+            has_index_buffer: false,
+        }
+    }
+
+    pub(crate) fn finish(self) -> CommandLog {
+        self.log
+    }
+
+    fn misuse(&mut self, misuse: Misuse) {
+        log::warn!("[RecordingCommand] {}", misuse);
+        self.log.misuse.push(misuse);
+    }
+
+    fn check(&mut self, command: &str, kind: ResourceKind, id: ResourceId) {
+        if let Err(misuse) = self.registry.check(command, kind, id) {
+            self.misuse(misuse);
+        }
+    }
+
+    fn check_render_pass(&mut self, command: &str) {
+        if !self.in_render_pass {
+            self.misuse(Misuse::OutsideRenderPass {
+                command: command.to_string(),
+            });
+        }
+    }
+
+    /// Barriers are placed by the graph, not by the callbacks of the nodes
+    pub(crate) fn barrier(&mut self, resource: ResourceId, from: Access, to: Access) {
+        self.log
+            .commands
+            .push(Command::Barrier { resource, from, to });
+    }
+
+    pub(crate) fn upload(&mut self, upload: Command) {
+        self.log.commands.push(upload);
+    }
+
+    pub(crate) fn read_attachment(&mut self, image: ResourceId) {
+        self.check("read_attachment", ResourceKind::ImageView, image);
+        self.log.commands.push(Command::ReadAttachment { image });
+    }
+
+    fn check_draw(&mut self, command: &str) {
+        self.check_render_pass(command);
+        match self.pipeline {
+            // The pipeline might have been dropped after it was bound
+            Some(pipeline) => self.check(command, ResourceKind::Pipeline, pipeline),
+            None => self.misuse(Misuse::NoPipeline {
+                command: command.to_string(),
+            }),
+        }
+    }
+}
+
+impl CommandEncoder<RecordingContext> for RecordingCommand {
+    fn begin_render_pass<I: IntoIterator<Item = Clear>>(
+        &mut self,
+        render_pass: &<RecordingContext as GpuContext>::RenderPassHandle,
+        frame_buffer: &<RecordingContext as GpuContext>::Framebuffer,
+        render_area: Rect,
+        clear_values: I,
+    ) {
+        if self.in_render_pass {
+            self.misuse(Misuse::NestedRenderPass);
+        }
+        self.check("begin_render_pass", ResourceKind::RenderPass, render_pass.0);
+        self.check(
+            "begin_render_pass",
+            ResourceKind::Framebuffer,
+            frame_buffer.0,
+        );
+        self.in_render_pass = true;
+        self.log.commands.push(Command::BeginRenderPass {
+            render_pass: render_pass.0,
+            framebuffer: frame_buffer.0,
+            area: (&render_area).into(),
+            clear_values: clear_values.into_iter().map(|c| (&c).into()).collect(),
+        });
+    }
+
+    fn end_render_pass(&mut self) {
+        self.check_render_pass("end_render_pass");
+        self.in_render_pass = false;
+        self.log.commands.push(Command::EndRenderPass);
+    }
+
+    fn set_viewport(&mut self, index: u32, viewport: Viewport) {
+        self.log
+            .commands
+            .push(Command::set_viewport(index, &viewport));
+    }
+
+    fn set_scissor(&mut self, index: u32, scissor: Rect) {
+        self.log.commands.push(Command::SetScissor {
+            index,
+            rect: (&scissor).into(),
+        });
+    }
+
+    fn bind_graphics_pipeline(
+        &mut self,
+        pipeline: &<RecordingContext as GpuContext>::PipelineHandle,
+    ) {
+        self.check("bind_graphics_pipeline", ResourceKind::Pipeline, pipeline.0);
+        self.pipeline = Some(pipeline.0);
+        self.log.commands.push(Command::BindGraphicsPipeline {
+            pipeline: pipeline.0,
+            name: self.registry.name(pipeline.0),
+        });
+    }
+
+    fn push_constants(
+        &mut self,
+        pipeline: &<RecordingContext as GpuContext>::PipelineHandle,
+        shader: ShaderType,
+        offset: u32,
+        data: &[u32],
+    ) {
+        self.check("push_constants", ResourceKind::Pipeline, pipeline.0);
+        self.log.commands.push(Command::PushConstants {
+            pipeline: pipeline.0,
+            stage: (&shader).into(),
+            offset,
+            data: data.to_vec(),
+        });
+    }
+
+    fn bind_vertex_buffer(
+        &mut self,
+        binding: u32,
+        buffer: &<RecordingContext as GpuContext>::BufferHandle,
+        range: BufferRange,
+    ) {
+        self.check("bind_vertex_buffer", ResourceKind::Buffer, buffer.0);
+        self.log.commands.push(Command::BindVertexBuffer {
+            binding,
+            buffer: buffer.0,
+            range: (&range).into(),
+        });
+    }
+
+    fn bind_index_buffer(
+        &mut self,
+        buffer: &<RecordingContext as GpuContext>::BufferHandle,
+        range: BufferRange,
+        index_type: IndexType,
+    ) {
+        self.check("bind_index_buffer", ResourceKind::Buffer, buffer.0);
+        self.has_index_buffer = true;
+        self.log.commands.push(Command::BindIndexBuffer {
+            buffer: buffer.0,
+            range: (&range).into(),
+            index_type: (&index_type).into(),
+        });
+    }
+
+    fn snort_glue(
+        &mut self,
+        set_idx: usize,
+        pipeline: &<RecordingContext as GpuContext>::PipelineHandle,
+        glue: &Glue<RecordingContext>,
+    ) {
+        let set = glue.handle.handle.0;
+        self.check("snort_glue", ResourceKind::Pipeline, pipeline.0);
+        self.check("snort_glue", ResourceKind::DescriptorSet, set);
+        // Everything that was written into the set needs to outlive it
+        for (kind, id) in self.registry.bound_resources(set) {
+            self.check("snort_glue", kind, id);
+        }
+        self.log.commands.push(Command::SnortGlue {
+            set_idx,
+            pipeline: pipeline.0,
+            set,
+        });
+    }
+
+    fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.check_draw("draw");
+        self.log.commands.push(Command::Draw {
+            vertices,
+            instances,
+        });
+    }
+
+    fn draw_indexed(&mut self, indices: Range<u32>, vertex_offset: i32, instances: Range<u32>) {
+        self.check_draw("draw_indexed");
+        if !self.has_index_buffer {
+            self.misuse(Misuse::NoIndexBuffer);
+        }
+        self.log.commands.push(Command::DrawIndexed {
+            indices,
+            vertex_offset,
+            instances,
+        });
+    }
+
+    fn copy_buffer<I>(
+        &mut self,
+        src: &<RecordingContext as GpuContext>::BufferHandle,
+        dst: &<RecordingContext as GpuContext>::BufferHandle,
+        regions: I,
+    ) where
+        I: IntoIterator<Item = BufferCopy>,
+    {
+        self.check("copy_buffer", ResourceKind::Buffer, src.0);
+        self.check("copy_buffer", ResourceKind::Buffer, dst.0);
+        self.log.commands.push(Command::CopyBuffer {
+            src: src.0,
+            dst: dst.0,
+            regions: regions
+                .into_iter()
+                .map(|r| (r.src_offset, r.dst_offset, r.size))
+                .collect(),
+        });
+    }
+
+    fn bind_compute_pipeline(
+        &mut self,
+        pipeline: &<RecordingContext as GpuContext>::ComputePipelineHandle,
+    ) {
+        self.check(
+            "bind_compute_pipeline",
+            ResourceKind::ComputePipeline,
+            pipeline.0,
+        );
+        self.compute_pipeline = Some(pipeline.0);
+        self.log.commands.push(Command::BindComputePipeline {
+            pipeline: pipeline.0,
+            name: self.registry.name(pipeline.0),
+        });
+    }
+
+    fn compute_push_constants(
+        &mut self,
+        pipeline: &<RecordingContext as GpuContext>::ComputePipelineHandle,
+        offset: u32,
+        data: &[u32],
+    ) {
+        self.check(
+            "compute_push_constants",
+            ResourceKind::ComputePipeline,
+            pipeline.0,
+        );
+        self.log.commands.push(Command::ComputePushConstants {
+            pipeline: pipeline.0,
+            offset,
+            data: data.to_vec(),
+        });
+    }
+
+    fn snort_compute_glue(
+        &mut self,
+        set_idx: usize,
+        pipeline: &<RecordingContext as GpuContext>::ComputePipelineHandle,
+        glue: &Glue<RecordingContext>,
+    ) {
+        let set = glue.handle.handle.0;
+        self.check(
+            "snort_compute_glue",
+            ResourceKind::ComputePipeline,
+            pipeline.0,
+        );
+        self.check("snort_compute_glue", ResourceKind::DescriptorSet, set);
+        for (kind, id) in self.registry.bound_resources(set) {
+            self.check("snort_compute_glue", kind, id);
+        }
+        self.log.commands.push(Command::SnortComputeGlue {
+            set_idx,
+            pipeline: pipeline.0,
+            set,
+        });
+    }
+
+    fn dispatch(&mut self, groups: [u32; 3]) {
+        if self.in_render_pass {
+            self.misuse(Misuse::InsideRenderPass {
+                command: "dispatch".to_string(),
+            });
+        }
+        match self.compute_pipeline {
+            Some(pipeline) => self.check("dispatch", ResourceKind::ComputePipeline, pipeline),
+            None => self.misuse(Misuse::NoPipeline {
+                command: "dispatch".to_string(),
+            }),
+        }
+        self.log.commands.push(Command::Dispatch { groups });
+    }
+}
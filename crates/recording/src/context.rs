@@ -0,0 +1,322 @@
+use std::{borrow::Borrow, sync::Arc};
+
+use bytemuck::Pod;
+use core::anyhow;
+use render::{
+    context::{GpuBuilder, GpuContext},
+    prelude::MixturePart,
+    resource::{
+        buffer::BufferDescriptor,
+        frame::Extent3D,
+        glue::{Descriptor, DescriptorWrite, Mixture},
+        pipeline::{
+            ComputePipelineDescriptor, GraphicsPipelineDescriptor, RenderContext, ShaderSource,
+        },
+        render_pass::RenderPassDescriptor,
+        texture::{SamplerDescriptor, TextureDescriptor},
+    },
+};
+
+use crate::{
+    command::RecordingCommand,
+    context_builder::RecordingBuilder,
+    graph::{builder::RecordingGraphBuilder, RecordingGraph},
+    record::{Command, CommandLog},
+    resources::{
+        BufferHandle, ComputePipelineHandle, DescriptorLayoutHandle, DescriptorSetHandle,
+        FramebufferHandle, ImageViewHandle, Misuse, PipelineHandle, Registry, RenderPassHandle,
+        ResourceId, ResourceKind, SamplerHandle, ShaderHandle, TextureHandle,
+    },
+    surface::RecordingSurface,
+};
+
+pub type ContextBuilder = RecordingBuilder;
+pub type Context = <ContextBuilder as GpuBuilder>::Context;
+
+/// Implementation of the Rendering Context that records what is done with it, instead of
+/// executing anything
+#[derive(Debug, Default)]
+pub struct RecordingContext {
+    registry: Arc<Registry>,
+}
+
+impl RecordingContext {
+    /// Name of the resource (as given in its descriptor), empty for unknown ids
+    pub fn name(&self, id: ResourceId) -> String {
+        self.registry.name(id)
+    }
+
+    /// Whether the resource was created and not yet dropped
+    pub fn is_alive(&self, id: ResourceId) -> bool {
+        matches!(self.registry.info(id), Some(info) if info.alive)
+    }
+
+    /// Misuse of the context (eg. dropping a resource twice) since the last call, frames of the
+    /// graph take these too, so they show up in the CommandLog of the next frame
+    pub fn take_misuse(&self) -> Vec<Misuse> {
+        self.registry.take_misuse()
+    }
+
+    /// Records commands outside of a graph, eg. for uploads
+    pub fn record(&self, cb: impl FnOnce(&mut RecordingCommand)) -> CommandLog {
+        let mut command = RecordingCommand::new(self.registry.clone());
+        cb(&mut command);
+        command.finish()
+    }
+}
+
+impl GpuContext for RecordingContext {
+    type SurfaceHandle = RecordingSurface;
+    type BufferHandle = BufferHandle;
+    type PipelineHandle = PipelineHandle;
+    type ComputePipelineHandle = ComputePipelineHandle;
+    type RenderPassHandle = RenderPassHandle;
+    type ShaderCode = ShaderHandle;
+    type ImageView = ImageViewHandle;
+    type Framebuffer = FramebufferHandle;
+    type CommandBuffer = ();
+    type DescriptorLayout = DescriptorLayoutHandle;
+    type DescriptorSet = DescriptorSetHandle;
+    type TextureHandle = TextureHandle;
+    type SamplerHandle = SamplerHandle;
+    type CommandEncoder = RecordingCommand;
+    type SwapchainImage = ImageViewHandle;
+    type Graph = RecordingGraph;
+    type GraphBuilder = RecordingGraphBuilder;
+
+    fn create_buffer(&self, desc: &BufferDescriptor) -> Self::BufferHandle {
+        BufferHandle(
+            self.registry
+                .create(ResourceKind::Buffer, desc.name.as_ref()),
+        )
+    }
+
+    unsafe fn write_to_buffer<D: Pod>(&self, buffer: &Self::BufferHandle, data: &D) {
+        self.write_to_buffer_raw(buffer, bytemuck::bytes_of(data));
+    }
+
+    unsafe fn write_to_buffer_raw(&self, buffer: &Self::BufferHandle, _data: &[u8]) {
+        self.registry
+            .validate("write_to_buffer", ResourceKind::Buffer, buffer.0);
+    }
+
+    /// Uploads show up at the start of the next frame of the graph
+    fn upload_buffer(&self, buffer: &Self::BufferHandle, offset: u64, data: &[u8]) {
+        self.registry
+            .validate("upload_buffer", ResourceKind::Buffer, buffer.0);
+        self.registry.upload(Command::UploadBuffer {
+            buffer: buffer.0,
+            offset,
+            size: data.len() as u64,
+        });
+    }
+
+    fn flush_uploads(&self) {}
+
+    fn drop_buffer(&self, buffer: Self::BufferHandle) {
+        self.registry.destroy(ResourceKind::Buffer, buffer.0);
+    }
+
+    fn create_texture(&self, desc: &TextureDescriptor) -> Self::TextureHandle {
+        TextureHandle(
+            self.registry
+                .create(ResourceKind::Texture, desc.name.as_ref()),
+        )
+    }
+
+    fn upload_texture(&self, texture: &Self::TextureHandle, _levels: &[&[u8]]) {
+        self.registry
+            .validate("upload_texture", ResourceKind::Texture, texture.0);
+    }
+
+    fn drop_texture(&self, texture: Self::TextureHandle) {
+        self.registry.destroy(ResourceKind::Texture, texture.0);
+    }
+
+    fn create_sampler(&self, _desc: &SamplerDescriptor) -> Self::SamplerHandle {
+        SamplerHandle(self.registry.create(ResourceKind::Sampler, "sampler"))
+    }
+
+    fn drop_sampler(&self, sampler: Self::SamplerHandle) {
+        self.registry.destroy(ResourceKind::Sampler, sampler.0);
+    }
+
+    fn create_render_pass(&self, _desc: &RenderPassDescriptor) -> Self::RenderPassHandle {
+        RenderPassHandle(
+            self.registry
+                .create(ResourceKind::RenderPass, "render_pass"),
+        )
+    }
+
+    fn drop_render_pass(&self, rp: Self::RenderPassHandle) {
+        self.registry.destroy(ResourceKind::RenderPass, rp.0);
+    }
+
+    fn create_graphics_pipeline(
+        &self,
+        desc: GraphicsPipelineDescriptor<Self>,
+        render_context: RenderContext<Self>,
+    ) -> Self::PipelineHandle {
+        let command = "create_graphics_pipeline";
+        let shaders = std::iter::once(desc.shaders.vertex)
+            .chain(std::iter::once(desc.shaders.fragment))
+            .chain(desc.shaders.geometry);
+        for shader in shaders {
+            self.registry
+                .validate(command, ResourceKind::Shader, shader.0);
+        }
+        for mixture in desc.mixtures.iter() {
+            self.registry.validate(
+                command,
+                ResourceKind::DescriptorLayout,
+                mixture.gpu_layout.handle.0,
+            );
+        }
+        if let RenderContext::RenderPass((render_pass, _subpass)) = render_context {
+            self.registry
+                .validate(command, ResourceKind::RenderPass, render_pass.0);
+        }
+
+        PipelineHandle(
+            self.registry
+                .create(ResourceKind::Pipeline, desc.name.as_ref()),
+        )
+    }
+
+    fn drop_pipeline(&self, pipeline: Self::PipelineHandle) {
+        self.registry.destroy(ResourceKind::Pipeline, pipeline.0);
+    }
+
+    fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<Self>,
+    ) -> Self::ComputePipelineHandle {
+        let command = "create_compute_pipeline";
+        self.registry
+            .validate(command, ResourceKind::Shader, desc.shader.0);
+        for mixture in desc.mixtures.iter() {
+            self.registry.validate(
+                command,
+                ResourceKind::DescriptorLayout,
+                mixture.gpu_layout.handle.0,
+            );
+        }
+
+        ComputePipelineHandle(
+            self.registry
+                .create(ResourceKind::ComputePipeline, desc.name.as_ref()),
+        )
+    }
+
+    fn drop_compute_pipeline(&self, pipeline: Self::ComputePipelineHandle) {
+        self.registry
+            .destroy(ResourceKind::ComputePipeline, pipeline.0);
+    }
+
+    fn compile_shader(&self, source: ShaderSource) -> anyhow::Result<Self::ShaderCode> {
+        let name = match source {
+            ShaderSource::GlslFile(path) => path.to_string_lossy().to_string(),
+            ShaderSource::GlslSource { name, .. } => name.unwrap_or("glsl_source").to_string(),
+            ShaderSource::Spirv(_) => "spirv".to_string(),
+        };
+        Ok(ShaderHandle(
+            self.registry.create(ResourceKind::Shader, name),
+        ))
+    }
+
+    fn create_framebuffer<I>(
+        &self,
+        rp: &Self::RenderPassHandle,
+        attachments: I,
+        _extent: Extent3D,
+    ) -> Self::Framebuffer
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Self::ImageView>,
+    {
+        let command = "create_framebuffer";
+        self.registry
+            .validate(command, ResourceKind::RenderPass, rp.0);
+        for attachment in attachments {
+            self.registry
+                .validate(command, ResourceKind::ImageView, attachment.borrow().0);
+        }
+        FramebufferHandle(
+            self.registry
+                .create(ResourceKind::Framebuffer, "framebuffer"),
+        )
+    }
+
+    fn drop_framebuffer(&self, fb: Self::Framebuffer) {
+        self.registry.destroy(ResourceKind::Framebuffer, fb.0);
+    }
+
+    fn create_descriptor_layout<I>(&self, parts: I) -> Self::DescriptorLayout
+    where
+        I: IntoIterator<Item = MixturePart>,
+    {
+        let names: Vec<String> = parts.into_iter().map(|p| p.name).collect();
+        DescriptorLayoutHandle(
+            self.registry
+                .create(ResourceKind::DescriptorLayout, names.join(", ")),
+        )
+    }
+
+    fn drop_descriptor_layout(&self, handle: Self::DescriptorLayout) {
+        self.registry
+            .destroy(ResourceKind::DescriptorLayout, handle.0);
+    }
+
+    fn create_descriptor_set(&self, layout: &Mixture<Self>) -> Self::DescriptorSet {
+        let layout = layout.gpu_layout.handle.0;
+        self.registry.validate(
+            "create_descriptor_set",
+            ResourceKind::DescriptorLayout,
+            layout,
+        );
+        DescriptorSetHandle(
+            self.registry
+                .create(ResourceKind::DescriptorSet, self.registry.name(layout)),
+        )
+    }
+
+    fn drop_descriptor_set(&self, handle: Self::DescriptorSet) {
+        self.registry.destroy(ResourceKind::DescriptorSet, handle.0);
+    }
+
+    fn update_descriptor_set(
+        &self,
+        handle: &Self::DescriptorSet,
+        writes: Vec<DescriptorWrite<Self>>,
+    ) {
+        let command = "update_descriptor_set";
+        self.registry
+            .validate(command, ResourceKind::DescriptorSet, handle.0);
+        for write in writes {
+            let DescriptorWrite {
+                binding,
+                array_offset,
+                descriptor,
+            } = write;
+            let resources = match descriptor {
+                Descriptor::Buffer(buffer, _range) => vec![(ResourceKind::Buffer, buffer.0)],
+                Descriptor::Texture(texture, sampler) => vec![
+                    (ResourceKind::Texture, texture.0),
+                    (ResourceKind::Sampler, sampler.0),
+                ],
+                Descriptor::StorageImage(texture) => vec![(ResourceKind::Texture, texture.0)],
+            };
+            for (kind, id) in resources.iter() {
+                self.registry.validate(command, *kind, *id);
+            }
+            self.registry
+                .bind(handle.0, binding, array_offset, resources);
+        }
+    }
+
+    fn wait_idle(&self) {}
+
+    fn create_graph(&self, surface: Self::SurfaceHandle) -> Self::GraphBuilder {
+        RecordingGraphBuilder::new(self.registry.clone(), surface)
+    }
+}
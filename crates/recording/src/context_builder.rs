@@ -0,0 +1,32 @@
+use render::{context::GpuBuilder, prelude::GpuContext, resource::frame::Extent2D};
+
+use crate::{context::RecordingContext, surface::RecordingSurface};
+
+pub struct RecordingBuilder {}
+
+impl RecordingBuilder {
+    pub fn create_offscreen_surface(&mut self, extent: Extent2D) -> RecordingSurface {
+        RecordingSurface::new(extent)
+    }
+}
+
+impl GpuBuilder for RecordingBuilder {
+    type Context = RecordingContext;
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    /// The window is ignored, nothing is ever drawn
+    fn create_surface<W: raw_window_handle::HasRawWindowHandle>(
+        &mut self,
+        _window: &W,
+        extent: Extent2D,
+    ) -> <Self::Context as GpuContext>::SurfaceHandle {
+        self.create_offscreen_surface(extent)
+    }
+
+    fn build(self) -> Self::Context {
+        RecordingContext::default()
+    }
+}
@@ -0,0 +1,148 @@
+use std::{borrow::Cow, sync::Arc};
+
+use render::{
+    graph::{
+        attachment::GraphAttachment,
+        builder::GraphBuilder,
+        compile::{compile, CompiledGraph, GraphError},
+        node::Node,
+        nodes::{
+            callbacks::UserData,
+            pass::{PassNode, PassNodeBuilder},
+        },
+    },
+    resource::frame::Extent2D,
+    util::format::TextureFormat,
+};
+
+use crate::{
+    context::RecordingContext,
+    resources::{Registry, RenderPassHandle, ResourceKind},
+    surface::RecordingSurface,
+};
+
+use super::{AttachmentIndex, RecordingGraph, RecordingNode, RecordingPassNode, FRAMES_IN_FLIGHT};
+
+pub(crate) const SURFACE_FORMAT: TextureFormat = TextureFormat::Bgra8Srgb;
+pub(crate) const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Sfloat;
+
+pub struct RecordingGraphBuilder {
+    pub(super) registry: Arc<Registry>,
+    pub(super) surface: RecordingSurface,
+    pub(super) offscreen: Option<Extent2D>,
+    pub(super) attachments: Vec<GraphAttachment>,
+    pub(super) nodes: Vec<Node<Self>>,
+}
+
+impl RecordingGraphBuilder {
+    pub(crate) fn new(registry: Arc<Registry>, surface: RecordingSurface) -> Self {
+        Self {
+            registry,
+            surface,
+            offscreen: None,
+            attachments: Default::default(),
+            nodes: Default::default(),
+        }
+    }
+
+    fn build_pass_node(&self, node: PassNode<Self>) -> RecordingPassNode {
+        let render_pass = Arc::new(RenderPassHandle(
+            self.registry
+                .create(ResourceKind::RenderPass, node.name.as_ref()),
+        ));
+        node.callbacks.borrow_mut().init(render_pass.clone());
+
+        RecordingPassNode {
+            graph_node: node,
+            render_pass,
+        }
+    }
+}
+
+impl GraphBuilder for RecordingGraphBuilder {
+    type Context = RecordingContext;
+    type AttachmentIndex = AttachmentIndex;
+    type Graph = RecordingGraph;
+
+    fn add_node(&mut self, node: Node<Self>) {
+        self.nodes.push(node);
+    }
+
+    fn add_attachment(&mut self, attachment: GraphAttachment) -> Self::AttachmentIndex {
+        let id = attachment.id;
+        self.attachments.push(attachment);
+        AttachmentIndex::Custom(id)
+    }
+
+    fn attachment_index(&self, name: Cow<'static, str>) -> Option<Self::AttachmentIndex> {
+        self.attachments
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| AttachmentIndex::Custom(a.id))
+    }
+
+    fn attachment_name(&self, index: Self::AttachmentIndex) -> Cow<'static, str> {
+        match index {
+            AttachmentIndex::Backbuffer => "backbuffer".into(),
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|a| a.id == id)
+                .map(|a| a.name.clone())
+                .unwrap_or_else(|| "unknown".into()),
+        }
+    }
+
+    fn get_backbuffer_attachment(&self) -> Self::AttachmentIndex {
+        AttachmentIndex::Backbuffer
+    }
+
+    fn render_offscreen(&mut self, extent: Extent2D) {
+        self.offscreen = Some(extent);
+    }
+
+    fn get_surface_format(&self) -> TextureFormat {
+        SURFACE_FORMAT
+    }
+
+    fn default_depth_format(&self) -> TextureFormat {
+        DEPTH_FORMAT
+    }
+
+    fn get_swapchain_image_count(&self) -> usize {
+        FRAMES_IN_FLIGHT
+    }
+
+    /// The counts that every desktop gpu supports
+    fn supports_samples(&self, samples: u8) -> bool {
+        matches!(samples, 1 | 2 | 4 | 8)
+    }
+
+    fn build(mut self) -> Result<Self::Graph, GraphError> {
+        let nodes = std::mem::take(&mut self.nodes);
+        let CompiledGraph { nodes, culled } = compile(&self, nodes)?;
+        let nodes = nodes
+            .into_iter()
+            .map(|n| match n.node {
+                Node::PassNode(n) => RecordingNode::PassNode(self.build_pass_node(n)),
+                Node::ComputeNode(n) => {
+                    n.callbacks.borrow_mut().init();
+                    RecordingNode::ComputeNode(n)
+                }
+            })
+            .collect();
+
+        Ok(RecordingGraph::new(
+            self.registry,
+            self.surface,
+            self.offscreen,
+            self.attachments,
+            nodes,
+            culled,
+        ))
+    }
+
+    fn build_pass_node<U: UserData>(&self, name: Cow<'static, str>) -> PassNodeBuilder<Self, U> {
+        PassNodeBuilder::new(name)
+    }
+}
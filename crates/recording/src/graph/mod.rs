@@ -0,0 +1,451 @@
+use std::{any::Any, sync::Arc};
+
+use app::{Resources, World};
+use render::{
+    command_encoder::CommandEncoder,
+    graph::{
+        attachment::GraphAttachment,
+        node::Node,
+        nodes::{
+            callbacks::FrameData,
+            compute::{BarrierTracker, ComputeNode, ComputeResource, ResourceBarrier},
+            pass::PassNode,
+        },
+        readback::{Readback, ReadbackError, ReadbackImage},
+        Graph,
+    },
+    resource::{
+        frame::{Clear, Extent2D},
+        pipeline::{Rect, Viewport},
+    },
+    util::format::TextureFormat,
+};
+use uuid::Uuid;
+
+use crate::{
+    command::RecordingCommand,
+    context::RecordingContext,
+    record::CommandLog,
+    resources::{
+        FramebufferHandle, Registry, RenderPassHandle, ResourceId, ResourceKind, TextureHandle,
+    },
+    surface::RecordingSurface,
+};
+
+use self::builder::{RecordingGraphBuilder, SURFACE_FORMAT};
+
+pub mod builder;
+
+/// Same as the gfx backend, so that frame indices behave the same
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachmentIndex {
+    Backbuffer,
+    Custom(Uuid),
+}
+
+pub(crate) struct RecordingPassNode {
+    pub(crate) graph_node: PassNode<RecordingGraphBuilder>,
+    pub(crate) render_pass: Arc<RenderPassHandle>,
+}
+
+pub(crate) enum RecordingNode {
+    PassNode(RecordingPassNode),
+    ComputeNode(ComputeNode<RecordingGraphBuilder>),
+}
+
+impl RecordingNode {
+    fn pass_node(&self) -> Option<&RecordingPassNode> {
+        match self {
+            RecordingNode::PassNode(node) => Some(node),
+            RecordingNode::ComputeNode(_) => None,
+        }
+    }
+}
+
+/// Barriers are recorded like every other command, so that tests can check their placement
+fn record_barriers(
+    command: &mut RecordingCommand,
+    barriers: Vec<ResourceBarrier<RecordingContext>>,
+) {
+    for barrier in barriers {
+        let resource = match &barrier.resource {
+            ComputeResource::Buffer(buffer) => buffer.get_handle().id(),
+            ComputeResource::Texture(texture) => texture.get_handle().id(),
+        };
+        command.barrier(
+            resource,
+            (&barrier.states.start).into(),
+            (&barrier.states.end).into(),
+        );
+    }
+}
+
+fn viewport(extent: &Extent2D) -> Viewport {
+    Viewport {
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: extent.width as i16,
+            height: extent.height as i16,
+        },
+        depth: 0.0..1.0,
+    }
+}
+
+pub struct RecordingGraph {
+    registry: Arc<Registry>,
+    surface: RecordingSurface,
+    /// Size of the backbuffers when rendering offscreen
+    offscreen: Option<Extent2D>,
+    extent: Extent2D,
+    /// One backbuffer per frame in flight
+    backbuffers: Vec<ResourceId>,
+    attachments: Vec<(GraphAttachment, ResourceId)>,
+    /// Textures of the attachments that are sampled (or read as input) by pass nodes
+    sampled: Vec<(Uuid, TextureHandle)>,
+    nodes: Vec<RecordingNode>,
+    /// Culled during compilation, only kept for into_builder
+    culled: Vec<Node<RecordingGraphBuilder>>,
+    /// Framebuffers indexed by [pass node][frame_index]
+    framebuffers: Vec<Vec<FramebufferHandle>>,
+    frame_index: usize,
+    /// Data returned by the passes, kept alive until the frame index is used again
+    pass_data: Vec<Vec<Box<dyn Any>>>,
+    /// Copies requested for the next execution
+    readbacks: Vec<(AttachmentIndex, Readback)>,
+}
+
+impl RecordingGraph {
+    pub(crate) fn new(
+        registry: Arc<Registry>,
+        surface: RecordingSurface,
+        offscreen: Option<Extent2D>,
+        attachments: Vec<GraphAttachment>,
+        nodes: Vec<RecordingNode>,
+        culled: Vec<Node<RecordingGraphBuilder>>,
+    ) -> Self {
+        let mut graph = Self {
+            extent: offscreen.clone().unwrap_or_else(|| surface.extent()),
+            registry,
+            surface,
+            offscreen,
+            backbuffers: Vec::new(),
+            attachments: attachments
+                .into_iter()
+                .map(|a| (a, ResourceId(0)))
+                .collect(),
+            sampled: Vec::new(),
+            nodes,
+            culled,
+            framebuffers: Vec::new(),
+            frame_index: 0,
+            pass_data: (0..FRAMES_IN_FLIGHT).map(|_| Vec::new()).collect(),
+            readbacks: Vec::new(),
+        };
+        graph.create_images();
+        graph
+    }
+
+    /// Executes the graph once and returns everything that was recorded
+    pub fn record(&mut self, world: &World, resources: &Resources) -> CommandLog {
+        self.execute(world, resources);
+        self.surface
+            .last_log()
+            .expect("[RecordingGraph] execute did not present a log")
+    }
+
+    fn image(&self, index: AttachmentIndex, frame_index: usize) -> ResourceId {
+        match index {
+            AttachmentIndex::Backbuffer => self.backbuffers[frame_index],
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|(desc, _)| desc.id == id)
+                .map(|(_, image)| *image)
+                .expect("[RecordingGraph] (execute) failed to load custom attachment"),
+        }
+    }
+
+    /// Size, format and samples of an attachment, None if it is not part of the graph
+    fn describe(&self, index: AttachmentIndex) -> Option<(Extent2D, TextureFormat, u8)> {
+        match index {
+            AttachmentIndex::Backbuffer => Some((self.extent.clone(), SURFACE_FORMAT, 1)),
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|(desc, _)| desc.id == id)
+                .map(|(desc, _)| (desc.size.extent(&self.extent), desc.format, desc.samples)),
+        }
+    }
+
+    fn texture(&self, index: AttachmentIndex) -> &TextureHandle {
+        self.sampled
+            .iter()
+            .find(|(id, _)| index == AttachmentIndex::Custom(*id))
+            .map(|(_, texture)| texture)
+            .expect("[RecordingGraph] (execute) failed to load sampled attachment")
+    }
+
+    /// Size of the render area of a pass, the size of its first attachment
+    fn pass_extent(&self, node: &PassNode<RecordingGraphBuilder>) -> Extent2D {
+        match node.first_attachment() {
+            Some(AttachmentIndex::Custom(id)) => self
+                .attachments
+                .iter()
+                .find(|(desc, _)| desc.id == id)
+                .map(|(desc, _)| desc.size.extent(&self.extent))
+                .expect("[RecordingGraph] (execute) failed to load custom attachment"),
+            _ => self.extent.clone(),
+        }
+    }
+
+    /// (Re)creates all images and framebuffers, which depend on the extent of the surface
+    fn create_images(&mut self) {
+        self.backbuffers = (0..FRAMES_IN_FLIGHT)
+            .map(|_| self.registry.create(ResourceKind::ImageView, "backbuffer"))
+            .collect();
+        for (desc, image) in self.attachments.iter_mut() {
+            *image = self
+                .registry
+                .create(ResourceKind::ImageView, desc.name.as_ref());
+        }
+        for node in self.nodes.iter().filter_map(RecordingNode::pass_node) {
+            let graph_node = &node.graph_node;
+            let inputs = graph_node.input_attachments.iter().map(|a| &a.index);
+            for index in graph_node.sampled_attachments.iter().chain(inputs) {
+                let id = match index {
+                    AttachmentIndex::Custom(id) => *id,
+                    AttachmentIndex::Backbuffer => {
+                        panic!("[RecordingGraph] the backbuffer can not be sampled")
+                    }
+                };
+                if self.sampled.iter().any(|(s, _)| *s == id) {
+                    continue;
+                }
+                let name = self
+                    .attachments
+                    .iter()
+                    .find(|(desc, _)| desc.id == id)
+                    .map(|(desc, _)| desc.name.clone())
+                    .expect("[RecordingGraph] failed to find sampled attachment");
+                let texture = self.registry.create(ResourceKind::Texture, name.as_ref());
+                self.sampled.push((id, TextureHandle(texture)));
+            }
+        }
+
+        let mut framebuffers = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter().filter_map(RecordingNode::pass_node) {
+            let graph_node = &node.graph_node;
+            let frames = (0..FRAMES_IN_FLIGHT)
+                .map(|frame_index| {
+                    let attachments = graph_node
+                        .output_attachments
+                        .iter()
+                        .chain(graph_node.input_attachments.iter())
+                        .chain(graph_node.depth_attachment.iter())
+                        .map(|a| a.index)
+                        .chain(graph_node.resolve_attachments.iter().map(|r| r.target));
+                    for index in attachments {
+                        self.registry.validate(
+                            "create_framebuffer",
+                            ResourceKind::ImageView,
+                            self.image(index, frame_index),
+                        );
+                    }
+                    FramebufferHandle(
+                        self.registry
+                            .create(ResourceKind::Framebuffer, graph_node.name.as_ref()),
+                    )
+                })
+                .collect();
+            framebuffers.push(frames);
+        }
+        self.framebuffers = framebuffers;
+    }
+
+    fn drop_images(&mut self) {
+        for framebuffer in self.framebuffers.drain(..).flatten() {
+            self.registry
+                .destroy(ResourceKind::Framebuffer, framebuffer.0);
+        }
+        let attachments = self.attachments.iter().map(|(_, image)| *image);
+        for image in self.backbuffers.drain(..).chain(attachments) {
+            self.registry.destroy(ResourceKind::ImageView, image);
+        }
+        for (_, texture) in self.sampled.drain(..) {
+            self.registry.destroy(ResourceKind::Texture, texture.id());
+        }
+    }
+}
+
+impl Graph for RecordingGraph {
+    type Context = RecordingContext;
+    type AttachmentIndex = AttachmentIndex;
+    type Builder = RecordingGraphBuilder;
+
+    fn execute(&mut self, world: &World, resources: &Resources) {
+        let extent = self
+            .offscreen
+            .clone()
+            .unwrap_or_else(|| self.surface.extent());
+        if extent.width != self.extent.width || extent.height != self.extent.height {
+            self.drop_images();
+            self.extent = extent.clone();
+            self.create_images();
+        }
+
+        let index = self.frame_index;
+        self.pass_data[index].clear();
+
+        let surface_viewport = viewport(&extent);
+
+        let mut command = RecordingCommand::new(self.registry.clone());
+        for upload in self.registry.take_uploads() {
+            command.upload(upload);
+        }
+        let mut tracker = BarrierTracker::new();
+        let mut framebuffers = self.framebuffers.iter();
+        for node in self.nodes.iter() {
+            let node = match node {
+                RecordingNode::PassNode(node) => node,
+                RecordingNode::ComputeNode(node) => {
+                    record_barriers(&mut command, tracker.compute_node(node));
+                    let frame_data = FrameData {
+                        cmd: &mut command,
+                        frame_index: index as u32,
+                        viewport: surface_viewport.clone(),
+                        sampled: Vec::new(),
+                        inputs: Vec::new(),
+                    };
+                    match node
+                        .callbacks
+                        .borrow_mut()
+                        .run(frame_data, world, resources)
+                    {
+                        Ok(Some(data)) => self.pass_data[index].push(data),
+                        Ok(None) => (),
+                        Err(e) => {
+                            panic!(
+                                "[RecordingGraph] execution failed during compute node: {}, with error: {}",
+                                node.name, e
+                            )
+                        }
+                    }
+                    continue;
+                }
+            };
+            let framebuffers = framebuffers
+                .next()
+                .expect("[RecordingGraph] (execute) missing framebuffers of pass node");
+            let graph_node = &node.graph_node;
+            record_barriers(&mut command, tracker.external());
+
+            // Same clear values as the gfx graph
+            let clear_values: Vec<Clear> = graph_node
+                .output_attachments
+                .iter()
+                .chain(graph_node.input_attachments.iter())
+                .map(|_| Clear::Color(0.2, 0.5, 0.1, 1.0))
+                .chain(
+                    graph_node
+                        .depth_attachment
+                        .iter()
+                        .map(|_| Clear::Depth(1.0, 0)),
+                )
+                .collect();
+            let pass_viewport = viewport(&self.pass_extent(graph_node));
+            command.begin_render_pass(
+                &node.render_pass,
+                &framebuffers[index],
+                pass_viewport.rect.clone(),
+                clear_values,
+            );
+
+            let frame_data = FrameData {
+                cmd: &mut command,
+                frame_index: index as u32,
+                viewport: pass_viewport,
+                sampled: graph_node
+                    .sampled_attachments
+                    .iter()
+                    .map(|a| self.texture(*a))
+                    .collect(),
+                inputs: graph_node
+                    .input_attachments
+                    .iter()
+                    .map(|a| self.texture(a.index))
+                    .collect(),
+            };
+            match graph_node
+                .callbacks
+                .borrow_mut()
+                .run(frame_data, world, resources)
+            {
+                Ok(Some(data)) => self.pass_data[index].push(data),
+                Ok(None) => (),
+                Err(e) => {
+                    panic!(
+                        "[RecordingGraph] execution failed during pass node: {}, with error: {}",
+                        graph_node.name, e
+                    )
+                }
+            }
+
+            command.end_render_pass();
+        }
+        record_barriers(&mut command, tracker.external());
+
+        // There are no pixels, readbacks are only recorded and completed with zeroed texels
+        for (index, readback) in std::mem::take(&mut self.readbacks) {
+            readback.complete(match self.describe(index) {
+                Some((_, _, samples)) if samples > 1 => Err(ReadbackError::Multisampled),
+                Some((extent, format, _)) => {
+                    command.read_attachment(self.image(index, self.frame_index));
+                    let size = (extent.width * extent.height) as usize * format.texel_size();
+                    Ok(ReadbackImage {
+                        extent,
+                        format,
+                        data: vec![0; size],
+                    })
+                }
+                None => Err(ReadbackError::UnknownAttachment(format!("{:?}", index))),
+            });
+        }
+
+        let mut log = command.finish();
+        log.misuse.extend(self.registry.take_misuse());
+        self.surface.present(log);
+        self.frame_index = (self.frame_index + 1) % FRAMES_IN_FLIGHT;
+    }
+
+    fn read_attachment(&mut self, index: Self::AttachmentIndex) -> Readback {
+        let readback = Readback::new();
+        self.readbacks.push((index, readback.clone()));
+        readback
+    }
+
+    fn into_builder(mut self) -> Self::Builder {
+        self.drop_images();
+        for node in self.nodes.iter().filter_map(RecordingNode::pass_node) {
+            self.registry
+                .destroy(ResourceKind::RenderPass, node.render_pass.0);
+        }
+        RecordingGraphBuilder {
+            registry: self.registry,
+            surface: self.surface,
+            offscreen: self.offscreen,
+            attachments: self.attachments.into_iter().map(|(desc, _)| desc).collect(),
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|n| match n {
+                    RecordingNode::PassNode(n) => Node::PassNode(n.graph_node),
+                    RecordingNode::ComputeNode(n) => Node::ComputeNode(n),
+                })
+                .chain(self.culled)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Recording implementation of the render api, for testing
+//!
+//! Nothing is executed, handles are plain ids and the command encoder appends every call to a
+//! [`CommandLog`]. Executing the graph "presents" the log of the frame to the surface (see
+//! [`RecordingGraph::record`]), so that tests can assert on the command stream or compare it to a
+//! ron snapshot. Resources are tracked by the context, so that misuse like binding a buffer
+//! that was already dropped shows up in the log as well.
+
+pub mod command;
+pub mod context;
+pub mod context_builder;
+pub mod graph;
+pub mod record;
+pub mod resources;
+pub mod surface;
+
+pub use context::{Context, ContextBuilder, RecordingContext};
+pub use graph::RecordingGraph;
+pub use record::{Command, CommandLog};
+pub use resources::{Misuse, ResourceId, ResourceKind};
+pub use surface::RecordingSurface;
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, cell::Cell, ops::Deref, rc::Rc, sync::Arc};
+
+    use app::{Resources, World};
+    use render::{
+        context::GpuBuilder,
+        graph::{
+            attachment::{AttachmentSize, GraphAttachment},
+            builder::GraphBuilder,
+            node::Node,
+        },
+        prelude::*,
+        resource::{
+            buffer::BufferDescriptor,
+            frame::Extent2D,
+            pipeline::{
+                AttributeDescriptor, PipelineShaders, PipelineStates, Primitive, Rasterizer,
+                RenderContext, VertexAttributeFormat, VertexBufferDescriptor, VertexInputRate,
+            },
+            render_pass::{LoadOp, StoreOp},
+        },
+    };
+
+    use super::*;
+    use crate::record::{Access, Stage};
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct Scale([f32; 4]);
+
+    unsafe impl bytemuck::Zeroable for Scale {}
+    unsafe impl bytemuck::Pod for Scale {}
+
+    struct Setup {
+        ctx: Arc<RecordingContext>,
+        graph: RecordingGraph,
+        vertex_buffer: Buffer<RecordingContext>,
+        scale_buffer: Buffer<RecordingContext>,
+    }
+
+    /// A single pass, that draws the vertex buffer twice with different push constants
+    fn setup() -> Setup {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 32,
+            height: 32,
+        });
+        let ctx = Arc::new(builder.build());
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+
+        let buffer = |name: &'static str, usage| {
+            resources.create_empty_buffer(BufferDescriptor {
+                name: name.into(),
+                size: 64,
+                memory_type: MemoryType::HostVisible,
+                usage,
+            })
+        };
+        let vertex_buffer = buffer("vertices", BufferUsage::Vertex);
+        let scale_buffer = buffer("scale", BufferUsage::Uniform);
+        let mixture = Arc::new(resources.stir(mixture![0: "scale" in Vertex: Scale]));
+        let glue = {
+            let mut bottle = resources.bottle(&mixture);
+            bottle.write_buffer(PartIndex::Binding(0), &scale_buffer, None);
+            bottle.apply()
+        };
+
+        let mut graph_builder = ctx.create_graph(surface);
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let mut pass = graph_builder.build_pass_node("test_pass".into());
+        pass.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        let compile = |name: &str| {
+            ctx.compile_shader(ShaderSource::GlslFile(std::path::Path::new(name).into()))
+                .unwrap()
+        };
+        let (vertex_shader, fragment_shader) = (compile("test.vert"), compile("test.frag"));
+        pass.init(Box::new(move |render_pass| {
+            let desc = GraphicsPipelineDescriptor {
+                name: "test_pipeline".into(),
+                mixtures: vec![&mixture],
+                push_constants: vec![(ShaderType::Vertex, 0..16)],
+                shaders: PipelineShaders {
+                    vertex: &vertex_shader,
+                    fragment: &fragment_shader,
+                    geometry: None,
+                },
+                rasterizer: Rasterizer::FILL,
+                vertex_buffers: vec![VertexBufferDescriptor::new(0, 16, VertexInputRate::Vertex)],
+                attributes: vec![AttributeDescriptor::new(
+                    0,
+                    0,
+                    0,
+                    VertexAttributeFormat::Vec4,
+                )],
+                primitive: Primitive::TriangleList,
+                blend_targets: vec![false],
+                depth: None,
+                samples: 1,
+                pipeline_states: PipelineStates::DYNAMIC,
+            };
+            Box::new(resources.create_graphics_pipeline(
+                desc,
+                RenderContext::RenderPass((render_pass.deref(), 0)),
+            ))
+        }));
+        let vertices = *vertex_buffer.get_handle();
+        pass.callback(Box::new(move |frame, pipeline, _world, _resources| {
+            let cmd = frame.cmd;
+            cmd.bind_graphics_pipeline(pipeline);
+            cmd.set_viewport(0, frame.viewport.clone());
+            cmd.snort_glue(0, pipeline, &glue);
+            cmd.bind_vertex_buffer(0, &vertices, BufferRange::WHOLE);
+            for offset in [0.0f32, 0.5].iter() {
+                let offset = [*offset, 0.0, 0.0, 0.0];
+                cmd.push_constants(
+                    pipeline,
+                    ShaderType::Vertex,
+                    0,
+                    bytemuck::cast_slice(&offset),
+                );
+                cmd.draw(0..3, 0..1);
+            }
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+
+        Setup {
+            ctx,
+            graph: graph_builder.build().unwrap(),
+            vertex_buffer,
+            scale_buffer,
+        }
+    }
+
+    /// Id of the first pipeline bound in log
+    fn bound_pipeline(log: &CommandLog) -> ResourceId {
+        log.commands()
+            .iter()
+            .find_map(|c| match c {
+                Command::BindGraphicsPipeline { pipeline, .. } => Some(*pipeline),
+                _ => None,
+            })
+            .expect("no pipeline was bound")
+    }
+
+    #[test]
+    fn records_command_stream() {
+        let mut setup = setup();
+        let log = setup.graph.record(&World::new(), &Resources::new());
+        log.assert_no_misuse();
+
+        assert_eq!(log.draw_count(), 2);
+        let push_constants: Vec<&Command> = log
+            .filter(|c| matches!(c, Command::PushConstants { .. }))
+            .collect();
+        assert_eq!(
+            push_constants[1],
+            &Command::PushConstants {
+                pipeline: bound_pipeline(&log),
+                stage: Stage::Vertex,
+                offset: 0,
+                data: bytemuck::cast_slice(&[0.5f32, 0.0, 0.0, 0.0]).to_vec(),
+            }
+        );
+        assert!(matches!(
+            log.commands().last(),
+            Some(Command::EndRenderPass)
+        ));
+        log.assert_snapshot(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots/command_stream.ron"),
+        );
+    }
+
+    #[test]
+    fn records_uploads_in_next_frame() {
+        let mut setup = setup();
+        let vertices = setup.vertex_buffer.get_handle();
+        setup.ctx.upload_buffer(vertices, 16, &[0; 32]);
+
+        let upload = Command::UploadBuffer {
+            buffer: vertices.id(),
+            offset: 16,
+            size: 32,
+        };
+        let log = setup.graph.record(&World::new(), &Resources::new());
+        log.assert_no_misuse();
+        assert_eq!(log.commands().first(), Some(&upload));
+        let log = setup.graph.record(&World::new(), &Resources::new());
+        assert!(!log.commands().contains(&upload));
+    }
+
+    #[test]
+    fn detects_use_after_drop() {
+        let Setup {
+            ctx,
+            mut graph,
+            vertex_buffer,
+            scale_buffer,
+        } = setup();
+        let vertices = vertex_buffer.get_handle().id();
+        let scale = scale_buffer.get_handle().id();
+        // Both are still used by the pass
+        drop(vertex_buffer);
+        drop(scale_buffer);
+        assert!(!ctx.is_alive(vertices));
+
+        let log = graph.record(&World::new(), &Resources::new());
+        let dropped: Vec<(String, ResourceId)> = log
+            .misuse()
+            .iter()
+            .filter_map(|m| match m {
+                Misuse::UseAfterDrop { command, id, .. } => Some((command.clone(), *id)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            dropped,
+            vec![
+                ("snort_glue".to_string(), scale),
+                ("bind_vertex_buffer".to_string(), vertices)
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_absolute_attachments() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 32,
+            height: 32,
+        });
+        let ctx = Arc::new(builder.build());
+
+        let mut graph_builder = ctx.create_graph(surface);
+        let shadow = graph_builder.add_attachment(GraphAttachment::new(
+            "shadow",
+            AttachmentSize::Absolute(16, 16),
+            graph_builder.default_depth_format(),
+        ));
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        // Added in reverse, the compiler has to order them
+        let sampled = Rc::new(Cell::new(None));
+        let mut main = graph_builder.build_pass_node::<()>("main".into());
+        main.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        main.add_sampled(shadow);
+        main.init(Box::new(|_| Box::new(())));
+        let textures = sampled.clone();
+        main.callback(Box::new(move |frame, _data, _world, _resources| {
+            textures.set(frame.sampled.first().map(|t| t.id()));
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(main.build()));
+        let mut depth = graph_builder.build_pass_node::<()>("depth".into());
+        depth.set_depth(shadow, LoadOp::Clear, StoreOp::Store);
+        depth.init(Box::new(|_| Box::new(())));
+        depth.callback(Box::new(|frame, _data, _world, _resources| {
+            assert_eq!(frame.viewport.rect.width, 16);
+            frame.cmd.set_viewport(0, frame.viewport.clone());
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(depth.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        let log = graph.record(&World::new(), &Resources::new());
+        log.assert_no_misuse();
+        let areas: Vec<(i16, i16)> = log
+            .commands()
+            .iter()
+            .filter_map(|c| match c {
+                Command::BeginRenderPass { area, .. } => Some((area.width, area.height)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(areas, vec![(16, 16), (32, 32)]);
+        let texture = sampled.get().expect("no sampled texture");
+        assert!(ctx.is_alive(texture));
+    }
+
+    #[test]
+    fn places_compute_barriers() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 32,
+            height: 32,
+        });
+        let ctx = Arc::new(builder.build());
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+        let particles = Arc::new(resources.create_empty_buffer(BufferDescriptor {
+            name: "particles".into(),
+            size: 64,
+            memory_type: MemoryType::DeviceLocal,
+            usage: BufferUsage::Storage,
+        }));
+        let shader = Arc::new(
+            ctx.compile_shader(ShaderSource::GlslFile(
+                std::path::Path::new("test.comp").into(),
+            ))
+            .unwrap(),
+        );
+
+        let mut graph_builder = ctx.create_graph(surface);
+        for (name, writes) in [("simulate", true), ("count", false)].iter() {
+            let mut node = graph_builder.build_compute_node(Cow::Borrowed(*name));
+            if *writes {
+                node.add_output(particles.clone());
+            } else {
+                node.add_input(particles.clone());
+            }
+            let (resources, shader) = (resources.clone(), shader.clone());
+            node.init(Box::new(move || {
+                Box::new(
+                    resources.create_compute_pipeline(ComputePipelineDescriptor {
+                        name: "particles".into(),
+                        mixtures: vec![],
+                        push_constants: vec![],
+                        shader: &shader,
+                    }),
+                )
+            }));
+            node.callback(Box::new(|frame, pipeline, _world, _resources| {
+                frame.cmd.bind_compute_pipeline(pipeline);
+                frame.cmd.dispatch([4, 1, 1]);
+                Ok(None)
+            }));
+            graph_builder.add_node(Node::ComputeNode(node.build()));
+        }
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let mut pass = graph_builder.build_pass_node::<()>("draw".into());
+        pass.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        pass.init(Box::new(|_| Box::new(())));
+        pass.callback(Box::new(|_frame, _data, _world, _resources| Ok(None)));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        let log = graph.record(&World::new(), &Resources::new());
+        log.assert_no_misuse();
+        let buffer = particles.get_handle().id();
+        let barriers: Vec<(Access, Access)> = log
+            .commands()
+            .iter()
+            .filter_map(|c| match c {
+                Command::Barrier { resource, from, to } if *resource == buffer => {
+                    Some((*from, *to))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            barriers,
+            vec![
+                (Access::External, Access::ComputeWrite),
+                (Access::ComputeWrite, Access::ComputeRead),
+                (Access::ComputeRead, Access::External),
+            ]
+        );
+        // The last barrier has to be placed before the render pass
+        let last_barrier = log
+            .commands()
+            .iter()
+            .rposition(|c| matches!(c, Command::Barrier { .. }));
+        let render_pass = log
+            .commands()
+            .iter()
+            .position(|c| matches!(c, Command::BeginRenderPass { .. }));
+        assert!(last_barrier < render_pass);
+    }
+}
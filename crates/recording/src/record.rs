@@ -0,0 +1,357 @@
+use std::{collections::HashMap, fs, ops::Range, path::Path};
+
+use render::{
+    command_encoder::IndexType,
+    graph::nodes::compute::ResourceAccess,
+    prelude::{BufferRange, ShaderType},
+    resource::{
+        frame::Clear,
+        pipeline::{Rect, Viewport},
+    },
+};
+use ron::ser::PrettyConfig;
+use serde::Serialize;
+
+use crate::resources::{Misuse, ResourceId};
+
+/// Setting this environment variable rewrites all snapshots instead of comparing against them
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedRect {
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl From<&Rect> for RecordedRect {
+    fn from(rect: &Rect) -> Self {
+        Self {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ClearValue {
+    Color([f32; 4]),
+    Depth(f32, u32),
+}
+
+impl From<&Clear> for ClearValue {
+    fn from(clear: &Clear) -> Self {
+        match clear {
+            Clear::Color(r, g, b, a) => ClearValue::Color([*r, *g, *b, *a]),
+            Clear::Depth(depth, stencil) => ClearValue::Depth(*depth, *stencil),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+    Compute,
+    Geometry,
+}
+
+impl From<&ShaderType> for Stage {
+    fn from(shader_type: &ShaderType) -> Self {
+        match shader_type {
+            ShaderType::Vertex => Stage::Vertex,
+            ShaderType::Fragment => Stage::Fragment,
+            ShaderType::Compute => Stage::Compute,
+            ShaderType::Geometry => Stage::Geometry,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum RecordedIndexType {
+    U16,
+    U32,
+}
+
+impl From<&IndexType> for RecordedIndexType {
+    fn from(index_type: &IndexType) -> Self {
+        match index_type {
+            IndexType::U16 => RecordedIndexType::U16,
+            IndexType::U32 => RecordedIndexType::U32,
+        }
+    }
+}
+
+/// Byte range of a buffer, size None is the whole buffer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedRange {
+    pub offset: u64,
+    pub size: Option<u64>,
+}
+
+impl From<&BufferRange> for RecordedRange {
+    fn from(range: &BufferRange) -> Self {
+        Self {
+            offset: range.offset,
+            size: range.size,
+        }
+    }
+}
+
+/// Mirror of ResourceAccess of the render graph
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Access {
+    External,
+    ComputeRead,
+    ComputeWrite,
+}
+
+impl From<&ResourceAccess> for Access {
+    fn from(access: &ResourceAccess) -> Self {
+        match access {
+            ResourceAccess::External => Access::External,
+            ResourceAccess::ComputeRead => Access::ComputeRead,
+            ResourceAccess::ComputeWrite => Access::ComputeWrite,
+        }
+    }
+}
+
+/// A single call to the CommandEncoder, resources are referenced by their id
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Command {
+    BeginRenderPass {
+        render_pass: ResourceId,
+        framebuffer: ResourceId,
+        area: RecordedRect,
+        clear_values: Vec<ClearValue>,
+    },
+    EndRenderPass,
+    SetViewport {
+        index: u32,
+        rect: RecordedRect,
+        depth: (f32, f32),
+    },
+    SetScissor {
+        index: u32,
+        rect: RecordedRect,
+    },
+    BindGraphicsPipeline {
+        pipeline: ResourceId,
+        name: String,
+    },
+    PushConstants {
+        pipeline: ResourceId,
+        stage: Stage,
+        offset: u32,
+        data: Vec<u32>,
+    },
+    BindVertexBuffer {
+        binding: u32,
+        buffer: ResourceId,
+        range: RecordedRange,
+    },
+    BindIndexBuffer {
+        buffer: ResourceId,
+        range: RecordedRange,
+        index_type: RecordedIndexType,
+    },
+    SnortGlue {
+        set_idx: usize,
+        pipeline: ResourceId,
+        set: ResourceId,
+    },
+    Draw {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    DrawIndexed {
+        indices: Range<u32>,
+        vertex_offset: i32,
+        instances: Range<u32>,
+    },
+    CopyBuffer {
+        src: ResourceId,
+        dst: ResourceId,
+        /// (src_offset, dst_offset, size)
+        regions: Vec<(u64, u64, u64)>,
+    },
+    BindComputePipeline {
+        pipeline: ResourceId,
+        name: String,
+    },
+    ComputePushConstants {
+        pipeline: ResourceId,
+        offset: u32,
+        data: Vec<u32>,
+    },
+    SnortComputeGlue {
+        set_idx: usize,
+        pipeline: ResourceId,
+        set: ResourceId,
+    },
+    Dispatch {
+        groups: [u32; 3],
+    },
+    /// Placed by the graph around compute nodes, one per resource
+    Barrier {
+        resource: ResourceId,
+        from: Access,
+        to: Access,
+    },
+    /// Placed by the graph at the end of a frame, for every requested readback
+    ReadAttachment {
+        image: ResourceId,
+    },
+    /// Placed by the graph at the start of a frame, for every upload_buffer since the last one
+    UploadBuffer {
+        buffer: ResourceId,
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl Command {
+    pub(crate) fn set_viewport(index: u32, viewport: &Viewport) -> Self {
+        Command::SetViewport {
+            index,
+            rect: (&viewport.rect).into(),
+            depth: (viewport.depth.start, viewport.depth.end),
+        }
+    }
+
+    pub fn is_draw(&self) -> bool {
+        matches!(self, Command::Draw { .. } | Command::DrawIndexed { .. })
+    }
+
+    /// Every resource the command references
+    fn resources_mut(&mut self) -> Vec<&mut ResourceId> {
+        match self {
+            Command::BeginRenderPass {
+                render_pass,
+                framebuffer,
+                ..
+            } => vec![render_pass, framebuffer],
+            Command::BindGraphicsPipeline { pipeline, .. }
+            | Command::PushConstants { pipeline, .. }
+            | Command::BindComputePipeline { pipeline, .. }
+            | Command::ComputePushConstants { pipeline, .. } => vec![pipeline],
+            Command::BindVertexBuffer { buffer, .. }
+            | Command::BindIndexBuffer { buffer, .. }
+            | Command::UploadBuffer { buffer, .. } => vec![buffer],
+            Command::SnortGlue { pipeline, set, .. }
+            | Command::SnortComputeGlue { pipeline, set, .. } => vec![pipeline, set],
+            Command::CopyBuffer { src, dst, .. } => vec![src, dst],
+            Command::Barrier { resource, .. } => vec![resource],
+            Command::ReadAttachment { image } => vec![image],
+            Command::EndRenderPass
+            | Command::SetViewport { .. }
+            | Command::SetScissor { .. }
+            | Command::Draw { .. }
+            | Command::DrawIndexed { .. }
+            | Command::Dispatch { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Everything that was recorded during one execution of the graph
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CommandLog {
+    pub commands: Vec<Command>,
+    pub misuse: Vec<Misuse>,
+}
+
+impl CommandLog {
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn misuse(&self) -> &[Misuse] {
+        &self.misuse
+    }
+
+    pub fn filter<'a>(
+        &'a self,
+        predicate: impl Fn(&Command) -> bool + 'a,
+    ) -> impl Iterator<Item = &'a Command> + 'a {
+        self.commands.iter().filter(move |c| predicate(c))
+    }
+
+    pub fn draw_count(&self) -> usize {
+        self.filter(Command::is_draw).count()
+    }
+
+    /// Commands with resources renumbered in the order they are first used
+    ///
+    /// Ids depend on the order resources were created in, which is not fixed when they are
+    /// created in the background (eg. pipelines), so snapshots of those should be normalized
+    pub fn normalized(&self) -> Self {
+        let mut ids = HashMap::new();
+        let mut log = self.clone();
+        for id in log.commands.iter_mut().flat_map(Command::resources_mut) {
+            let next = ResourceId(ids.len() as u64);
+            *id = *ids.entry(*id).or_insert(next);
+        }
+        log
+    }
+
+    /// Panics with all detected misuse, if there was any
+    pub fn assert_no_misuse(&self) {
+        if !self.misuse.is_empty() {
+            let misuse: Vec<String> = self.misuse.iter().map(|m| m.to_string()).collect();
+            panic!(
+                "[CommandLog] detected misuse of the render api:\n  {}",
+                misuse.join("\n  ")
+            );
+        }
+    }
+
+    pub fn to_ron(&self) -> String {
+        // Fixed line endings, so snapshots are the same on every platform
+        let config = PrettyConfig::new()
+            .with_new_line("\n".to_string())
+            .with_indentor("  ".to_string());
+        ron::ser::to_string_pretty(self, config).expect("[CommandLog] failed to serialize")
+    }
+
+    /// Compares the log with the ron snapshot at path
+    ///
+    /// The snapshot is only written if UPDATE_SNAPSHOTS is set, a missing one panics
+    pub fn assert_snapshot(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let actual = self.to_ron();
+        if std::env::var_os(UPDATE_SNAPSHOTS).is_some() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).expect("[CommandLog] failed to create snapshot dir");
+            }
+            fs::write(path, &actual).expect("[CommandLog] failed to write snapshot");
+            log::info!("[CommandLog] wrote snapshot {:?}", path);
+            return;
+        }
+
+        if !path.exists() {
+            panic!(
+                "[CommandLog] snapshot {:?} is missing, rerun with {} set to write it",
+                path, UPDATE_SNAPSHOTS
+            );
+        }
+        let expected = fs::read_to_string(path).expect("[CommandLog] failed to read snapshot");
+        if expected.trim_end() != actual.trim_end() {
+            let line = expected
+                .lines()
+                .zip(actual.lines())
+                .position(|(e, a)| e != a)
+                .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+            panic!(
+                "[CommandLog] command stream differs from snapshot {:?} at line {}, rerun with {} set to update it\n--- expected\n{}\n--- actual\n{}",
+                path,
+                line + 1,
+                UPDATE_SNAPSHOTS,
+                expected,
+                actual
+            );
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use core::thiserror::{self, Error};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+
+use crate::record::Command;
+
+/// Every handle of the recording backend is just an id into the registry of the context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct ResourceId(pub u64);
+
+impl Display for ResourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum ResourceKind {
+    Buffer,
+    RenderPass,
+    Pipeline,
+    ComputePipeline,
+    Shader,
+    ImageView,
+    Framebuffer,
+    DescriptorLayout,
+    DescriptorSet,
+    Texture,
+    Sampler,
+}
+
+macro_rules! handle {
+    ($name:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(pub(crate) ResourceId);
+
+        impl $name {
+            pub fn id(&self) -> ResourceId {
+                self.0
+            }
+        }
+    };
+}
+
+handle!(BufferHandle);
+handle!(RenderPassHandle);
+handle!(PipelineHandle);
+handle!(ComputePipelineHandle);
+handle!(ShaderHandle);
+handle!(ImageViewHandle);
+handle!(FramebufferHandle);
+handle!(DescriptorLayoutHandle);
+handle!(DescriptorSetHandle);
+handle!(TextureHandle);
+handle!(SamplerHandle);
+
+/// Wrong usage of the api, that would be undefined behaviour (or a validation error) on a gpu
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+pub enum Misuse {
+    #[error("{command} uses {kind:?} {id} ({name}) after it was dropped")]
+    UseAfterDrop {
+        command: String,
+        kind: ResourceKind,
+        id: ResourceId,
+        name: String,
+    },
+    #[error("{kind:?} {id} ({name}) was dropped twice")]
+    DoubleDrop {
+        kind: ResourceKind,
+        id: ResourceId,
+        name: String,
+    },
+    #[error("{command} uses {id}, which is not a {expected:?}")]
+    WrongKind {
+        command: String,
+        expected: ResourceKind,
+        id: ResourceId,
+    },
+    #[error("{command} is only valid inside of a render pass")]
+    OutsideRenderPass { command: String },
+    #[error("begin_render_pass while another render pass is still active")]
+    NestedRenderPass,
+    #[error("{command} without a bound pipeline")]
+    NoPipeline { command: String },
+    #[error("draw_indexed without a bound index buffer")]
+    NoIndexBuffer,
+    #[error("{command} is not allowed inside of a render pass")]
+    InsideRenderPass { command: String },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResourceInfo {
+    pub(crate) kind: ResourceKind,
+    pub(crate) name: String,
+    pub(crate) alive: bool,
+}
+
+/// Resources written into a descriptor set, keyed by (binding, array_offset)
+type SetBindings = HashMap<(u32, usize), Vec<(ResourceKind, ResourceId)>>;
+
+/// Keeps track of all resources of a context, resources are never removed so that uses after a
+/// drop can be reported with the name of the resource
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+    next_id: AtomicU64,
+    resources: RwLock<HashMap<ResourceId, ResourceInfo>>,
+    descriptor_sets: RwLock<HashMap<ResourceId, SetBindings>>,
+    /// Misuse that happened outside of a command encoder (eg. in the context)
+    misuse: Mutex<Vec<Misuse>>,
+    /// Uploads of the context, until the next frame of the graph takes them
+    uploads: Mutex<Vec<Command>>,
+}
+
+impl Registry {
+    pub(crate) fn create(&self, kind: ResourceKind, name: impl Into<String>) -> ResourceId {
+        let id = ResourceId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.resources.write().insert(
+            id,
+            ResourceInfo {
+                kind,
+                name: name.into(),
+                alive: true,
+            },
+        );
+        id
+    }
+
+    pub(crate) fn info(&self, id: ResourceId) -> Option<ResourceInfo> {
+        self.resources.read().get(&id).cloned()
+    }
+
+    pub(crate) fn name(&self, id: ResourceId) -> String {
+        self.info(id).map(|i| i.name).unwrap_or_default()
+    }
+
+    pub(crate) fn destroy(&self, kind: ResourceKind, id: ResourceId) {
+        let mut resources = self.resources.write();
+        match resources.get_mut(&id) {
+            Some(info) if info.alive => info.alive = false,
+            Some(info) => self.report(Misuse::DoubleDrop {
+                kind,
+                id,
+                name: info.name.clone(),
+            }),
+            None => self.report(Misuse::WrongKind {
+                command: "drop".to_string(),
+                expected: kind,
+                id,
+            }),
+        }
+        if kind == ResourceKind::DescriptorSet {
+            self.descriptor_sets.write().remove(&id);
+        }
+    }
+
+    /// Checks that id refers to a living resource of kind
+    pub(crate) fn check(
+        &self,
+        command: &str,
+        kind: ResourceKind,
+        id: ResourceId,
+    ) -> Result<(), Misuse> {
+        match self.resources.read().get(&id) {
+            Some(info) if info.kind != kind => Err(Misuse::WrongKind {
+                command: command.to_string(),
+                expected: kind,
+                id,
+            }),
+            Some(info) if !info.alive => Err(Misuse::UseAfterDrop {
+                command: command.to_string(),
+                kind,
+                id,
+                name: info.name.clone(),
+            }),
+            Some(_) => Ok(()),
+            None => Err(Misuse::WrongKind {
+                command: command.to_string(),
+                expected: kind,
+                id,
+            }),
+        }
+    }
+
+    /// Same as check, but the misuse is reported to the registry
+    pub(crate) fn validate(&self, command: &str, kind: ResourceKind, id: ResourceId) {
+        if let Err(misuse) = self.check(command, kind, id) {
+            self.report(misuse);
+        }
+    }
+
+    /// Writes resources (eg. a buffer or a texture with its sampler) into the set, replacing
+    /// what was written to the same element before
+    pub(crate) fn bind(
+        &self,
+        set: ResourceId,
+        binding: u32,
+        array_offset: usize,
+        resources: Vec<(ResourceKind, ResourceId)>,
+    ) {
+        self.descriptor_sets
+            .write()
+            .entry(set)
+            .or_default()
+            .insert((binding, array_offset), resources);
+    }
+
+    /// All resources that are currently written into set, sorted by id
+    pub(crate) fn bound_resources(&self, set: ResourceId) -> Vec<(ResourceKind, ResourceId)> {
+        let mut resources: Vec<(ResourceKind, ResourceId)> = self
+            .descriptor_sets
+            .read()
+            .get(&set)
+            .map(|bindings| bindings.values().flatten().copied().collect())
+            .unwrap_or_default();
+        resources.sort_by_key(|(_kind, id)| *id);
+        resources.dedup();
+        resources
+    }
+
+    pub(crate) fn report(&self, misuse: Misuse) {
+        log::warn!("[RecordingContext] {}", misuse);
+        self.misuse.lock().push(misuse);
+    }
+
+    pub(crate) fn take_misuse(&self) -> Vec<Misuse> {
+        std::mem::take(&mut *self.misuse.lock())
+    }
+
+    pub(crate) fn upload(&self, command: Command) {
+        self.uploads.lock().push(command);
+    }
+
+    pub(crate) fn take_uploads(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.uploads.lock())
+    }
+}
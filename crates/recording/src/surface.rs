@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use render::resource::frame::Extent2D;
+
+use crate::record::CommandLog;
+
+#[derive(Debug)]
+struct SurfaceData {
+    extent: RwLock<Extent2D>,
+    log: Mutex<Option<CommandLog>>,
+}
+
+/// Surface of the recording backend, the graph "presents" the commands of a frame into this
+///
+/// Clones refer to the same surface, so one can be kept around to read the logs back
+#[derive(Debug, Clone)]
+pub struct RecordingSurface {
+    data: Arc<SurfaceData>,
+}
+
+impl RecordingSurface {
+    pub fn new(extent: Extent2D) -> Self {
+        Self {
+            data: Arc::new(SurfaceData {
+                extent: RwLock::new(extent),
+                log: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.data.extent.read().clone()
+    }
+
+    /// The graph will pick up the new size in the next frame
+    pub fn resize(&self, extent: Extent2D) {
+        *self.data.extent.write() = extent;
+    }
+
+    /// Commands of the last executed frame
+    pub fn last_log(&self) -> Option<CommandLog> {
+        self.data.log.lock().clone()
+    }
+
+    pub(crate) fn present(&self, log: CommandLog) {
+        *self.data.log.lock() = Some(log);
+    }
+}
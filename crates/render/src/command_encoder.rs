@@ -76,4 +76,24 @@ pub trait CommandEncoder<C: GpuContext + ?Sized> {
     fn copy_buffer<I>(&mut self, src: &C::BufferHandle, dst: &C::BufferHandle, regions: I)
     where
         I: IntoIterator<Item = BufferCopy>;
+
+    // Compute, which has its own bind point for pipelines and descriptor sets
+    fn bind_compute_pipeline(&mut self, pipeline: &C::ComputePipelineHandle);
+
+    fn compute_push_constants(
+        &mut self,
+        pipeline: &C::ComputePipelineHandle,
+        offset: u32,
+        data: &[u32],
+    );
+
+    fn snort_compute_glue(
+        &mut self,
+        set_idx: usize,
+        pipeline: &C::ComputePipelineHandle,
+        glue: &Glue<C>,
+    );
+
+    /// Dispatches work groups of the bound compute pipeline (not allowed inside a render pass)
+    fn dispatch(&mut self, groups: [u32; 3]);
 }
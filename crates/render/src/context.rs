@@ -7,12 +7,19 @@ use crate::{
     resource::{
         frame::Extent2D,
         glue::Mixture,
-        pipeline::{GraphicsPipelineDescriptor, RenderContext, ShaderSource},
+        pipeline::{
+            ComputePipelineDescriptor, GraphicsPipelineDescriptor, RenderContext, ShaderSource,
+        },
     },
 };
 use crate::{
     graph::Graph,
-    resource::{frame::Extent3D, glue::DescriptorWrite, render_pass::RenderPassDescriptor},
+    resource::{
+        frame::Extent3D,
+        glue::DescriptorWrite,
+        render_pass::RenderPassDescriptor,
+        texture::{SamplerDescriptor, TextureDescriptor},
+    },
 };
 use app::core::anyhow;
 use bytemuck::Pod;
@@ -38,6 +45,7 @@ pub trait GpuContext: Send + Sync {
     type SurfaceHandle: Send + Sync + Clone; // TODO: Maybe add specific Trait
     type BufferHandle: Send + Sync + Debug;
     type PipelineHandle: Send + Sync + Debug;
+    type ComputePipelineHandle: Send + Sync + Debug;
     type RenderPassHandle: Send + Sync + Debug;
     type ShaderCode: Debug + Send + Sized;
     type ImageView: Debug + Send + Sync;
@@ -45,6 +53,8 @@ pub trait GpuContext: Send + Sync {
     type CommandBuffer: Debug + Send + Sync;
     type DescriptorLayout: Debug + Send + Sync;
     type DescriptorSet: Debug + Send + Sync;
+    type TextureHandle: Debug + Send + Sync;
+    type SamplerHandle: Debug + Send + Sync;
     /// eg. The Command buffer in recording state
     type CommandEncoder: CommandEncoder<Self> + Debug + Send + Sync;
     type SwapchainImage: Borrow<Self::ImageView> + Debug + Send + Sync;
@@ -71,9 +81,30 @@ pub trait GpuContext: Send + Sync {
     /// same as write to buffer
     unsafe fn write_to_buffer_raw(&self, buffer: &Self::BufferHandle, data: &[u8]);
 
+    /// Copies data into the buffer at offset, through a ring of host visible staging buffers.
+    /// This is how DeviceLocal buffers are filled, the copy is visible to all work that is
+    /// submitted after this returns (use flush_uploads to wait for it)
+    fn upload_buffer(&self, buffer: &Self::BufferHandle, offset: u64, data: &[u8]);
+
+    /// Blocks until all uploads that were submitted so far have finished
+    fn flush_uploads(&self);
+
     /// Drop a Buffer handle
     fn drop_buffer(&self, buffer: Self::BufferHandle);
 
+    // Textures
+    fn create_texture(&self, desc: &TextureDescriptor) -> Self::TextureHandle;
+
+    /// Uploads the first levels.len() mip levels of the texture (tightly packed texels), the
+    /// remaining levels of the descriptor are generated from the last uploaded one. The upload
+    /// is finished when this returns
+    fn upload_texture(&self, texture: &Self::TextureHandle, levels: &[&[u8]]);
+
+    fn drop_texture(&self, texture: Self::TextureHandle);
+
+    fn create_sampler(&self, desc: &SamplerDescriptor) -> Self::SamplerHandle;
+    fn drop_sampler(&self, sampler: Self::SamplerHandle);
+
     // Render Passes
     fn create_render_pass(&self, desc: &RenderPassDescriptor) -> Self::RenderPassHandle;
     fn drop_render_pass(&self, rp: Self::RenderPassHandle);
@@ -88,9 +119,23 @@ pub trait GpuContext: Send + Sync {
         Self: Sized;
     fn drop_pipeline(&self, pipeline: Self::PipelineHandle);
 
+    fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<Self>,
+    ) -> Self::ComputePipelineHandle
+    where
+        Self: Sized;
+    fn drop_compute_pipeline(&self, pipeline: Self::ComputePipelineHandle);
+
     // NOTE(luca): Maybe this should not be provided by context
     fn compile_shader(&self, source: ShaderSource) -> anyhow::Result<Self::ShaderCode>;
 
+    /// The SPIR-V of compiled shader code, used for reflection. Backends that do not compile to
+    /// SPIR-V return None
+    fn shader_spirv<'a>(&self, _code: &'a Self::ShaderCode) -> Option<&'a [u32]> {
+        None
+    }
+
     // TODO: if we want to support multi surface or headless drawing a surface can not be bound to
     //  the context...
     /// Will return the format of the created surface
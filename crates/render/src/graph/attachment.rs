@@ -2,7 +2,10 @@ use std::borrow::Cow;
 
 use uuid::Uuid;
 
-use crate::util::format::{ImageTiling, TextureFormat};
+use crate::{
+    resource::frame::Extent2D,
+    util::format::{ImageTiling, TextureFormat},
+};
 
 #[derive(Debug, Clone)]
 pub enum AttachmentSize {
@@ -12,6 +15,20 @@ pub enum AttachmentSize {
 
 impl AttachmentSize {
     pub const SWAPCHAIN: Self = Self::Relative(1.0, 1.0);
+
+    /// Extent of the attachment for a surface of the given size
+    pub fn extent(&self, surface: &Extent2D) -> Extent2D {
+        match self {
+            AttachmentSize::Relative(width, height) => Extent2D {
+                width: ((surface.width as f32 * width) as u32).max(1),
+                height: ((surface.height as f32 * height) as u32).max(1),
+            },
+            AttachmentSize::Absolute(width, height) => Extent2D {
+                width: *width,
+                height: *height,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -23,6 +40,9 @@ pub struct GraphAttachment {
     pub format: TextureFormat,
     pub is_backbuffer: bool,
     pub tiling: ImageTiling,
+    /// Samples per texel, multisampled attachments can not be sampled or read back, but are
+    /// resolved into single sampled ones (see PassNodeBuilder::add_resolve)
+    pub samples: u8,
 }
 
 impl GraphAttachment {}
@@ -40,6 +60,7 @@ impl GraphAttachment {
             format,
             is_backbuffer: false,
             tiling: ImageTiling::Optimal,
+            samples: 1,
         }
     }
 }
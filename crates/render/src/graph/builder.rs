@@ -1,32 +1,49 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt::Debug};
 
-use crate::{prelude::GpuContext, util::format::TextureFormat};
+use crate::{prelude::GpuContext, resource::frame::Extent2D, util::format::TextureFormat};
 
 use super::{
     attachment::GraphAttachment,
+    compile::GraphError,
     node::Node,
-    nodes::{callbacks::UserData, pass::PassNodeBuilder},
+    nodes::{callbacks::UserData, compute::ComputeNodeBuilder, pass::PassNodeBuilder},
     Graph,
 };
 
 pub trait GraphBuilder {
     type Context: GpuContext;
-    type AttachmentIndex: Clone + Copy;
+    type AttachmentIndex: Clone + Copy + PartialEq + Debug;
     type Graph: Graph;
 
     fn add_node(&mut self, node: Node<Self>);
     fn add_attachment(&mut self, attachment: GraphAttachment) -> Self::AttachmentIndex;
     fn attachment_index(&self, name: Cow<'static, str>) -> Option<Self::AttachmentIndex>;
+    fn attachment_name(&self, index: Self::AttachmentIndex) -> Cow<'static, str>;
 
     fn get_backbuffer_attachment(&self) -> Self::AttachmentIndex;
 
+    /// Renders into an offscreen color attachment of the given size instead of the surface, the
+    /// backbuffer is never presented but can be read back (see Graph::read_attachment)
+    fn render_offscreen(&mut self, extent: Extent2D);
+
     fn get_surface_format(&self) -> TextureFormat;
     fn default_depth_format(&self) -> TextureFormat;
     fn get_swapchain_image_count(&self) -> usize;
+    /// Whether attachments can have the number of samples per texel, the color and depth
+    /// attachments of a pass need the same count
+    fn supports_samples(&self, samples: u8) -> bool;
 
     fn build_pass_node<U: UserData>(&self, name: Cow<'static, str>) -> PassNodeBuilder<Self, U> {
         PassNodeBuilder::new(name)
     }
 
-    fn build(self) -> Self::Graph;
+    fn build_compute_node<U: UserData>(
+        &self,
+        name: Cow<'static, str>,
+    ) -> ComputeNodeBuilder<Self, U> {
+        ComputeNodeBuilder::new(name)
+    }
+
+    /// Compiles the nodes (see graph::compile) and creates the backend resources
+    fn build(self) -> Result<Self::Graph, GraphError>;
 }
@@ -0,0 +1,560 @@
+use std::{borrow::Cow, ops::Range};
+
+use app::core::thiserror::{self, Error};
+
+use crate::resource::render_pass::LoadOp;
+
+use super::{builder::GraphBuilder, node::Node, nodes::pass::PassNode};
+
+/// How a pass node uses one of its attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentUsage {
+    Color,
+    Depth,
+    Input,
+    /// Read through a sampler, outside of the render pass
+    Sampled,
+}
+
+impl AttachmentUsage {
+    fn writes(&self) -> bool {
+        !matches!(self, AttachmentUsage::Input | AttachmentUsage::Sampled)
+    }
+
+    /// Layout the attachment needs to be in during the pass
+    fn layout(&self) -> AttachmentLayout {
+        match self {
+            AttachmentUsage::Color => AttachmentLayout::ColorAttachment,
+            AttachmentUsage::Depth => AttachmentLayout::DepthStencilAttachment,
+            AttachmentUsage::Input | AttachmentUsage::Sampled => AttachmentLayout::ShaderReadOnly,
+        }
+    }
+}
+
+/// Backend agnostic version of the image layouts, that are needed for the attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentLayout {
+    /// Contents are discarded
+    Undefined,
+    ColorAttachment,
+    DepthStencilAttachment,
+    ShaderReadOnly,
+    Present,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentUse<I> {
+    pub index: I,
+    pub usage: AttachmentUsage,
+    pub load: LoadOp,
+}
+
+/// Everything the compiler needs to know about a node
+#[derive(Debug, Clone)]
+pub struct NodeInfo<I> {
+    pub name: Cow<'static, str>,
+    /// Compute nodes are never culled or reordered, since the resources they access are not
+    /// declared by pass nodes
+    pub compute: bool,
+    pub attachments: Vec<AttachmentUse<I>>,
+}
+
+impl<G: GraphBuilder + ?Sized> PassNode<G> {
+    /// All attachments of the node, in the order of the render pass attachments (outputs, inputs,
+    /// depth and then resolve targets), followed by the sampled attachments that are not part of
+    /// the render pass
+    pub fn attachment_uses(&self) -> Vec<AttachmentUse<<G as GraphBuilder>::AttachmentIndex>> {
+        let outputs = self
+            .output_attachments
+            .iter()
+            .map(|a| (a, AttachmentUsage::Color));
+        let inputs = self
+            .input_attachments
+            .iter()
+            .map(|a| (a, AttachmentUsage::Input));
+        let depth = self
+            .depth_attachment
+            .iter()
+            .map(|a| (a, AttachmentUsage::Depth));
+        // Resolve targets are written like outputs, their previous content is discarded
+        let resolves = self.resolve_attachments.iter().map(|r| AttachmentUse {
+            index: r.target,
+            usage: AttachmentUsage::Color,
+            load: LoadOp::DontCare,
+        });
+        let sampled = self.sampled_attachments.iter().map(|index| AttachmentUse {
+            index: *index,
+            usage: AttachmentUsage::Sampled,
+            load: LoadOp::Load,
+        });
+        outputs
+            .chain(inputs)
+            .chain(depth)
+            .map(|(a, usage)| AttachmentUse {
+                index: a.index,
+                usage,
+                load: a.load.clone(),
+            })
+            .chain(resolves)
+            .chain(sampled)
+            .collect()
+    }
+
+    /// The first attachment of the render pass, which decides the size of the render area
+    pub fn first_attachment(&self) -> Option<<G as GraphBuilder>::AttachmentIndex> {
+        self.output_attachments
+            .iter()
+            .chain(self.depth_attachment.iter())
+            .map(|a| a.index)
+            .next()
+    }
+}
+
+impl<G: GraphBuilder + ?Sized> Node<G> {
+    pub fn info(&self) -> NodeInfo<<G as GraphBuilder>::AttachmentIndex> {
+        match self {
+            Node::PassNode(n) => NodeInfo {
+                name: n.name.clone(),
+                compute: false,
+                attachments: n.attachment_uses(),
+            },
+            Node::ComputeNode(n) => NodeInfo {
+                name: n.name.clone(),
+                compute: true,
+                attachments: Vec::new(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum GraphError {
+    #[error("the nodes {nodes:?} depend on each other")]
+    Cycle { nodes: Vec<Cow<'static, str>> },
+    #[error("node {node} reads {attachment} before it is written")]
+    ReadBeforeWrite {
+        node: Cow<'static, str>,
+        attachment: Cow<'static, str>,
+    },
+}
+
+/// Result of the compilation, nodes are referenced by their insertion index
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    /// Nodes in execution order
+    pub order: Vec<usize>,
+    /// Nodes whose outputs are never used
+    pub culled: Vec<usize>,
+    /// Layout transitions of every attachment (in the order of NodeInfo::attachments) during the
+    /// pass, indexed by node. Empty for compute and culled nodes
+    pub layouts: Vec<Vec<Range<AttachmentLayout>>>,
+}
+
+/// Orders the nodes from the attachments they read and write, culls nodes that do not contribute
+/// to the backbuffer and computes the layout transitions of the attachments
+///
+/// Writers of an attachment are executed in insertion order, readers after the writer that was
+/// added before them (or the first one) and before the writer that follows it
+pub fn schedule<I: Copy + PartialEq>(
+    nodes: &[NodeInfo<I>],
+    backbuffer: I,
+    attachment_name: impl Fn(I) -> Cow<'static, str>,
+) -> Result<Schedule, GraphError> {
+    let count = nodes.len();
+
+    let mut attachments: Vec<I> = Vec::new();
+    for a in nodes.iter().flat_map(|n| n.attachments.iter()) {
+        if !attachments.contains(&a.index) {
+            attachments.push(a.index);
+        }
+    }
+
+    // Dependencies through attachments, these are also used for culling
+    let mut reads_from: Vec<Vec<usize>> = vec![Vec::new(); count];
+    // Writers wait for the readers of the previous write, this only orders the nodes
+    let mut overwrites: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for attachment in attachments.iter().copied() {
+        let mut writers: Vec<usize> = Vec::new();
+        let mut readers = Vec::new();
+        for (i, node) in nodes.iter().enumerate() {
+            let uses: Vec<&AttachmentUse<I>> = node
+                .attachments
+                .iter()
+                .filter(|a| a.index == attachment)
+                .collect();
+            if uses.iter().any(|a| a.usage.writes()) {
+                let loads = uses
+                    .iter()
+                    .any(|a| a.usage.writes() && matches!(a.load, LoadOp::Load));
+                match writers.last() {
+                    Some(&writer) => reads_from[i].push(writer),
+                    None if loads && attachment != backbuffer => {
+                        return Err(GraphError::ReadBeforeWrite {
+                            node: node.name.clone(),
+                            attachment: attachment_name(attachment),
+                        })
+                    }
+                    None => (),
+                }
+                writers.push(i);
+            } else if !uses.is_empty() {
+                readers.push(i);
+            }
+        }
+        for reader in readers {
+            // The writer before the reader, readers added before any writer read the first one
+            let writer = writers
+                .iter()
+                .rev()
+                .find(|&&w| w < reader)
+                .or_else(|| writers.first());
+            match writer {
+                Some(&writer) => {
+                    reads_from[reader].push(writer);
+                    if let Some(&next) = writers.iter().find(|&&w| w > writer) {
+                        overwrites[next].push(reader);
+                    }
+                }
+                None => {
+                    return Err(GraphError::ReadBeforeWrite {
+                        node: nodes[reader].name.clone(),
+                        attachment: attachment_name(attachment),
+                    })
+                }
+            }
+        }
+    }
+
+    // Compute nodes split the graph, everything that was added before them runs before them
+    let mut dependencies: Vec<Vec<usize>> = reads_from
+        .iter()
+        .zip(overwrites.iter())
+        .map(|(reads, overwrites)| reads.iter().chain(overwrites.iter()).copied().collect())
+        .collect();
+    for (c, _) in nodes.iter().enumerate().filter(|(_, n)| n.compute) {
+        for i in 0..count {
+            if i < c {
+                dependencies[c].push(i);
+            } else if i > c {
+                dependencies[i].push(c);
+            }
+        }
+    }
+
+    // Kahn's algorithm, the insertion order decides between nodes that are ready at the same time
+    let mut order = Vec::with_capacity(count);
+    let mut done = vec![false; count];
+    while order.len() < count {
+        let next =
+            (0..count).find(|&i| !done[i] && dependencies[i].iter().all(|&d| d == i || done[d]));
+        match next {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => {
+                return Err(GraphError::Cycle {
+                    nodes: (0..count)
+                        .filter(|&i| !done[i])
+                        .map(|i| nodes[i].name.clone())
+                        .collect(),
+                })
+            }
+        }
+    }
+
+    // Everything that contributes to the backbuffer (or a compute node) is kept
+    let mut keep: Vec<bool> = nodes
+        .iter()
+        .map(|n| {
+            n.compute
+                || n.attachments
+                    .iter()
+                    .any(|a| a.index == backbuffer && a.usage.writes())
+        })
+        .collect();
+    for &i in order.iter().rev() {
+        if keep[i] {
+            for &d in reads_from[i].iter() {
+                keep[d] = true;
+            }
+        }
+    }
+    let (order, culled): (Vec<usize>, Vec<usize>) = order.into_iter().partition(|&i| keep[i]);
+
+    // Every attachment transitions into the layout of its next use at the end of a pass
+    let mut layouts = vec![Vec::new(); count];
+    let mut current: Vec<(I, AttachmentLayout)> = Vec::new();
+    for (position, &i) in order.iter().enumerate() {
+        layouts[i] = nodes[i]
+            .attachments
+            .iter()
+            .map(|a| {
+                let last = current
+                    .iter()
+                    .find(|(index, _)| *index == a.index)
+                    .map(|(_, layout)| *layout)
+                    .unwrap_or(AttachmentLayout::Undefined);
+                let initial = match (a.usage, &a.load) {
+                    (AttachmentUsage::Input | AttachmentUsage::Sampled, _) | (_, LoadOp::Load) => {
+                        last
+                    }
+                    _ => AttachmentLayout::Undefined,
+                };
+                let next_use = order[position + 1..]
+                    .iter()
+                    .find_map(|&n| nodes[n].attachments.iter().find(|b| b.index == a.index));
+                let last_layout = match next_use {
+                    Some(next) => next.usage.layout(),
+                    None if a.index == backbuffer => AttachmentLayout::Present,
+                    None => a.usage.layout(),
+                };
+                initial..last_layout
+            })
+            .collect();
+        for (a, layouts) in nodes[i].attachments.iter().zip(layouts[i].iter()) {
+            current.retain(|(index, _)| *index != a.index);
+            current.push((a.index, layouts.end));
+        }
+    }
+
+    Ok(Schedule {
+        order,
+        culled,
+        layouts,
+    })
+}
+
+pub struct CompiledNode<G: GraphBuilder + ?Sized> {
+    pub node: Node<G>,
+    /// See Schedule::layouts
+    pub layouts: Vec<Range<AttachmentLayout>>,
+}
+
+pub struct CompiledGraph<G: GraphBuilder + ?Sized> {
+    /// Nodes in execution order
+    pub nodes: Vec<CompiledNode<G>>,
+    /// Backends keep these, so that they can be returned by Graph::into_builder
+    pub culled: Vec<Node<G>>,
+}
+
+/// Schedules the nodes of a graph builder, this is called by the backends during build
+pub fn compile<G: GraphBuilder + ?Sized>(
+    builder: &G,
+    nodes: Vec<Node<G>>,
+) -> Result<CompiledGraph<G>, GraphError> {
+    let infos: Vec<_> = nodes.iter().map(Node::info).collect();
+    let Schedule {
+        order,
+        culled,
+        mut layouts,
+    } = schedule(&infos, builder.get_backbuffer_attachment(), |index| {
+        builder.attachment_name(index)
+    })?;
+
+    for i in culled.iter() {
+        log::info!("[Graph] (compile) culled node {}", infos[*i].name);
+    }
+
+    let mut nodes: Vec<Option<Node<G>>> = nodes.into_iter().map(Some).collect();
+    let mut take = |i: usize| {
+        nodes[i]
+            .take()
+            .expect("[Graph] (compile) node scheduled twice")
+    };
+    Ok(CompiledGraph {
+        nodes: order
+            .into_iter()
+            .map(|i| CompiledNode {
+                node: take(i),
+                layouts: std::mem::take(&mut layouts[i]),
+            })
+            .collect(),
+        culled: culled.into_iter().map(take).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKBUFFER: u32 = 0;
+    const GBUFFER: u32 = 1;
+    const DEPTH: u32 = 2;
+    const UNUSED: u32 = 3;
+
+    fn node(name: &'static str, attachments: &[(u32, AttachmentUsage, LoadOp)]) -> NodeInfo<u32> {
+        NodeInfo {
+            name: name.into(),
+            compute: false,
+            attachments: attachments
+                .iter()
+                .map(|(index, usage, load)| AttachmentUse {
+                    index: *index,
+                    usage: *usage,
+                    load: load.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn compute(name: &'static str) -> NodeInfo<u32> {
+        NodeInfo {
+            name: name.into(),
+            compute: true,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn run(nodes: &[NodeInfo<u32>]) -> Result<Schedule, GraphError> {
+        schedule(nodes, BACKBUFFER, |index| format!("#{}", index).into())
+    }
+
+    use AttachmentLayout::*;
+    use AttachmentUsage::*;
+
+    #[test]
+    fn sorts_and_transitions_attachments() {
+        let nodes = [
+            node(
+                "composite",
+                &[
+                    (BACKBUFFER, Color, LoadOp::Clear),
+                    (GBUFFER, Input, LoadOp::Load),
+                ],
+            ),
+            node(
+                "geometry",
+                &[
+                    (GBUFFER, Color, LoadOp::Clear),
+                    (DEPTH, Depth, LoadOp::Clear),
+                ],
+            ),
+        ];
+        let schedule = run(&nodes).unwrap();
+        assert_eq!(schedule.order, vec![1, 0]);
+        assert!(schedule.culled.is_empty());
+        assert_eq!(
+            schedule.layouts[1],
+            vec![Undefined..ShaderReadOnly, Undefined..DepthStencilAttachment]
+        );
+        assert_eq!(
+            schedule.layouts[0],
+            vec![Undefined..Present, ShaderReadOnly..ShaderReadOnly]
+        );
+    }
+
+    #[test]
+    fn sampled_attachments_order_passes() {
+        const SHADOW: u32 = 4;
+        let nodes = [
+            node(
+                "main",
+                &[
+                    (BACKBUFFER, Color, LoadOp::Clear),
+                    (DEPTH, Depth, LoadOp::Clear),
+                    (SHADOW, Sampled, LoadOp::Load),
+                ],
+            ),
+            node("shadow", &[(SHADOW, Depth, LoadOp::Clear)]),
+        ];
+        let schedule = run(&nodes).unwrap();
+        assert_eq!(schedule.order, vec![1, 0]);
+        assert!(schedule.culled.is_empty());
+        assert_eq!(schedule.layouts[1], vec![Undefined..ShaderReadOnly]);
+        assert_eq!(schedule.layouts[0][2], ShaderReadOnly..ShaderReadOnly);
+    }
+
+    #[test]
+    fn readers_see_the_write_before_them() {
+        let nodes = [
+            node("geometry", &[(GBUFFER, Color, LoadOp::Clear)]),
+            node(
+                "composite",
+                &[
+                    (BACKBUFFER, Color, LoadOp::Clear),
+                    (GBUFFER, Sampled, LoadOp::Load),
+                ],
+            ),
+            node("decals", &[(GBUFFER, Color, LoadOp::Load)]),
+            node(
+                "overlay",
+                &[
+                    (BACKBUFFER, Color, LoadOp::Load),
+                    (GBUFFER, Sampled, LoadOp::Load),
+                ],
+            ),
+        ];
+        let schedule = run(&nodes).unwrap();
+        // The decals may only overwrite the gbuffer after it was composited
+        assert_eq!(schedule.order, vec![0, 1, 2, 3]);
+        assert!(schedule.culled.is_empty());
+    }
+
+    #[test]
+    fn culls_unused_passes() {
+        let nodes = [
+            node("debug", &[(UNUSED, Color, LoadOp::Clear)]),
+            node("main", &[(BACKBUFFER, Color, LoadOp::Clear)]),
+            node("overlay", &[(BACKBUFFER, Color, LoadOp::Load)]),
+        ];
+        let schedule = run(&nodes).unwrap();
+        assert_eq!(schedule.order, vec![1, 2]);
+        assert_eq!(schedule.culled, vec![0]);
+        assert_eq!(schedule.layouts[1], vec![Undefined..ColorAttachment]);
+        assert_eq!(schedule.layouts[2], vec![ColorAttachment..Present]);
+    }
+
+    #[test]
+    fn keeps_compute_nodes_in_place() {
+        let nodes = [
+            compute("simulate"),
+            node("main", &[(BACKBUFFER, Color, LoadOp::Clear)]),
+            compute("readback"),
+        ];
+        let schedule = run(&nodes).unwrap();
+        assert_eq!(schedule.order, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let cycle = [
+            node(
+                "a",
+                &[
+                    (GBUFFER, Input, LoadOp::Load),
+                    (DEPTH, Color, LoadOp::Clear),
+                ],
+            ),
+            node(
+                "b",
+                &[
+                    (DEPTH, Input, LoadOp::Load),
+                    (GBUFFER, Color, LoadOp::Clear),
+                ],
+            ),
+            node("main", &[(BACKBUFFER, Color, LoadOp::Clear)]),
+        ];
+        assert_eq!(
+            run(&cycle),
+            Err(GraphError::Cycle {
+                nodes: vec!["a".into(), "b".into()]
+            })
+        );
+
+        let read_before_write = [node(
+            "main",
+            &[
+                (BACKBUFFER, Color, LoadOp::Clear),
+                (GBUFFER, Input, LoadOp::Load),
+            ],
+        )];
+        assert_eq!(
+            run(&read_before_write),
+            Err(GraphError::ReadBeforeWrite {
+                node: "main".into(),
+                attachment: "#1".into(),
+            })
+        );
+    }
+}
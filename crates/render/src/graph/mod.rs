@@ -2,12 +2,14 @@ use app::{Resources, World};
 
 use crate::prelude::GpuContext;
 
-use self::builder::GraphBuilder;
+use self::{builder::GraphBuilder, readback::Readback};
 
 pub mod attachment;
 pub mod builder;
+pub mod compile;
 pub mod node;
 pub mod nodes;
+pub mod readback;
 
 // TODO: Send + Sync (mainly pass callbacks are destroying that currently)
 pub trait Graph {
@@ -17,6 +19,10 @@ pub trait Graph {
 
     fn execute(&mut self, world: &World, resources: &Resources);
 
+    /// Copies the attachment into host memory at the end of the next execution, the readback is
+    /// completed once that frame finished rendering
+    fn read_attachment(&mut self, index: Self::AttachmentIndex) -> Readback;
+
     /// This function will remove all the prebuild things and strips down to the builder graph
     fn into_builder(self) -> Self::Builder;
 }
@@ -1,10 +1,14 @@
-use super::{builder::GraphBuilder, nodes::pass::PassNode};
+use super::{
+    builder::GraphBuilder,
+    nodes::{compute::ComputeNode, pass::PassNode},
+};
 
 pub enum Node<G>
 where
     G: GraphBuilder + ?Sized,
 {
     PassNode(PassNode<G>),
+    ComputeNode(ComputeNode<G>),
 }
 
 // pub trait Node: Downcast {
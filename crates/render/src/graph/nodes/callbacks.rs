@@ -12,6 +12,12 @@ pub struct FrameData<'a, Context: GpuContext> {
     pub cmd: &'a mut <Context as GpuContext>::CommandEncoder,
     pub frame_index: u32,
     pub viewport: Viewport,
+    /// Textures of the sampled attachments of a pass node, in the order they were added.
+    /// Absolute sized attachments keep their texture, relative ones change with the surface
+    pub sampled: Vec<&'a <Context as GpuContext>::TextureHandle>,
+    /// Textures of the input attachments of a pass node, in the order they were added. They are
+    /// bound like sampled textures, but only the texel of the fragment may be read
+    pub inputs: Vec<&'a <Context as GpuContext>::TextureHandle>,
 }
 
 pub type PassReturn = Option<Box<dyn Any>>;
@@ -21,6 +27,7 @@ pub trait InitCallback<Context: GpuContext, U> =
 pub trait PassCallback<Context: GpuContext, U> =
     FnMut(FrameData<'_, Context>, &mut U, &World, &Resources) -> anyhow::Result<PassReturn>;
 pub trait UserData = Send + Sync + 'static;
+pub trait ComputeInitCallback<U> = Fn() -> Box<U>;
 
 pub trait PassCallbacks<Context: GpuContext> {
     fn init(&mut self, render_pass: Arc<<Context as GpuContext>::RenderPassHandle>);
@@ -69,3 +76,53 @@ impl<Context: GpuContext, U: UserData> PassCallbacks<Context> for PassCallbacksI
         self.runner.deref_mut()(frame_data, data, world, resources)
     }
 }
+
+pub trait ComputeCallbacks<Context: GpuContext> {
+    fn init(&mut self);
+    fn run(
+        &mut self,
+        data: FrameData<Context>,
+        world: &World,
+        resources: &Resources,
+    ) -> anyhow::Result<PassReturn>;
+}
+
+pub struct ComputeCallbacksImpl<Context: GpuContext, U: UserData + ?Sized> {
+    init: Box<dyn ComputeInitCallback<U>>,
+    runner: Box<dyn PassCallback<Context, U>>,
+    user_data: Option<Box<U>>,
+}
+
+impl<Context: GpuContext, U: UserData> ComputeCallbacksImpl<Context, U> {
+    pub(crate) fn create(
+        init: Box<dyn ComputeInitCallback<U>>,
+        runner: Box<dyn PassCallback<Context, U>>,
+    ) -> Self {
+        Self {
+            init,
+            runner,
+            user_data: None,
+        }
+    }
+}
+
+impl<Context: GpuContext, U: UserData> ComputeCallbacks<Context>
+    for ComputeCallbacksImpl<Context, U>
+{
+    fn init(&mut self) {
+        self.user_data = Some(self.init.deref()());
+    }
+
+    fn run(
+        &mut self,
+        frame_data: FrameData<Context>,
+        world: &World,
+        resources: &Resources,
+    ) -> anyhow::Result<PassReturn> {
+        let data = self
+            .user_data
+            .as_mut()
+            .expect("[ComputeCallbacks] no user data, did u call init for this node?");
+        self.runner.deref_mut()(frame_data, data, world, resources)
+    }
+}
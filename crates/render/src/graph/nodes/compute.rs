@@ -0,0 +1,256 @@
+use std::{borrow::Cow, cell::RefCell, ops::Range, sync::Arc};
+
+use crate::{
+    context::GpuContext,
+    graph::builder::GraphBuilder,
+    resource::{buffer::Buffer, texture::Texture},
+};
+
+use super::callbacks::{
+    ComputeCallbacks, ComputeCallbacksImpl, ComputeInitCallback, PassCallback, UserData,
+};
+
+/// A resource that is accessed by a compute node
+///
+/// The graph only needs to know about them to place the barriers, binding them to the pipeline is
+/// still done with glue in the callback
+pub enum ComputeResource<Context: GpuContext> {
+    Buffer(Arc<Buffer<Context>>),
+    /// Only storage textures (see TextureDescriptor::storage) can be outputs
+    Texture(Arc<Texture<Context>>),
+}
+
+impl<Context: GpuContext> ComputeResource<Context> {
+    /// Resources are compared by identity, not by content
+    pub fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Buffer(a), Self::Buffer(b)) => Arc::ptr_eq(a, b),
+            (Self::Texture(a), Self::Texture(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl<Context: GpuContext> Clone for ComputeResource<Context> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Buffer(b) => Self::Buffer(b.clone()),
+            Self::Texture(t) => Self::Texture(t.clone()),
+        }
+    }
+}
+
+impl<Context: GpuContext> From<Arc<Buffer<Context>>> for ComputeResource<Context> {
+    fn from(buffer: Arc<Buffer<Context>>) -> Self {
+        Self::Buffer(buffer)
+    }
+}
+
+impl<Context: GpuContext> From<Arc<Texture<Context>>> for ComputeResource<Context> {
+    fn from(texture: Arc<Texture<Context>>) -> Self {
+        Self::Texture(texture)
+    }
+}
+
+pub struct ComputeNode<G: GraphBuilder + ?Sized> {
+    pub name: Cow<'static, str>,
+    pub inputs: Vec<ComputeResource<<G as GraphBuilder>::Context>>,
+    pub outputs: Vec<ComputeResource<<G as GraphBuilder>::Context>>,
+    pub callbacks: RefCell<Box<dyn ComputeCallbacks<<G as GraphBuilder>::Context>>>,
+}
+
+//
+// Barriers
+//
+
+/// How a resource is accessed during the execution of a graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceAccess {
+    /// Everything outside of compute nodes: uploads, pass nodes and previous frames
+    External,
+    ComputeRead,
+    ComputeWrite,
+}
+
+pub struct ResourceBarrier<Context: GpuContext> {
+    pub resource: ComputeResource<Context>,
+    pub states: Range<ResourceAccess>,
+}
+
+/// Infers the barriers around compute nodes from their declared inputs and outputs
+///
+/// Backends create one per execution, call compute_node before every compute node and external
+/// before every pass node and at the end of the frame
+pub struct BarrierTracker<Context: GpuContext> {
+    /// Resources that were accessed by a compute node since the last call to external
+    accesses: Vec<(ComputeResource<Context>, ResourceAccess)>,
+}
+
+impl<Context: GpuContext> Default for BarrierTracker<Context> {
+    fn default() -> Self {
+        Self {
+            accesses: Vec::new(),
+        }
+    }
+}
+
+impl<Context: GpuContext> BarrierTracker<Context> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn transition(
+        &mut self,
+        resource: &ComputeResource<Context>,
+        access: ResourceAccess,
+    ) -> Option<ResourceBarrier<Context>> {
+        let position = self.accesses.iter().position(|(r, _)| r.same(resource));
+        let last = position
+            .map(|p| self.accesses[p].1)
+            .unwrap_or(ResourceAccess::External);
+
+        let needed = match (last, access) {
+            // Reads only wait for writes
+            (ResourceAccess::ComputeWrite, ResourceAccess::ComputeRead) => true,
+            (_, ResourceAccess::ComputeRead) => false,
+            // Writes wait for everything before them
+            (_, ResourceAccess::ComputeWrite) => true,
+            (last, ResourceAccess::External) => last != ResourceAccess::External,
+        };
+
+        match position {
+            Some(p) => self.accesses[p].1 = access,
+            None => self.accesses.push((resource.clone(), access)),
+        }
+
+        if needed {
+            Some(ResourceBarrier {
+                resource: resource.clone(),
+                states: last..access,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Barriers that are needed before the node is executed
+    pub fn compute_node<G>(&mut self, node: &ComputeNode<G>) -> Vec<ResourceBarrier<Context>>
+    where
+        G: GraphBuilder<Context = Context> + ?Sized,
+    {
+        // Resources that are read and written only need the write barrier
+        let reads = node
+            .inputs
+            .iter()
+            .filter(|i| !node.outputs.iter().any(|o| o.same(i)))
+            .map(|r| (r, ResourceAccess::ComputeRead));
+        let writes = node
+            .outputs
+            .iter()
+            .map(|r| (r, ResourceAccess::ComputeWrite));
+
+        reads
+            .chain(writes)
+            .filter_map(|(resource, access)| self.transition(resource, access))
+            .collect()
+    }
+
+    /// Barriers that make the results of the compute nodes visible to everything else
+    pub fn external(&mut self) -> Vec<ResourceBarrier<Context>> {
+        let resources: Vec<ComputeResource<Context>> =
+            self.accesses.iter().map(|(r, _)| r.clone()).collect();
+        let barriers = resources
+            .iter()
+            .filter_map(|r| self.transition(r, ResourceAccess::External))
+            .collect();
+        self.accesses.clear();
+        barriers
+    }
+}
+
+//
+// Builder for Compute Nodes
+//
+
+pub struct ComputeNodeBuilder<G: GraphBuilder + ?Sized, U: UserData> {
+    name: Cow<'static, str>,
+    inputs: Vec<ComputeResource<<G as GraphBuilder>::Context>>,
+    outputs: Vec<ComputeResource<<G as GraphBuilder>::Context>>,
+    init: Option<Box<dyn ComputeInitCallback<U>>>,
+    cb: Option<Box<dyn PassCallback<<G as GraphBuilder>::Context, U>>>,
+}
+
+impl<G: GraphBuilder + ?Sized, U: UserData> ComputeNodeBuilder<G, U> {
+    pub fn new(name: Cow<'static, str>) -> Self {
+        Self {
+            name,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            init: None,
+            cb: None,
+        }
+    }
+
+    /// A resource that is only read by the node
+    pub fn add_input(
+        &mut self,
+        resource: impl Into<ComputeResource<<G as GraphBuilder>::Context>>,
+    ) -> &mut Self {
+        self.inputs.push(resource.into());
+        self
+    }
+
+    /// A resource that is written (and possibly read) by the node
+    pub fn add_output(
+        &mut self,
+        resource: impl Into<ComputeResource<<G as GraphBuilder>::Context>>,
+    ) -> &mut Self {
+        let resource = resource.into();
+        if let ComputeResource::Texture(texture) = &resource {
+            assert!(
+                texture.desc().storage,
+                "[ComputeNodeBuilder] (add_output) {} is not a storage texture",
+                texture.desc().name
+            );
+        }
+        self.outputs.push(resource);
+        self
+    }
+
+    pub fn init(&mut self, func: Box<dyn ComputeInitCallback<U> + 'static>) -> &mut Self {
+        self.init = Some(func);
+        self
+    }
+
+    pub fn callback(
+        &mut self,
+        func: Box<dyn PassCallback<<G as GraphBuilder>::Context, U> + 'static>,
+    ) -> &mut Self {
+        self.cb = Some(func);
+        self
+    }
+
+    pub fn build(&mut self) -> ComputeNode<G>
+    where
+        <G as GraphBuilder>::Context: 'static,
+    {
+        let init = self
+            .init
+            .take()
+            .expect("[ComputeNodeBuilder] (build) no init callback");
+
+        let runner = self
+            .cb
+            .take()
+            .expect("[ComputeNodeBuilder] (build) no callback");
+
+        let callbacks = ComputeCallbacksImpl::create(init, runner);
+
+        ComputeNode {
+            name: self.name.clone(),
+            inputs: self.inputs.drain(..).collect(),
+            outputs: self.outputs.drain(..).collect(),
+            callbacks: RefCell::new(Box::new(callbacks)),
+        }
+    }
+}
@@ -1,2 +1,3 @@
 pub mod pass;
 pub mod callbacks;
+pub mod compute;
@@ -14,11 +14,26 @@ pub struct PassAttachment<I: Clone + Copy> {
     pub store: StoreOp,
 }
 
+/// Multisampled output of a pass, that is resolved into a single sampled attachment at the end of
+/// the pass
+#[derive(Clone)]
+pub struct PassResolve<I: Clone + Copy> {
+    pub source: I,
+    pub target: I,
+}
+
 pub struct PassNode<G: GraphBuilder + ?Sized> {
     pub name: Cow<'static, str>,
     pub output_attachments: Vec<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
+    /// Attachments of previous passes that are part of the render pass, but only read at the
+    /// texel of the fragment (see FrameData::inputs)
     pub input_attachments: Vec<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
     pub depth_attachment: Option<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
+    /// Attachments of previous passes that are read through samplers, they are not part of the
+    /// render pass (see FrameData::sampled)
+    pub sampled_attachments: Vec<<G as GraphBuilder>::AttachmentIndex>,
+    /// Targets of the resolves are part of the render pass, after the depth attachment
+    pub resolve_attachments: Vec<PassResolve<<G as GraphBuilder>::AttachmentIndex>>,
     pub callbacks: RefCell<Box<dyn PassCallbacks<<G as GraphBuilder>::Context>>>,
 }
 
@@ -49,6 +64,8 @@ pub struct PassNodeBuilder<G: GraphBuilder + ?Sized, U: UserData> {
     output_attachments: Vec<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
     input_attachments: Vec<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
     depth_attachment: Option<PassAttachment<<G as GraphBuilder>::AttachmentIndex>>,
+    sampled_attachments: Vec<<G as GraphBuilder>::AttachmentIndex>,
+    resolve_attachments: Vec<PassResolve<<G as GraphBuilder>::AttachmentIndex>>,
     init: Option<Box<dyn InitCallback<<G as GraphBuilder>::Context, U>>>,
     cb: Option<Box<dyn PassCallback<<G as GraphBuilder>::Context, U>>>,
 }
@@ -60,6 +77,8 @@ impl<G: GraphBuilder + ?Sized, U: UserData> PassNodeBuilder<G, U> {
             output_attachments: Vec::new(),
             input_attachments: Vec::new(),
             depth_attachment: None,
+            sampled_attachments: Vec::new(),
+            resolve_attachments: Vec::new(),
             init: None,
             cb: None,
         }
@@ -76,6 +95,7 @@ impl<G: GraphBuilder + ?Sized, U: UserData> PassNodeBuilder<G, U> {
         self
     }
 
+    /// Reads the attachment at the texel of the fragment, eg. the hdr color of a tone mapping pass
     pub fn add_input(
         &mut self,
         index: <G as GraphBuilder>::AttachmentIndex,
@@ -97,6 +117,24 @@ impl<G: GraphBuilder + ?Sized, U: UserData> PassNodeBuilder<G, U> {
         self
     }
 
+    /// Reads the attachment through a sampler, eg. the depth of a shadow pass
+    pub fn add_sampled(&mut self, index: <G as GraphBuilder>::AttachmentIndex) -> &mut Self {
+        self.sampled_attachments.push(index);
+        self
+    }
+
+    /// Resolves a multisampled output into a single sampled attachment at the end of the pass,
+    /// eg. the scene color into the backbuffer
+    pub fn add_resolve(
+        &mut self,
+        source: <G as GraphBuilder>::AttachmentIndex,
+        target: <G as GraphBuilder>::AttachmentIndex,
+    ) -> &mut Self {
+        self.resolve_attachments
+            .push(PassResolve { source, target });
+        self
+    }
+
     pub fn init(
         &mut self,
         func: Box<dyn InitCallback<<G as GraphBuilder>::Context, U> + 'static>,
@@ -134,6 +172,8 @@ impl<G: GraphBuilder + ?Sized, U: UserData> PassNodeBuilder<G, U> {
             output_attachments: self.output_attachments.drain(..).collect(),
             input_attachments: self.input_attachments.drain(..).collect(),
             depth_attachment: self.depth_attachment.take(),
+            sampled_attachments: self.sampled_attachments.drain(..).collect(),
+            resolve_attachments: self.resolve_attachments.drain(..).collect(),
             callbacks: RefCell::new(Box::new(callbacks)),
         }
     }
@@ -0,0 +1,103 @@
+//! Copies of attachments in host memory, eg. for screenshots or image based tests
+
+use std::sync::Arc;
+
+use app::core::thiserror::{self, Error};
+use parking_lot::Mutex;
+
+use crate::{resource::frame::Extent2D, util::format::TextureFormat};
+
+/// Texels of an attachment, row by row starting at the top without any padding
+#[derive(Debug, Clone)]
+pub struct ReadbackImage {
+    pub extent: Extent2D,
+    /// Layout of a single texel in data
+    pub format: TextureFormat,
+    pub data: Vec<u8>,
+}
+
+impl ReadbackImage {
+    /// The texels as 8 bit rgba, None if the format has no 8 bit color channels
+    pub fn to_rgba8(&self) -> Option<Vec<u8>> {
+        match self.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8Srgb => Some(self.data.clone()),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8Srgb => {
+                let mut rgba = self.data.clone();
+                rgba.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+                Some(rgba)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ReadbackError {
+    #[error("the backbuffer can only be read back when the graph renders offscreen")]
+    Backbuffer,
+    #[error("attachment {0} is not part of the graph")]
+    UnknownAttachment(String),
+    #[error("texels of format {0:?} can not be read back by this backend")]
+    Format(TextureFormat),
+    #[error("multisampled attachments can only be read back through their resolve target")]
+    Multisampled,
+}
+
+type ReadbackResult = Result<ReadbackImage, ReadbackError>;
+
+/// Handle to a copy requested by Graph::read_attachment, which the graph completes once the frame
+/// finished rendering
+///
+/// Clones refer to the same copy
+#[derive(Debug, Clone, Default)]
+pub struct Readback(Arc<Mutex<Option<ReadbackResult>>>);
+
+impl Readback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn complete(&self, result: ReadbackResult) {
+        *self.0.lock() = Some(result);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.lock().is_some()
+    }
+
+    /// The result of the copy, None while the frame is still in flight (or it was taken)
+    pub fn take(&self) -> Option<ReadbackResult> {
+        self.0.lock().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_and_swizzles() {
+        let readback = Readback::new();
+        let handle = readback.clone();
+        assert!(handle.take().is_none());
+
+        readback.complete(Ok(ReadbackImage {
+            extent: Extent2D {
+                width: 2,
+                height: 1,
+            },
+            format: TextureFormat::Bgra8Srgb,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        }));
+        assert!(handle.is_ready());
+        let image = handle.take().unwrap().unwrap();
+        assert_eq!(image.to_rgba8(), Some(vec![3, 2, 1, 4, 7, 6, 5, 8]));
+        assert!(handle.take().is_none());
+
+        let depth = ReadbackImage {
+            format: TextureFormat::Depth32Sfloat,
+            ..image
+        };
+        assert_eq!(depth.to_rgba8(), None);
+    }
+}
@@ -15,8 +15,12 @@ pub mod prelude {
         buffer::{Buffer, BufferRange, BufferUsage, MemoryType},
         frame::Extent3D,
         glue::{Glue, GlueBottle, MixturePart, PartIndex, PartType},
-        pipeline::{GraphicsPipelineDescriptor, ShaderSource, ShaderType},
+        pipeline::{
+            ComputePipeline, ComputePipelineDescriptor, GraphicsPipelineDescriptor, ShaderSource,
+            ShaderType,
+        },
         swap_buffer::SwapBuffer,
+        texture::{Sampler, SamplerDescriptor, Texture, TextureDescriptor},
     };
     pub use crate::resources::GpuResources;
     pub use crate::{mixture, parse_line};
@@ -47,6 +47,8 @@ pub enum BufferUsage {
     Vertex,
     Index,
     Staging,
+    /// Read and written by compute shaders, can also be bound as vertex buffer afterwards
+    Storage,
 }
 
 #[derive(Clone, Debug)]
@@ -8,6 +8,10 @@ use crate::resource::buffer::BufferRange;
 pub enum PartType {
     Uniform(usize),
     Sampler,
+    /// Read-write buffer of arbitrary size (eg. a runtime sized array)
+    StorageBuffer,
+    /// Read-write image, bound without sampler
+    StorageImage,
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +73,11 @@ impl<Context: GpuContext> Mixture<Context> {
 #[derive(Debug)]
 pub enum Descriptor<'a, Context: GpuContext + ?Sized> {
     Buffer(&'a <Context as GpuContext>::BufferHandle, BufferRange),
+    Texture(
+        &'a <Context as GpuContext>::TextureHandle,
+        &'a <Context as GpuContext>::SamplerHandle,
+    ),
+    StorageImage(&'a <Context as GpuContext>::TextureHandle),
 }
 
 #[derive(Debug)]
@@ -141,8 +150,24 @@ impl<'a, Context: GpuContext> GlueBottle<'a, Context> {
                 };
                 self.writes.push(write);
             }
-            PartType::Sampler => {
-                panic!("[GlueBottle] (write_buffer) mismatch descriptor is sampler")
+            PartType::StorageBuffer => {
+                // Storage buffers are bound from the offset to the end of the buffer
+                let range = BufferRange {
+                    offset: buffer_offset.unwrap_or(0),
+                    size: None,
+                };
+                let write = DescriptorWrite {
+                    binding: part.binding,
+                    array_offset: 0,
+                    descriptor: Descriptor::Buffer(buffer, range),
+                };
+                self.writes.push(write);
+            }
+            PartType::Sampler | PartType::StorageImage => {
+                panic!(
+                    "[GlueBottle] (write_buffer) mismatch descriptor is {:?}",
+                    part.type_info
+                )
             }
         }
     }
@@ -175,8 +200,72 @@ impl<'a, Context: GpuContext> GlueBottle<'a, Context> {
                 };
                 self.writes.push(write);
             }
+            PartType::Sampler | PartType::StorageBuffer | PartType::StorageImage => {
+                panic!(
+                    "[GlueBottle] (write_array) mismatch descriptor is {:?}",
+                    part.type_info
+                )
+            }
+        }
+    }
+
+    pub fn write_texture(
+        &mut self,
+        index: PartIndex,
+        texture: &'a Context::TextureHandle,
+        sampler: &'a Context::SamplerHandle,
+    ) {
+        let part = self
+            .parts
+            .iter()
+            .find(|p| match &index {
+                PartIndex::Name(name) => &p.name == name,
+                PartIndex::Binding(id) => &p.binding == id,
+            })
+            .expect("[GlueBottle] (write_texture) failed to find matching part index");
+
+        match part.type_info {
             PartType::Sampler => {
-                panic!("[GlueBottle] (write_array) mismatch descriptor is sampler")
+                let write = DescriptorWrite {
+                    binding: part.binding,
+                    array_offset: 0,
+                    descriptor: Descriptor::Texture(texture, sampler),
+                };
+                self.writes.push(write);
+            }
+            PartType::Uniform(_) | PartType::StorageBuffer | PartType::StorageImage => {
+                panic!(
+                    "[GlueBottle] (write_texture) mismatch descriptor is {:?}",
+                    part.type_info
+                )
+            }
+        }
+    }
+
+    pub fn write_storage_image(&mut self, index: PartIndex, texture: &'a Context::TextureHandle) {
+        let part = self
+            .parts
+            .iter()
+            .find(|p| match &index {
+                PartIndex::Name(name) => &p.name == name,
+                PartIndex::Binding(id) => &p.binding == id,
+            })
+            .expect("[GlueBottle] (write_storage_image) failed to find matching part index");
+
+        match part.type_info {
+            PartType::StorageImage => {
+                let write = DescriptorWrite {
+                    binding: part.binding,
+                    array_offset: 0,
+                    descriptor: Descriptor::StorageImage(texture),
+                };
+                self.writes.push(write);
+            }
+            PartType::Uniform(_) | PartType::Sampler | PartType::StorageBuffer => {
+                panic!(
+                    "[GlueBottle] (write_storage_image) mismatch descriptor is {:?}",
+                    part.type_info
+                )
             }
         }
     }
@@ -2,7 +2,9 @@ pub mod buffer;
 pub mod frame;
 pub mod glue;
 pub mod pipeline;
+pub mod reflect;
 // TODO: After implementing the render graph this should not be public api
 pub mod render_pass;
 
 pub mod swap_buffer;
+pub mod texture;
@@ -22,7 +22,7 @@ pub enum ShaderSource<'a> {
     Spirv(Vec<u32>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
@@ -102,7 +102,7 @@ impl VertexBufferDescriptor {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VertexAttributeFormat {
     Vec2,
     Vec3,
@@ -267,12 +267,23 @@ pub struct GraphicsPipelineDescriptor<'a, Context: GpuContext> {
     /// A Vec representing if a color attachment should use alpha blending or not
     pub blend_targets: Vec<bool>,
     /// Enable a depth testing function
-    pub depth: Option<DepthDescriptor>, // TODO: Multisampling
+    pub depth: Option<DepthDescriptor>,
+    /// Samples per texel of the attachments of the render pass, 1 disables multisampling
+    pub samples: u8,
     /// The viewport for this pipeline
     pub pipeline_states: PipelineStates,
     // TODO: Descriptors? !!!!
 }
 
+#[derive(Debug)]
+pub struct ComputePipelineDescriptor<'a, Context: GpuContext> {
+    pub name: Cow<'static, str>,
+    pub mixtures: Vec<&'a Mixture<Context>>,
+    /// Push Constants (all of them are visible to the compute stage)
+    pub push_constants: Vec<Range<u32>>,
+    pub shader: &'a <Context as GpuContext>::ShaderCode,
+}
+
 #[derive(Debug)]
 pub enum RenderContext<'a, Context: GpuContext + ?Sized> {
     RenderPass((&'a <Context as GpuContext>::RenderPassHandle, SubpassId)),
@@ -325,6 +336,48 @@ impl<Context: GpuContext> Drop for GraphicsPipeline<Context> {
     }
 }
 
+#[derive(Debug)]
+pub struct ComputePipeline<Context: GpuContext> {
+    name: Cow<'static, str>,
+    ctx: Arc<Context>,
+    handle: ManuallyDrop<Context::ComputePipelineHandle>,
+}
+
+impl<Context: GpuContext> ComputePipeline<Context> {
+    pub fn new(
+        name: Cow<'static, str>,
+        handle: Context::ComputePipelineHandle,
+        ctx: Arc<Context>,
+    ) -> Self {
+        Self {
+            name,
+            ctx,
+            handle: ManuallyDrop::new(handle),
+        }
+    }
+
+    pub fn get_handle(&self) -> &Context::ComputePipelineHandle {
+        self.handle.deref()
+    }
+}
+
+impl<Context: GpuContext> Deref for ComputePipeline<Context> {
+    type Target = Context::ComputePipelineHandle;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.deref()
+    }
+}
+
+impl<Context: GpuContext> Drop for ComputePipeline<Context> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ctx
+                .drop_compute_pipeline(ManuallyDrop::take(&mut self.handle));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::command_encoder::*;
@@ -399,6 +452,7 @@ mod tests {
             let render_pass = {
                 let color_attachment = Attachment {
                     format: ctx.get_surface_format(),
+                    samples: 1,
                     load_op: LoadOp::Clear,
                     store_op: StoreOp::Store,
                     layouts: TextureLayout::Undefined..TextureLayout::Present,
@@ -449,6 +503,7 @@ mod tests {
                 primitive: Primitive::TriangleList,
                 blend_targets: vec![true],
                 depth: None,
+                samples: 1,
                 pipeline_states: PipelineStates {
                     viewport: PipelineState::Dynamic,
                     scissor: PipelineState::Dynamic,
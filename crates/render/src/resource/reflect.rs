@@ -0,0 +1,1079 @@
+//! Reflection of SPIR-V shaders
+//!
+//! Extracts the interface of a shader (descriptor bindings, uniform block sizes, push constants
+//! and vertex inputs) so that mixtures can be generated from it and pipeline descriptors can be
+//! checked against the shaders they use. Only the subset of SPIR-V needed for that is parsed.
+
+use std::{collections::HashMap, fmt, ops::Range};
+
+use app::core::thiserror::{self, Error};
+
+use crate::{
+    context::GpuContext,
+    resource::{
+        glue::{MixturePart, PartType},
+        pipeline::{
+            AttributeDescriptor, GraphicsPipelineDescriptor, ShaderType, VertexAttributeFormat,
+        },
+    },
+};
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+mod op {
+    pub const NAME: u32 = 5;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+}
+
+mod decoration {
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+#[derive(Debug, Error)]
+pub enum ReflectError {
+    #[error("not a SPIR-V module (magic number {0:#x})")]
+    InvalidMagic(u32),
+    #[error("SPIR-V module is truncated at word {0}")]
+    Truncated(usize),
+    #[error("instruction with opcode {0} is missing operands")]
+    MissingOperand(u32),
+    #[error("SPIR-V module has no entry point")]
+    NoEntryPoint,
+    #[error("unsupported execution model {0}")]
+    UnsupportedStage(u32),
+    #[error("id {0} is not a known type")]
+    UnknownType(u32),
+    #[error("member {member} of {name} has no offset")]
+    MissingOffset { name: String, member: u32 },
+    #[error("type {0} can not be used in a buffer")]
+    UnsizedType(u32),
+    #[error(
+        "binding {binding} of set {set} is used by the {first:?} and the {second:?} shader, but a \
+         mixture part can only be visible to a single stage"
+    )]
+    SharedBinding {
+        set: u32,
+        binding: u32,
+        first: ShaderType,
+        second: ShaderType,
+    },
+}
+
+/// A descriptor binding of a shader
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    /// Name of the variable, or of the block if the variable has none
+    pub name: String,
+    /// The size of uniforms is the size of the block in bytes
+    pub type_info: PartType,
+    pub array_size: usize,
+}
+
+/// A vertex input of a shader
+#[derive(Debug, Clone)]
+pub struct ReflectedInput {
+    pub location: u32,
+    pub name: String,
+    /// `None` if the type has no equivalent `VertexAttributeFormat`
+    pub format: Option<VertexAttributeFormat>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub stage: ShaderType,
+    pub entry_point: String,
+    pub bindings: Vec<ReflectedBinding>,
+    /// Byte range of the push constant block (from the first member that is used)
+    pub push_constants: Option<Range<u32>>,
+    /// Inputs of the shader, only filled for vertex shaders
+    pub inputs: Vec<ReflectedInput>,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Bool,
+    Scalar { width: u32, float: bool },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { storage: bool },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Debug, Default)]
+struct Decorations {
+    buffer_block: bool,
+    built_in: bool,
+    array_stride: Option<u32>,
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+struct Variable {
+    id: u32,
+    pointer: u32,
+    storage: u32,
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, String)>,
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+}
+
+/// Decodes a nul terminated literal string
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, ReflectError> {
+        if words.len() < HEADER_LEN {
+            return Err(ReflectError::Truncated(words.len()));
+        }
+        if words[0] != MAGIC {
+            return Err(ReflectError::InvalidMagic(words[0]));
+        }
+
+        let mut module = Module::default();
+        let mut position = HEADER_LEN;
+        while position < words.len() {
+            let count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+            if count == 0 || position + count > words.len() {
+                return Err(ReflectError::Truncated(position));
+            }
+            module.instruction(opcode, &words[position + 1..position + count])?;
+            position += count;
+        }
+        Ok(module)
+    }
+
+    fn instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), ReflectError> {
+        let operand = |i: usize| {
+            operands
+                .get(i)
+                .copied()
+                .ok_or(ReflectError::MissingOperand(opcode))
+        };
+        let string = |i: usize| literal_string(operands.get(i..).unwrap_or_default());
+        match opcode {
+            op::NAME => {
+                self.names.insert(operand(0)?, string(1));
+            }
+            op::ENTRY_POINT => {
+                // Only the first entry point is reflected
+                if self.entry_point.is_none() {
+                    self.entry_point = Some((operand(0)?, string(2)));
+                }
+            }
+            op::DECORATE => {
+                let entry = self.decorations.entry(operand(0)?).or_default();
+                match operand(1)? {
+                    decoration::BUFFER_BLOCK => entry.buffer_block = true,
+                    decoration::BUILT_IN => entry.built_in = true,
+                    decoration::ARRAY_STRIDE => entry.array_stride = Some(operand(2)?),
+                    decoration::LOCATION => entry.location = Some(operand(2)?),
+                    decoration::BINDING => entry.binding = Some(operand(2)?),
+                    decoration::DESCRIPTOR_SET => entry.set = Some(operand(2)?),
+                    _ => {}
+                }
+            }
+            op::MEMBER_DECORATE => {
+                let entry = self
+                    .member_decorations
+                    .entry((operand(0)?, operand(1)?))
+                    .or_default();
+                match operand(2)? {
+                    decoration::OFFSET => entry.offset = Some(operand(3)?),
+                    decoration::MATRIX_STRIDE => entry.matrix_stride = Some(operand(3)?),
+                    _ => {}
+                }
+            }
+            op::TYPE_BOOL => {
+                self.types.insert(operand(0)?, Type::Bool);
+            }
+            op::TYPE_INT | op::TYPE_FLOAT => {
+                let float = opcode == op::TYPE_FLOAT;
+                let width = operand(1)?;
+                self.types
+                    .insert(operand(0)?, Type::Scalar { width, float });
+            }
+            op::TYPE_VECTOR => {
+                let (component, count) = (operand(1)?, operand(2)?);
+                self.types
+                    .insert(operand(0)?, Type::Vector { component, count });
+            }
+            op::TYPE_MATRIX => {
+                let (column, count) = (operand(1)?, operand(2)?);
+                self.types
+                    .insert(operand(0)?, Type::Matrix { column, count });
+            }
+            op::TYPE_IMAGE => {
+                // Sampled is 2 for images that are used without a sampler
+                let storage = operand(7)? == 2;
+                self.types.insert(operand(0)?, Type::Image { storage });
+            }
+            op::TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, Type::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, Type::SampledImage);
+            }
+            op::TYPE_ARRAY => {
+                let element = operand(1)?;
+                let length_id = operand(2)?;
+                let length = self
+                    .constants
+                    .get(&length_id)
+                    .copied()
+                    .ok_or(ReflectError::UnknownType(length_id))?;
+                self.types
+                    .insert(operand(0)?, Type::Array { element, length });
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, Type::RuntimeArray);
+            }
+            op::TYPE_STRUCT => {
+                let members = operands.get(1..).unwrap_or_default().to_vec();
+                self.types.insert(operand(0)?, Type::Struct { members });
+            }
+            op::TYPE_POINTER => {
+                let pointee = operand(2)?;
+                self.types.insert(operand(0)?, Type::Pointer { pointee });
+            }
+            op::CONSTANT => {
+                // Wider constants are never used as array lengths
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            op::VARIABLE => self.variables.push(Variable {
+                pointer: operand(0)?,
+                id: operand(1)?,
+                storage: operand(2)?,
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn get_type(&self, id: u32) -> Result<&Type, ReflectError> {
+        self.types.get(&id).ok_or(ReflectError::UnknownType(id))
+    }
+
+    fn decorations(&self, id: u32) -> Option<&Decorations> {
+        self.decorations.get(&id)
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Size of a type inside a buffer, matrix stride is a decoration of the member
+    fn size_of(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, ReflectError> {
+        Ok(match self.get_type(id)? {
+            Type::Scalar { width, .. } => width / 8,
+            Type::Vector { component, count } => self.size_of(*component, None)? * count,
+            Type::Matrix { column, count } => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.size_of(*column, None)?,
+                };
+                stride * count
+            }
+            Type::Array { element, length } => {
+                let stride = match self.decorations(id).and_then(|d| d.array_stride) {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, matrix_stride)?,
+                };
+                stride * length
+            }
+            // Runtime arrays take up the rest of the buffer
+            Type::RuntimeArray => 0,
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let (offset, stride) = self.member_layout(id, index as u32)?;
+                    size = size.max(offset + self.size_of(*member, stride)?);
+                }
+                size
+            }
+            _ => return Err(ReflectError::UnsizedType(id)),
+        })
+    }
+
+    fn member_layout(&self, id: u32, member: u32) -> Result<(u32, Option<u32>), ReflectError> {
+        let decorations = self.member_decorations.get(&(id, member));
+        let offset =
+            decorations
+                .and_then(|d| d.offset)
+                .ok_or_else(|| ReflectError::MissingOffset {
+                    name: self.name(id),
+                    member,
+                })?;
+        Ok((offset, decorations.and_then(|d| d.matrix_stride)))
+    }
+
+    /// Strips arrays of descriptors, returns the inner type and the number of descriptors
+    fn descriptor_type(&self, id: u32) -> Result<(u32, usize), ReflectError> {
+        match self.get_type(id)? {
+            Type::Array { element, length } => Ok((*element, *length as usize)),
+            _ => Ok((id, 1)),
+        }
+    }
+
+    fn binding(&self, variable: &Variable, pointee: u32) -> Result<ReflectedBinding, ReflectError> {
+        let decorations = self.decorations(variable.id);
+        let (type_id, array_size) = self.descriptor_type(pointee)?;
+        let type_decorations = self.decorations(type_id);
+        let is_block = |f: fn(&Decorations) -> bool| type_decorations.map(f).unwrap_or(false);
+
+        let type_info = match (variable.storage, self.get_type(type_id)?) {
+            (storage::STORAGE_BUFFER, _) => PartType::StorageBuffer,
+            (storage::UNIFORM, _) if is_block(|d| d.buffer_block) => PartType::StorageBuffer,
+            (storage::UNIFORM, _) => PartType::Uniform(self.size_of(type_id, None)? as usize),
+            (_, Type::Image { storage: true }) => PartType::StorageImage,
+            // Sampled images and samplers are all bound as combined image samplers
+            _ => PartType::Sampler,
+        };
+
+        let mut name = self.name(variable.id);
+        if name.is_empty() {
+            name = self.name(type_id);
+        }
+        Ok(ReflectedBinding {
+            set: decorations.and_then(|d| d.set).unwrap_or(0),
+            binding: decorations.and_then(|d| d.binding).unwrap_or(0),
+            name,
+            type_info,
+            array_size,
+        })
+    }
+
+    fn push_constants(&self, pointee: u32) -> Result<Range<u32>, ReflectError> {
+        let start = match self.get_type(pointee)? {
+            Type::Struct { members } => {
+                let mut start = u32::MAX;
+                for index in 0..members.len() {
+                    start = start.min(self.member_layout(pointee, index as u32)?.0);
+                }
+                start
+            }
+            _ => 0,
+        };
+        Ok(start.min(self.size_of(pointee, None)?)..self.size_of(pointee, None)?)
+    }
+
+    fn input_format(&self, id: u32) -> Result<Option<VertexAttributeFormat>, ReflectError> {
+        Ok(match self.get_type(id)? {
+            Type::Vector { component, count } => match (self.get_type(*component)?, count) {
+                (Type::Scalar { float: true, .. }, 2) => Some(VertexAttributeFormat::Vec2),
+                (Type::Scalar { float: true, .. }, 3) => Some(VertexAttributeFormat::Vec3),
+                (Type::Scalar { float: true, .. }, 4) => Some(VertexAttributeFormat::Vec4),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+}
+
+impl ShaderReflection {
+    pub fn from_spirv(words: &[u32]) -> Result<Self, ReflectError> {
+        let module = Module::parse(words)?;
+        let (model, entry_point) = module
+            .entry_point
+            .clone()
+            .ok_or(ReflectError::NoEntryPoint)?;
+        let stage = match model {
+            0 => ShaderType::Vertex,
+            3 => ShaderType::Geometry,
+            4 => ShaderType::Fragment,
+            5 => ShaderType::Compute,
+            model => return Err(ReflectError::UnsupportedStage(model)),
+        };
+
+        let mut bindings = Vec::new();
+        let mut push_constants = None;
+        let mut inputs = Vec::new();
+        for variable in module.variables.iter() {
+            let pointee = match module.get_type(variable.pointer)? {
+                Type::Pointer { pointee } => *pointee,
+                _ => return Err(ReflectError::UnknownType(variable.pointer)),
+            };
+            match variable.storage {
+                storage::UNIFORM_CONSTANT | storage::UNIFORM | storage::STORAGE_BUFFER => {
+                    bindings.push(module.binding(variable, pointee)?)
+                }
+                storage::PUSH_CONSTANT => push_constants = Some(module.push_constants(pointee)?),
+                storage::INPUT if stage == ShaderType::Vertex => {
+                    let decorations = module.decorations(variable.id);
+                    if let Some(location) =
+                        decorations.filter(|d| !d.built_in).and_then(|d| d.location)
+                    {
+                        let name = module.name(variable.id);
+                        // Matrices take up one location per column
+                        let (column, columns) = match module.get_type(pointee)? {
+                            Type::Matrix { column, count } => (*column, *count),
+                            _ => (pointee, 1),
+                        };
+                        for i in 0..columns {
+                            inputs.push(ReflectedInput {
+                                location: location + i,
+                                name: name.clone(),
+                                format: module.input_format(column)?,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        bindings.sort_by_key(|b| (b.set, b.binding));
+        inputs.sort_by_key(|i| i.location);
+
+        Ok(Self {
+            stage,
+            entry_point,
+            bindings,
+            push_constants,
+            inputs,
+        })
+    }
+
+    /// Reflects the shader code, if the backend compiles to SPIR-V
+    pub fn from_code<Context: GpuContext>(
+        ctx: &Context,
+        code: &Context::ShaderCode,
+    ) -> Option<Result<Self, ReflectError>> {
+        ctx.shader_spirv(code).map(Self::from_spirv)
+    }
+}
+
+/// Generates the parts of the mixture for descriptor set `set`, from the bindings of all shaders
+/// of a pipeline
+pub fn mixture_parts(
+    shaders: &[&ShaderReflection],
+    set: u32,
+) -> Result<Vec<MixturePart>, ReflectError> {
+    let mut parts: Vec<MixturePart> = Vec::new();
+    for shader in shaders {
+        for binding in shader.bindings.iter().filter(|b| b.set == set) {
+            if let Some(part) = parts.iter().find(|p| p.binding == binding.binding) {
+                return Err(ReflectError::SharedBinding {
+                    set,
+                    binding: binding.binding,
+                    first: part.shader_type.clone(),
+                    second: shader.stage.clone(),
+                });
+            }
+            parts.push(MixturePart {
+                binding: binding.binding,
+                name: binding.name.clone(),
+                shader_type: shader.stage.clone(),
+                is_dynamic: false,
+                array_size: binding.array_size,
+                type_info: binding.type_info.clone(),
+            });
+        }
+    }
+    parts.sort_by_key(|p| p.binding);
+    Ok(parts)
+}
+
+/// A difference between a pipeline descriptor and its shaders
+#[derive(Debug, Clone)]
+pub enum LayoutMismatch {
+    MissingMixture {
+        set: u32,
+        binding: u32,
+        name: String,
+    },
+    MissingBinding {
+        set: u32,
+        binding: u32,
+        name: String,
+    },
+    BindingType {
+        set: u32,
+        binding: u32,
+        name: String,
+        shader: PartType,
+        part: PartType,
+    },
+    UniformSize {
+        set: u32,
+        binding: u32,
+        name: String,
+        shader: usize,
+        part: usize,
+    },
+    BindingStage {
+        set: u32,
+        binding: u32,
+        name: String,
+        used_by: ShaderType,
+        visible_to: ShaderType,
+    },
+    ArraySize {
+        set: u32,
+        binding: u32,
+        name: String,
+        shader: usize,
+        part: usize,
+    },
+    PushConstants {
+        stage: ShaderType,
+        shader: Range<u32>,
+        declared: Option<Range<u32>>,
+    },
+    MissingAttribute {
+        location: u32,
+        name: String,
+    },
+    AttributeFormat {
+        location: u32,
+        name: String,
+        shader: Option<VertexAttributeFormat>,
+        declared: VertexAttributeFormat,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutMismatch::MissingMixture { set, binding, name } => write!(
+                f,
+                "{} (set {}, binding {}) has no mixture for its set",
+                name, set, binding
+            ),
+            LayoutMismatch::MissingBinding { set, binding, name } => write!(
+                f,
+                "{} (set {}, binding {}) is missing in the mixture",
+                name, set, binding
+            ),
+            LayoutMismatch::BindingType {
+                set,
+                binding,
+                name,
+                shader,
+                part,
+            } => write!(
+                f,
+                "{} (set {}, binding {}) is a {:?} in the shader, but a {:?} in the mixture",
+                name, set, binding, shader, part
+            ),
+            LayoutMismatch::UniformSize {
+                set,
+                binding,
+                name,
+                shader,
+                part,
+            } => write!(
+                f,
+                "{} (set {}, binding {}) is {} bytes in the shader, but only {} in the mixture",
+                name, set, binding, shader, part
+            ),
+            LayoutMismatch::BindingStage {
+                set,
+                binding,
+                name,
+                used_by,
+                visible_to,
+            } => write!(
+                f,
+                "{} (set {}, binding {}) is used by the {:?} shader, but only visible to {:?}",
+                name, set, binding, used_by, visible_to
+            ),
+            LayoutMismatch::ArraySize {
+                set,
+                binding,
+                name,
+                shader,
+                part,
+            } => write!(
+                f,
+                "{} (set {}, binding {}) has {} elements in the shader, but {} in the mixture",
+                name, set, binding, shader, part
+            ),
+            LayoutMismatch::PushConstants {
+                stage,
+                shader,
+                declared,
+            } => write!(
+                f,
+                "push constants of the {:?} shader use bytes {:?}, but the pipeline declares {:?}",
+                stage, shader, declared
+            ),
+            LayoutMismatch::MissingAttribute { location, name } => write!(
+                f,
+                "vertex input {} (location {}) has no attribute",
+                name, location
+            ),
+            LayoutMismatch::AttributeFormat {
+                location,
+                name,
+                shader,
+                declared,
+            } => write!(
+                f,
+                "vertex input {} (location {}) is {:?} in the shader, but the attribute is {:?}",
+                name, location, shader, declared
+            ),
+        }
+    }
+}
+
+fn list(mismatches: &[LayoutMismatch]) -> String {
+    mismatches.iter().map(|m| format!("\n  - {}", m)).collect()
+}
+
+#[derive(Debug, Error)]
+#[error("pipeline {pipeline} does not match its shaders:{}", list(.mismatches))]
+pub struct PipelineLayoutError {
+    pub pipeline: String,
+    pub mismatches: Vec<LayoutMismatch>,
+}
+
+fn check_binding(
+    binding: &ReflectedBinding,
+    stage: &ShaderType,
+    part: &MixturePart,
+    mismatches: &mut Vec<LayoutMismatch>,
+) {
+    let (set, name) = (binding.set, binding.name.clone());
+    match (&binding.type_info, &part.type_info) {
+        (PartType::Uniform(shader), PartType::Uniform(size)) if size < shader => {
+            mismatches.push(LayoutMismatch::UniformSize {
+                set,
+                binding: binding.binding,
+                name: name.clone(),
+                shader: *shader,
+                part: *size,
+            })
+        }
+        (PartType::Uniform(_), PartType::Uniform(_))
+        | (PartType::Sampler, PartType::Sampler)
+        | (PartType::StorageBuffer, PartType::StorageBuffer)
+        | (PartType::StorageImage, PartType::StorageImage) => {}
+        (shader, part) => mismatches.push(LayoutMismatch::BindingType {
+            set,
+            binding: binding.binding,
+            name: name.clone(),
+            shader: shader.clone(),
+            part: part.clone(),
+        }),
+    }
+    if &part.shader_type != stage {
+        mismatches.push(LayoutMismatch::BindingStage {
+            set,
+            binding: binding.binding,
+            name: name.clone(),
+            used_by: stage.clone(),
+            visible_to: part.shader_type.clone(),
+        });
+    }
+    if part.array_size != binding.array_size {
+        mismatches.push(LayoutMismatch::ArraySize {
+            set,
+            binding: binding.binding,
+            name,
+            shader: binding.array_size,
+            part: part.array_size,
+        });
+    }
+}
+
+/// The parts of a pipeline descriptor that have to match its shaders
+pub struct PipelineLayout<'a> {
+    pub name: &'a str,
+    /// Parts of the mixture of each descriptor set
+    pub mixtures: Vec<&'a [MixturePart]>,
+    pub push_constants: &'a [(ShaderType, Range<u32>)],
+    pub attributes: &'a [AttributeDescriptor],
+}
+
+impl<'a> PipelineLayout<'a> {
+    pub fn of<Context: GpuContext>(desc: &'a GraphicsPipelineDescriptor<Context>) -> Self {
+        Self {
+            name: &desc.name,
+            mixtures: desc.mixtures.iter().map(|m| m.parts.as_slice()).collect(),
+            push_constants: &desc.push_constants,
+            attributes: &desc.attributes,
+        }
+    }
+
+    /// Checks that the mixtures, push constants and vertex attributes provide everything the
+    /// shaders use
+    pub fn validate(&self, shaders: &[&ShaderReflection]) -> Result<(), PipelineLayoutError> {
+        let mut mismatches = Vec::new();
+
+        for shader in shaders {
+            for binding in shader.bindings.iter() {
+                let parts = match self.mixtures.get(binding.set as usize) {
+                    Some(parts) => parts,
+                    None => {
+                        mismatches.push(LayoutMismatch::MissingMixture {
+                            set: binding.set,
+                            binding: binding.binding,
+                            name: binding.name.clone(),
+                        });
+                        continue;
+                    }
+                };
+                match parts.iter().find(|p| p.binding == binding.binding) {
+                    Some(part) => check_binding(binding, &shader.stage, part, &mut mismatches),
+                    None => mismatches.push(LayoutMismatch::MissingBinding {
+                        set: binding.set,
+                        binding: binding.binding,
+                        name: binding.name.clone(),
+                    }),
+                }
+            }
+
+            if let Some(range) = &shader.push_constants {
+                let declared = self
+                    .push_constants
+                    .iter()
+                    .find(|(stage, _)| stage == &shader.stage)
+                    .map(|(_, declared)| declared.clone());
+                let covered = declared
+                    .as_ref()
+                    .map(|d| d.start <= range.start && range.end <= d.end)
+                    .unwrap_or(false);
+                if !covered {
+                    mismatches.push(LayoutMismatch::PushConstants {
+                        stage: shader.stage.clone(),
+                        shader: range.clone(),
+                        declared,
+                    });
+                }
+            }
+
+            for input in shader.inputs.iter() {
+                match self
+                    .attributes
+                    .iter()
+                    .find(|a| a.location == input.location)
+                {
+                    Some(attribute) if input.format.as_ref() != Some(&attribute.format) => {
+                        mismatches.push(LayoutMismatch::AttributeFormat {
+                            location: input.location,
+                            name: input.name.clone(),
+                            shader: input.format.clone(),
+                            declared: attribute.format.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                    None => mismatches.push(LayoutMismatch::MissingAttribute {
+                        location: input.location,
+                        name: input.name.clone(),
+                    }),
+                }
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(PipelineLayoutError {
+                pipeline: self.name.to_string(),
+                mismatches,
+            })
+        }
+    }
+}
+
+/// Checks the descriptor against the shaders it uses, see `PipelineLayout::validate`
+pub fn validate_pipeline<Context: GpuContext>(
+    desc: &GraphicsPipelineDescriptor<Context>,
+    shaders: &[&ShaderReflection],
+) -> Result<(), PipelineLayoutError> {
+    PipelineLayout::of(desc).validate(shaders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal SPIR-V assembler, so that the tests do not need a shader compiler
+    struct Assembler {
+        words: Vec<u32>,
+    }
+
+    fn string(s: &str) -> Vec<u32> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        bytes
+            .chunks(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    }
+
+    impl Assembler {
+        fn new() -> Self {
+            Self {
+                words: vec![MAGIC, 0x0001_0000, 0, 100, 0],
+            }
+        }
+
+        fn op(&mut self, opcode: u32, operands: &[u32]) -> &mut Self {
+            self.words
+                .push(((operands.len() as u32 + 1) << 16) | opcode);
+            self.words.extend_from_slice(operands);
+            self
+        }
+
+        fn name(&mut self, id: u32, name: &str) -> &mut Self {
+            let mut operands = vec![id];
+            operands.extend(string(name));
+            self.op(op::NAME, &operands)
+        }
+
+        fn entry_point(&mut self, model: u32) -> &mut Self {
+            let mut operands = vec![model, 1];
+            operands.extend(string("main"));
+            self.op(op::ENTRY_POINT, &operands)
+        }
+
+        fn decorate(&mut self, id: u32, decoration: u32, value: u32) -> &mut Self {
+            self.op(op::DECORATE, &[id, decoration, value])
+        }
+
+        fn offset(&mut self, id: u32, member: u32, offset: u32) -> &mut Self {
+            self.op(
+                op::MEMBER_DECORATE,
+                &[id, member, decoration::OFFSET, offset],
+            )
+        }
+    }
+
+    const FLOAT: u32 = 2;
+    const VEC3: u32 = 3;
+    const VEC4: u32 = 4;
+    const MAT4: u32 = 5;
+
+    fn common_types(asm: &mut Assembler) {
+        asm.op(op::TYPE_FLOAT, &[FLOAT, 32])
+            .op(op::TYPE_VECTOR, &[VEC3, FLOAT, 3])
+            .op(op::TYPE_VECTOR, &[VEC4, FLOAT, 4])
+            .op(op::TYPE_MATRIX, &[MAT4, VEC4, 4]);
+    }
+
+    /// Interface of solid.vert
+    fn vertex_shader() -> Vec<u32> {
+        let mut asm = Assembler::new();
+        asm.entry_point(0)
+            .name(8, "camera")
+            .name(6, "CameraBuffer")
+            .name(13, "in_pos")
+            .name(14, "in_normal")
+            .decorate(8, decoration::BINDING, 0)
+            .decorate(8, decoration::DESCRIPTOR_SET, 0)
+            .offset(6, 0, 0)
+            .op(op::MEMBER_DECORATE, &[6, 0, decoration::MATRIX_STRIDE, 16])
+            .offset(9, 0, 0)
+            .op(op::MEMBER_DECORATE, &[9, 0, decoration::MATRIX_STRIDE, 16])
+            .decorate(13, decoration::LOCATION, 0)
+            .decorate(14, decoration::LOCATION, 1)
+            .decorate(18, decoration::BUILT_IN, 42);
+        common_types(&mut asm);
+        asm.op(op::TYPE_STRUCT, &[6, MAT4])
+            .op(op::TYPE_POINTER, &[7, storage::UNIFORM, 6])
+            .op(op::VARIABLE, &[7, 8, storage::UNIFORM])
+            .op(op::TYPE_STRUCT, &[9, MAT4])
+            .op(op::TYPE_POINTER, &[10, storage::PUSH_CONSTANT, 9])
+            .op(op::VARIABLE, &[10, 11, storage::PUSH_CONSTANT])
+            .op(op::TYPE_POINTER, &[12, storage::INPUT, VEC3])
+            .op(op::VARIABLE, &[12, 13, storage::INPUT])
+            .op(op::VARIABLE, &[12, 14, storage::INPUT])
+            .op(op::TYPE_INT, &[16, 32, 1])
+            .op(op::TYPE_POINTER, &[17, storage::INPUT, 16])
+            .op(op::VARIABLE, &[17, 18, storage::INPUT]);
+        asm.words
+    }
+
+    /// Interface of solid.frag, with an additional array of textures
+    fn fragment_shader() -> Vec<u32> {
+        let mut asm = Assembler::new();
+        asm.entry_point(4)
+            .name(8, "light")
+            .name(11, "textures")
+            .decorate(8, decoration::BINDING, 1)
+            .decorate(11, decoration::BINDING, 2)
+            .offset(6, 0, 64)
+            .offset(6, 1, 80)
+            .offset(6, 2, 96)
+            .offset(6, 3, 112)
+            .offset(7, 0, 0)
+            .offset(7, 1, 16);
+        common_types(&mut asm);
+        asm.op(op::TYPE_STRUCT, &[6, VEC4, VEC4, VEC4, FLOAT])
+            .op(op::TYPE_POINTER, &[20, storage::PUSH_CONSTANT, 6])
+            .op(op::VARIABLE, &[20, 21, storage::PUSH_CONSTANT])
+            .op(op::TYPE_STRUCT, &[7, VEC3, VEC3])
+            .op(op::TYPE_POINTER, &[22, storage::UNIFORM, 7])
+            .op(op::VARIABLE, &[22, 8, storage::UNIFORM])
+            .op(op::TYPE_IMAGE, &[9, FLOAT, 1, 0, 0, 0, 1, 0])
+            .op(op::TYPE_SAMPLED_IMAGE, &[10, 9])
+            .op(op::TYPE_INT, &[23, 32, 0])
+            .op(op::CONSTANT, &[23, 24, 2])
+            .op(op::TYPE_ARRAY, &[25, 10, 24])
+            .op(op::TYPE_POINTER, &[26, storage::UNIFORM_CONSTANT, 25])
+            .op(op::VARIABLE, &[26, 11, storage::UNIFORM_CONSTANT]);
+        asm.words
+    }
+
+    #[test]
+    fn reflects_shader_interface() {
+        let vertex = ShaderReflection::from_spirv(&vertex_shader()).unwrap();
+        assert_eq!(vertex.stage, ShaderType::Vertex);
+        assert_eq!(vertex.push_constants, Some(0..64));
+        assert_eq!(vertex.bindings.len(), 1);
+        assert_eq!(vertex.bindings[0].name, "camera");
+        assert!(matches!(
+            vertex.bindings[0].type_info,
+            PartType::Uniform(64)
+        ));
+        // The built in vertex index is not an input
+        let inputs: Vec<_> = vertex
+            .inputs
+            .iter()
+            .map(|i| (i.location, i.format.clone()))
+            .collect();
+        assert_eq!(
+            inputs,
+            vec![
+                (0, Some(VertexAttributeFormat::Vec3)),
+                (1, Some(VertexAttributeFormat::Vec3))
+            ]
+        );
+
+        let fragment = ShaderReflection::from_spirv(&fragment_shader()).unwrap();
+        assert_eq!(fragment.stage, ShaderType::Fragment);
+        assert_eq!(fragment.push_constants, Some(64..116));
+
+        let parts = mixture_parts(&[&vertex, &fragment], 0).unwrap();
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|p| (p.binding, p.shader_type.clone(), p.array_size))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                (0, ShaderType::Vertex, 1),
+                (1, ShaderType::Fragment, 1),
+                (2, ShaderType::Fragment, 2)
+            ]
+        );
+        assert!(matches!(
+            fragment.bindings[0].type_info,
+            PartType::Uniform(28)
+        ));
+        assert!(matches!(fragment.bindings[1].type_info, PartType::Sampler));
+    }
+
+    #[test]
+    fn reports_layout_mismatches() {
+        let vertex = ShaderReflection::from_spirv(&vertex_shader()).unwrap();
+        let fragment = ShaderReflection::from_spirv(&fragment_shader()).unwrap();
+        let shaders = [&vertex, &fragment];
+
+        let mut parts = mixture_parts(&shaders, 0).unwrap();
+        let attributes = vec![
+            AttributeDescriptor::new(0, 0, 0, VertexAttributeFormat::Vec3),
+            AttributeDescriptor::new(1, 0, 12, VertexAttributeFormat::Vec3),
+        ];
+        let push_constants = vec![(ShaderType::Vertex, 0..64), (ShaderType::Fragment, 64..128)];
+        let layout = PipelineLayout {
+            name: "solid",
+            mixtures: vec![&parts],
+            push_constants: &push_constants,
+            attributes: &attributes,
+        };
+        layout.validate(&shaders).unwrap();
+
+        // The material as it was before the offset in solid.frag
+        parts[1].type_info = PartType::Uniform(16);
+        let push_constants = vec![(ShaderType::Vertex, 0..64), (ShaderType::Fragment, 0..52)];
+        let layout = PipelineLayout {
+            name: "solid",
+            mixtures: vec![&parts],
+            push_constants: &push_constants,
+            attributes: &attributes[..1],
+        };
+        let error = layout.validate(&shaders).unwrap_err();
+        assert!(matches!(
+            error.mismatches.as_slice(),
+            [
+                LayoutMismatch::MissingAttribute { location: 1, .. },
+                LayoutMismatch::UniformSize {
+                    binding: 1,
+                    shader: 28,
+                    part: 16,
+                    ..
+                },
+                LayoutMismatch::PushConstants {
+                    stage: ShaderType::Fragment,
+                    ..
+                }
+            ]
+        ));
+        assert!(error
+            .to_string()
+            .contains("in_normal (location 1) has no attribute"));
+    }
+}
@@ -24,7 +24,8 @@ pub enum StoreOp {
 #[derive(Debug, Clone)]
 pub struct Attachment {
     pub format: TextureFormat,
-    // TODO: Multisampling
+    /// Samples per texel, 1 if the attachment is not multisampled
+    pub samples: u8,
     pub load_op: LoadOp,
     pub store_op: StoreOp,
     pub layouts: Range<AttachmentLayout>,
@@ -0,0 +1,197 @@
+use std::{borrow::Cow, mem::ManuallyDrop, ops::Deref, sync::Arc};
+
+use crate::{context::GpuContext, resource::frame::Extent2D, util::format::TextureFormat};
+
+/// A sampled 2D texture, the content is uploaded through the context (see upload_texture)
+#[derive(Debug, Clone)]
+pub struct TextureDescriptor {
+    pub name: Cow<'static, str>,
+    pub extent: Extent2D,
+    pub format: TextureFormat,
+    /// Number of mip levels, levels that are not uploaded are generated from the previous one
+    pub mip_levels: u32,
+    /// The texture can also be bound as storage image, to be written by compute shaders
+    pub storage: bool,
+}
+
+impl TextureDescriptor {
+    /// A texture with the full mip chain, down to 1x1
+    pub fn with_mips(name: Cow<'static, str>, extent: Extent2D, format: TextureFormat) -> Self {
+        Self {
+            name,
+            mip_levels: mip_level_count(&extent),
+            extent,
+            format,
+            storage: false,
+        }
+    }
+
+    /// A single level texture, that is written by compute shaders (eg. a storage image)
+    pub fn storage(name: Cow<'static, str>, extent: Extent2D, format: TextureFormat) -> Self {
+        Self {
+            name,
+            extent,
+            format,
+            mip_levels: 1,
+            storage: true,
+        }
+    }
+
+    /// Size in bytes of all levels that are uploaded
+    pub fn level_size(&self, level: u32) -> usize {
+        let extent = mip_extent(&self.extent, level);
+        (extent.width * extent.height) as usize * self.format.texel_size()
+    }
+}
+
+/// Number of levels of a full mip chain for extent
+pub fn mip_level_count(extent: &Extent2D) -> u32 {
+    32 - extent.width.max(extent.height).max(1).leading_zeros()
+}
+
+/// Extent of the mip level, each level is half the size of the previous one
+pub fn mip_extent(extent: &Extent2D, level: u32) -> Extent2D {
+    Extent2D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+/// What happens with texture coordinates outside of 0..1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Debug, Clone)]
+pub struct SamplerDescriptor {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mip_filter: Filter,
+    pub wrap: WrapMode,
+}
+
+impl SamplerDescriptor {
+    pub const LINEAR: Self = Self {
+        mag_filter: Filter::Linear,
+        min_filter: Filter::Linear,
+        mip_filter: Filter::Linear,
+        wrap: WrapMode::Repeat,
+    };
+
+    pub const NEAREST: Self = Self {
+        mag_filter: Filter::Nearest,
+        min_filter: Filter::Nearest,
+        mip_filter: Filter::Nearest,
+        wrap: WrapMode::Repeat,
+    };
+}
+
+#[derive(Debug)]
+pub struct Texture<Context: GpuContext> {
+    desc: TextureDescriptor,
+    ctx: Arc<Context>,
+    handle: ManuallyDrop<Context::TextureHandle>,
+}
+
+impl<Context: GpuContext> Texture<Context> {
+    pub fn new(desc: TextureDescriptor, handle: Context::TextureHandle, ctx: Arc<Context>) -> Self {
+        Self {
+            desc,
+            ctx,
+            handle: ManuallyDrop::new(handle),
+        }
+    }
+
+    pub fn get_handle(&self) -> &Context::TextureHandle {
+        self.handle.deref()
+    }
+
+    pub fn desc(&self) -> &TextureDescriptor {
+        &self.desc
+    }
+}
+
+impl<Context: GpuContext> Deref for Texture<Context> {
+    type Target = Context::TextureHandle;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.deref()
+    }
+}
+
+impl<Context: GpuContext> Drop for Texture<Context> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ctx.drop_texture(ManuallyDrop::take(&mut self.handle));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sampler<Context: GpuContext> {
+    ctx: Arc<Context>,
+    handle: ManuallyDrop<Context::SamplerHandle>,
+}
+
+impl<Context: GpuContext> Sampler<Context> {
+    pub fn new(handle: Context::SamplerHandle, ctx: Arc<Context>) -> Self {
+        Self {
+            ctx,
+            handle: ManuallyDrop::new(handle),
+        }
+    }
+
+    pub fn get_handle(&self) -> &Context::SamplerHandle {
+        self.handle.deref()
+    }
+}
+
+impl<Context: GpuContext> Deref for Sampler<Context> {
+    type Target = Context::SamplerHandle;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.deref()
+    }
+}
+
+impl<Context: GpuContext> Drop for Sampler<Context> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ctx.drop_sampler(ManuallyDrop::take(&mut self.handle));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain() {
+        let extent = Extent2D {
+            width: 256,
+            height: 64,
+        };
+        assert_eq!(mip_level_count(&extent), 9);
+        let last = mip_extent(&extent, 8);
+        assert_eq!((last.width, last.height), (1, 1));
+        let level = mip_extent(&extent, 7);
+        assert_eq!((level.width, level.height), (2, 1));
+        assert_eq!(
+            mip_level_count(&Extent2D {
+                width: 1,
+                height: 1
+            }),
+            1
+        );
+    }
+}
@@ -1,15 +1,19 @@
 use crate::resource::{
     glue::Glue,
-    pipeline::{GraphicsPipeline, GraphicsPipelineDescriptor, RenderContext},
+    pipeline::{
+        ComputePipeline, ComputePipelineDescriptor, GraphicsPipeline, GraphicsPipelineDescriptor,
+        RenderContext,
+    },
 };
 use crate::{
     context::GpuContext,
     resource::{
-        buffer::{Buffer, BufferDescriptor},
+        buffer::{Buffer, BufferDescriptor, BufferUsage, MemoryType},
         glue::{DescriptorSet, GlueBottle, Mixture, MixturePart},
+        texture::{Sampler, SamplerDescriptor, Texture, TextureDescriptor},
     },
 };
-use std::{mem::ManuallyDrop, sync::Arc};
+use std::{borrow::Cow, mem::ManuallyDrop, sync::Arc};
 
 #[derive(Debug)]
 pub struct GpuResources<Context: GpuContext> {
@@ -26,54 +30,62 @@ impl<Context: GpuContext> GpuResources<Context> {
         Buffer::new(desc.name.into(), handle, self.ctx.clone())
     }
 
-    // pub fn create_device_local_buffer<T: Pod>(
-    //     &self,
-    //     name: Cow<'static, str>,
-    //     usage: BufferUsage,
-    //     data: &T,
-    // ) -> Buffer<Context> {
-    //     let size = std::mem::size_of::<T>() as u64;
-
-    //     let staging_buffer = {
-    //         let staging_desc = BufferDescriptor {
-    //             name: format!("{}-staging", name).into(),
-    //             size,
-    //             memory_type: MemoryType::HostVisible,
-    //             usage: BufferUsage::Staging,
-    //         };
-
-    //         let handle = self.ctx.create_buffer(&staging_desc);
-
-    //         unsafe {
-    //             self.ctx.write_to_buffer(&handle, data);
-    //         }
-
-    //         handle
-    //     };
-
-    //     let handle = {
-    //         let desc = BufferDescriptor {
-    //             name: name.clone(),
-    //             size,
-    //             memory_type: MemoryType::DeviceLocal,
-    //             usage,
-    //         };
+    /// Creates a DeviceLocal buffer with data as its content, see GpuContext::upload_buffer
+    pub fn create_device_local_buffer(
+        &self,
+        name: Cow<'static, str>,
+        usage: BufferUsage,
+        data: &[u8],
+    ) -> Buffer<Context> {
+        let desc = BufferDescriptor {
+            name,
+            size: data.len() as u64,
+            memory_type: MemoryType::DeviceLocal,
+            usage,
+        };
+        let handle = self.ctx.create_buffer(&desc);
+        self.ctx.upload_buffer(&handle, 0, data);
+        Buffer::new(desc.name.into(), handle, self.ctx.clone())
+    }
 
-    //         self.ctx.create_buffer(&desc)
-    //     };
+    /// Creates the texture and uploads levels, see GpuContext::upload_texture
+    pub fn create_texture(&self, desc: TextureDescriptor, levels: &[&[u8]]) -> Texture<Context> {
+        assert!(
+            !levels.is_empty() && levels.len() <= desc.mip_levels as usize,
+            "[GpuResources] (create_texture) {} needs between 1 and {} levels, got {}",
+            desc.name,
+            desc.mip_levels,
+            levels.len()
+        );
+        for (level, data) in levels.iter().enumerate() {
+            assert_eq!(
+                data.len(),
+                desc.level_size(level as u32),
+                "[GpuResources] (create_texture) {} level {} has wrong size",
+                desc.name,
+                level
+            );
+        }
+        let handle = self.ctx.create_texture(&desc);
+        self.ctx.upload_texture(&handle, levels);
+        Texture::new(desc, handle, self.ctx.clone())
+    }
 
-    //     self.ctx.single_shot_command(true, |cmd| {
-    //         let copy = BufferCopy {
-    //             src_offset: 0,
-    //             dst_offset: 0,
-    //             size,
-    //         };
-    //         cmd.copy_buffer(&staging_buffer, &handle, vec![copy]);
-    //     });
+    /// Creates a storage texture without content, it is ready to be written by compute shaders
+    pub fn create_storage_texture(&self, desc: TextureDescriptor) -> Texture<Context> {
+        assert!(
+            desc.storage,
+            "[GpuResources] (create_storage_texture) {} is not a storage texture",
+            desc.name
+        );
+        let handle = self.ctx.create_texture(&desc);
+        Texture::new(desc, handle, self.ctx.clone())
+    }
 
-    //     self.ctx.drop_buffer(staging_buffer);
-    //     Buffer::new(name, handle, self.ctx.clone())
-    // }
+    pub fn create_sampler(&self, desc: &SamplerDescriptor) -> Sampler<Context> {
+        let handle = self.ctx.create_sampler(desc);
+        Sampler::new(handle, self.ctx.clone())
+    }
 
     pub fn create_graphics_pipeline(
         &self,
@@ -85,6 +97,15 @@ impl<Context: GpuContext> GpuResources<Context> {
         GraphicsPipeline::new(name, handle, self.ctx.clone())
     }
 
+    pub fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<Context>,
+    ) -> ComputePipeline<Context> {
+        let name = desc.name.clone();
+        let handle = self.ctx.create_compute_pipeline(desc);
+        ComputePipeline::new(name, handle, self.ctx.clone())
+    }
+
     pub fn stir(&self, parts: Vec<MixturePart>) -> Mixture<Context> {
         let handle = self.ctx.create_descriptor_layout(parts.clone());
         Mixture::new(parts, self.ctx.clone(), handle)
@@ -42,6 +42,26 @@ impl TextureFormat {
     pub fn has_stencil(&self) -> bool {
         self == &TextureFormat::Depth24PlusStencil8
     }
+
+    /// Size of a single texel in bytes
+    pub fn texel_size(&self) -> usize {
+        use TextureFormat::*;
+        match self {
+            R8Unorm | R8Snorm | R8Uint | R8Sint => 1,
+            R16Uint | R16Sint | R16Sfloat | Rg8Unorm | Rg8Snorm | Rg8Uint | Rg8Sint => 2,
+            R32Uint | R32Sint | R32Sfloat | Rg16Uint | Rg16Sint | Rg16Sfloat => 4,
+            Rgba8Unorm | Rgba8Srgb | Rgba8Snorm | Rgba8Uint | Rgba8Sint | Bgra8Unorm
+            | Bgra8Srgb => 4,
+            Depth32Sfloat | Depth24PlusStencil8 => 4,
+            Rg32Uint | Rg32Sint | Rg32Sfloat | Rgba16Uint | Rgba16Sint | Rgba16Sfloat => 8,
+            Rgba32Uint | Rgba32Sint | Rgba32Sfloat => 16,
+        }
+    }
+
+    /// Whether the color channels are stored with the srgb transfer function
+    pub fn is_srgb(&self) -> bool {
+        matches!(self, TextureFormat::Rgba8Srgb | TextureFormat::Bgra8Srgb)
+    }
 }
 
 /// This is basically a copy from gfx-hal
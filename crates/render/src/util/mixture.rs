@@ -30,6 +30,26 @@ macro_rules! parse_line {
             type_info: PartType::Sampler,
         }
     };
+    ($binding:literal: $name:literal in $type:tt: buffer) => {
+        MixturePart {
+            binding: $binding,
+            name: String::from($name),
+            shader_type: ShaderType::$type,
+            is_dynamic: false,
+            array_size: 1,
+            type_info: PartType::StorageBuffer,
+        }
+    };
+    ($binding:literal: $name:literal in $type:tt: image) => {
+        MixturePart {
+            binding: $binding,
+            name: String::from($name),
+            shader_type: ShaderType::$type,
+            is_dynamic: false,
+            array_size: 1,
+            type_info: PartType::StorageImage,
+        }
+    };
     ($binding:literal: $name:literal in $type:tt: $buffer_type:ty) => {
         MixturePart {
             binding: $binding,
@@ -56,12 +76,14 @@ macro_rules! parse_line {
 ///   - A Plain Type but as a dynamic uniform buffer: [dynamic Material] (the [] braces are actually needed)
 ///   - An Array type of any plain type: [DirectionalLight; 20]
 ///   - Just the word: 'sampler'
+///   - Just the word: 'buffer' (a storage buffer) or 'image' (a storage image)
 ///
 /// ** Examples
 /// - 0: "lights" in fragment: [Light; 20]
 /// - 1: "camera" in vertex: ShaderCamera
 /// - 2: "material" in fragment: [dynamic Material]
 /// - 3: "albedo" in fragment: sampler
+/// - 0: "heights" in Compute: image
 #[macro_export]
 macro_rules! mixture {
     [$($binding:literal: $name:literal in $type:ident: $rest:tt),*] => {
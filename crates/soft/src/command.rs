@@ -0,0 +1,489 @@
+use std::{collections::HashMap, convert::TryInto, ops::Range, sync::Arc};
+
+use render::{
+    command_encoder::{CommandEncoder, IndexType},
+    context::GpuContext,
+    prelude::{BufferRange, ShaderType},
+    resource::{
+        buffer::BufferCopy,
+        frame::Clear,
+        glue::Glue,
+        pipeline::{Primitive, Rect, VertexAttributeFormat, VertexInputRate, Viewport},
+        render_pass::LoadOp,
+    },
+};
+
+use crate::{
+    context::SoftContext,
+    raster::{self, RasterState, Target},
+    resources::{
+        color_range, SoftBuffer, SoftComputePipeline, SoftDescriptorSet, SoftFramebuffer,
+        SoftPipeline, Texels,
+    },
+    shader::{ShaderEnv, VertexOutput},
+    texture::{SoftSampler, SoftTexture},
+};
+
+/// What the shaders can see of the bound descriptor sets
+#[derive(Default)]
+struct BoundResources {
+    uniforms: HashMap<(usize, u32), Vec<u8>>,
+    textures: HashMap<(usize, u32), (SoftTexture, SoftSampler)>,
+    storage_buffers: HashMap<(usize, u32), (SoftBuffer, BufferRange)>,
+    storage_images: HashMap<(usize, u32), SoftTexture>,
+}
+
+impl BoundResources {
+    fn new(sets: &[Option<SoftDescriptorSet>]) -> Self {
+        let mut bound = Self::default();
+        for (set_idx, set) in sets.iter().enumerate() {
+            if let Some(set) = set {
+                for (binding, bytes) in set.snapshot() {
+                    bound.uniforms.insert((set_idx, binding), bytes);
+                }
+                for (binding, texture) in set.bound_textures() {
+                    bound.textures.insert((set_idx, binding), texture);
+                }
+                for (binding, buffer) in set.bound_storage_buffers() {
+                    bound.storage_buffers.insert((set_idx, binding), buffer);
+                }
+                for (binding, image) in set.bound_storage_images() {
+                    bound.storage_images.insert((set_idx, binding), image);
+                }
+            }
+        }
+        bound
+    }
+
+    fn env<'a>(&'a self, push_constants: &'a [u8]) -> ShaderEnv<'a> {
+        ShaderEnv {
+            push_constants,
+            uniforms: &self.uniforms,
+            textures: &self.textures,
+            storage_buffers: &self.storage_buffers,
+            storage_images: &self.storage_images,
+        }
+    }
+}
+
+fn write_push_constants(push_constants: &mut Vec<u8>, offset: u32, data: &[u32]) {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    let start = offset as usize;
+    if push_constants.len() < start + bytes.len() {
+        push_constants.resize(start + bytes.len(), 0);
+    }
+    push_constants[start..start + bytes.len()].copy_from_slice(bytes);
+}
+
+fn bind_set(sets: &mut Vec<Option<SoftDescriptorSet>>, set_idx: usize, glue: &Glue<SoftContext>) {
+    if sets.len() <= set_idx {
+        sets.resize(set_idx + 1, None);
+    }
+    sets[set_idx] = Some((*glue.handle.handle).clone());
+}
+
+/// Command encoder of the software backend
+///
+/// Commands are executed immediately, so buffer writes after a draw are not visible to that draw
+/// (which would be the case on a real gpu, until the command buffer is submitted)
+#[derive(Debug, Default)]
+pub struct SoftCommand {
+    framebuffer: Option<SoftFramebuffer>,
+    pipeline: Option<Arc<SoftPipeline>>,
+    viewport: Option<Viewport>,
+    scissor: Option<Rect>,
+    push_constants: Vec<u8>,
+    vertex_buffers: HashMap<u32, (SoftBuffer, BufferRange)>,
+    index_buffer: Option<(SoftBuffer, BufferRange, IndexType)>,
+    sets: Vec<Option<SoftDescriptorSet>>,
+    // Compute has its own bind point
+    compute_pipeline: Option<Arc<SoftComputePipeline>>,
+    compute_push_constants: Vec<u8>,
+    compute_sets: Vec<Option<SoftDescriptorSet>>,
+}
+
+impl SoftCommand {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn read_indices(&self, indices: Range<u32>, vertex_offset: i32) -> Vec<u32> {
+        let (buffer, range, index_type) = match &self.index_buffer {
+            Some(index_buffer) => index_buffer,
+            None => {
+                log::warn!("[SoftCommand] (draw_indexed) no index buffer bound");
+                return Vec::new();
+            }
+        };
+
+        let data = buffer.data.read();
+        let size = index_type.get_size();
+        indices
+            .map_while(|i| {
+                let start = range.offset as usize + i as usize * size;
+                let bytes = data.get(start..start + size)?;
+                let index = match index_type {
+                    IndexType::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+                    IndexType::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+                };
+                Some((index + vertex_offset as i64) as u32)
+            })
+            .collect()
+    }
+
+    fn execute(&self, vertices: &[u32], instances: Range<u32>) {
+        let (pipeline, framebuffer) = match (&self.pipeline, &self.framebuffer) {
+            (Some(pipeline), Some(framebuffer)) => (pipeline, framebuffer),
+            _ => {
+                log::warn!("[SoftCommand] draw without bound pipeline or active render pass");
+                return;
+            }
+        };
+
+        let mut lines: Vec<[usize; 2]> = Vec::new();
+        let triangles: Vec<[usize; 3]> = match pipeline.primitive {
+            Primitive::TriangleList => (0..vertices.len() / 3)
+                .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+                .collect(),
+            // Every second triangle is flipped to keep the winding
+            Primitive::TriangleStrip => (0..vertices.len().saturating_sub(2))
+                .map(|i| {
+                    if i % 2 == 0 {
+                        [i, i + 1, i + 2]
+                    } else {
+                        [i + 1, i, i + 2]
+                    }
+                })
+                .collect(),
+            Primitive::LineList => {
+                lines = (0..vertices.len() / 2)
+                    .map(|i| [2 * i, 2 * i + 1])
+                    .collect();
+                Vec::new()
+            }
+            Primitive::LineStrip => {
+                lines = (0..vertices.len().saturating_sub(1))
+                    .map(|i| [i, i + 1])
+                    .collect();
+                Vec::new()
+            }
+            _ => {
+                log::warn!(
+                    "[SoftCommand] primitive {:?} is not supported",
+                    pipeline.primitive
+                );
+                return;
+            }
+        };
+
+        let bound = BoundResources::new(&self.sets);
+        let env = bound.env(&self.push_constants);
+
+        let vertex_data: HashMap<u32, _> = pipeline
+            .vertex_buffers
+            .iter()
+            .filter_map(|desc| {
+                let (buffer, range) = self.vertex_buffers.get(&desc.binding)?;
+                Some((desc.binding, (desc, buffer.data.read(), range.offset)))
+            })
+            .collect();
+        let attribute_count = pipeline
+            .attributes
+            .iter()
+            .map(|a| a.location as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let fetch = |vertex: u32, instance: u32| -> Vec<[f32; 4]> {
+            let mut attributes = vec![[0.0, 0.0, 0.0, 1.0]; attribute_count];
+            for attribute in pipeline.attributes.iter() {
+                if let Some((desc, data, offset)) = vertex_data.get(&attribute.binding) {
+                    let element = match desc.rate {
+                        VertexInputRate::Vertex => vertex,
+                        VertexInputRate::Instance => instance,
+                    };
+                    let start = *offset as usize
+                        + element as usize * desc.stride as usize
+                        + attribute.offset as usize;
+                    let components = match attribute.format {
+                        VertexAttributeFormat::Vec2 => 2,
+                        VertexAttributeFormat::Vec3 => 3,
+                        VertexAttributeFormat::Vec4 => 4,
+                    };
+                    let value = &mut attributes[attribute.location as usize];
+                    for (c, v) in value.iter_mut().take(components).enumerate() {
+                        let at = start + c * 4;
+                        *v = data
+                            .get(at..at + 4)
+                            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                            .unwrap_or(0.0);
+                    }
+                }
+            }
+            attributes
+        };
+
+        // The pipeline writes its output to the first color and depth attachment
+        let color_image = framebuffer
+            .attachments
+            .iter()
+            .find(|a| matches!(a.0.read().texels, Texels::Color(_)));
+        let depth_image = framebuffer
+            .attachments
+            .iter()
+            .find(|a| matches!(a.0.read().texels, Texels::Depth(_)));
+        let mut color_image = color_image.map(|i| i.0.write());
+        let mut depth_image = depth_image.map(|i| i.0.write());
+
+        // Like in vulkan, the pipeline has to be created for the samples of the attachments
+        let samples = color_image
+            .as_ref()
+            .or(depth_image.as_ref())
+            .map(|image| image.samples)
+            .unwrap_or(1);
+        if samples != pipeline.samples as u32 {
+            log::warn!(
+                "[SoftCommand] pipeline {} uses {} samples, but the attachments have {}",
+                pipeline.name,
+                pipeline.samples,
+                samples
+            );
+            return;
+        }
+
+        let (width, height) = (framebuffer.extent.width, framebuffer.extent.height);
+        let size = (width * height * samples) as usize;
+        let color_range = color_image
+            .as_ref()
+            .and_then(|image| color_range(image.format));
+        let mut target = Target {
+            width,
+            height,
+            samples,
+            color_range,
+            color: color_image
+                .as_mut()
+                .and_then(|image| match &mut image.texels {
+                    Texels::Color(texels) if texels.len() >= size => Some(&mut texels[..]),
+                    _ => None,
+                }),
+            depth: depth_image
+                .as_mut()
+                .and_then(|image| match &mut image.texels {
+                    Texels::Depth(texels) if texels.len() >= size => Some(&mut texels[..]),
+                    _ => None,
+                }),
+        };
+
+        let full = Rect {
+            x: 0,
+            y: 0,
+            width: width as i16,
+            height: height as i16,
+        };
+        let viewport = pipeline
+            .pipeline_states
+            .viewport
+            .to_option()
+            .or_else(|| self.viewport.clone())
+            .unwrap_or(Viewport {
+                rect: full.clone(),
+                depth: 0.0..1.0,
+            });
+        let scissor = pipeline
+            .pipeline_states
+            .scissor
+            .to_option()
+            .or_else(|| self.scissor.clone())
+            .unwrap_or(full);
+
+        let state = RasterState {
+            viewport: &viewport,
+            scissor: &scissor,
+            culling: &pipeline.rasterizer.culling,
+            depth: pipeline.depth.as_ref(),
+            blend: pipeline.blend_targets.first().copied().unwrap_or(false),
+            fragment: pipeline.fragment.as_ref(),
+            env: &env,
+        };
+
+        for instance in instances {
+            let mut cache: HashMap<u32, VertexOutput> = HashMap::new();
+            let outputs: Vec<VertexOutput> = vertices
+                .iter()
+                .map(|v| {
+                    *cache
+                        .entry(*v)
+                        .or_insert_with(|| (pipeline.vertex)(&env, &fetch(*v, instance)))
+                })
+                .collect();
+
+            for [a, b, c] in triangles.iter() {
+                raster::draw_triangle(
+                    &state,
+                    &mut target,
+                    [&outputs[*a], &outputs[*b], &outputs[*c]],
+                );
+            }
+            for [a, b] in lines.iter() {
+                raster::draw_line(&state, &mut target, [&outputs[*a], &outputs[*b]]);
+            }
+        }
+    }
+}
+
+impl CommandEncoder<SoftContext> for SoftCommand {
+    fn begin_render_pass<I: IntoIterator<Item = Clear>>(
+        &mut self,
+        render_pass: &<SoftContext as GpuContext>::RenderPassHandle,
+        frame_buffer: &<SoftContext as GpuContext>::Framebuffer,
+        _render_area: Rect,
+        clear_values: I,
+    ) {
+        // Like in vulkan the clear values are indexed by attachment
+        let clear_values: Vec<Clear> = clear_values.into_iter().collect();
+        for (i, (attachment, image)) in render_pass
+            .attachments
+            .iter()
+            .zip(frame_buffer.attachments.iter())
+            .enumerate()
+        {
+            if let (LoadOp::Clear, Some(clear)) = (&attachment.load_op, clear_values.get(i)) {
+                image.0.write().clear(clear);
+            }
+        }
+        self.framebuffer = Some(frame_buffer.clone());
+    }
+
+    fn end_render_pass(&mut self) {
+        self.framebuffer = None;
+    }
+
+    fn set_viewport(&mut self, _index: u32, viewport: Viewport) {
+        self.viewport = Some(viewport);
+    }
+
+    fn set_scissor(&mut self, _index: u32, scissor: Rect) {
+        self.scissor = Some(scissor);
+    }
+
+    fn bind_graphics_pipeline(&mut self, pipeline: &<SoftContext as GpuContext>::PipelineHandle) {
+        self.pipeline = Some(pipeline.clone());
+    }
+
+    fn push_constants(
+        &mut self,
+        _pipeline: &<SoftContext as GpuContext>::PipelineHandle,
+        _shader: ShaderType,
+        offset: u32,
+        data: &[u32],
+    ) {
+        write_push_constants(&mut self.push_constants, offset, data);
+    }
+
+    fn bind_vertex_buffer(
+        &mut self,
+        binding: u32,
+        buffer: &<SoftContext as GpuContext>::BufferHandle,
+        range: BufferRange,
+    ) {
+        self.vertex_buffers.insert(binding, (buffer.clone(), range));
+    }
+
+    fn bind_index_buffer(
+        &mut self,
+        buffer: &<SoftContext as GpuContext>::BufferHandle,
+        range: BufferRange,
+        index_type: IndexType,
+    ) {
+        self.index_buffer = Some((buffer.clone(), range, index_type));
+    }
+
+    fn snort_glue(
+        &mut self,
+        set_idx: usize,
+        _pipeline: &<SoftContext as GpuContext>::PipelineHandle,
+        glue: &Glue<SoftContext>,
+    ) {
+        bind_set(&mut self.sets, set_idx, glue);
+    }
+
+    fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        let vertices: Vec<u32> = vertices.collect();
+        self.execute(&vertices, instances);
+    }
+
+    fn draw_indexed(&mut self, indices: Range<u32>, vertex_offset: i32, instances: Range<u32>) {
+        let vertices = self.read_indices(indices, vertex_offset);
+        self.execute(&vertices, instances);
+    }
+
+    fn copy_buffer<I>(
+        &mut self,
+        src: &<SoftContext as GpuContext>::BufferHandle,
+        dst: &<SoftContext as GpuContext>::BufferHandle,
+        regions: I,
+    ) where
+        I: IntoIterator<Item = BufferCopy>,
+    {
+        for region in regions {
+            let data = src.read_range(&BufferRange {
+                offset: region.src_offset,
+                size: Some(region.size),
+            });
+            let mut dst = dst.data.write();
+            let start = (region.dst_offset as usize).min(dst.len());
+            let len = data.len().min(dst.len() - start);
+            dst[start..start + len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    fn bind_compute_pipeline(
+        &mut self,
+        pipeline: &<SoftContext as GpuContext>::ComputePipelineHandle,
+    ) {
+        self.compute_pipeline = Some(pipeline.clone());
+    }
+
+    fn compute_push_constants(
+        &mut self,
+        _pipeline: &<SoftContext as GpuContext>::ComputePipelineHandle,
+        offset: u32,
+        data: &[u32],
+    ) {
+        write_push_constants(&mut self.compute_push_constants, offset, data);
+    }
+
+    fn snort_compute_glue(
+        &mut self,
+        set_idx: usize,
+        _pipeline: &<SoftContext as GpuContext>::ComputePipelineHandle,
+        glue: &Glue<SoftContext>,
+    ) {
+        bind_set(&mut self.compute_sets, set_idx, glue);
+    }
+
+    /// Runs every invocation of every work group, one after another
+    fn dispatch(&mut self, groups: [u32; 3]) {
+        if self.framebuffer.is_some() {
+            log::warn!("[SoftCommand] (dispatch) called inside of a render pass");
+        }
+        let pipeline = match &self.compute_pipeline {
+            Some(pipeline) => pipeline,
+            None => {
+                log::warn!("[SoftCommand] (dispatch) no compute pipeline bound");
+                return;
+            }
+        };
+
+        let bound = BoundResources::new(&self.compute_sets);
+        let env = bound.env(&self.compute_push_constants);
+        let [x, y, z] = pipeline.local_size;
+        for gz in 0..groups[2] * z {
+            for gy in 0..groups[1] * y {
+                for gx in 0..groups[0] * x {
+                    (pipeline.shader)(&env, [gx, gy, gz]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::{borrow::Borrow, collections::HashMap, ops::Deref, path::Path, sync::Arc};
+
+use bytemuck::Pod;
+use core::{
+    anyhow::{self, anyhow},
+    thiserror::{self, Error},
+};
+use parking_lot::RwLock;
+use render::{
+    context::{GpuBuilder, GpuContext},
+    prelude::MixturePart,
+    resource::{
+        buffer::BufferDescriptor,
+        frame::Extent3D,
+        glue::{Descriptor, DescriptorWrite, Mixture},
+        pipeline::{
+            ComputePipelineDescriptor, GraphicsPipelineDescriptor, PolygonMode, RenderContext,
+            ShaderSource,
+        },
+        render_pass::RenderPassDescriptor,
+        texture::{SamplerDescriptor, TextureDescriptor},
+    },
+};
+
+use crate::{
+    command::SoftCommand,
+    context_builder::SoftBuilder,
+    graph::{builder::SoftGraphBuilder, SoftGraph},
+    resources::{
+        BoundBuffer, SoftBuffer, SoftComputePipeline, SoftDescriptorLayout, SoftDescriptorSet,
+        SoftFramebuffer, SoftImage, SoftPipeline, SoftRenderPass,
+    },
+    shader::SoftShader,
+    surface::SoftSurface,
+    texture::{SoftSampler, SoftTexture},
+};
+
+pub type ContextBuilder = SoftBuilder;
+pub type Context = <ContextBuilder as GpuBuilder>::Context;
+
+/// There is no rust version of a shader that was requested from `compile_shader`
+#[derive(Debug, Error)]
+#[error("[SoftContext] shader {name} has no software version, it has to be registered with register_shader")]
+pub struct MissingShader {
+    pub name: String,
+}
+
+/// The software implementation of the Rendering Context described in render
+#[derive(Debug, Default)]
+pub struct SoftContext {
+    shaders: RwLock<HashMap<String, SoftShader>>,
+}
+
+impl SoftContext {
+    /// Registers the rust implementation of a shader, compile_shader will look it up by the name
+    /// of the shader source (or the file name for GlslFile)
+    pub fn register_shader(&self, name: impl Into<String>, shader: SoftShader) {
+        self.shaders.write().insert(name.into(), shader);
+    }
+
+    fn find_shader(&self, name: &str) -> anyhow::Result<SoftShader> {
+        self.shaders.read().get(name).cloned().ok_or_else(|| {
+            MissingShader {
+                name: name.to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+impl GpuContext for SoftContext {
+    type SurfaceHandle = SoftSurface;
+    type BufferHandle = SoftBuffer;
+    type PipelineHandle = Arc<SoftPipeline>;
+    type ComputePipelineHandle = Arc<SoftComputePipeline>;
+    type RenderPassHandle = SoftRenderPass;
+    type ShaderCode = SoftShader;
+    type ImageView = SoftImage;
+    type Framebuffer = SoftFramebuffer;
+    type CommandBuffer = ();
+    type DescriptorLayout = SoftDescriptorLayout;
+    type DescriptorSet = SoftDescriptorSet;
+    type TextureHandle = SoftTexture;
+    type SamplerHandle = SoftSampler;
+    type CommandEncoder = SoftCommand;
+    type SwapchainImage = SoftImage;
+    type Graph = SoftGraph;
+    type GraphBuilder = SoftGraphBuilder;
+
+    fn create_buffer(&self, desc: &BufferDescriptor) -> Self::BufferHandle {
+        SoftBuffer::new(desc.name.to_string(), desc.size as usize)
+    }
+
+    unsafe fn write_to_buffer<D: Pod>(&self, buffer: &Self::BufferHandle, data: &D) {
+        self.write_to_buffer_raw(buffer, bytemuck::bytes_of(data));
+    }
+
+    unsafe fn write_to_buffer_raw(&self, buffer: &Self::BufferHandle, data: &[u8]) {
+        let mut buffer_data = buffer.data.write();
+        if data.len() > buffer_data.len() {
+            panic!(
+                "[SoftContext] (write_to_buffer) {} bytes do not fit into buffer [{}] of size {}",
+                data.len(),
+                buffer.name,
+                buffer_data.len()
+            );
+        }
+        buffer_data[..data.len()].copy_from_slice(data);
+    }
+
+    fn upload_buffer(&self, buffer: &Self::BufferHandle, offset: u64, data: &[u8]) {
+        let mut buffer_data = buffer.data.write();
+        let offset = offset as usize;
+        if offset + data.len() > buffer_data.len() {
+            panic!(
+                "[SoftContext] (upload_buffer) {} bytes at {} do not fit into buffer [{}] of size {}",
+                data.len(),
+                offset,
+                buffer.name,
+                buffer_data.len()
+            );
+        }
+        buffer_data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    // Uploads are executed immediately
+    fn flush_uploads(&self) {}
+
+    fn drop_buffer(&self, _buffer: Self::BufferHandle) {}
+
+    fn create_texture(&self, desc: &TextureDescriptor) -> Self::TextureHandle {
+        SoftTexture::new(desc)
+    }
+
+    fn upload_texture(&self, texture: &Self::TextureHandle, levels: &[&[u8]]) {
+        texture.upload(levels);
+    }
+
+    fn drop_texture(&self, _texture: Self::TextureHandle) {}
+
+    fn create_sampler(&self, desc: &SamplerDescriptor) -> Self::SamplerHandle {
+        SoftSampler(desc.clone())
+    }
+
+    fn drop_sampler(&self, _sampler: Self::SamplerHandle) {}
+
+    fn create_render_pass(&self, desc: &RenderPassDescriptor) -> Self::RenderPassHandle {
+        SoftRenderPass {
+            attachments: desc.attachments.clone(),
+        }
+    }
+
+    fn drop_render_pass(&self, _rp: Self::RenderPassHandle) {}
+
+    fn create_graphics_pipeline(
+        &self,
+        desc: GraphicsPipelineDescriptor<Self>,
+        _render_context: RenderContext<Self>,
+    ) -> Self::PipelineHandle {
+        let vertex = match desc.shaders.vertex {
+            SoftShader::Vertex(shader) => shader.clone(),
+            other => panic!(
+                "[SoftContext] (create_graphics_pipeline) {:?} used as vertex shader in {}",
+                other, desc.name
+            ),
+        };
+        let fragment = match desc.shaders.fragment {
+            SoftShader::Fragment(shader) => shader.clone(),
+            other => panic!(
+                "[SoftContext] (create_graphics_pipeline) {:?} used as fragment shader in {}",
+                other, desc.name
+            ),
+        };
+        if desc.shaders.geometry.is_some() {
+            log::warn!("[SoftContext] geometry shaders are not supported, ignoring it");
+        }
+        if !matches!(desc.rasterizer.polygon_mode, PolygonMode::Fill) {
+            log::warn!(
+                "[SoftContext] polygon mode {:?} is not supported, using Fill",
+                desc.rasterizer.polygon_mode
+            );
+        }
+
+        Arc::new(SoftPipeline {
+            name: desc.name.to_string(),
+            vertex,
+            fragment,
+            vertex_buffers: desc.vertex_buffers,
+            attributes: desc.attributes,
+            primitive: desc.primitive,
+            rasterizer: desc.rasterizer,
+            blend_targets: desc.blend_targets,
+            depth: desc.depth,
+            samples: desc.samples,
+            pipeline_states: desc.pipeline_states,
+        })
+    }
+
+    fn drop_pipeline(&self, _pipeline: Self::PipelineHandle) {}
+
+    fn create_compute_pipeline(
+        &self,
+        desc: ComputePipelineDescriptor<Self>,
+    ) -> Self::ComputePipelineHandle {
+        let (shader, local_size) = match desc.shader {
+            SoftShader::Compute(shader, local_size) => (shader.clone(), *local_size),
+            other => panic!(
+                "[SoftContext] (create_compute_pipeline) {:?} used as compute shader in {}",
+                other, desc.name
+            ),
+        };
+        Arc::new(SoftComputePipeline {
+            name: desc.name.to_string(),
+            shader,
+            local_size,
+        })
+    }
+
+    fn drop_compute_pipeline(&self, _pipeline: Self::ComputePipelineHandle) {}
+
+    fn compile_shader(&self, source: ShaderSource) -> anyhow::Result<Self::ShaderCode> {
+        match source {
+            ShaderSource::GlslFile(path) => {
+                let name = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .ok_or_else(|| anyhow!("[SoftContext] invalid shader path: {:?}", path))?;
+                self.find_shader(name)
+            }
+            ShaderSource::GlslSource {
+                name, shader_type, ..
+            } => {
+                let name = name.ok_or_else(|| {
+                    anyhow!("[SoftContext] shaders need a name to be found in the registry")
+                })?;
+                // The name might also be a path
+                let name = Path::new(name)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or(name);
+                let shader = self.find_shader(name)?;
+                if std::mem::discriminant(&shader.shader_type())
+                    != std::mem::discriminant(&shader_type)
+                {
+                    return Err(anyhow!(
+                        "[SoftContext] shader {} is registered as {:?}, but {:?} was requested",
+                        name,
+                        shader.shader_type(),
+                        shader_type
+                    ));
+                }
+                Ok(shader)
+            }
+            ShaderSource::Spirv(_) => Err(anyhow!(
+                "[SoftContext] spirv can not be executed by the software backend"
+            )),
+        }
+    }
+
+    fn create_framebuffer<I>(
+        &self,
+        _rp: &Self::RenderPassHandle,
+        attachments: I,
+        extent: Extent3D,
+    ) -> Self::Framebuffer
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Self::ImageView>,
+    {
+        SoftFramebuffer {
+            attachments: attachments
+                .into_iter()
+                .map(|a| a.borrow().clone())
+                .collect(),
+            extent,
+        }
+    }
+
+    fn drop_framebuffer(&self, _fb: Self::Framebuffer) {}
+
+    fn create_descriptor_layout<I>(&self, parts: I) -> Self::DescriptorLayout
+    where
+        I: IntoIterator<Item = MixturePart>,
+    {
+        SoftDescriptorLayout {
+            parts: parts.into_iter().collect(),
+        }
+    }
+
+    fn drop_descriptor_layout(&self, _handle: Self::DescriptorLayout) {}
+
+    fn create_descriptor_set(&self, layout: &Mixture<Self>) -> Self::DescriptorSet {
+        SoftDescriptorSet {
+            parts: layout.gpu_layout.handle.deref().parts.clone(),
+            bindings: Default::default(),
+            textures: Default::default(),
+            storage_images: Default::default(),
+        }
+    }
+
+    fn drop_descriptor_set(&self, _handle: Self::DescriptorSet) {}
+
+    fn update_descriptor_set(
+        &self,
+        handle: &Self::DescriptorSet,
+        writes: Vec<DescriptorWrite<Self>>,
+    ) {
+        let mut bindings = handle.bindings.write();
+        for write in writes {
+            let DescriptorWrite {
+                binding,
+                array_offset,
+                descriptor,
+            } = write;
+            match descriptor {
+                Descriptor::Buffer(buffer, range) => {
+                    let bound = bindings.entry(binding).or_insert_with(Vec::new);
+                    // A later write to the same array element replaces the old one
+                    bound.retain(|b| b.array_offset != array_offset);
+                    bound.push(BoundBuffer {
+                        array_offset,
+                        buffer: buffer.clone(),
+                        range,
+                    });
+                }
+                Descriptor::Texture(texture, sampler) => {
+                    handle
+                        .textures
+                        .write()
+                        .insert(binding, (texture.clone(), sampler.clone()));
+                }
+                Descriptor::StorageImage(texture) => {
+                    handle
+                        .storage_images
+                        .write()
+                        .insert(binding, texture.clone());
+                }
+            }
+        }
+    }
+
+    fn wait_idle(&self) {}
+
+    fn create_graph(&self, surface: Self::SurfaceHandle) -> Self::GraphBuilder {
+        SoftGraphBuilder::new(surface)
+    }
+}
@@ -0,0 +1,33 @@
+use render::{context::GpuBuilder, prelude::GpuContext, resource::frame::Extent2D};
+
+use crate::{context::SoftContext, surface::SoftSurface};
+
+pub struct SoftBuilder {}
+
+impl SoftBuilder {
+    /// Surfaces of the software backend are never bound to a window, so this does not need one
+    pub fn create_offscreen_surface(&mut self, extent: Extent2D) -> SoftSurface {
+        SoftSurface::new(extent)
+    }
+}
+
+impl GpuBuilder for SoftBuilder {
+    type Context = SoftContext;
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    /// The window is ignored, frames can be read back from the surface instead
+    fn create_surface<W: raw_window_handle::HasRawWindowHandle>(
+        &mut self,
+        _window: &W,
+        extent: Extent2D,
+    ) -> <Self::Context as GpuContext>::SurfaceHandle {
+        self.create_offscreen_surface(extent)
+    }
+
+    fn build(self) -> Self::Context {
+        SoftContext::default()
+    }
+}
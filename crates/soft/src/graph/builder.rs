@@ -0,0 +1,169 @@
+use std::{borrow::Cow, sync::Arc};
+
+use render::{
+    graph::{
+        attachment::GraphAttachment,
+        builder::GraphBuilder,
+        compile::{compile, CompiledGraph, GraphError},
+        node::Node,
+        nodes::{
+            callbacks::UserData,
+            pass::{PassAttachment, PassNode, PassNodeBuilder},
+        },
+    },
+    resource::{frame::Extent2D, render_pass::Attachment},
+    util::format::{TextureFormat, TextureLayout},
+};
+
+use crate::{context::SoftContext, resources::SoftRenderPass, surface::SoftSurface};
+
+use super::{AttachmentIndex, SoftGraph, SoftNode, SoftPassNode, FRAMES_IN_FLIGHT};
+
+pub(crate) const SURFACE_FORMAT: TextureFormat = TextureFormat::Rgba8Srgb;
+pub(crate) const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Sfloat;
+
+pub struct SoftGraphBuilder {
+    pub(super) surface: SoftSurface,
+    pub(super) offscreen: Option<Extent2D>,
+    pub(super) attachments: Vec<GraphAttachment>,
+    pub(super) nodes: Vec<Node<Self>>,
+}
+
+impl SoftGraphBuilder {
+    pub(crate) fn new(surface: SoftSurface) -> Self {
+        Self {
+            surface,
+            offscreen: None,
+            attachments: Default::default(),
+            nodes: Default::default(),
+        }
+    }
+
+    /// Format and samples of an attachment
+    fn describe(&self, index: AttachmentIndex) -> (TextureFormat, u8) {
+        match index {
+            AttachmentIndex::Backbuffer => (SURFACE_FORMAT, 1),
+            AttachmentIndex::Custom(id) => {
+                let attachment = self
+                    .attachments
+                    .iter()
+                    .find(|a| a.id == id)
+                    .expect("[SoftGraph] failed to find attachment");
+                (attachment.format, attachment.samples)
+            }
+        }
+    }
+
+    fn build_pass_node(&self, node: PassNode<Self>) -> SoftPassNode {
+        let attachment = |a: &PassAttachment<AttachmentIndex>| {
+            let (format, samples) = self.describe(a.index);
+            Attachment {
+                format,
+                samples,
+                load_op: a.load.clone(),
+                store_op: a.store.clone(),
+                layouts: TextureLayout::General..TextureLayout::General,
+            }
+        };
+
+        let mut attachments: Vec<Attachment> =
+            node.output_attachments.iter().map(attachment).collect();
+        attachments.extend(node.input_attachments.iter().map(attachment));
+        attachments.extend(node.depth_attachment.iter().map(attachment));
+
+        let render_pass = Arc::new(SoftRenderPass { attachments });
+        node.callbacks.borrow_mut().init(render_pass.clone());
+
+        SoftPassNode {
+            graph_node: node,
+            render_pass,
+        }
+    }
+}
+
+impl GraphBuilder for SoftGraphBuilder {
+    type Context = SoftContext;
+    type AttachmentIndex = AttachmentIndex;
+    type Graph = SoftGraph;
+
+    fn add_node(&mut self, node: Node<Self>) {
+        self.nodes.push(node);
+    }
+
+    fn add_attachment(&mut self, attachment: GraphAttachment) -> Self::AttachmentIndex {
+        let id = attachment.id;
+        self.attachments.push(attachment);
+        AttachmentIndex::Custom(id)
+    }
+
+    fn attachment_index(&self, name: Cow<'static, str>) -> Option<Self::AttachmentIndex> {
+        self.attachments
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| AttachmentIndex::Custom(a.id))
+    }
+
+    fn attachment_name(&self, index: Self::AttachmentIndex) -> Cow<'static, str> {
+        match index {
+            AttachmentIndex::Backbuffer => "backbuffer".into(),
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|a| a.id == id)
+                .map(|a| a.name.clone())
+                .unwrap_or_else(|| "unknown".into()),
+        }
+    }
+
+    fn get_backbuffer_attachment(&self) -> Self::AttachmentIndex {
+        AttachmentIndex::Backbuffer
+    }
+
+    fn render_offscreen(&mut self, extent: Extent2D) {
+        self.offscreen = Some(extent);
+    }
+
+    fn get_surface_format(&self) -> TextureFormat {
+        SURFACE_FORMAT
+    }
+
+    fn default_depth_format(&self) -> TextureFormat {
+        DEPTH_FORMAT
+    }
+
+    fn get_swapchain_image_count(&self) -> usize {
+        FRAMES_IN_FLIGHT
+    }
+
+    /// The rasterizer knows the standard sample positions of these counts
+    fn supports_samples(&self, samples: u8) -> bool {
+        matches!(samples, 1 | 2 | 4 | 8)
+    }
+
+    fn build(mut self) -> Result<Self::Graph, GraphError> {
+        let nodes = std::mem::take(&mut self.nodes);
+        let CompiledGraph { nodes, culled } = compile(&self, nodes)?;
+        let nodes = nodes
+            .into_iter()
+            .map(|n| match n.node {
+                Node::PassNode(n) => SoftNode::PassNode(self.build_pass_node(n)),
+                Node::ComputeNode(n) => {
+                    n.callbacks.borrow_mut().init();
+                    SoftNode::ComputeNode(n)
+                }
+            })
+            .collect();
+
+        Ok(SoftGraph::new(
+            self.surface,
+            self.offscreen,
+            self.attachments,
+            nodes,
+            culled,
+        ))
+    }
+
+    fn build_pass_node<U: UserData>(&self, name: Cow<'static, str>) -> PassNodeBuilder<Self, U> {
+        PassNodeBuilder::new(name)
+    }
+}
@@ -0,0 +1,375 @@
+use std::{any::Any, sync::Arc};
+
+use app::{Resources, World};
+use render::{
+    command_encoder::CommandEncoder,
+    graph::{
+        attachment::{AttachmentSize, GraphAttachment},
+        node::Node,
+        nodes::{callbacks::FrameData, compute::ComputeNode, pass::PassNode},
+        readback::{Readback, ReadbackError},
+        Graph,
+    },
+    resource::{
+        frame::{Clear, Extent2D, Extent3D},
+        pipeline::{Rect, Viewport},
+        texture::TextureDescriptor,
+    },
+};
+use uuid::Uuid;
+
+use crate::{
+    command::SoftCommand,
+    context::SoftContext,
+    resources::{is_depth_format, SoftFramebuffer, SoftImage, SoftRenderPass},
+    surface::{Frame, SoftSurface},
+    texture::SoftTexture,
+};
+
+use self::builder::{SoftGraphBuilder, SURFACE_FORMAT};
+
+pub mod builder;
+
+/// Same as the gfx backend, so that frame indices behave the same
+pub(crate) const FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachmentIndex {
+    Backbuffer,
+    Custom(Uuid),
+}
+
+pub(crate) struct SoftPassNode {
+    pub(crate) graph_node: PassNode<SoftGraphBuilder>,
+    pub(crate) render_pass: Arc<SoftRenderPass>,
+}
+
+/// Compute nodes need no barriers, since every command is executed directly
+pub(crate) enum SoftNode {
+    PassNode(SoftPassNode),
+    ComputeNode(ComputeNode<SoftGraphBuilder>),
+}
+
+fn viewport(extent: &Extent2D) -> Viewport {
+    Viewport {
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: extent.width as i16,
+            height: extent.height as i16,
+        },
+        depth: 0.0..1.0,
+    }
+}
+
+pub struct SoftGraph {
+    surface: SoftSurface,
+    /// Size of the backbuffer when rendering offscreen, it is never presented to the surface
+    offscreen: Option<Extent2D>,
+    extent: Extent2D,
+    backbuffer: SoftImage,
+    attachments: Vec<(GraphAttachment, SoftImage)>,
+    /// Copies of the attachments that are sampled (or read as input) by pass nodes, updated
+    /// before these passes
+    sampled: Vec<(Uuid, SoftTexture)>,
+    nodes: Vec<SoftNode>,
+    /// Culled during compilation, only kept for into_builder
+    culled: Vec<Node<SoftGraphBuilder>>,
+    frame_index: usize,
+    /// Data returned by the passes, kept alive until the frame index is used again
+    pass_data: Vec<Vec<Box<dyn Any>>>,
+    /// Copies requested for the next execution
+    readbacks: Vec<(AttachmentIndex, Readback)>,
+}
+
+impl SoftGraph {
+    pub(crate) fn new(
+        surface: SoftSurface,
+        offscreen: Option<Extent2D>,
+        attachments: Vec<GraphAttachment>,
+        nodes: Vec<SoftNode>,
+        culled: Vec<Node<SoftGraphBuilder>>,
+    ) -> Self {
+        let extent = offscreen.clone().unwrap_or_else(|| surface.extent());
+        let attachments: Vec<(GraphAttachment, SoftImage)> = attachments
+            .into_iter()
+            .map(|desc| {
+                let image =
+                    SoftImage::new(desc.size.extent(&extent), desc.format, desc.samples as u32);
+                (desc, image)
+            })
+            .collect();
+        let mut sampled: Vec<(Uuid, SoftTexture)> = Vec::new();
+        for node in nodes.iter() {
+            if let SoftNode::PassNode(node) = node {
+                let graph_node = &node.graph_node;
+                let inputs = graph_node.input_attachments.iter().map(|a| &a.index);
+                for index in graph_node.sampled_attachments.iter().chain(inputs) {
+                    match index {
+                        AttachmentIndex::Custom(id) if Self::multisampled(&attachments, *id) => {
+                            panic!("[SoftGraph] multisampled attachments can not be sampled")
+                        }
+                        AttachmentIndex::Custom(id) if sampled.iter().all(|(s, _)| s != id) => {
+                            sampled.push((*id, Self::sampled_texture(&attachments, *id)))
+                        }
+                        AttachmentIndex::Custom(_) => (),
+                        AttachmentIndex::Backbuffer => {
+                            panic!("[SoftGraph] the backbuffer can not be sampled")
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            surface,
+            offscreen,
+            backbuffer: SoftImage::new(extent.clone(), SURFACE_FORMAT, 1),
+            extent,
+            attachments,
+            sampled,
+            nodes,
+            culled,
+            frame_index: 0,
+            pass_data: (0..FRAMES_IN_FLIGHT).map(|_| Vec::new()).collect(),
+            readbacks: Vec::new(),
+        }
+    }
+
+    /// Sampled textures are kept (glue might reference them), they take the size of the
+    /// attachment when it is copied
+    fn resize(&mut self, extent: Extent2D) {
+        self.backbuffer = SoftImage::new(extent.clone(), SURFACE_FORMAT, 1);
+        for (desc, image) in self.attachments.iter_mut() {
+            if let AttachmentSize::Relative(_, _) = desc.size {
+                let samples = desc.samples as u32;
+                *image = SoftImage::new(desc.size.extent(&extent), desc.format, samples);
+            }
+        }
+        self.extent = extent;
+    }
+
+    fn multisampled(attachments: &[(GraphAttachment, SoftImage)], id: Uuid) -> bool {
+        attachments
+            .iter()
+            .any(|(desc, _)| desc.id == id && desc.samples > 1)
+    }
+
+    fn sampled_texture(attachments: &[(GraphAttachment, SoftImage)], id: Uuid) -> SoftTexture {
+        let (desc, image) = attachments
+            .iter()
+            .find(|(desc, _)| desc.id == id)
+            .expect("[SoftGraph] failed to find sampled attachment");
+        SoftTexture::new(&TextureDescriptor {
+            name: desc.name.clone(),
+            extent: image.0.read().extent.clone(),
+            format: desc.format,
+            mip_levels: 1,
+            storage: false,
+        })
+    }
+
+    fn texture(&self, index: AttachmentIndex) -> &SoftTexture {
+        self.sampled
+            .iter()
+            .find(|(id, _)| index == AttachmentIndex::Custom(*id))
+            .map(|(_, texture)| texture)
+            .expect("[SoftGraph] (execute) failed to load sampled attachment")
+    }
+
+    fn find_image(&self, index: AttachmentIndex) -> Option<SoftImage> {
+        match index {
+            AttachmentIndex::Backbuffer => Some(self.backbuffer.clone()),
+            AttachmentIndex::Custom(id) => self
+                .attachments
+                .iter()
+                .find(|(desc, _)| desc.id == id)
+                .map(|(_, image)| image.clone()),
+        }
+    }
+
+    fn image(&self, index: AttachmentIndex) -> SoftImage {
+        self.find_image(index)
+            .expect("[SoftGraph] (execute) failed to load custom attachment")
+    }
+}
+
+impl Graph for SoftGraph {
+    type Context = SoftContext;
+    type AttachmentIndex = AttachmentIndex;
+    type Builder = SoftGraphBuilder;
+
+    fn execute(&mut self, world: &World, resources: &Resources) {
+        // Surfaces created for a window follow its size, headless ones are resized directly
+        if let Ok(resize_events) = resources.get::<app::Events<window::events::WindowResize>>() {
+            if let Some(&window::events::WindowResize(new_extent)) = resize_events.iter().last() {
+                self.surface.resize(Extent2D {
+                    width: new_extent.width,
+                    height: new_extent.height,
+                });
+            }
+        }
+        let extent = self
+            .offscreen
+            .clone()
+            .unwrap_or_else(|| self.surface.extent());
+        if extent.width != self.extent.width || extent.height != self.extent.height {
+            self.resize(extent.clone());
+        }
+
+        let index = self.frame_index;
+        self.pass_data[index].clear();
+
+        let mut command = SoftCommand::new();
+        for node in self.nodes.iter() {
+            let node = match node {
+                SoftNode::PassNode(node) => node,
+                SoftNode::ComputeNode(node) => {
+                    let frame_data = FrameData {
+                        cmd: &mut command,
+                        frame_index: index as u32,
+                        viewport: viewport(&extent),
+                        sampled: Vec::new(),
+                        inputs: Vec::new(),
+                    };
+                    match node
+                        .callbacks
+                        .borrow_mut()
+                        .run(frame_data, world, resources)
+                    {
+                        Ok(Some(data)) => self.pass_data[index].push(data),
+                        Ok(None) => (),
+                        Err(e) => {
+                            panic!(
+                                "[SoftGraph] execution failed during compute node: {}, with error: {}",
+                                node.name, e
+                            )
+                        }
+                    }
+                    continue;
+                }
+            };
+            let graph_node = &node.graph_node;
+            let mut attachments = graph_node.output_attachments.clone();
+            attachments.extend(graph_node.input_attachments.clone());
+            if let Some(a) = &graph_node.depth_attachment {
+                attachments.push(a.clone())
+            };
+            // The render area is the size of the attachments, which might not be the surface
+            let pass_extent = graph_node
+                .first_attachment()
+                .map(|a| self.image(a).0.read().extent.clone())
+                .unwrap_or_else(|| extent.clone());
+            let framebuffer = SoftFramebuffer {
+                attachments: attachments.iter().map(|a| self.image(a.index)).collect(),
+                extent: Extent3D {
+                    width: pass_extent.width,
+                    height: pass_extent.height,
+                    depth: 1,
+                },
+            };
+            let viewport = viewport(&pass_extent);
+
+            // Previous passes are finished, so their attachments can be copied
+            let copy = |a: &AttachmentIndex| {
+                let texture = self.texture(*a);
+                texture.copy_image(&self.image(*a).0.read());
+                texture
+            };
+            let sampled: Vec<&SoftTexture> =
+                graph_node.sampled_attachments.iter().map(copy).collect();
+            let inputs: Vec<&SoftTexture> = graph_node
+                .input_attachments
+                .iter()
+                .map(|a| copy(&a.index))
+                .collect();
+
+            // Same clear values as the gfx graph
+            let clear_values: Vec<Clear> = node
+                .render_pass
+                .attachments
+                .iter()
+                .map(|a| {
+                    if is_depth_format(a.format) {
+                        Clear::Depth(1.0, 0)
+                    } else {
+                        Clear::Color(0.2, 0.5, 0.1, 1.0)
+                    }
+                })
+                .collect();
+            command.begin_render_pass(
+                &node.render_pass,
+                &framebuffer,
+                viewport.rect.clone(),
+                clear_values,
+            );
+
+            let frame_data = FrameData {
+                cmd: &mut command,
+                frame_index: index as u32,
+                viewport,
+                sampled,
+                inputs,
+            };
+            match graph_node
+                .callbacks
+                .borrow_mut()
+                .run(frame_data, world, resources)
+            {
+                Ok(Some(data)) => self.pass_data[index].push(data),
+                Ok(None) => (),
+                Err(e) => {
+                    panic!(
+                        "[SoftGraph] execution failed during pass node: {}, with error: {}",
+                        graph_node.name, e
+                    )
+                }
+            }
+
+            command.end_render_pass();
+
+            // Like the subpass resolves of the gfx graph, at the end of the pass
+            for resolve in graph_node.resolve_attachments.iter() {
+                let (source, target) = (self.image(resolve.source), self.image(resolve.target));
+                target.0.write().resolve(&source.0.read());
+            }
+        }
+
+        // Every command was executed directly, so the copies are done with the frame
+        for (index, readback) in std::mem::take(&mut self.readbacks) {
+            readback.complete(match self.find_image(index) {
+                Some(image) => image.0.read().read_back(),
+                None => Err(ReadbackError::UnknownAttachment(format!("{:?}", index))),
+            });
+        }
+
+        if self.offscreen.is_none() {
+            self.surface
+                .present(Frame::from_image(&self.backbuffer.0.read()));
+        }
+        self.frame_index = (self.frame_index + 1) % FRAMES_IN_FLIGHT;
+    }
+
+    fn read_attachment(&mut self, index: Self::AttachmentIndex) -> Readback {
+        let readback = Readback::new();
+        self.readbacks.push((index, readback.clone()));
+        readback
+    }
+
+    fn into_builder(self) -> Self::Builder {
+        SoftGraphBuilder {
+            surface: self.surface,
+            offscreen: self.offscreen,
+            attachments: self.attachments.into_iter().map(|(desc, _)| desc).collect(),
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|n| match n {
+                    SoftNode::PassNode(n) => Node::PassNode(n.graph_node),
+                    SoftNode::ComputeNode(n) => Node::ComputeNode(n),
+                })
+                .chain(self.culled)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,636 @@
+#![feature(trait_alias)]
+
+//! Software implementation of the render api
+//!
+//! Everything is executed on the cpu, directly when the command is recorded. This is by no means
+//! fast, but it lets us run the renderer (and compare its output) on machines without a gpu.
+//! Since there is no way to run glsl on the cpu, shaders are rust functions that are registered
+//! on the context under the name of the shader file (see [`SoftContext::register_shader`])
+
+pub mod command;
+pub mod context;
+pub mod context_builder;
+pub mod graph;
+mod raster;
+pub mod resources;
+pub mod shader;
+pub mod surface;
+pub mod texture;
+
+pub use context::{Context, ContextBuilder, MissingShader, SoftContext};
+pub use shader::{ShaderEnv, SoftShader, Varyings, VertexOutput};
+pub use surface::{Frame, SoftSurface};
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Deref, sync::Arc};
+
+    use app::{Resources, World};
+    use render::{
+        context::GpuBuilder,
+        graph::{
+            attachment::{AttachmentSize, GraphAttachment},
+            builder::GraphBuilder,
+            node::Node,
+            Graph,
+        },
+        prelude::*,
+        resource::{
+            buffer::BufferDescriptor,
+            frame::Extent2D,
+            pipeline::{
+                AttributeDescriptor, DepthDescriptor, PipelineShaders, PipelineStates, Primitive,
+                Rasterizer, RenderContext, VertexAttributeFormat, VertexBufferDescriptor,
+                VertexInputRate,
+            },
+            render_pass::{LoadOp, StoreOp},
+        },
+        util::format::TextureFormat,
+    };
+
+    use super::*;
+    use bytemuck::{Pod, Zeroable};
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct Scale([f32; 4]);
+
+    unsafe impl Zeroable for Scale {}
+    unsafe impl Pod for Scale {}
+
+    const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+    /// Push constants: offset of the vertices at 0, color at 16
+    /// Uniform (binding 0): scale of the vertices in xy
+    fn register_shaders(ctx: &SoftContext) {
+        ctx.register_shader(
+            "test.vert",
+            SoftShader::vertex(|env, attributes| {
+                let offset: [f32; 4] = env.push_constant(0);
+                let scale: [f32; 4] = env.uniform(0, 0);
+                let [x, y, z, w] = attributes[0];
+                VertexOutput::new([
+                    x * scale[0] + offset[0],
+                    y * scale[1] + offset[1],
+                    z + offset[2],
+                    w,
+                ])
+            }),
+        );
+        ctx.register_shader(
+            "test.frag",
+            SoftShader::fragment(|env, _varyings| env.push_constant(16)),
+        );
+    }
+
+    #[test]
+    fn depth_tested_triangles() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 32,
+            height: 32,
+        });
+        let ctx = Arc::new(builder.build());
+        register_shaders(&ctx);
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+
+        // A triangle in the middle and one covering the whole screen
+        let vertices: [[f32; 4]; 6] = [
+            [-1.0, -1.0, 0.0, 1.0],
+            [1.0, -1.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [-4.0, -4.0, 0.0, 1.0],
+            [8.0, -4.0, 0.0, 1.0],
+            [-4.0, 8.0, 0.0, 1.0],
+        ];
+        let vertex_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-vertices".into(),
+            size: std::mem::size_of_val(&vertices) as u64,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Vertex,
+        });
+        let scale_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-scale".into(),
+            size: 16,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Uniform,
+        });
+        unsafe {
+            ctx.write_to_buffer(&vertex_buffer, &vertices);
+            ctx.write_to_buffer(&scale_buffer, &Scale([0.5, 0.5, 1.0, 1.0]));
+        }
+
+        let mixture = Arc::new(resources.stir(mixture![0: "scale" in Vertex: Scale]));
+        let glue = {
+            let mut bottle = resources.bottle(&mixture);
+            bottle.write_buffer(PartIndex::Binding(0), &scale_buffer, None);
+            bottle.apply()
+        };
+
+        let mut graph_builder = ctx.create_graph(surface.clone());
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let depth = graph_builder.add_attachment(GraphAttachment::new(
+            "depth",
+            AttachmentSize::SWAPCHAIN,
+            graph_builder.default_depth_format(),
+        ));
+
+        let mut pass = graph_builder.build_pass_node("test_pass".into());
+        pass.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        pass.set_depth(depth, LoadOp::Clear, StoreOp::DontCare);
+        let vertex_shader = ctx
+            .compile_shader(ShaderSource::GlslFile(
+                std::path::Path::new("test.vert").into(),
+            ))
+            .unwrap();
+        let fragment_shader = ctx
+            .compile_shader(ShaderSource::GlslFile(
+                std::path::Path::new("test.frag").into(),
+            ))
+            .unwrap();
+        pass.init(Box::new(move |render_pass| {
+            let desc = GraphicsPipelineDescriptor {
+                name: "test_pipeline".into(),
+                mixtures: vec![&mixture],
+                push_constants: vec![(ShaderType::Vertex, 0..16), (ShaderType::Fragment, 16..32)],
+                shaders: PipelineShaders {
+                    vertex: &vertex_shader,
+                    fragment: &fragment_shader,
+                    geometry: None,
+                },
+                rasterizer: Rasterizer::FILL,
+                vertex_buffers: vec![VertexBufferDescriptor::new(0, 16, VertexInputRate::Vertex)],
+                attributes: vec![AttributeDescriptor::new(
+                    0,
+                    0,
+                    0,
+                    VertexAttributeFormat::Vec4,
+                )],
+                primitive: Primitive::TriangleList,
+                blend_targets: vec![false],
+                depth: Some(DepthDescriptor::LESS),
+                samples: 1,
+                pipeline_states: PipelineStates::DYNAMIC,
+            };
+            Box::new(resources.create_graphics_pipeline(
+                desc,
+                RenderContext::RenderPass((render_pass.deref(), 0)),
+            ))
+        }));
+        pass.callback(Box::new(move |frame, pipeline, _world, _resources| {
+            let cmd = frame.cmd;
+            cmd.bind_graphics_pipeline(pipeline);
+            cmd.set_viewport(0, frame.viewport.clone());
+            cmd.set_scissor(0, frame.viewport.rect);
+            cmd.snort_glue(0, pipeline, &glue);
+            cmd.bind_vertex_buffer(0, &vertex_buffer, BufferRange::WHOLE);
+
+            // The green triangle is in front, so the red one drawn afterwards is hidden behind it
+            let draws = [
+                ([0.0f32, 0.0, 0.2, 0.0], GREEN, 0..3),
+                ([0.0, 0.0, 0.5, 0.0], RED, 3..6),
+            ];
+            for (offset, color, vertices) in draws.iter() {
+                cmd.push_constants(
+                    pipeline,
+                    ShaderType::Vertex,
+                    0,
+                    bytemuck::cast_slice(offset),
+                );
+                cmd.push_constants(
+                    pipeline,
+                    ShaderType::Fragment,
+                    16,
+                    bytemuck::cast_slice(color),
+                );
+                cmd.draw(vertices.clone(), 0..1);
+            }
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+
+        let mut graph = graph_builder.build().unwrap();
+        graph.execute(&World::new(), &Resources::new());
+
+        let frame = surface.last_frame().expect("graph did not present");
+        assert_eq!((frame.width, frame.height), (32, 32));
+        // Center is covered by the green triangle
+        assert_eq!(frame.pixel(16, 16), [0, 255, 0, 255]);
+        // Outside of the (scaled down) green triangle
+        assert_eq!(frame.pixel(16, 4), [255, 0, 0, 255]);
+        assert_eq!(frame.pixel(1, 30), [255, 0, 0, 255]);
+
+        // Rendering is deterministic
+        graph.execute(&World::new(), &Resources::new());
+        let second = surface.last_frame().unwrap();
+        assert_eq!(frame.differing_pixels(&second, 0), Some(0));
+        assert_eq!(frame.mean_difference(&second), Some(0.0));
+    }
+
+    #[test]
+    fn multisampled_edges_are_resolved() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 8,
+            height: 8,
+        });
+        let ctx = Arc::new(builder.build());
+        register_shaders(&ctx);
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+
+        // The lower left half of the screen, the diagonal cuts through the centers of texels
+        let vertices: [[f32; 4]; 3] = [
+            [-1.0, -1.0, 0.0, 1.0],
+            [1.0, -1.0, 0.0, 1.0],
+            [-1.0, 1.0, 0.0, 1.0],
+        ];
+        let vertex_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-vertices".into(),
+            size: std::mem::size_of_val(&vertices) as u64,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Vertex,
+        });
+        let scale_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-scale".into(),
+            size: 16,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Uniform,
+        });
+        unsafe {
+            ctx.write_to_buffer(&vertex_buffer, &vertices);
+            ctx.write_to_buffer(&scale_buffer, &Scale([1.0, 1.0, 1.0, 1.0]));
+        }
+        let mixture = Arc::new(resources.stir(mixture![0: "scale" in Vertex: Scale]));
+        let glue = {
+            let mut bottle = resources.bottle(&mixture);
+            bottle.write_buffer(PartIndex::Binding(0), &scale_buffer, None);
+            bottle.apply()
+        };
+
+        let mut graph_builder = ctx.create_graph(surface);
+        graph_builder.render_offscreen(Extent2D {
+            width: 8,
+            height: 8,
+        });
+        assert!(graph_builder.supports_samples(4));
+        assert!(!graph_builder.supports_samples(3));
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let mut desc = GraphAttachment::new(
+            "scene",
+            AttachmentSize::SWAPCHAIN,
+            graph_builder.get_surface_format(),
+        );
+        desc.samples = 4;
+        let scene = graph_builder.add_attachment(desc);
+
+        let mut pass = graph_builder.build_pass_node("scene_pass".into());
+        pass.add_output(scene, LoadOp::Clear, StoreOp::DontCare);
+        pass.add_resolve(scene, backbuffer);
+        let shader = |name: &str| {
+            ctx.compile_shader(ShaderSource::GlslFile(std::path::Path::new(name).into()))
+                .unwrap()
+        };
+        let (vertex_shader, fragment_shader) = (shader("test.vert"), shader("test.frag"));
+        pass.init(Box::new(move |render_pass| {
+            let desc = GraphicsPipelineDescriptor {
+                name: "test_pipeline".into(),
+                mixtures: vec![&mixture],
+                push_constants: vec![(ShaderType::Vertex, 0..16), (ShaderType::Fragment, 16..32)],
+                shaders: PipelineShaders {
+                    vertex: &vertex_shader,
+                    fragment: &fragment_shader,
+                    geometry: None,
+                },
+                rasterizer: Rasterizer::FILL,
+                vertex_buffers: vec![VertexBufferDescriptor::new(0, 16, VertexInputRate::Vertex)],
+                attributes: vec![AttributeDescriptor::new(
+                    0,
+                    0,
+                    0,
+                    VertexAttributeFormat::Vec4,
+                )],
+                primitive: Primitive::TriangleList,
+                blend_targets: vec![false],
+                depth: None,
+                samples: 4,
+                pipeline_states: PipelineStates::DYNAMIC,
+            };
+            Box::new(resources.create_graphics_pipeline(
+                desc,
+                RenderContext::RenderPass((render_pass.deref(), 0)),
+            ))
+        }));
+        pass.callback(Box::new(move |frame, pipeline, _world, _resources| {
+            let cmd = frame.cmd;
+            cmd.bind_graphics_pipeline(pipeline);
+            cmd.set_viewport(0, frame.viewport.clone());
+            cmd.set_scissor(0, frame.viewport.rect);
+            cmd.snort_glue(0, pipeline, &glue);
+            cmd.bind_vertex_buffer(0, &vertex_buffer, BufferRange::WHOLE);
+            let offset = [0.0f32; 4];
+            cmd.push_constants(
+                pipeline,
+                ShaderType::Vertex,
+                0,
+                bytemuck::cast_slice(&offset),
+            );
+            cmd.push_constants(
+                pipeline,
+                ShaderType::Fragment,
+                16,
+                bytemuck::cast_slice(&GREEN),
+            );
+            cmd.draw(0..3, 0..1);
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        let resolved = graph.read_attachment(backbuffer);
+        let multisampled = graph.read_attachment(scene);
+        graph.execute(&World::new(), &Resources::new());
+        assert!(matches!(
+            multisampled.take(),
+            Some(Err(render::graph::readback::ReadbackError::Multisampled))
+        ));
+
+        let image = resolved.take().unwrap().unwrap();
+        let rgba = image.to_rgba8().unwrap();
+        let pixel = |x: usize, y: usize| &rgba[(y * 8 + x) * 4..(y * 8 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(7, 7), [124, 188, 89, 255]);
+        // Half of the samples on the diagonal are covered, so the edge is blended
+        let edge = pixel(0, 7);
+        assert!(edge[0] > 0 && edge[0] < 124, "{:?}", edge);
+        assert!(edge[1] > 188 && edge[1] < 255, "{:?}", edge);
+    }
+
+    #[test]
+    fn offscreen_backbuffer_is_read_back() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 32,
+            height: 32,
+        });
+        let ctx = Arc::new(builder.build());
+
+        let mut graph_builder = ctx.create_graph(surface.clone());
+        graph_builder.render_offscreen(Extent2D {
+            width: 8,
+            height: 4,
+        });
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let mut pass = graph_builder.build_pass_node::<()>("clear".into());
+        pass.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        pass.init(Box::new(|_render_pass| Box::new(())));
+        pass.callback(Box::new(|_frame, _data, _world, _resources| Ok(None)));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        let readback = graph.read_attachment(backbuffer);
+        assert!(!readback.is_ready());
+        graph.execute(&World::new(), &Resources::new());
+
+        // Nothing is presented, the clear color ends up in the readback
+        assert!(surface.last_frame().is_none());
+        let image = readback.take().unwrap().unwrap();
+        assert_eq!((image.extent.width, image.extent.height), (8, 4));
+        let rgba = image.to_rgba8().unwrap();
+        assert_eq!(rgba.len(), 8 * 4 * 4);
+        assert_eq!(rgba[..4], [124, 188, 89, 255]);
+    }
+
+    #[test]
+    fn input_attachments_are_copied() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 4,
+            height: 4,
+        });
+        let ctx = Arc::new(builder.build());
+
+        let mut graph_builder = ctx.create_graph(surface);
+        let hdr = graph_builder.add_attachment(GraphAttachment::new(
+            "hdr",
+            AttachmentSize::SWAPCHAIN,
+            TextureFormat::Rgba16Sfloat,
+        ));
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+        let texel = std::rc::Rc::new(std::cell::Cell::new(None));
+        let mut resolve = graph_builder.build_pass_node::<()>("resolve".into());
+        resolve.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        resolve.add_input(hdr, LoadOp::Load, StoreOp::DontCare);
+        resolve.init(Box::new(|_render_pass| Box::new(())));
+        let read = texel.clone();
+        resolve.callback(Box::new(move |frame, _data, _world, _resources| {
+            assert!(frame.sampled.is_empty());
+            read.set(frame.inputs.first().map(|input| input.load([3, 3])));
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(resolve.build()));
+        let mut clear = graph_builder.build_pass_node::<()>("clear".into());
+        clear.add_output(hdr, LoadOp::Clear, StoreOp::Store);
+        clear.init(Box::new(|_render_pass| Box::new(())));
+        clear.callback(Box::new(|_frame, _data, _world, _resources| Ok(None)));
+        graph_builder.add_node(Node::PassNode(clear.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        graph.execute(&World::new(), &Resources::new());
+        // The clear color of the previous pass, at full precision
+        assert_eq!(texel.get(), Some([0.2, 0.5, 0.1, 1.0]));
+    }
+
+    #[test]
+    fn hdr_colors_are_not_clamped() {
+        const BRIGHT: [f32; 4] = [4.0, 2.0, 0.5, 1.0];
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 4,
+            height: 4,
+        });
+        let ctx = Arc::new(builder.build());
+        register_shaders(&ctx);
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+
+        // Covers the whole screen
+        let vertices: [[f32; 4]; 3] = [
+            [-1.0, -1.0, 0.0, 1.0],
+            [3.0, -1.0, 0.0, 1.0],
+            [-1.0, 3.0, 0.0, 1.0],
+        ];
+        let vertex_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-vertices".into(),
+            size: std::mem::size_of_val(&vertices) as u64,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Vertex,
+        });
+        let scale_buffer = resources.create_empty_buffer(BufferDescriptor {
+            name: "test-scale".into(),
+            size: 16,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Uniform,
+        });
+        unsafe {
+            ctx.write_to_buffer(&vertex_buffer, &vertices);
+            ctx.write_to_buffer(&scale_buffer, &Scale([1.0, 1.0, 1.0, 1.0]));
+        }
+        let mixture = Arc::new(resources.stir(mixture![0: "scale" in Vertex: Scale]));
+        let glue = {
+            let mut bottle = resources.bottle(&mixture);
+            bottle.write_buffer(PartIndex::Binding(0), &scale_buffer, None);
+            bottle.apply()
+        };
+
+        let mut graph_builder = ctx.create_graph(surface);
+        let hdr = graph_builder.add_attachment(GraphAttachment::new(
+            "hdr",
+            AttachmentSize::SWAPCHAIN,
+            TextureFormat::Rgba16Sfloat,
+        ));
+        let backbuffer = graph_builder.get_backbuffer_attachment();
+
+        let mut pass = graph_builder.build_pass_node("hdr_pass".into());
+        pass.add_output(hdr, LoadOp::Clear, StoreOp::Store);
+        let shader = |name: &str| {
+            ctx.compile_shader(ShaderSource::GlslFile(std::path::Path::new(name).into()))
+                .unwrap()
+        };
+        let (vertex_shader, fragment_shader) = (shader("test.vert"), shader("test.frag"));
+        pass.init(Box::new(move |render_pass| {
+            let desc = GraphicsPipelineDescriptor {
+                name: "test_pipeline".into(),
+                mixtures: vec![&mixture],
+                push_constants: vec![(ShaderType::Vertex, 0..16), (ShaderType::Fragment, 16..32)],
+                shaders: PipelineShaders {
+                    vertex: &vertex_shader,
+                    fragment: &fragment_shader,
+                    geometry: None,
+                },
+                rasterizer: Rasterizer::FILL,
+                vertex_buffers: vec![VertexBufferDescriptor::new(0, 16, VertexInputRate::Vertex)],
+                attributes: vec![AttributeDescriptor::new(
+                    0,
+                    0,
+                    0,
+                    VertexAttributeFormat::Vec4,
+                )],
+                primitive: Primitive::TriangleList,
+                blend_targets: vec![false],
+                depth: None,
+                samples: 1,
+                pipeline_states: PipelineStates::DYNAMIC,
+            };
+            Box::new(resources.create_graphics_pipeline(
+                desc,
+                RenderContext::RenderPass((render_pass.deref(), 0)),
+            ))
+        }));
+        pass.callback(Box::new(move |frame, pipeline, _world, _resources| {
+            let cmd = frame.cmd;
+            cmd.bind_graphics_pipeline(pipeline);
+            cmd.set_viewport(0, frame.viewport.clone());
+            cmd.set_scissor(0, frame.viewport.rect);
+            cmd.snort_glue(0, pipeline, &glue);
+            cmd.bind_vertex_buffer(0, &vertex_buffer, BufferRange::WHOLE);
+            let offset = [0.0f32; 4];
+            cmd.push_constants(
+                pipeline,
+                ShaderType::Vertex,
+                0,
+                bytemuck::cast_slice(&offset),
+            );
+            cmd.push_constants(
+                pipeline,
+                ShaderType::Fragment,
+                16,
+                bytemuck::cast_slice(&BRIGHT),
+            );
+            cmd.draw(0..3, 0..1);
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(pass.build()));
+
+        let texel = std::rc::Rc::new(std::cell::Cell::new(None));
+        let mut resolve = graph_builder.build_pass_node::<()>("resolve".into());
+        resolve.add_output(backbuffer, LoadOp::Clear, StoreOp::Store);
+        resolve.add_input(hdr, LoadOp::Load, StoreOp::DontCare);
+        resolve.init(Box::new(|_render_pass| Box::new(())));
+        let read = texel.clone();
+        resolve.callback(Box::new(move |frame, _data, _world, _resources| {
+            read.set(frame.inputs.first().map(|input| input.load([1, 2])));
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::PassNode(resolve.build()));
+        let mut graph = graph_builder.build().unwrap();
+
+        graph.execute(&World::new(), &Resources::new());
+        assert_eq!(texel.get(), Some(BRIGHT));
+    }
+
+    #[test]
+    fn compute_node_writes_storage_buffer() {
+        let mut builder = ContextBuilder::new();
+        let surface = builder.create_offscreen_surface(Extent2D {
+            width: 4,
+            height: 4,
+        });
+        let ctx = Arc::new(builder.build());
+        // Doubles every element of the buffer, with 4 invocations per work group
+        ctx.register_shader(
+            "double.comp",
+            SoftShader::compute([4, 1, 1], |env, [x, _, _]| {
+                let value: u32 = env.load(0, 0, x as usize);
+                env.store(0, 0, x as usize, value * 2);
+            }),
+        );
+        let resources = Arc::new(GpuResources::new(ctx.clone()));
+
+        let values: [u32; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+        let buffer = Arc::new(resources.create_empty_buffer(BufferDescriptor {
+            name: "values".into(),
+            size: std::mem::size_of_val(&values) as u64,
+            memory_type: MemoryType::HostVisible,
+            usage: BufferUsage::Storage,
+        }));
+        unsafe { ctx.write_to_buffer(&buffer, &values) };
+        let mixture = Arc::new(resources.stir(mixture![0: "values" in Compute: buffer]));
+        let glue = {
+            let mut bottle = resources.bottle(&mixture);
+            bottle.write_buffer(PartIndex::Binding(0), &buffer, None);
+            bottle.apply()
+        };
+        let shader = ctx
+            .compile_shader(ShaderSource::GlslFile(
+                std::path::Path::new("double.comp").into(),
+            ))
+            .unwrap();
+
+        let mut graph_builder = ctx.create_graph(surface);
+        let mut node = graph_builder.build_compute_node("double".into());
+        node.add_output(buffer.clone());
+        node.init(Box::new(move || {
+            Box::new(
+                resources.create_compute_pipeline(ComputePipelineDescriptor {
+                    name: "double".into(),
+                    mixtures: vec![&mixture],
+                    push_constants: vec![],
+                    shader: &shader,
+                }),
+            )
+        }));
+        node.callback(Box::new(move |frame, pipeline, _world, _resources| {
+            frame.cmd.bind_compute_pipeline(pipeline);
+            frame.cmd.snort_compute_glue(0, pipeline, &glue);
+            frame.cmd.dispatch([2, 1, 1]);
+            Ok(None)
+        }));
+        graph_builder.add_node(Node::ComputeNode(node.build()));
+        let mut graph = graph_builder.build().unwrap();
+        graph.execute(&World::new(), &Resources::new());
+
+        let data = buffer.get_handle().read_range(&BufferRange::WHOLE);
+        let result: &[u32] = bytemuck::cast_slice(&data);
+        assert_eq!(result, &[2, 4, 6, 8, 10, 12, 14, 16]);
+    }
+}
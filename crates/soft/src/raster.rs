@@ -0,0 +1,447 @@
+//! Triangle and line rasterization, loosely following the rules of the vulkan spec
+
+use render::resource::pipeline::{
+    ComparisonFunction, CullFace, Culling, DepthDescriptor, Rect, Viewport, Winding,
+};
+
+use crate::shader::{FragmentShader, ShaderEnv, Varyings, VertexOutput, MAX_VARYINGS};
+
+/// Minimal w after clipping, so that the perspective divide is well defined
+const W_EPSILON: f32 = 1e-6;
+
+/// Most samples per texel, see sample_positions
+pub(crate) const MAX_SAMPLES: usize = 8;
+
+/// Positions of the samples inside of a texel, the standard locations of the vulkan spec
+fn sample_positions(samples: u32) -> &'static [(f32, f32)] {
+    match samples {
+        2 => &[(0.75, 0.75), (0.25, 0.25)],
+        4 => &[
+            (0.375, 0.125),
+            (0.875, 0.375),
+            (0.125, 0.625),
+            (0.625, 0.875),
+        ],
+        8 => &[
+            (0.5625, 0.3125),
+            (0.4375, 0.6875),
+            (0.8125, 0.5625),
+            (0.3125, 0.1875),
+            (0.1875, 0.8125),
+            (0.0625, 0.4375),
+            (0.6875, 0.9375),
+            (0.9375, 0.0625),
+        ],
+        _ => &[(0.5, 0.5)],
+    }
+}
+
+/// Color and depth hold `samples` values per texel
+pub(crate) struct Target<'a> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) samples: u32,
+    pub(crate) color: Option<&'a mut [[f32; 4]]>,
+    /// Colors are clamped for normalized formats (see `color_range`)
+    pub(crate) color_range: Option<(f32, f32)>,
+    pub(crate) depth: Option<&'a mut [f32]>,
+}
+
+pub(crate) struct RasterState<'a> {
+    pub(crate) viewport: &'a Viewport,
+    pub(crate) scissor: &'a Rect,
+    pub(crate) culling: &'a Culling,
+    pub(crate) depth: Option<&'a DepthDescriptor>,
+    pub(crate) blend: bool,
+    pub(crate) fragment: &'a dyn FragmentShader,
+    pub(crate) env: &'a ShaderEnv<'a>,
+}
+
+/// Vertex after the viewport transform, varyings are already divided by w
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    varyings: Varyings,
+}
+
+fn lerp(a: &VertexOutput, b: &VertexOutput, t: f32) -> VertexOutput {
+    let mut result = *a;
+    for i in 0..4 {
+        result.position[i] = a.position[i] + (b.position[i] - a.position[i]) * t;
+    }
+    for i in 0..MAX_VARYINGS {
+        result.varyings[i] = a.varyings[i] + (b.varyings[i] - a.varyings[i]) * t;
+    }
+    result
+}
+
+/// Sutherland-Hodgman against a single plane, everything with distance >= 0 is kept
+fn clip(polygon: Vec<VertexOutput>, distance: impl Fn(&[f32; 4]) -> f32) -> Vec<VertexOutput> {
+    let mut result = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let (d_current, d_next) = (distance(&current.position), distance(&next.position));
+        if d_current >= 0.0 {
+            result.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            result.push(lerp(current, next, d_current / (d_current - d_next)));
+        }
+    }
+    result
+}
+
+fn to_screen(vertex: &VertexOutput, viewport: &Viewport) -> ScreenVertex {
+    let [x, y, z, w] = vertex.position;
+    let inv_w = 1.0 / w;
+    let rect = &viewport.rect;
+    let mut varyings = vertex.varyings;
+    varyings.iter_mut().for_each(|v| *v *= inv_w);
+    ScreenVertex {
+        x: rect.x as f32 + (x * inv_w + 1.0) * 0.5 * rect.width as f32,
+        y: rect.y as f32 + (y * inv_w + 1.0) * 0.5 * rect.height as f32,
+        z: viewport.depth.start + z * inv_w * (viewport.depth.end - viewport.depth.start),
+        inv_w,
+        varyings,
+    }
+}
+
+pub(crate) fn draw_triangle(
+    state: &RasterState,
+    target: &mut Target,
+    vertices: [&VertexOutput; 3],
+) {
+    // x and y do not need to be clipped, since the scissor takes care of that
+    let polygon = vertices.iter().map(|v| **v).collect();
+    let polygon = clip(polygon, |p| p[3] - W_EPSILON);
+    let polygon = clip(polygon, |p| p[2]);
+    let polygon = clip(polygon, |p| p[3] - p[2]);
+    if polygon.len() < 3 {
+        return;
+    }
+
+    let screen: Vec<ScreenVertex> = polygon
+        .iter()
+        .map(|v| to_screen(v, state.viewport))
+        .collect();
+    for i in 1..screen.len() - 1 {
+        rasterize(state, target, [&screen[0], &screen[i], &screen[i + 1]]);
+    }
+}
+
+/// Clips the segment against the same planes as triangles, None if nothing is left
+fn clip_segment(mut a: VertexOutput, mut b: VertexOutput) -> Option<(VertexOutput, VertexOutput)> {
+    let planes: [fn(&[f32; 4]) -> f32; 3] = [|p| p[3] - W_EPSILON, |p| p[2], |p| p[3] - p[2]];
+    for distance in planes.iter() {
+        let (d_a, d_b) = (distance(&a.position), distance(&b.position));
+        if d_a < 0.0 && d_b < 0.0 {
+            return None;
+        }
+        let t = d_a / (d_a - d_b);
+        if d_a < 0.0 {
+            a = lerp(&a, &b, t);
+        } else if d_b < 0.0 {
+            b = lerp(&a, &b, t);
+        }
+    }
+    Some((a, b))
+}
+
+/// Lines are a texel wide and cover every sample of the texels they pass through, like the
+/// bresenham lines of the spec
+pub(crate) fn draw_line(state: &RasterState, target: &mut Target, vertices: [&VertexOutput; 2]) {
+    let (a, b) = match clip_segment(*vertices[0], *vertices[1]) {
+        Some(segment) => segment,
+        None => return,
+    };
+    let (a, b) = (to_screen(&a, state.viewport), to_screen(&b, state.viewport));
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0);
+    if !steps.is_finite() {
+        return;
+    }
+
+    let scissor = state.scissor;
+    let positions = sample_positions(target.samples);
+    // One texel per step along the major axis, sampled at the middle of the step
+    for step in 0..steps as u32 {
+        let t = (step as f32 + 0.5) / steps;
+        let (x, y) = ((a.x + dx * t).floor(), (a.y + dy * t).floor());
+        let inside = x >= (scissor.x as f32).max(0.0)
+            && y >= (scissor.y as f32).max(0.0)
+            && x < (scissor.x as f32 + scissor.width as f32).min(target.width as f32)
+            && y < (scissor.y as f32 + scissor.height as f32).min(target.height as f32);
+        if !inside {
+            continue;
+        }
+        let texel = (y as u32 * target.width + x as u32) as usize * positions.len();
+
+        let z = a.z + (b.z - a.z) * t;
+        let mut passed = [false; MAX_SAMPLES];
+        for (s, passed) in passed.iter_mut().enumerate().take(positions.len()) {
+            if let (Some(desc), Some(depth)) = (state.depth, target.depth.as_mut()) {
+                if !depth_test(&desc.function, z, depth[texel + s]) {
+                    continue;
+                }
+                if desc.write {
+                    depth[texel + s] = z;
+                }
+            }
+            *passed = true;
+        }
+        if !passed.contains(&true) {
+            continue;
+        }
+
+        // Perspective correct interpolation
+        let inv_w = a.inv_w + (b.inv_w - a.inv_w) * t;
+        let mut varyings = [0.0; MAX_VARYINGS];
+        for (i, v) in varyings.iter_mut().enumerate() {
+            *v = (a.varyings[i] + (b.varyings[i] - a.varyings[i]) * t) / inv_w;
+        }
+        shade(state, target, texel, &passed[..positions.len()], &varyings);
+    }
+}
+
+/// Shades a texel once and writes the output to the samples that passed
+fn shade(
+    state: &RasterState,
+    target: &mut Target,
+    texel: usize,
+    passed: &[bool],
+    varyings: &Varyings,
+) {
+    if let Some(color) = target.color.as_mut() {
+        let output = (state.fragment)(state.env, varyings);
+        for s in (0..passed.len()).filter(|s| passed[*s]) {
+            let index = texel + s;
+            let output = if state.blend {
+                blend(output, color[index])
+            } else {
+                output
+            };
+            color[index] = match target.color_range {
+                Some((min, max)) => [
+                    output[0].clamp(min, max),
+                    output[1].clamp(min, max),
+                    output[2].clamp(min, max),
+                    output[3].clamp(min, max),
+                ],
+                None => output,
+            };
+        }
+    }
+}
+
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Pixels exactly on an edge belong to only one of the triangles sharing it
+fn covers(w: f32, a: &ScreenVertex, b: &ScreenVertex) -> bool {
+    let dy = b.y - a.y;
+    w > 0.0 || (w == 0.0 && (dy > 0.0 || (dy == 0.0 && b.x < a.x)))
+}
+
+fn depth_test(function: &ComparisonFunction, depth: f32, current: f32) -> bool {
+    match function {
+        ComparisonFunction::Never => false,
+        ComparisonFunction::Less => depth < current,
+        ComparisonFunction::Equal => (depth - current).abs() <= f32::EPSILON,
+        ComparisonFunction::LessEqual => depth <= current,
+        ComparisonFunction::Greater => depth > current,
+        ComparisonFunction::NotEqual => (depth - current).abs() > f32::EPSILON,
+        ComparisonFunction::GreaterEqual => depth >= current,
+        ComparisonFunction::Always => true,
+    }
+}
+
+fn blend(src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    // Same as gfx_hal BlendState::ALPHA
+    let a = src[3];
+    [
+        src[0] * a + dst[0] * (1.0 - a),
+        src[1] * a + dst[1] * (1.0 - a),
+        src[2] * a + dst[2] * (1.0 - a),
+        a + dst[3] * (1.0 - a),
+    ]
+}
+
+fn rasterize(state: &RasterState, target: &mut Target, [v0, v1, v2]: [&ScreenVertex; 3]) {
+    let area = edge(v0, v1, v2.x, v2.y);
+    if area == 0.0 || !area.is_finite() {
+        return;
+    }
+
+    // The spec defines the area with the opposite sign (since y points down)
+    let front_facing = match state.culling.winding {
+        Winding::CounterClockwise => area < 0.0,
+        Winding::Clockwise => area > 0.0,
+    };
+    let culled = match state.culling.cull_face {
+        CullFace::None => false,
+        CullFace::Front => front_facing,
+        CullFace::Back => !front_facing,
+    };
+    if culled {
+        return;
+    }
+
+    // From here on the triangle has a positive area
+    let (v1, v2, area) = if area < 0.0 {
+        (v2, v1, -area)
+    } else {
+        (v1, v2, area)
+    };
+
+    let scissor = state.scissor;
+    let min_x =
+        v0.x.min(v1.x)
+            .min(v2.x)
+            .floor()
+            .max(scissor.x as f32)
+            .max(0.0) as u32;
+    let min_y =
+        v0.y.min(v1.y)
+            .min(v2.y)
+            .floor()
+            .max(scissor.y as f32)
+            .max(0.0) as u32;
+    let max_x =
+        v0.x.max(v1.x)
+            .max(v2.x)
+            .ceil()
+            .min(scissor.x as f32 + scissor.width as f32)
+            .min(target.width as f32)
+            .max(0.0) as u32;
+    let max_y =
+        v0.y.max(v1.y)
+            .max(v2.y)
+            .ceil()
+            .min(scissor.y as f32 + scissor.height as f32)
+            .min(target.height as f32)
+            .max(0.0) as u32;
+
+    // Coverage and depth are tested per sample, but every texel is only shaded once at its center
+    let positions = sample_positions(target.samples);
+    for py in min_y..max_y {
+        for px in min_x..max_x {
+            let texel = (py * target.width + px) as usize * positions.len();
+            let mut passed = [false; MAX_SAMPLES];
+            for (s, (sx, sy)) in positions.iter().enumerate() {
+                let (x, y) = (px as f32 + sx, py as f32 + sy);
+                let (w0, w1, w2) = (edge(v1, v2, x, y), edge(v2, v0, x, y), edge(v0, v1, x, y));
+                if !(covers(w0, v1, v2) && covers(w1, v2, v0) && covers(w2, v0, v1)) {
+                    continue;
+                }
+
+                let z = (w0 * v0.z + w1 * v1.z + w2 * v2.z) / area;
+                if let (Some(desc), Some(depth)) = (state.depth, target.depth.as_mut()) {
+                    if !depth_test(&desc.function, z, depth[texel + s]) {
+                        continue;
+                    }
+                    if desc.write {
+                        depth[texel + s] = z;
+                    }
+                }
+                passed[s] = true;
+            }
+            if !passed.contains(&true) {
+                continue;
+            }
+
+            let (x, y) = (px as f32 + 0.5, py as f32 + 0.5);
+            let (w0, w1, w2) = (edge(v1, v2, x, y), edge(v2, v0, x, y), edge(v0, v1, x, y));
+            let (b0, b1, b2) = (w0 / area, w1 / area, w2 / area);
+
+            // Perspective correct interpolation
+            let inv_w = b0 * v0.inv_w + b1 * v1.inv_w + b2 * v2.inv_w;
+            let mut varyings = [0.0; MAX_VARYINGS];
+            for (i, v) in varyings.iter_mut().enumerate() {
+                *v = (b0 * v0.varyings[i] + b1 * v1.varyings[i] + b2 * v2.varyings[i]) / inv_w;
+            }
+
+            shade(state, target, texel, &passed[..positions.len()], &varyings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use render::resource::pipeline::Rasterizer;
+
+    use super::*;
+
+    #[test]
+    fn lines_are_depth_tested() {
+        let (width, height) = (8, 8);
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: width as i16,
+            height: height as i16,
+        };
+        let viewport = Viewport {
+            rect: rect.clone(),
+            depth: 0.0..1.0,
+        };
+        let (uniforms, textures, storage_buffers, storage_images) = (
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let env = ShaderEnv {
+            push_constants: &[],
+            uniforms: &uniforms,
+            textures: &textures,
+            storage_buffers: &storage_buffers,
+            storage_images: &storage_images,
+        };
+        let depth_desc = DepthDescriptor {
+            function: ComparisonFunction::LessEqual,
+            write: false,
+        };
+        let state = RasterState {
+            viewport: &viewport,
+            scissor: &rect,
+            culling: &Rasterizer::FILL.culling,
+            depth: Some(&depth_desc),
+            blend: false,
+            fragment: &|_env: &ShaderEnv, _varyings: &Varyings| [1.0, 0.0, 0.0, 1.0],
+            env: &env,
+        };
+
+        // The left half of the screen is occluded
+        let mut color = vec![[0.0; 4]; width * height];
+        let mut depth: Vec<f32> = (0..width * height)
+            .map(|i| if i % width < 4 { 0.25 } else { 1.0 })
+            .collect();
+        let mut target = Target {
+            width: width as u32,
+            height: height as u32,
+            samples: 1,
+            color: Some(&mut color),
+            color_range: Some((0.0, 1.0)),
+            depth: Some(&mut depth),
+        };
+        // A horizontal line through the middle of the screen, at depth 0.5
+        let a = VertexOutput::new([-1.0, 0.0, 0.5, 1.0]);
+        let b = VertexOutput::new([1.0, 0.0, 0.5, 1.0]);
+        draw_line(&state, &mut target, [&a, &b]);
+
+        let row: Vec<bool> = color[4 * width..5 * width]
+            .iter()
+            .map(|texel| texel[0] == 1.0)
+            .collect();
+        assert_eq!(row, [false, false, false, false, true, true, true, true]);
+        // Only a single row is covered
+        assert!(color[3 * width..4 * width]
+            .iter()
+            .all(|texel| texel[0] == 0.0));
+    }
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use parking_lot::RwLock;
+use render::{
+    graph::readback::{ReadbackError, ReadbackImage},
+    prelude::{BufferRange, MixturePart, PartType},
+    resource::{
+        frame::{Clear, Extent2D, Extent3D},
+        pipeline::{
+            AttributeDescriptor, DepthDescriptor, PipelineStates, Primitive, Rasterizer,
+            VertexBufferDescriptor,
+        },
+        render_pass::Attachment,
+    },
+    util::format::TextureFormat,
+};
+
+use crate::{
+    shader::{ComputeShader, FragmentShader, VertexShader},
+    surface::Frame,
+    texture::{SoftSampler, SoftTexture},
+};
+
+//
+// Buffers
+//
+
+#[derive(Clone)]
+pub struct SoftBuffer {
+    pub(crate) name: String,
+    pub(crate) data: Arc<RwLock<Vec<u8>>>,
+}
+
+impl SoftBuffer {
+    pub(crate) fn new(name: String, size: usize) -> Self {
+        Self {
+            name,
+            data: Arc::new(RwLock::new(vec![0u8; size])),
+        }
+    }
+
+    /// Copy of the bytes in range, clamped to the size of the buffer
+    pub(crate) fn read_range(&self, range: &BufferRange) -> Vec<u8> {
+        let data = self.data.read();
+        let start = (range.offset as usize).min(data.len());
+        let end = match range.size {
+            Some(size) => (start + size as usize).min(data.len()),
+            None => data.len(),
+        };
+        data[start..end].to_vec()
+    }
+}
+
+impl Debug for SoftBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoftBuffer")
+            .field("name", &self.name)
+            .field("size", &self.data.read().len())
+            .finish()
+    }
+}
+
+//
+// Images
+//
+
+pub(crate) fn is_depth_format(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Depth32Sfloat | TextureFormat::Depth24PlusStencil8
+    )
+}
+
+/// Range that written colors are clamped to, None for float and integer formats
+pub(crate) fn color_range(format: TextureFormat) -> Option<(f32, f32)> {
+    use TextureFormat::*;
+    match format {
+        R8Unorm | Rg8Unorm | Rgba8Unorm | Rgba8Srgb | Bgra8Unorm | Bgra8Srgb => Some((0.0, 1.0)),
+        R8Snorm | Rg8Snorm | Rgba8Snorm => Some((-1.0, 1.0)),
+        _ => None,
+    }
+}
+
+pub(crate) enum Texels {
+    /// Linear colors, conversion into the target format happens on read back
+    Color(Vec<[f32; 4]>),
+    Depth(Vec<f32>),
+}
+
+pub struct Image {
+    pub(crate) extent: Extent2D,
+    pub(crate) format: TextureFormat,
+    /// Samples per texel, the samples of a texel are stored next to each other
+    pub(crate) samples: u32,
+    pub(crate) texels: Texels,
+}
+
+impl Image {
+    pub(crate) fn new(extent: Extent2D, format: TextureFormat, samples: u32) -> Self {
+        let size = (extent.width * extent.height * samples) as usize;
+        let texels = if is_depth_format(format) {
+            Texels::Depth(vec![1.0; size])
+        } else {
+            Texels::Color(vec![[0.0; 4]; size])
+        };
+        Self {
+            extent,
+            format,
+            samples,
+            texels,
+        }
+    }
+
+    pub(crate) fn clear(&mut self, clear: &Clear) {
+        match (&mut self.texels, clear) {
+            (Texels::Color(texels), Clear::Color(r, g, b, a)) => {
+                texels.iter_mut().for_each(|t| *t = [*r, *g, *b, *a])
+            }
+            (Texels::Depth(texels), Clear::Depth(depth, _stencil)) => {
+                texels.iter_mut().for_each(|t| *t = *depth)
+            }
+            _ => log::warn!(
+                "[SoftImage] (clear) clear value {:?} does not match format {:?}",
+                clear,
+                self.format
+            ),
+        }
+    }
+
+    /// Averages the samples of every texel of source, which needs the same size and texel type
+    pub(crate) fn resolve(&mut self, source: &Image) {
+        let samples = source.samples as usize;
+        match (&mut self.texels, &source.texels) {
+            (Texels::Color(texels), Texels::Color(source)) => {
+                let weight = 1.0 / samples as f32;
+                for (texel, samples) in texels.iter_mut().zip(source.chunks_exact(samples)) {
+                    *texel = [0.0; 4];
+                    for sample in samples {
+                        for (t, s) in texel.iter_mut().zip(sample.iter()) {
+                            *t += s * weight;
+                        }
+                    }
+                }
+            }
+            _ => log::warn!(
+                "[SoftImage] (resolve) can not resolve {:?} into {:?}",
+                source.format,
+                self.format
+            ),
+        }
+    }
+
+    /// The texels in the layout of the format, 8 bit formats are encoded like presented frames
+    pub(crate) fn read_back(&self) -> Result<ReadbackImage, ReadbackError> {
+        if self.samples > 1 {
+            return Err(ReadbackError::Multisampled);
+        }
+        let data = match (&self.texels, self.format) {
+            (
+                Texels::Color(_),
+                TextureFormat::Rgba8Unorm
+                | TextureFormat::Rgba8Srgb
+                | TextureFormat::Bgra8Unorm
+                | TextureFormat::Bgra8Srgb,
+            ) => {
+                let mut data = Frame::from_image(self).as_bytes().to_vec();
+                if matches!(
+                    self.format,
+                    TextureFormat::Bgra8Unorm | TextureFormat::Bgra8Srgb
+                ) {
+                    data.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+                }
+                data
+            }
+            (Texels::Color(texels), TextureFormat::Rgba32Sfloat) => {
+                bytemuck::cast_slice(texels).to_vec()
+            }
+            (Texels::Depth(texels), TextureFormat::Depth32Sfloat) => {
+                bytemuck::cast_slice(texels).to_vec()
+            }
+            (_, format) => return Err(ReadbackError::Format(format)),
+        };
+        Ok(ReadbackImage {
+            extent: self.extent.clone(),
+            format: self.format,
+            data,
+        })
+    }
+}
+
+/// The ImageView of the software backend, which is just a shared image
+#[derive(Clone)]
+pub struct SoftImage(pub(crate) Arc<RwLock<Image>>);
+
+impl SoftImage {
+    pub(crate) fn new(extent: Extent2D, format: TextureFormat, samples: u32) -> Self {
+        Self(Arc::new(RwLock::new(Image::new(extent, format, samples))))
+    }
+}
+
+impl Debug for SoftImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let image = self.0.read();
+        f.debug_struct("SoftImage")
+            .field("extent", &image.extent)
+            .field("format", &image.format)
+            .field("samples", &image.samples)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftFramebuffer {
+    pub(crate) attachments: Vec<SoftImage>,
+    pub(crate) extent: Extent3D,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftRenderPass {
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+//
+// Pipelines
+//
+
+pub struct SoftPipeline {
+    pub(crate) name: String,
+    pub(crate) vertex: Arc<dyn VertexShader>,
+    pub(crate) fragment: Arc<dyn FragmentShader>,
+    pub(crate) vertex_buffers: Vec<VertexBufferDescriptor>,
+    pub(crate) attributes: Vec<AttributeDescriptor>,
+    pub(crate) primitive: Primitive,
+    pub(crate) rasterizer: Rasterizer,
+    pub(crate) blend_targets: Vec<bool>,
+    pub(crate) depth: Option<DepthDescriptor>,
+    pub(crate) samples: u8,
+    pub(crate) pipeline_states: PipelineStates,
+}
+
+impl Debug for SoftPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoftPipeline")
+            .field("name", &self.name)
+            .field("primitive", &self.primitive)
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
+
+pub struct SoftComputePipeline {
+    pub(crate) name: String,
+    pub(crate) shader: Arc<dyn ComputeShader>,
+    pub(crate) local_size: [u32; 3],
+}
+
+impl Debug for SoftComputePipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoftComputePipeline")
+            .field("name", &self.name)
+            .field("local_size", &self.local_size)
+            .finish()
+    }
+}
+
+//
+// Descriptors
+//
+
+#[derive(Debug, Clone)]
+pub struct SoftDescriptorLayout {
+    pub(crate) parts: Vec<MixturePart>,
+}
+
+#[derive(Debug)]
+pub(crate) struct BoundBuffer {
+    pub(crate) array_offset: usize,
+    pub(crate) buffer: SoftBuffer,
+    pub(crate) range: BufferRange,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftDescriptorSet {
+    pub(crate) parts: Vec<MixturePart>,
+    pub(crate) bindings: Arc<RwLock<HashMap<u32, Vec<BoundBuffer>>>>,
+    pub(crate) textures: Arc<RwLock<HashMap<u32, (SoftTexture, SoftSampler)>>>,
+    pub(crate) storage_images: Arc<RwLock<HashMap<u32, SoftTexture>>>,
+}
+
+impl SoftDescriptorSet {
+    /// Copies the current content of all bound uniforms, keyed by binding
+    pub(crate) fn snapshot(&self) -> Vec<(u32, Vec<u8>)> {
+        let bindings = self.bindings.read();
+        self.parts
+            .iter()
+            .filter_map(|part| {
+                let element_size = match part.type_info {
+                    PartType::Uniform(size) => size,
+                    _ => return None,
+                };
+                let writes = bindings.get(&part.binding)?;
+                let mut bytes = vec![0u8; element_size * part.array_size.max(1)];
+                for write in writes {
+                    let data = write.buffer.read_range(&write.range);
+                    let start = (write.array_offset * element_size).min(bytes.len());
+                    let len = data.len().min(bytes.len() - start);
+                    bytes[start..start + len].copy_from_slice(&data[..len]);
+                }
+                Some((part.binding, bytes))
+            })
+            .collect()
+    }
+
+    /// Bound storage buffers, which are accessed directly instead of a snapshot
+    pub(crate) fn bound_storage_buffers(&self) -> Vec<(u32, (SoftBuffer, BufferRange))> {
+        let bindings = self.bindings.read();
+        self.parts
+            .iter()
+            .filter(|part| matches!(part.type_info, PartType::StorageBuffer))
+            .filter_map(|part| {
+                let write = bindings.get(&part.binding)?.first()?;
+                Some((part.binding, (write.buffer.clone(), write.range.clone())))
+            })
+            .collect()
+    }
+
+    /// All bound storage images, keyed by binding
+    pub(crate) fn bound_storage_images(&self) -> Vec<(u32, SoftTexture)> {
+        self.storage_images
+            .read()
+            .iter()
+            .map(|(binding, texture)| (*binding, texture.clone()))
+            .collect()
+    }
+
+    /// All bound textures with their sampler, keyed by binding
+    pub(crate) fn bound_textures(&self) -> Vec<(u32, (SoftTexture, SoftSampler))> {
+        self.textures
+            .read()
+            .iter()
+            .map(|(binding, bound)| (*binding, bound.clone()))
+            .collect()
+    }
+}
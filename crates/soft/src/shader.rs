@@ -0,0 +1,172 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use bytemuck::Pod;
+use render::prelude::{BufferRange, ShaderType};
+
+use crate::{
+    resources::SoftBuffer,
+    texture::{SoftSampler, SoftTexture},
+};
+
+/// Maximum number of floats that can be passed from the vertex to the fragment shader
+pub const MAX_VARYINGS: usize = 16;
+
+/// Values that are interpolated across the triangle (eg. the `out` variables of a vertex shader)
+pub type Varyings = [f32; MAX_VARYINGS];
+
+#[derive(Debug, Clone, Copy)]
+pub struct VertexOutput {
+    /// Clip space position, equivalent to gl_Position
+    pub position: [f32; 4],
+    pub varyings: Varyings,
+}
+
+impl VertexOutput {
+    pub fn new(position: [f32; 4]) -> Self {
+        Self {
+            position,
+            varyings: [0.0; MAX_VARYINGS],
+        }
+    }
+}
+
+/// Vertex shaders get the vertex attributes, indexed by location
+pub trait VertexShader = Fn(&ShaderEnv, &[[f32; 4]]) -> VertexOutput + Send + Sync;
+/// Fragment shaders get the interpolated varyings and return the color of the first output
+pub trait FragmentShader = Fn(&ShaderEnv, &Varyings) -> [f32; 4] + Send + Sync;
+/// Compute shaders are invoked once per invocation, with gl_GlobalInvocationID
+pub trait ComputeShader = Fn(&ShaderEnv, [u32; 3]) + Send + Sync;
+
+/// The "compiled" shader code of the software backend
+#[derive(Clone)]
+pub enum SoftShader {
+    Vertex(Arc<dyn VertexShader>),
+    Fragment(Arc<dyn FragmentShader>),
+    /// With the local size of the work groups, like layout(local_size_x = ...) in
+    Compute(Arc<dyn ComputeShader>, [u32; 3]),
+}
+
+impl SoftShader {
+    pub fn vertex(shader: impl VertexShader + 'static) -> Self {
+        Self::Vertex(Arc::new(shader))
+    }
+
+    pub fn fragment(shader: impl FragmentShader + 'static) -> Self {
+        Self::Fragment(Arc::new(shader))
+    }
+
+    pub fn compute(local_size: [u32; 3], shader: impl ComputeShader + 'static) -> Self {
+        Self::Compute(Arc::new(shader), local_size)
+    }
+
+    pub fn shader_type(&self) -> ShaderType {
+        match self {
+            SoftShader::Vertex(_) => ShaderType::Vertex,
+            SoftShader::Fragment(_) => ShaderType::Fragment,
+            SoftShader::Compute(..) => ShaderType::Compute,
+        }
+    }
+}
+
+impl Debug for SoftShader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SoftShader::{:?}", self.shader_type())
+    }
+}
+
+/// Reads a T from the start of bytes, missing bytes are treated as zero
+fn read_pod<T: Pod>(bytes: &[u8]) -> T {
+    let mut value = T::zeroed();
+    let target = bytemuck::bytes_of_mut(&mut value);
+    let len = target.len().min(bytes.len());
+    target[..len].copy_from_slice(&bytes[..len]);
+    value
+}
+
+/// Resources that are bound while a shader is executed
+pub struct ShaderEnv<'a> {
+    pub(crate) push_constants: &'a [u8],
+    pub(crate) uniforms: &'a HashMap<(usize, u32), Vec<u8>>,
+    pub(crate) textures: &'a HashMap<(usize, u32), (SoftTexture, SoftSampler)>,
+    pub(crate) storage_buffers: &'a HashMap<(usize, u32), (SoftBuffer, BufferRange)>,
+    pub(crate) storage_images: &'a HashMap<(usize, u32), SoftTexture>,
+}
+
+impl<'a> ShaderEnv<'a> {
+    /// Reads the push constants at offset (in bytes), like layout(offset = ...)
+    pub fn push_constant<T: Pod>(&self, offset: u32) -> T {
+        read_pod(self.push_constants.get(offset as usize..).unwrap_or(&[]))
+    }
+
+    /// Reads the uniform buffer bound to binding of the descriptor set at set_idx
+    ///
+    /// Unbound uniforms read as zero
+    pub fn uniform<T: Pod>(&self, set_idx: usize, binding: u32) -> T {
+        match self.uniforms.get(&(set_idx, binding)) {
+            Some(bytes) => read_pod(bytes),
+            None => T::zeroed(),
+        }
+    }
+
+    /// Samples the texture bound to binding of the descriptor set at set_idx, like texture(...)
+    ///
+    /// There are no derivatives on the cpu, so this always samples the first mip level, use
+    /// sample_lod to select one. Unbound textures read as zero
+    pub fn sample(&self, set_idx: usize, binding: u32, uv: [f32; 2]) -> [f32; 4] {
+        self.sample_lod(set_idx, binding, uv, 0.0)
+    }
+
+    /// Same as sample, but with an explicit level of detail, like textureLod(...)
+    pub fn sample_lod(&self, set_idx: usize, binding: u32, uv: [f32; 2], lod: f32) -> [f32; 4] {
+        match self.textures.get(&(set_idx, binding)) {
+            Some((texture, sampler)) => texture.sample(&sampler.0, uv, lod),
+            None => [0.0; 4],
+        }
+    }
+
+    /// Reads element index of the storage buffer bound to binding (as array of T)
+    ///
+    /// Unbound buffers and elements out of bounds read as zero (like robust buffer access)
+    pub fn load<T: Pod>(&self, set_idx: usize, binding: u32, index: usize) -> T {
+        match self.storage_buffers.get(&(set_idx, binding)) {
+            Some((buffer, range)) => {
+                let data = buffer.data.read();
+                let start = range.offset as usize + index * std::mem::size_of::<T>();
+                let end = start + std::mem::size_of::<T>();
+                match data.get(start..end) {
+                    Some(bytes) => read_pod(bytes),
+                    None => T::zeroed(),
+                }
+            }
+            None => T::zeroed(),
+        }
+    }
+
+    /// Writes element index of the storage buffer bound to binding, writes out of bounds are
+    /// discarded
+    pub fn store<T: Pod>(&self, set_idx: usize, binding: u32, index: usize, value: T) {
+        if let Some((buffer, range)) = self.storage_buffers.get(&(set_idx, binding)) {
+            let mut data = buffer.data.write();
+            let start = range.offset as usize + index * std::mem::size_of::<T>();
+            let end = start + std::mem::size_of::<T>();
+            if let Some(bytes) = data.get_mut(start..end) {
+                bytes.copy_from_slice(bytemuck::bytes_of(&value));
+            }
+        }
+    }
+
+    /// Reads a texel of the storage image bound to binding, like imageLoad(...)
+    pub fn image_load(&self, set_idx: usize, binding: u32, coord: [u32; 2]) -> [f32; 4] {
+        match self.storage_images.get(&(set_idx, binding)) {
+            Some(texture) => texture.load(coord),
+            None => [0.0; 4],
+        }
+    }
+
+    /// Writes a texel of the storage image bound to binding, like imageStore(...)
+    pub fn image_store(&self, set_idx: usize, binding: u32, coord: [u32; 2], value: [f32; 4]) {
+        if let Some(texture) = self.storage_images.get(&(set_idx, binding)) {
+            texture.store(coord, value);
+        }
+    }
+}
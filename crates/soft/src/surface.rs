@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use render::{resource::frame::Extent2D, util::format::TextureFormat};
+
+use crate::resources::{Image, Texels};
+
+/// Image that was presented to a surface, in 8 bit rgba
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl Frame {
+    pub(crate) fn from_image(image: &Image) -> Self {
+        let srgb = matches!(
+            image.format,
+            TextureFormat::Rgba8Srgb | TextureFormat::Bgra8Srgb
+        );
+        let pixels = match &image.texels {
+            Texels::Color(texels) => texels
+                .iter()
+                .map(|[r, g, b, a]| {
+                    let encode = |c: f32| if srgb { linear_to_srgb(c) } else { c };
+                    [
+                        to_unorm(encode(*r)),
+                        to_unorm(encode(*g)),
+                        to_unorm(encode(*b)),
+                        to_unorm(*a),
+                    ]
+                })
+                .collect(),
+            Texels::Depth(texels) => texels
+                .iter()
+                .map(|d| {
+                    let d = to_unorm(*d);
+                    [d, d, d, 255]
+                })
+                .collect(),
+        };
+
+        Self {
+            width: image.extent.width,
+            height: image.extent.height,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Raw rgba bytes, row by row starting at the top
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.pixels)
+    }
+
+    /// Mean absolute difference per channel (0 - 255), None if the sizes do not match
+    pub fn mean_difference(&self, other: &Frame) -> Option<f32> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let sum: u64 = self
+            .as_bytes()
+            .iter()
+            .zip(other.as_bytes())
+            .map(|(a, b)| (*a as i32 - *b as i32).unsigned_abs() as u64)
+            .sum();
+        Some(sum as f32 / self.as_bytes().len().max(1) as f32)
+    }
+
+    /// Number of pixels where any channel differs by more than tolerance, None if the sizes do
+    /// not match
+    pub fn differing_pixels(&self, other: &Frame, tolerance: u8) -> Option<usize> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let count = self
+            .pixels
+            .iter()
+            .zip(other.pixels.iter())
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .any(|(a, b)| (*a as i32 - *b as i32).abs() > tolerance as i32)
+            })
+            .count();
+        Some(count)
+    }
+}
+
+#[derive(Debug)]
+struct SurfaceData {
+    extent: RwLock<Extent2D>,
+    frame: Mutex<Option<Frame>>,
+}
+
+/// Offscreen surface, the graph presents its backbuffer into this
+///
+/// Clones refer to the same surface, so one can be kept around to read the frames back
+#[derive(Debug, Clone)]
+pub struct SoftSurface {
+    data: Arc<SurfaceData>,
+}
+
+impl SoftSurface {
+    pub fn new(extent: Extent2D) -> Self {
+        Self {
+            data: Arc::new(SurfaceData {
+                extent: RwLock::new(extent),
+                frame: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.data.extent.read().clone()
+    }
+
+    /// The graph will pick up the new size in the next frame
+    pub fn resize(&self, extent: Extent2D) {
+        *self.data.extent.write() = extent;
+    }
+
+    /// The last presented frame
+    pub fn last_frame(&self) -> Option<Frame> {
+        self.data.frame.lock().clone()
+    }
+
+    pub(crate) fn present(&self, frame: Frame) {
+        *self.data.frame.lock() = Some(frame);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{fmt::Debug, sync::Arc};
+
+use parking_lot::RwLock;
+use render::{
+    resource::{
+        frame::Extent2D,
+        texture::{mip_extent, Filter, SamplerDescriptor, TextureDescriptor, WrapMode},
+    },
+    util::format::TextureFormat,
+};
+
+use crate::resources::{Image, Texels};
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Decodes tightly packed texels of format into linear rgba
+fn decode(format: TextureFormat, bytes: &[u8]) -> Vec<[f32; 4]> {
+    let unorm = |b: u8| b as f32 / 255.0;
+    let srgb = |b: u8| srgb_to_linear(unorm(b));
+    let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    match format {
+        TextureFormat::R8Unorm => bytes.iter().map(|b| [unorm(*b), 0.0, 0.0, 1.0]).collect(),
+        TextureFormat::Rg8Unorm => bytes
+            .chunks_exact(2)
+            .map(|t| [unorm(t[0]), unorm(t[1]), 0.0, 1.0])
+            .collect(),
+        TextureFormat::Rgba8Unorm => bytes
+            .chunks_exact(4)
+            .map(|t| [unorm(t[0]), unorm(t[1]), unorm(t[2]), unorm(t[3])])
+            .collect(),
+        TextureFormat::Rgba8Srgb => bytes
+            .chunks_exact(4)
+            .map(|t| [srgb(t[0]), srgb(t[1]), srgb(t[2]), unorm(t[3])])
+            .collect(),
+        TextureFormat::Bgra8Unorm => bytes
+            .chunks_exact(4)
+            .map(|t| [unorm(t[2]), unorm(t[1]), unorm(t[0]), unorm(t[3])])
+            .collect(),
+        TextureFormat::Bgra8Srgb => bytes
+            .chunks_exact(4)
+            .map(|t| [srgb(t[2]), srgb(t[1]), srgb(t[0]), unorm(t[3])])
+            .collect(),
+        TextureFormat::R32Sfloat => bytes
+            .chunks_exact(4)
+            .map(|t| [float(t), 0.0, 0.0, 1.0])
+            .collect(),
+        TextureFormat::Rgba32Sfloat => bytes
+            .chunks_exact(16)
+            .map(|t| {
+                [
+                    float(&t[0..]),
+                    float(&t[4..]),
+                    float(&t[8..]),
+                    float(&t[12..]),
+                ]
+            })
+            .collect(),
+        other => {
+            log::warn!(
+                "[SoftTexture] format {:?} can not be sampled, reading zeros",
+                other
+            );
+            vec![[0.0; 4]; bytes.len() / other.texel_size()]
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MipLevel {
+    pub(crate) extent: Extent2D,
+    /// Linear colors, srgb formats are decoded on upload
+    pub(crate) texels: Vec<[f32; 4]>,
+}
+
+impl MipLevel {
+    /// Box filters the level down to extent (which is half of this level)
+    fn downsample(&self, extent: Extent2D) -> Self {
+        let mut texels = Vec::with_capacity((extent.width * extent.height) as usize);
+        for y in 0..extent.height {
+            for x in 0..extent.width {
+                let mut sum = [0.0f32; 4];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                    let texel = self.fetch((x * 2 + dx) as i64, (y * 2 + dy) as i64);
+                    sum.iter_mut().zip(texel.iter()).for_each(|(s, t)| *s += t);
+                }
+                texels.push(sum.map(|s| s / 4.0));
+            }
+        }
+        Self { extent, texels }
+    }
+
+    /// Texel at x, y clamped to the edge
+    fn fetch(&self, x: i64, y: i64) -> [f32; 4] {
+        let x = x.clamp(0, self.extent.width as i64 - 1) as u32;
+        let y = y.clamp(0, self.extent.height as i64 - 1) as u32;
+        self.texels[(y * self.extent.width + x) as usize]
+    }
+
+    fn sample(&self, sampler: &SamplerDescriptor, filter: Filter, uv: [f32; 2]) -> [f32; 4] {
+        let Extent2D { width, height } = self.extent;
+        let texel = |x: i64, y: i64| {
+            self.fetch(wrap(x, width, sampler.wrap), wrap(y, height, sampler.wrap))
+        };
+        let (u, v) = (uv[0] * width as f32, uv[1] * height as f32);
+        match filter {
+            Filter::Nearest => texel(u.floor() as i64, v.floor() as i64),
+            Filter::Linear => {
+                let (u, v) = (u - 0.5, v - 0.5);
+                let (x, y) = (u.floor() as i64, v.floor() as i64);
+                let (tx, ty) = (u - u.floor(), v - v.floor());
+                let top = lerp(texel(x, y), texel(x + 1, y), tx);
+                let bottom = lerp(texel(x, y + 1), texel(x + 1, y + 1), tx);
+                lerp(top, bottom, ty)
+            }
+        }
+    }
+}
+
+fn wrap(coord: i64, size: u32, mode: WrapMode) -> i64 {
+    let size = size as i64;
+    match mode {
+        WrapMode::Repeat => coord.rem_euclid(size),
+        WrapMode::MirroredRepeat => {
+            let coord = coord.rem_euclid(2 * size);
+            if coord >= size {
+                2 * size - 1 - coord
+            } else {
+                coord
+            }
+        }
+        WrapMode::ClampToEdge => coord.clamp(0, size - 1),
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut result = a;
+    result
+        .iter_mut()
+        .zip(b.iter())
+        .for_each(|(a, b)| *a += (b - *a) * t);
+    result
+}
+
+/// Texture of the software backend, all levels are stored as linear floats
+#[derive(Clone)]
+pub struct SoftTexture {
+    pub(crate) desc: TextureDescriptor,
+    pub(crate) levels: Arc<RwLock<Vec<MipLevel>>>,
+}
+
+impl SoftTexture {
+    pub(crate) fn new(desc: &TextureDescriptor) -> Self {
+        let levels = (0..desc.mip_levels)
+            .map(|level| {
+                let extent = mip_extent(&desc.extent, level);
+                MipLevel {
+                    texels: vec![[0.0; 4]; (extent.width * extent.height) as usize],
+                    extent,
+                }
+            })
+            .collect();
+        Self {
+            desc: desc.clone(),
+            levels: Arc::new(RwLock::new(levels)),
+        }
+    }
+
+    pub(crate) fn upload(&self, data: &[&[u8]]) {
+        let mut levels = self.levels.write();
+        for (level, bytes) in data.iter().enumerate().take(levels.len()) {
+            levels[level].texels = decode(self.desc.format, bytes);
+        }
+        for level in data.len().max(1)..levels.len() {
+            let extent = levels[level].extent.clone();
+            levels[level] = levels[level - 1].downsample(extent);
+        }
+    }
+
+    /// Samples the texture at uv with an explicit level of detail (like textureLod)
+    pub(crate) fn sample(&self, sampler: &SamplerDescriptor, uv: [f32; 2], lod: f32) -> [f32; 4] {
+        let levels = self.levels.read();
+        let max_level = (levels.len() - 1) as f32;
+        let lod = lod.clamp(0.0, max_level);
+        let filter = if lod > 0.0 {
+            sampler.min_filter
+        } else {
+            sampler.mag_filter
+        };
+        match sampler.mip_filter {
+            Filter::Nearest => levels[lod.round() as usize].sample(sampler, filter, uv),
+            Filter::Linear => {
+                let lower = lod.floor();
+                let a = levels[lower as usize].sample(sampler, filter, uv);
+                let b = levels[lod.ceil() as usize].sample(sampler, filter, uv);
+                lerp(a, b, lod - lower)
+            }
+        }
+    }
+
+    /// Copies an attachment into the first level, depth is replicated into rgb (like sampling a
+    /// depth texture)
+    pub(crate) fn copy_image(&self, image: &Image) {
+        let mut levels = self.levels.write();
+        let level = &mut levels[0];
+        level.extent = image.extent.clone();
+        level.texels = match &image.texels {
+            Texels::Color(texels) => texels.clone(),
+            Texels::Depth(texels) => texels.iter().map(|d| [*d, *d, *d, 1.0]).collect(),
+        };
+    }
+
+    /// Texel of the first level, out of bounds reads are zero
+    pub(crate) fn load(&self, coord: [u32; 2]) -> [f32; 4] {
+        let levels = self.levels.read();
+        let level = &levels[0];
+        if coord[0] < level.extent.width && coord[1] < level.extent.height {
+            level.texels[(coord[1] * level.extent.width + coord[0]) as usize]
+        } else {
+            [0.0; 4]
+        }
+    }
+
+    /// Writes a texel of the first level, out of bounds writes are discarded
+    pub(crate) fn store(&self, coord: [u32; 2], value: [f32; 4]) {
+        let mut levels = self.levels.write();
+        let level = &mut levels[0];
+        if coord[0] < level.extent.width && coord[1] < level.extent.height {
+            level.texels[(coord[1] * level.extent.width + coord[0]) as usize] = value;
+        }
+    }
+}
+
+impl Debug for SoftTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoftTexture")
+            .field("name", &self.desc.name)
+            .field("extent", &self.desc.extent)
+            .field("format", &self.desc.format)
+            .finish()
+    }
+}
+
+/// Samplers are just their descriptor, filtering happens in ShaderEnv::sample
+#[derive(Debug, Clone)]
+pub struct SoftSampler(pub(crate) SamplerDescriptor);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_and_samples_mips() {
+        let desc = TextureDescriptor::with_mips(
+            "checker".into(),
+            Extent2D {
+                width: 2,
+                height: 2,
+            },
+            TextureFormat::Rgba8Unorm,
+        );
+        let texture = SoftTexture::new(&desc);
+        let (black, white) = ([0u8, 0, 0, 255], [255u8; 4]);
+        let texels = [white, black, black, white].concat();
+        texture.upload(&[&texels]);
+
+        let sampler = SamplerDescriptor::NEAREST;
+        assert_eq!(texture.sample(&sampler, [0.25, 0.25], 0.0), [1.0; 4]);
+        assert_eq!(
+            texture.sample(&sampler, [0.75, 0.25], 0.0),
+            [0.0, 0.0, 0.0, 1.0]
+        );
+        // Repeat wraps to the first texel again
+        assert_eq!(texture.sample(&sampler, [1.25, 0.25], 0.0), [1.0; 4]);
+        // The 1x1 level is the average of all texels
+        assert_eq!(
+            texture.sample(&sampler, [0.5, 0.5], 1.0),
+            [0.5, 0.5, 0.5, 1.0]
+        );
+    }
+}
@@ -48,7 +48,8 @@ impl Deref for AsyncComputePool {
 
 impl AsyncComputePool {
     pub fn new() -> Self {
-        let cpus = (num_cpus::get() as f32 / 2.0).floor();
+        // At least one thread, or tasks would never run on single core machines
+        let cpus = (num_cpus::get() as f32 / 2.0).floor().max(1.0);
         Self(task_pool::TaskPool::new(
             Some(cpus as _),
             "async_compute_pool",
@@ -1,5 +1,5 @@
 pub use winit::dpi::{PhysicalPosition, PhysicalSize};
-pub use winit::event::{ElementState, VirtualKeyCode};
+pub use winit::event::{ElementState, MouseButton, VirtualKeyCode};
 
 pub struct WindowResize(pub PhysicalSize<u32>);
 pub struct CursorMoved {
@@ -10,3 +10,14 @@ pub struct KeyboardInput {
     pub key: VirtualKeyCode,
     pub state: ElementState,
 }
+pub struct MouseInput {
+    pub button: MouseButton,
+    pub state: ElementState,
+}
+/// Scrolled lines (or pixels divided by `PIXELS_PER_LINE`), y is positive when scrolling up
+pub struct MouseWheel {
+    pub delta: glam::Vec2,
+}
+
+/// Used to convert pixel deltas of touchpads into lines
+pub const PIXELS_PER_LINE: f32 = 20.0;
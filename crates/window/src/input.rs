@@ -1,16 +1,49 @@
 use glam::Vec2;
 use std::cell::{Ref, RefMut};
-use winit::event::{ElementState, VirtualKeyCode};
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
 
 use app::{stages, App, Events, IntoFunctionSystem};
 
-use crate::events::{CursorMoved, KeyboardInput};
+use crate::events::{CursorMoved, KeyboardInput, MouseInput, MouseWheel};
+
+const KEY_COUNT: usize = VirtualKeyCode::Cut as usize + 1;
+/// Left, right and middle
+const BUTTON_COUNT: usize = 3;
 
 #[derive(Debug)]
 pub struct Input {
     pub mouse_pos: Vec2,
     pub mouse_delta: Vec2,
-    keys: [ElementState; VirtualKeyCode::Cut as usize + 1],
+    /// Scrolled lines of this frame
+    pub scroll_delta: Vec2,
+    keys: [ElementState; KEY_COUNT],
+    buttons: [ElementState; BUTTON_COUNT],
+    /// State of the last frame, to detect presses and releases
+    previous_keys: [ElementState; KEY_COUNT],
+    previous_buttons: [ElementState; BUTTON_COUNT],
+}
+
+fn button_index(button: MouseButton) -> Option<usize> {
+    match button {
+        MouseButton::Left => Some(0),
+        MouseButton::Right => Some(1),
+        MouseButton::Middle => Some(2),
+        MouseButton::Other(_) => None,
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            mouse_pos: glam::vec2(0.0, 0.0),
+            mouse_delta: glam::vec2(0.0, 0.0),
+            scroll_delta: glam::vec2(0.0, 0.0),
+            keys: [ElementState::Released; KEY_COUNT],
+            buttons: [ElementState::Released; BUTTON_COUNT],
+            previous_keys: [ElementState::Released; KEY_COUNT],
+            previous_buttons: [ElementState::Released; BUTTON_COUNT],
+        }
+    }
 }
 
 impl Input {
@@ -21,12 +54,43 @@ impl Input {
     pub fn is_pressed(&self, key: VirtualKeyCode) -> bool {
         self.key(key) == ElementState::Pressed
     }
+
+    /// Pressed this frame, but not in the last one
+    pub fn is_just_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.is_pressed(key) && self.previous_keys[key as usize] == ElementState::Released
+    }
+
+    pub fn mouse_button(&self, button: MouseButton) -> ElementState {
+        button_index(button)
+            .map(|i| self.buttons[i])
+            .unwrap_or(ElementState::Released)
+    }
+
+    pub fn is_mouse_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_button(button) == ElementState::Pressed
+    }
+
+    pub fn is_mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.is_mouse_pressed(button) && !self.was_mouse_pressed(button)
+    }
+
+    pub fn is_mouse_just_released(&self, button: MouseButton) -> bool {
+        !self.is_mouse_pressed(button) && self.was_mouse_pressed(button)
+    }
+
+    fn was_mouse_pressed(&self, button: MouseButton) -> bool {
+        button_index(button)
+            .map(|i| self.previous_buttons[i] == ElementState::Pressed)
+            .unwrap_or(false)
+    }
 }
 
 fn input_system(
     mut input: RefMut<Input>,
     cursor_moved: Ref<Events<CursorMoved>>,
     keys: Ref<Events<KeyboardInput>>,
+    buttons: Ref<Events<MouseInput>>,
+    wheel: Ref<Events<MouseWheel>>,
 ) {
     // Only use the last of the cursor events
     if let Some(CursorMoved { absolute, .. }) = cursor_moved.iter().last() {
@@ -37,17 +101,23 @@ fn input_system(
         input.mouse_delta = glam::Vec2::ZERO;
     }
 
+    input.previous_keys = input.keys;
     for KeyboardInput { key, state } in keys.iter() {
         input.keys[*key as usize] = *state;
     }
+
+    input.previous_buttons = input.buttons;
+    for MouseInput { button, state } in buttons.iter() {
+        if let Some(i) = button_index(*button) {
+            input.buttons[i] = *state;
+        }
+    }
+
+    input.scroll_delta = wheel.iter().map(|MouseWheel { delta }| delta).sum();
 }
 
 pub(crate) fn init(app: &mut App) {
-    app.insert_resource(Input {
-        mouse_pos: glam::vec2(0.0, 0.0),
-        mouse_delta: glam::vec2(0.0, 0.0),
-        keys: [ElementState::Released; VirtualKeyCode::Cut as usize + 1],
-    });
+    app.insert_resource(Input::default());
 
     app.add_system(stages::PREPARE_FRAME, input_system.into_system());
 }
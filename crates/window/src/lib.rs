@@ -12,7 +12,7 @@ use winit::{
 };
 use winit::{event::KeyboardInput, event_loop::EventLoop};
 use winit::{
-    event::{ElementState, VirtualKeyCode},
+    event::{ElementState, MouseScrollDelta, VirtualKeyCode},
     window::{Window, WindowBuilder},
 };
 
@@ -73,6 +73,8 @@ pub fn init_window(app: &mut App) {
         app.add_event::<events::WindowResize>();
         app.add_event::<events::CursorMoved>();
         app.add_event::<events::KeyboardInput>();
+        app.add_event::<events::MouseInput>();
+        app.add_event::<events::MouseWheel>();
 
         // Initialize Submodules
         input::init(app);
@@ -117,6 +119,19 @@ pub fn init_window(app: &mut App) {
                             }
                             dispatch_event(&mut resources, events::KeyboardInput { key, state })
                         }
+                        WindowEvent::MouseInput { button, state, .. } => {
+                            dispatch_event(&mut resources, events::MouseInput { button, state })
+                        }
+                        WindowEvent::MouseWheel { delta, .. } => {
+                            let delta = match delta {
+                                MouseScrollDelta::LineDelta(x, y) => glam::vec2(x, y),
+                                MouseScrollDelta::PixelDelta(position) => {
+                                    glam::vec2(position.x as f32, position.y as f32)
+                                        / events::PIXELS_PER_LINE
+                                }
+                            };
+                            dispatch_event(&mut resources, events::MouseWheel { delta })
+                        }
 
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit;
@@ -4,6 +4,7 @@ use app::{AssetHandle, AssetServer, IntoFunctionSystem, QueryBorrow, Timing};
 use artisan::{components::Transform, mesh::Model, prelude::glam};
 
 mod logger;
+mod overlay;
 mod world;
 
 fn spawner_plugin(app: &mut app::App) {
@@ -35,6 +36,7 @@ fn main() {
     app.add_plugin(artisan::init_artisan);
     app.add_plugin(models::init_models);
     app.add_plugin(spawner_plugin);
+    app.add_plugin(overlay::init_overlay);
 
     app.run();
 }
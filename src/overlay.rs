@@ -0,0 +1,31 @@
+use app::{stages, IntoFunctionSystem, QueryBorrow, Res, Timing};
+use artisan::{
+    font::Font,
+    prelude::glam,
+    text::{Text, TextStyle},
+};
+
+/// Marks the text that shows the frames per second
+struct FpsLabel;
+
+fn fps_label(timing: Res<Timing>, mut query: QueryBorrow<(&mut Text, &FpsLabel)>) {
+    for (_e, (text, _)) in query.iter() {
+        text.text = format!("FPS: {}", timing.fps());
+    }
+}
+
+/// Text drawn on top of the game, like the frames per second in the top left corner
+pub fn init_overlay(app: &mut app::App) {
+    let font = app
+        .load_asset::<Font>("assets/fonts/FiraCode-Regular.ttf")
+        .expect("failed to load the overlay font");
+    app.get_world_mut().spawn((
+        Text {
+            text: String::new(),
+            style: TextStyle::new(font, 18.0, glam::vec4(1.0, 1.0, 1.0, 1.0)),
+            position: glam::vec2(8.0, 8.0),
+        },
+        FpsLabel,
+    ));
+    app.add_system(stages::UPDATE, fps_label.into_system());
+}
@@ -1,16 +1,18 @@
-use app::{stages, AssetHandle, AssetServer, Events, IntoFunctionSystem, Res};
+use app::{stages, AssetServer, Events, IntoFunctionSystem, Res, ResMut};
 use artisan::{
+    bounds::Aabb,
     camera::Camera,
     components::{ModelComponent, Transform},
+    debug::DebugDraw,
     material::Color,
     mesh::{Mesh, MeshPart, Model, Vertex},
     prelude::glam::{self, IVec2, Vec3},
     renderer::ActiveContext,
+    ui::UiState,
     UP,
 };
 use noise::{MultiFractal, NoiseFn};
 use std::{borrow::Borrow, collections::HashMap, sync::Arc};
-use tasks::futures::future;
 use window::{events::CursorMoved, WindowState};
 
 use artisan::material::Material;
@@ -18,7 +20,8 @@ use artisan::material::Material;
 #[derive(Debug)]
 pub struct World {
     // model: AssetHandle<
-    debug_mesh: AssetHandle<Mesh>,
+    /// The terrain under the cursor, that is highlighted
+    highlight: Option<Vec3>,
     height_map: HashMap<IVec2, f32>,
 }
 
@@ -103,11 +106,13 @@ fn mouse_picking(
     camera: Res<Camera>,
     window: Res<WindowState>,
     cursor_moved: Res<Events<CursorMoved>>,
-    context: Res<Arc<ActiveContext>>,
-    asset_server: Res<AssetServer>,
-    world: Res<World>,
+    mut world: ResMut<World>,
+    mut debug: ResMut<DebugDraw>,
+    ui: Res<UiState>,
 ) {
-    if let Some(CursorMoved { relative, .. }) = cursor_moved.iter().last() {
+    // The terrain under the ui is not picked
+    let cursor = cursor_moved.iter().last().filter(|_| !ui.wants_mouse());
+    if let Some(CursorMoved { relative, .. }) = cursor {
         let ray = camera.mouse_ray(
             relative,
             window.size.width as f32 / window.size.height as f32,
@@ -168,60 +173,40 @@ fn mouse_picking(
                 }
             }
         }
-        if let Some(intersection) = intersection {
-            let mut parts = Vec::new();
-            let mut vertices = debug_rect(
-                HeightOption::Terrain(&world.height_map),
-                intersection.x.floor() as _,
-                intersection.z.floor() as _,
-            );
-            vertices.extend(artisan::factory::cube_at(
-                glam::Vec3::splat(0.2),
-                intersection,
-            ));
-            parts.push(MeshPart::from_data(
-                "first_debug_mesh_part",
-                &vertices,
-                Material::BRONZE,
-                &context,
-            ));
-            if !parts.is_empty() {
-                // log::info!("NEW PARTS");
-                let mesh = Mesh::new("debug_mesh", parts);
-                future::block_on(asset_server.update_asset(&world.debug_mesh, mesh));
-            }
+        if intersection.is_some() {
+            world.highlight = intersection;
         }
     }
-}
 
-enum HeightOption<'a> {
-    Terrain(&'a HashMap<IVec2, f32>),
-    Fixed(f32),
+    if let Some(intersection) = world.highlight {
+        let tile = tile_outline(
+            &world.height_map,
+            intersection.x.floor() as _,
+            intersection.z.floor() as _,
+        );
+        let yellow = glam::vec4(1.0, 0.9, 0.2, 1.0);
+        for (i, corner) in tile.iter().enumerate() {
+            debug.line(*corner, tile[(i + 1) % tile.len()], yellow);
+        }
+        let half = Vec3::splat(0.2);
+        debug.aabb(
+            &Aabb::new(intersection - half, intersection + half),
+            glam::vec4(0.8, 0.5, 0.2, 1.0),
+        );
+    }
 }
 
-fn debug_rect(height_option: HeightOption, x: i32, z: i32) -> Vec<Vertex> {
-    let vertex = |o_x: i32, o_z: i32| -> Vertex {
-        let height = match height_option {
-            HeightOption::Terrain(hm) => {
-                hm.get(&glam::ivec2(x + o_x, z + o_z))
-                    .expect("failed to get height")
-                    + 0.01f32
-            }
-            HeightOption::Fixed(h) => h,
-        };
-
-        Vertex {
-            pos: glam::vec3((x + o_x) as f32, height, (z + o_z) as f32),
-            normal: UP,
-        }
+/// Corners of the tile, slightly above the terrain
+fn tile_outline(height_map: &HashMap<IVec2, f32>, x: i32, z: i32) -> [Vec3; 4] {
+    let corner = |o_x: i32, o_z: i32| -> Vec3 {
+        let height = height_map
+            .get(&glam::ivec2(x + o_x, z + o_z))
+            .expect("failed to get height")
+            + 0.01f32;
+        glam::vec3((x + o_x) as f32, height, (z + o_z) as f32)
     };
 
-    let v00 = vertex(0, 0);
-    let v10 = vertex(1, 0);
-    let v01 = vertex(0, 1);
-    let v11 = vertex(1, 1);
-
-    vec![v00, v10, v01, v01, v10, v11]
+    [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)]
 }
 
 pub fn spawn_world(app: &mut app::App) {
@@ -295,7 +280,10 @@ pub fn spawn_world(app: &mut app::App) {
         .iter()
         .map(|(t, vertices)| {
             let context = app.get_res::<Arc<ActiveContext>>();
-            MeshPart::from_data("world", &vertices, to_color(t).into(), &context)
+            let asset_server = app.get_res::<AssetServer>();
+            let material = asset_server
+                .add_loaded_asset(format!("world-{:?}", t), Material::from(to_color(t)));
+            MeshPart::from_data("world", &vertices, material, &context)
         })
         .collect();
 
@@ -311,36 +299,12 @@ pub fn spawn_world(app: &mut app::App) {
         asset_server.add_loaded_asset("world-model", model)
     };
 
-    let (debug_mesh, debug_model) = {
-        let asset_server = app.get_res::<AssetServer>();
-        let context = app.get_res::<Arc<ActiveContext>>();
-        let vertices = debug_rect(HeightOption::Terrain(&height_map), 0, 0);
-        let part = MeshPart::from_data(
-            "debug_mesh_part",
-            vertices.as_slice(),
-            Material::BRONZE,
-            &context,
-        );
-        let mesh = Mesh::new("debug_mesh", vec![part]);
-        let mesh = asset_server.add_loaded_asset("debug_mesh", mesh);
-
-        let mut model = Model::new();
-        model.add_mesh(glam::Mat4::IDENTITY, mesh.clone_strong().unwrap());
-
-        let model = asset_server.add_loaded_asset("debug_mesh_model", model);
-
-        (mesh, model)
-    };
-
     let world = World {
         height_map,
-        debug_mesh,
+        highlight: None,
     };
     app.insert_resource(world);
 
     app.get_world_mut()
         .spawn((ModelComponent(model), Transform::UNIT));
-
-    app.get_world_mut()
-        .spawn((ModelComponent(debug_model), Transform::UNIT));
 }